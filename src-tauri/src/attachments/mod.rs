@@ -0,0 +1,390 @@
+//! Photos and documents attached to units, leases, complaints, inspections,
+//! tenants and properties, e.g. move-in and move-out condition evidence.
+//!
+//! Files are copied into `attachments/` under the app data directory and
+//! named by their SHA-256, so attaching the same file twice stores it once.
+//! Images also get a PNG thumbnail under `attachments/thumbnails/`.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Manager};
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::settings;
+
+mod thumbnail;
+
+const THUMBNAILS: &str = "thumbnails";
+
+/// File types that can be attached, with the content type recorded for each.
+const CONTENT_TYPES: &[(&str, &str)] = &[
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("webp", "image/webp"),
+    ("heic", "image/heic"),
+    ("pdf", "application/pdf"),
+    ("doc", "application/msword"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("xls", "application/vnd.ms-excel"),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    ("txt", "text/plain"),
+    ("csv", "text/csv"),
+];
+
+/// What an attachment belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum OwnerType {
+    Unit,
+    Lease,
+    Complaint,
+    Inspection,
+    Tenant,
+    Property,
+}
+
+impl OwnerType {
+    fn lookup(self) -> &'static str {
+        match self {
+            OwnerType::Unit => "SELECT 1 FROM units WHERE unit_id = ?1",
+            OwnerType::Lease => "SELECT 1 FROM leases WHERE lease_id = ?1",
+            OwnerType::Complaint => "SELECT 1 FROM complaints WHERE complaint_id = ?1",
+            OwnerType::Inspection => "SELECT 1 FROM inspections WHERE inspection_id = ?1",
+            OwnerType::Tenant => "SELECT 1 FROM tenants WHERE tenant_id = ?1",
+            OwnerType::Property => "SELECT 1 FROM properties WHERE property_id = ?1",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            OwnerType::Unit => "unit",
+            OwnerType::Lease => "lease",
+            OwnerType::Complaint => "complaint",
+            OwnerType::Inspection => "inspection",
+            OwnerType::Tenant => "tenant",
+            OwnerType::Property => "property",
+        }
+    }
+}
+
+/// When condition evidence was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum ConditionStage {
+    #[serde(rename = "Move-in")]
+    #[sqlx(rename = "Move-in")]
+    MoveIn,
+    #[serde(rename = "Move-out")]
+    #[sqlx(rename = "Move-out")]
+    MoveOut,
+    Routine,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub attachment_id: i64,
+    pub owner_type: OwnerType,
+    pub owner_id: i64,
+    pub stage: Option<ConditionStage>,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub sha256: String,
+    pub caption: Option<String>,
+    pub uploaded_by: Option<String>,
+    pub created_at: Option<String>,
+    #[serde(skip)]
+    pub stored_name: String,
+    #[serde(skip)]
+    pub thumbnail_name: Option<String>,
+    /// Absolute path of the stored copy.
+    #[sqlx(skip)]
+    pub path: String,
+    #[sqlx(skip)]
+    pub thumbnail_path: Option<String>,
+}
+
+const ATTACHMENT_COLUMNS: &str = "attachment_id, owner_type, owner_id, stage, file_name,
+     content_type, size_bytes, sha256, caption, uploaded_by, created_at, stored_name,
+     thumbnail_name";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewAttachment {
+    pub owner_type: OwnerType,
+    pub owner_id: i64,
+    /// File to copy into the store.
+    pub path: String,
+    #[serde(default)]
+    pub stage: Option<ConditionStage>,
+    #[serde(default)]
+    pub caption: Option<String>,
+    #[serde(default)]
+    pub uploaded_by: Option<String>,
+}
+
+/// Copies the file into the store and attaches it. Files larger than
+/// `attachments.max_mb` (default 25) are refused.
+#[tauri::command]
+pub async fn add_attachment(app: AppHandle, attachment: NewAttachment) -> Result<Attachment> {
+    let pool = db::pool(&app).await?;
+    add(&pool, &store_dir(&app)?, &attachment).await
+}
+
+/// Attachments of one record, optionally only those of a condition stage.
+#[tauri::command]
+pub async fn get_attachments(
+    app: AppHandle,
+    owner_type: OwnerType,
+    owner_id: i64,
+    stage: Option<ConditionStage>,
+) -> Result<Vec<Attachment>> {
+    let pool = db::pool(&app).await?;
+    let store = store_dir(&app)?;
+    let attachments = sqlx::query_as(&format!(
+        "SELECT {ATTACHMENT_COLUMNS} FROM attachments
+         WHERE owner_type = ?1 AND owner_id = ?2 AND (?3 IS NULL OR stage = ?3)
+         ORDER BY created_at, attachment_id"
+    ))
+    .bind(owner_type)
+    .bind(owner_id)
+    .bind(stage)
+    .fetch_all(&pool)
+    .await?;
+    Ok(with_paths(&store, attachments))
+}
+
+/// The unit's condition file: its own attachments and those of its leases,
+/// complaints and inspections.
+#[tauri::command]
+pub async fn get_unit_attachments(app: AppHandle, unit_id: i64) -> Result<Vec<Attachment>> {
+    let pool = db::pool(&app).await?;
+    unit_attachments(&pool, &store_dir(&app)?, unit_id).await
+}
+
+/// Removes the attachment. Its file is deleted once nothing else uses it.
+#[tauri::command]
+pub async fn remove_attachment(app: AppHandle, attachment_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    remove(&pool, &store_dir(&app)?, attachment_id).await
+}
+
+/// Deletes stored files no attachment refers to, e.g. after a unit was
+/// deleted, and returns how many were removed. The daily job does the same.
+#[tauri::command]
+pub async fn prune_attachment_files(app: AppHandle) -> Result<usize> {
+    let pool = db::pool(&app).await?;
+    prune(&pool, &store_dir(&app)?).await
+}
+
+pub fn store_dir(app: &AppHandle) -> Result<PathBuf> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|_| std::io::Error::other("the app data directory is unavailable"))?;
+    Ok(dir.join("attachments"))
+}
+
+pub async fn add(pool: &SqlitePool, store: &Path, new: &NewAttachment) -> Result<Attachment> {
+    let source = Path::new(&new.path);
+    let file_name = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::InvalidInput(format!("{} is not a file", new.path)))?
+        .to_string();
+    let extension = source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let Some(&(_, content_type)) = CONTENT_TYPES.iter().find(|(ext, _)| *ext == extension) else {
+        return Err(Error::InvalidInput(format!(
+            "{file_name} is not an image, PDF, Word, Excel or text file"
+        )));
+    };
+    let exists: Option<(i64,)> = sqlx::query_as(new.owner_type.lookup())
+        .bind(new.owner_id)
+        .fetch_optional(pool)
+        .await?;
+    if exists.is_none() {
+        return Err(Error::NotFound(format!(
+            "{} {}",
+            new.owner_type.label(),
+            new.owner_id
+        )));
+    }
+
+    let max_mb: u64 = settings::get_or(pool, "attachments.max_mb", 25).await?;
+    if std::fs::metadata(source)?.len() > max_mb * 1024 * 1024 {
+        return Err(Error::InvalidInput(format!(
+            "{file_name} is larger than {max_mb} MB"
+        )));
+    }
+    let bytes = std::fs::read(source)?;
+    let sha256 = format!("{:x}", Sha256::digest(&bytes));
+
+    let stored_name = format!("{}/{sha256}.{extension}", &sha256[..2]);
+    let stored = store.join(&stored_name);
+    if !stored.exists() {
+        std::fs::create_dir_all(stored.parent().unwrap_or(store))?;
+        std::fs::write(&stored, &bytes)?;
+    }
+    let thumbnail_name = format!("{THUMBNAILS}/{sha256}.png");
+    let thumbnail_name = if store.join(&thumbnail_name).exists() {
+        Some(thumbnail_name)
+    } else if let Some(png) = thumbnail::render(&bytes, &extension) {
+        std::fs::create_dir_all(store.join(THUMBNAILS))?;
+        std::fs::write(store.join(&thumbnail_name), png)?;
+        Some(thumbnail_name)
+    } else {
+        None
+    };
+
+    let attachment_id = sqlx::query(
+        "INSERT INTO attachments
+             (owner_type, owner_id, stage, file_name, content_type, size_bytes, sha256,
+              stored_name, thumbnail_name, caption, uploaded_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+    )
+    .bind(new.owner_type)
+    .bind(new.owner_id)
+    .bind(new.stage)
+    .bind(&file_name)
+    .bind(content_type)
+    .bind(bytes.len() as i64)
+    .bind(&sha256)
+    .bind(&stored_name)
+    .bind(&thumbnail_name)
+    .bind(&new.caption)
+    .bind(&new.uploaded_by)
+    .execute(pool)
+    .await
+    .map_err(|err| match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => Error::InvalidInput(format!(
+            "{file_name} is already attached to this {}",
+            new.owner_type.label()
+        )),
+        _ => err.into(),
+    })?
+    .last_insert_rowid();
+
+    let attachment = sqlx::query_as(&format!(
+        "SELECT {ATTACHMENT_COLUMNS} FROM attachments WHERE attachment_id = ?1"
+    ))
+    .bind(attachment_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(with_paths(store, vec![attachment]).remove(0))
+}
+
+pub async fn unit_attachments(
+    pool: &SqlitePool,
+    store: &Path,
+    unit_id: i64,
+) -> Result<Vec<Attachment>> {
+    let attachments = sqlx::query_as(&format!(
+        "SELECT {ATTACHMENT_COLUMNS} FROM attachments
+         WHERE (owner_type = 'Unit' AND owner_id = ?1)
+            OR (owner_type = 'Lease'
+                AND owner_id IN (SELECT lease_id FROM leases WHERE unit_id = ?1))
+            OR (owner_type = 'Complaint'
+                AND owner_id IN (SELECT complaint_id FROM complaints WHERE unit_id = ?1))
+            OR (owner_type = 'Inspection'
+                AND owner_id IN (SELECT inspection_id FROM inspections WHERE unit_id = ?1))
+         ORDER BY stage IS NULL, stage, created_at, attachment_id"
+    ))
+    .bind(unit_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(with_paths(store, attachments))
+}
+
+pub async fn remove(pool: &SqlitePool, store: &Path, attachment_id: i64) -> Result<()> {
+    let removed: Option<(String, String, Option<String>)> = sqlx::query_as(
+        "DELETE FROM attachments WHERE attachment_id = ?1
+         RETURNING sha256, stored_name, thumbnail_name",
+    )
+    .bind(attachment_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((sha256, stored_name, thumbnail_name)) = removed else {
+        return Err(Error::NotFound(format!("attachment {attachment_id}")));
+    };
+    let (shared,): (bool,) =
+        sqlx::query_as("SELECT EXISTS (SELECT 1 FROM attachments WHERE sha256 = ?1)")
+            .bind(&sha256)
+            .fetch_one(pool)
+            .await?;
+    if !shared {
+        remove_file(&store.join(stored_name))?;
+        if let Some(thumbnail_name) = thumbnail_name {
+            remove_file(&store.join(thumbnail_name))?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn prune(pool: &SqlitePool, store: &Path) -> Result<usize> {
+    let names: Vec<(String, Option<String>)> =
+        sqlx::query_as("SELECT stored_name, thumbnail_name FROM attachments")
+            .fetch_all(pool)
+            .await?;
+    let keep: HashSet<PathBuf> = names
+        .into_iter()
+        .flat_map(|(stored, thumbnail)| [Some(stored), thumbnail])
+        .flatten()
+        .map(|name| store.join(name))
+        .collect();
+
+    let Ok(dirs) = std::fs::read_dir(store) else {
+        return Ok(0);
+    };
+    let mut removed = 0;
+    for dir in dirs {
+        let dir = dir?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        for file in std::fs::read_dir(&dir)? {
+            let file = file?.path();
+            if file.is_file() && !keep.contains(&file) {
+                remove_file(&file)?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+fn with_paths(store: &Path, mut attachments: Vec<Attachment>) -> Vec<Attachment> {
+    for attachment in &mut attachments {
+        attachment.path = store
+            .join(&attachment.stored_name)
+            .to_string_lossy()
+            .into_owned();
+        attachment.thumbnail_path = attachment
+            .thumbnail_name
+            .as_ref()
+            .map(|name| store.join(name).to_string_lossy().into_owned());
+    }
+    attachments
+}
+
+/// Deletes a stored file; one already gone is not an error.
+fn remove_file(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
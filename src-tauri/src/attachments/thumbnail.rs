@@ -0,0 +1,151 @@
+//! Small PNG previews of attached photos.
+//!
+//! Only PNG and JPEG are decoded. Anything else, or an image that fails to
+//! decode, simply has no thumbnail. EXIF orientation is not applied, so a
+//! rotated phone photo previews the way the camera stored it.
+
+use std::io::Cursor;
+
+/// Longest side of a thumbnail, in pixels.
+const SIZE: u32 = 256;
+
+/// Larger images are not decoded, so a crafted file cannot exhaust memory.
+const MAX_PIXELS: u64 = 50_000_000;
+
+/// An 8-bit RGB image.
+struct Rgb {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// Encodes a thumbnail of `bytes` as PNG, if it is an image we can read.
+pub fn render(bytes: &[u8], extension: &str) -> Option<Vec<u8>> {
+    let image = match extension {
+        "png" => decode_png(bytes)?,
+        "jpg" | "jpeg" => decode_jpeg(bytes)?,
+        _ => return None,
+    };
+    encode_png(&shrink(&image, SIZE)).ok()
+}
+
+fn decode_png(bytes: &[u8]) -> Option<Rgb> {
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let info = reader.info();
+    if u64::from(info.width) * u64::from(info.height) > MAX_PIXELS {
+        return None;
+    }
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer).ok()?;
+    let data = &buffer[..frame.buffer_size()];
+    let pixels = match frame.color_type {
+        png::ColorType::Rgb => data.to_vec(),
+        png::ColorType::Rgba => data.chunks_exact(4).flat_map(over_white).collect(),
+        png::ColorType::Grayscale => data.iter().flat_map(|&v| [v, v, v]).collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks_exact(2)
+            .flat_map(|ga| over_white(&[ga[0], ga[0], ga[0], ga[1]]))
+            .collect(),
+        png::ColorType::Indexed => return None,
+    };
+    Some(Rgb {
+        width: frame.width,
+        height: frame.height,
+        pixels,
+    })
+}
+
+fn decode_jpeg(bytes: &[u8]) -> Option<Rgb> {
+    let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(bytes));
+    decoder.read_info().ok()?;
+    let info = decoder.info()?;
+    let (width, height) = (u32::from(info.width), u32::from(info.height));
+    if u64::from(width) * u64::from(height) > MAX_PIXELS {
+        return None;
+    }
+    let data = decoder.decode().ok()?;
+    let pixels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::RGB24 => data,
+        jpeg_decoder::PixelFormat::L8 => data.iter().flat_map(|&v| [v, v, v]).collect(),
+        // Big-endian 16-bit grey; keep the high byte.
+        jpeg_decoder::PixelFormat::L16 => data
+            .chunks_exact(2)
+            .flat_map(|v| [v[0], v[0], v[0]])
+            .collect(),
+        jpeg_decoder::PixelFormat::CMYK32 => data
+            .chunks_exact(4)
+            .flat_map(|cmyk| {
+                let k = 255 - u16::from(cmyk[3]);
+                [0, 1, 2].map(|i| ((255 - u16::from(cmyk[i])) * k / 255) as u8)
+            })
+            .collect(),
+    };
+    Some(Rgb {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Flattens an RGBA pixel onto a white background.
+fn over_white(rgba: &[u8]) -> [u8; 3] {
+    let alpha = u16::from(rgba[3]);
+    [0, 1, 2].map(|i| ((u16::from(rgba[i]) * alpha + 255 * (255 - alpha)) / 255) as u8)
+}
+
+/// Scales the image down to fit `size` x `size`, averaging the source pixels
+/// each thumbnail pixel covers. Smaller images are left as they are.
+fn shrink(image: &Rgb, size: u32) -> Rgb {
+    let longest = image.width.max(image.height);
+    if longest <= size {
+        return Rgb {
+            width: image.width,
+            height: image.height,
+            pixels: image.pixels.clone(),
+        };
+    }
+    let scale = |n: u32| (u64::from(n) * u64::from(size) / u64::from(longest)).max(1) as u32;
+    let (width, height) = (scale(image.width), scale(image.height));
+    let span = |i: u32, out: u32, src: u32| {
+        let start = u64::from(i) * u64::from(src) / u64::from(out);
+        let end = (u64::from(i + 1) * u64::from(src) / u64::from(out)).max(start + 1);
+        start as usize..end as usize
+    };
+
+    let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        let rows = span(y, height, image.height);
+        for x in 0..width {
+            let cols = span(x, width, image.width);
+            let mut sum = [0u64; 3];
+            for row in rows.clone() {
+                for col in cols.clone() {
+                    let at = (row * image.width as usize + col) * 3;
+                    for (channel, total) in sum.iter_mut().enumerate() {
+                        *total += u64::from(image.pixels[at + channel]);
+                    }
+                }
+            }
+            let count = (rows.len() * cols.len()) as u64;
+            pixels.extend(sum.map(|total| (total / count) as u8));
+        }
+    }
+    Rgb {
+        width,
+        height,
+        pixels,
+    }
+}
+
+fn encode_png(image: &Rgb) -> Result<Vec<u8>, png::EncodingError> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.pixels)?;
+    writer.finish()?;
+    Ok(out)
+}
@@ -0,0 +1,435 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::billing::payments::{self, NewPayment};
+use crate::db;
+use crate::error::{Error, Result};
+
+/// Amounts closer than this are treated as equal.
+const EPSILON: f64 = 0.005;
+
+/// How a payment is spread over the tenant's open charges.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum AllocationMode {
+    /// Settle the oldest due charges first.
+    #[default]
+    OldestFirst,
+    /// Apply exactly the listed amounts; anything left becomes credit.
+    Manual { allocations: Vec<Allocation> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Allocation {
+    pub charge_id: i64,
+    pub amount: f64,
+}
+
+/// A charge together with what has been paid towards it.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ChargeBalance {
+    pub charge_id: i64,
+    pub tenant_id: i64,
+    pub unit_id: Option<i64>,
+    pub property_id: Option<i64>,
+    pub category: String,
+    pub description: Option<String>,
+    pub amount: f64,
+    pub due_date: String,
+    pub status: String,
+    pub paid: f64,
+    pub outstanding: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllocationResult {
+    pub payment_id: String,
+    pub allocations: Vec<Allocation>,
+    /// Overpayment added to the tenant's credit.
+    pub credited: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantBalance {
+    pub tenant_id: i64,
+    pub outstanding: f64,
+    pub credit: f64,
+    pub open_charges: Vec<ChargeBalance>,
+}
+
+/// Records a payment and allocates it in one transaction.
+#[tauri::command]
+pub async fn record_payment(
+    app: AppHandle,
+    payment: NewPayment,
+    allocation: Option<AllocationMode>,
+) -> Result<AllocationResult> {
+    if payment.amount <= 0.0 {
+        return Err(Error::InvalidInput(
+            "payment amount must be positive".into(),
+        ));
+    }
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let payment_id = payments::insert_payment(&mut tx, &payment).await?;
+    let result = allocate(
+        &mut tx,
+        payment.tenant_id,
+        &payment_id,
+        payment.amount,
+        allocation.unwrap_or_default(),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+/// Allocates whatever part of an existing payment has not been applied yet.
+#[tauri::command]
+pub async fn allocate_payment(
+    app: AppHandle,
+    payment_id: String,
+    allocation: Option<AllocationMode>,
+) -> Result<AllocationResult> {
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let (tenant_id, unapplied) = unapplied_amount(&mut tx, &payment_id).await?;
+    if unapplied <= EPSILON {
+        return Err(Error::InvalidInput(format!(
+            "payment {payment_id} is already fully allocated"
+        )));
+    }
+    let result = allocate(
+        &mut tx,
+        tenant_id,
+        &payment_id,
+        unapplied,
+        allocation.unwrap_or_default(),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+/// Spends the tenant's credit on their oldest open charges.
+#[tauri::command]
+pub async fn apply_tenant_credit(app: AppHandle, tenant_id: i64) -> Result<Vec<Allocation>> {
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let credit = credit_balance(&mut tx, tenant_id).await?;
+    if credit <= EPSILON {
+        return Ok(Vec::new());
+    }
+    let open = open_charges(&mut tx, tenant_id).await?;
+    let (applied, _) = plan(credit, &open, &AllocationMode::OldestFirst)?;
+    for allocation in &applied {
+        sqlx::query(
+            "INSERT INTO tenant_credits (tenant_id, amount, charge_id, note)
+             VALUES (?1, ?2, ?3, 'Credit applied')",
+        )
+        .bind(tenant_id)
+        .bind(-allocation.amount)
+        .bind(allocation.charge_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(applied)
+}
+
+#[tauri::command]
+pub async fn get_tenant_balance(app: AppHandle, tenant_id: i64) -> Result<TenantBalance> {
+    let pool = db::pool(&app).await?;
+    tenant_balance(&pool, tenant_id).await
+}
+
+#[tauri::command]
+pub async fn get_payment_allocations(
+    app: AppHandle,
+    payment_id: String,
+) -> Result<Vec<Allocation>> {
+    let pool = db::pool(&app).await?;
+    let rows: Vec<(i64, f64)> = sqlx::query_as(
+        "SELECT charge_id, CAST(amount AS REAL) FROM payment_allocations
+         WHERE payment_id = ?1 ORDER BY allocation_id",
+    )
+    .bind(payment_id)
+    .fetch_all(&pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(charge_id, amount)| Allocation { charge_id, amount })
+        .collect())
+}
+
+pub async fn tenant_balance(pool: &SqlitePool, tenant_id: i64) -> Result<TenantBalance> {
+    let mut conn = pool.acquire().await?;
+    let open_charges = open_charges(&mut conn, tenant_id).await?;
+    let credit = credit_balance(&mut conn, tenant_id).await?;
+    Ok(TenantBalance {
+        tenant_id,
+        outstanding: round_cents(open_charges.iter().map(|c| c.outstanding).sum()),
+        credit,
+        open_charges,
+    })
+}
+
+/// Allocates `available` from `payment_id` and books any remainder as credit.
+///
+/// A charge mirrored from a Pending payments row can take allocations like any
+/// other; that row then stays a bill and can no longer be marked Paid.
+pub async fn allocate(
+    conn: &mut SqliteConnection,
+    tenant_id: i64,
+    payment_id: &str,
+    available: f64,
+    mode: AllocationMode,
+) -> Result<AllocationResult> {
+    let open = open_charges(conn, tenant_id).await?;
+    let (allocations, remainder) = plan(available, &open, &mode)?;
+    for allocation in &allocations {
+        sqlx::query(
+            "INSERT INTO payment_allocations (payment_id, charge_id, amount) VALUES (?1, ?2, ?3)",
+        )
+        .bind(payment_id)
+        .bind(allocation.charge_id)
+        .bind(allocation.amount)
+        .execute(&mut *conn)
+        .await?;
+    }
+    if remainder > EPSILON {
+        sqlx::query(
+            "INSERT INTO tenant_credits (tenant_id, amount, payment_id, note)
+             VALUES (?1, ?2, ?3, 'Overpayment')",
+        )
+        .bind(tenant_id)
+        .bind(remainder)
+        .bind(payment_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(AllocationResult {
+        payment_id: payment_id.to_string(),
+        allocations,
+        credited: if remainder > EPSILON { remainder } else { 0.0 },
+    })
+}
+
+/// Splits `available` over `open` (oldest first) and returns the allocations
+/// with whatever is left over.
+pub fn plan(
+    available: f64,
+    open: &[ChargeBalance],
+    mode: &AllocationMode,
+) -> Result<(Vec<Allocation>, f64)> {
+    let mut remaining = round_cents(available);
+    let mut allocations = Vec::new();
+    match mode {
+        AllocationMode::OldestFirst => {
+            for charge in open {
+                if remaining <= EPSILON {
+                    break;
+                }
+                let amount = round_cents(charge.outstanding.min(remaining));
+                remaining = round_cents(remaining - amount);
+                allocations.push(Allocation {
+                    charge_id: charge.charge_id,
+                    amount,
+                });
+            }
+        }
+        AllocationMode::Manual {
+            allocations: requested,
+        } => {
+            for request in requested {
+                let amount = round_cents(request.amount);
+                if amount <= 0.0 {
+                    return Err(Error::InvalidInput(
+                        "allocation amounts must be positive".into(),
+                    ));
+                }
+                let charge = open
+                    .iter()
+                    .find(|c| c.charge_id == request.charge_id)
+                    .ok_or_else(|| {
+                        Error::InvalidInput(format!(
+                            "charge {} is not an open charge of this tenant",
+                            request.charge_id
+                        ))
+                    })?;
+                let already: f64 = allocations
+                    .iter()
+                    .filter(|a| a.charge_id == charge.charge_id)
+                    .map(|a| a.amount)
+                    .sum();
+                if amount + already > charge.outstanding + EPSILON {
+                    return Err(Error::InvalidInput(format!(
+                        "charge {} only has {:.2} outstanding",
+                        charge.charge_id, charge.outstanding
+                    )));
+                }
+                if amount > remaining + EPSILON {
+                    return Err(Error::InvalidInput(
+                        "allocations exceed the payment amount".into(),
+                    ));
+                }
+                remaining = round_cents(remaining - amount);
+                allocations.push(Allocation {
+                    charge_id: charge.charge_id,
+                    amount,
+                });
+            }
+        }
+    }
+    Ok((allocations, remaining.max(0.0)))
+}
+
+pub async fn open_charges(
+    conn: &mut SqliteConnection,
+    tenant_id: i64,
+) -> Result<Vec<ChargeBalance>> {
+    Ok(sqlx::query_as(
+        "SELECT charge_id, tenant_id, unit_id, property_id, category, description,
+                CAST(amount AS REAL) AS amount, due_date, status,
+                CAST(paid AS REAL) AS paid, CAST(outstanding AS REAL) AS outstanding
+         FROM charge_balances
+         WHERE tenant_id = ?1 AND status = 'Open' AND outstanding > 0.005
+         ORDER BY due_date, charge_id",
+    )
+    .bind(tenant_id)
+    .fetch_all(conn)
+    .await?)
+}
+
+async fn credit_balance(conn: &mut SqliteConnection, tenant_id: i64) -> Result<f64> {
+    let (credit,): (f64,) = sqlx::query_as(
+        "SELECT CAST(COALESCE(SUM(amount), 0) AS REAL) FROM tenant_credits WHERE tenant_id = ?1",
+    )
+    .bind(tenant_id)
+    .fetch_one(conn)
+    .await?;
+    Ok(round_cents(credit))
+}
+
+/// Tenant and not-yet-applied amount of an existing payment.
+async fn unapplied_amount(conn: &mut SqliteConnection, payment_id: &str) -> Result<(i64, f64)> {
+    let row: Option<(i64, f64)> = sqlx::query_as(
+        "SELECT CAST(p.tenant_id AS INTEGER),
+                CAST(p.amount_paid
+                     - COALESCE((SELECT SUM(amount) FROM payment_allocations WHERE payment_id = p.payment_id), 0)
+                     - COALESCE((SELECT SUM(amount) FROM tenant_credits
+                                 WHERE payment_id = p.payment_id AND amount > 0), 0) AS REAL)
+         FROM payments p WHERE p.payment_id = ?1",
+    )
+    .bind(payment_id)
+    .fetch_optional(conn)
+    .await?;
+    let (tenant_id, unapplied) =
+        row.ok_or_else(|| Error::NotFound(format!("payment {payment_id}")))?;
+    Ok((tenant_id, round_cents(unapplied)))
+}
+
+pub fn round_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn charge(charge_id: i64, amount: f64, paid: f64) -> ChargeBalance {
+        ChargeBalance {
+            charge_id,
+            tenant_id: 1,
+            unit_id: Some(1),
+            property_id: Some(1),
+            category: "Rent".into(),
+            description: None,
+            amount,
+            due_date: format!("2025-0{charge_id}-05"),
+            status: "Open".into(),
+            paid,
+            outstanding: round_cents(amount - paid),
+        }
+    }
+
+    fn manual(allocations: &[(i64, f64)]) -> AllocationMode {
+        AllocationMode::Manual {
+            allocations: allocations
+                .iter()
+                .map(|&(charge_id, amount)| Allocation { charge_id, amount })
+                .collect(),
+        }
+    }
+
+    fn amounts(allocations: &[Allocation]) -> Vec<(i64, f64)> {
+        allocations
+            .iter()
+            .map(|a| (a.charge_id, a.amount))
+            .collect()
+    }
+
+    #[test]
+    fn oldest_first_settles_in_order() {
+        let open = [charge(1, 10_000.0, 4_000.0), charge(2, 10_000.0, 0.0)];
+        let (allocations, left) = plan(9_000.0, &open, &AllocationMode::OldestFirst).unwrap();
+        assert_eq!(amounts(&allocations), [(1, 6_000.0), (2, 3_000.0)]);
+        assert_eq!(left, 0.0);
+    }
+
+    #[test]
+    fn overpayment_is_left_over() {
+        let open = [charge(1, 1_000.0, 0.0)];
+        let (allocations, left) = plan(1_250.5, &open, &AllocationMode::OldestFirst).unwrap();
+        assert_eq!(amounts(&allocations), [(1, 1_000.0)]);
+        assert_eq!(left, 250.5);
+        let (allocations, left) = plan(300.0, &[], &AllocationMode::OldestFirst).unwrap();
+        assert!(allocations.is_empty());
+        assert_eq!(left, 300.0);
+    }
+
+    #[test]
+    fn amounts_are_rounded_to_cents() {
+        let open = [charge(1, 100.0, 0.0), charge(2, 100.0, 0.0)];
+        let (allocations, left) =
+            plan(0.1 + 0.2 + 100.0, &open, &AllocationMode::OldestFirst).unwrap();
+        assert_eq!(amounts(&allocations), [(1, 100.0), (2, 0.3)]);
+        assert_eq!(left, 0.0);
+    }
+
+    #[test]
+    fn manual_applies_exact_amounts() {
+        let open = [charge(1, 5_000.0, 0.0), charge(2, 5_000.0, 0.0)];
+        let (allocations, left) =
+            plan(6_000.0, &open, &manual(&[(2, 5_000.0), (1, 500.0)])).unwrap();
+        assert_eq!(amounts(&allocations), [(2, 5_000.0), (1, 500.0)]);
+        assert_eq!(left, 500.0);
+    }
+
+    #[test]
+    fn manual_rejects_bad_requests() {
+        let open = [charge(1, 5_000.0, 4_000.0)];
+        // More than the charge has outstanding, also across two requests.
+        assert!(plan(2_000.0, &open, &manual(&[(1, 1_500.0)])).is_err());
+        assert!(plan(2_000.0, &open, &manual(&[(1, 600.0), (1, 600.0)])).is_err());
+        // More than the payment.
+        assert!(plan(500.0, &open, &manual(&[(1, 800.0)])).is_err());
+        // Not one of the tenant's open charges.
+        assert!(plan(500.0, &open, &manual(&[(9, 100.0)])).is_err());
+        assert!(plan(500.0, &open, &manual(&[(1, 0.0)])).is_err());
+        assert!(plan(500.0, &open, &manual(&[(1, -10.0)])).is_err());
+    }
+
+    #[test]
+    fn manual_tolerates_cent_noise() {
+        let open = [charge(1, 1_000.0, 666.67)];
+        let (allocations, left) = plan(333.33, &open, &manual(&[(1, 333.334)])).unwrap();
+        assert_eq!(amounts(&allocations), [(1, 333.33)]);
+        assert_eq!(left, 0.0);
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::Result;
+
+/// Something a tenant owes: rent, a utility bill, a late fee.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Charge {
+    pub charge_id: i64,
+    pub tenant_id: i64,
+    pub unit_id: Option<i64>,
+    pub property_id: Option<i64>,
+    pub category: String,
+    pub description: Option<String>,
+    pub amount: f64,
+    pub due_date: String,
+    pub status: String,
+    pub source_payment_id: Option<String>,
+    pub parent_charge_id: Option<i64>,
+}
+
+/// Columns selected into [`Charge`], with money decoded as `REAL`.
+pub const CHARGE_COLUMNS: &str =
+    "charge_id, tenant_id, unit_id, property_id, category, description,
+     CAST(amount AS REAL) AS amount, due_date, status, source_payment_id, parent_charge_id";
+
+/// Fields for a new charge. Status starts as `Open`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewCharge {
+    pub tenant_id: i64,
+    pub unit_id: Option<i64>,
+    pub property_id: Option<i64>,
+    pub category: String,
+    pub description: Option<String>,
+    pub amount: f64,
+    pub due_date: String,
+    #[serde(default)]
+    pub parent_charge_id: Option<i64>,
+}
+
+#[tauri::command]
+pub async fn get_tenant_charges(
+    app: AppHandle,
+    tenant_id: i64,
+    status: Option<String>,
+) -> Result<Vec<Charge>> {
+    let pool = db::pool(&app).await?;
+    let charges = sqlx::query_as(&format!(
+        "SELECT {CHARGE_COLUMNS} FROM charges
+         WHERE tenant_id = ?1 AND (?2 IS NULL OR status = ?2)
+         ORDER BY due_date, charge_id"
+    ))
+    .bind(tenant_id)
+    .bind(status)
+    .fetch_all(&pool)
+    .await?;
+    Ok(charges)
+}
+
+pub async fn insert_charge<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+    charge: &NewCharge,
+) -> Result<i64> {
+    let result = sqlx::query(
+        "INSERT INTO charges
+             (tenant_id, unit_id, property_id, category, description, amount, due_date, parent_charge_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )
+    .bind(charge.tenant_id)
+    .bind(charge.unit_id)
+    .bind(charge.property_id)
+    .bind(&charge.category)
+    .bind(&charge.description)
+    .bind(charge.amount)
+    .bind(&charge.due_date)
+    .bind(charge.parent_charge_id)
+    .execute(executor)
+    .await?;
+    Ok(result.last_insert_rowid())
+}
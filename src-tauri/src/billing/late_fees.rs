@@ -0,0 +1,475 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::billing::charges::{self, NewCharge};
+use crate::db;
+use crate::error::{Error, Result};
+use crate::jobs;
+use crate::period;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum FeeType {
+    /// `fee_value` once the grace period is over.
+    Flat,
+    /// `fee_value` percent of the overdue charge.
+    Percentage,
+    /// `fee_value` for every day past the grace period.
+    Daily,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct LateFeeRule {
+    #[serde(default)]
+    pub rule_id: Option<i64>,
+    /// `None` makes this the default for properties without their own rule.
+    pub property_id: Option<i64>,
+    pub category: String,
+    pub fee_type: FeeType,
+    pub fee_value: f64,
+    pub grace_days: i64,
+    pub max_fee: Option<f64>,
+    pub max_fee_percent: Option<f64>,
+    pub is_active: bool,
+}
+
+impl LateFeeRule {
+    /// Fee owed on a charge of `amount` that is `days_overdue` days past due,
+    /// or `None` while still within the grace period.
+    pub fn fee_for(&self, amount: f64, days_overdue: i64) -> Option<f64> {
+        let days_late = days_overdue - self.grace_days;
+        if days_late <= 0 {
+            return None;
+        }
+        let mut fee = match self.fee_type {
+            FeeType::Flat => self.fee_value,
+            FeeType::Percentage => amount * self.fee_value / 100.0,
+            FeeType::Daily => self.fee_value * days_late as f64,
+        };
+        if let Some(max) = self.max_fee {
+            fee = fee.min(max);
+        }
+        if let Some(percent) = self.max_fee_percent {
+            fee = fee.min(amount * percent / 100.0);
+        }
+        let fee = (fee * 100.0).round() / 100.0;
+        (fee > 0.0).then_some(fee)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+        if self.fee_value < 0.0 {
+            return invalid("fee value cannot be negative");
+        }
+        if self.fee_type == FeeType::Percentage && self.fee_value > 100.0 {
+            return invalid("percentage fee cannot exceed 100%");
+        }
+        if self.grace_days < 0 {
+            return invalid("grace days cannot be negative");
+        }
+        if self.max_fee.is_some_and(|m| m < 0.0) || self.max_fee_percent.is_some_and(|m| m < 0.0) {
+            return invalid("fee caps cannot be negative");
+        }
+        Ok(())
+    }
+}
+
+const RULE_COLUMNS: &str =
+    "rule_id, property_id, category, fee_type, CAST(fee_value AS REAL) AS fee_value,
+     grace_days, CAST(max_fee AS REAL) AS max_fee, max_fee_percent, is_active";
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct LateFee {
+    pub late_fee_id: i64,
+    pub charge_id: i64,
+    pub source_charge_id: i64,
+    pub tenant_id: i64,
+    pub amount: f64,
+    pub status: String,
+    pub days_late: i64,
+    pub assessed_on: String,
+    pub waived_at: Option<String>,
+    pub waived_by: Option<String>,
+    pub waiver_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LateFeeRun {
+    pub created: usize,
+    /// Daily fees whose amount grew since the last run.
+    pub updated: usize,
+}
+
+#[tauri::command]
+pub async fn get_late_fee_rules(app: AppHandle) -> Result<Vec<LateFeeRule>> {
+    let pool = db::pool(&app).await?;
+    load_rules(&pool, false).await
+}
+
+/// Inserts the rule, or updates it when `ruleId` is set.
+#[tauri::command]
+pub async fn save_late_fee_rule(app: AppHandle, rule: LateFeeRule) -> Result<LateFeeRule> {
+    rule.validate()?;
+    let pool = db::pool(&app).await?;
+    let rule_id = match rule.rule_id {
+        Some(rule_id) => {
+            let result = sqlx::query(
+                "UPDATE late_fee_rules
+                 SET property_id = ?2, category = ?3, fee_type = ?4, fee_value = ?5, grace_days = ?6,
+                     max_fee = ?7, max_fee_percent = ?8, is_active = ?9, updated_at = CURRENT_TIMESTAMP
+                 WHERE rule_id = ?1",
+            )
+            .bind(rule_id)
+            .bind(rule.property_id)
+            .bind(&rule.category)
+            .bind(rule.fee_type)
+            .bind(rule.fee_value)
+            .bind(rule.grace_days)
+            .bind(rule.max_fee)
+            .bind(rule.max_fee_percent)
+            .bind(rule.is_active)
+            .execute(&pool)
+            .await
+            .map_err(duplicate_rule)?;
+            if result.rows_affected() == 0 {
+                return Err(Error::NotFound(format!("late fee rule {rule_id}")));
+            }
+            rule_id
+        }
+        None => sqlx::query(
+            "INSERT INTO late_fee_rules
+                 (property_id, category, fee_type, fee_value, grace_days, max_fee, max_fee_percent, is_active)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )
+        .bind(rule.property_id)
+        .bind(&rule.category)
+        .bind(rule.fee_type)
+        .bind(rule.fee_value)
+        .bind(rule.grace_days)
+        .bind(rule.max_fee)
+        .bind(rule.max_fee_percent)
+        .bind(rule.is_active)
+        .execute(&pool)
+        .await
+        .map_err(duplicate_rule)?
+        .last_insert_rowid(),
+    };
+    Ok(LateFeeRule {
+        rule_id: Some(rule_id),
+        ..rule
+    })
+}
+
+#[tauri::command]
+pub async fn delete_late_fee_rule(app: AppHandle, rule_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    sqlx::query("DELETE FROM late_fee_rules WHERE rule_id = ?1")
+        .bind(rule_id)
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_late_fees(app: AppHandle, tenant_id: Option<i64>) -> Result<Vec<LateFee>> {
+    let pool = db::pool(&app).await?;
+    let fees = sqlx::query_as(
+        "SELECT lf.late_fee_id, lf.charge_id, lf.source_charge_id, c.tenant_id,
+                CAST(c.amount AS REAL) AS amount, c.status, lf.days_late, lf.assessed_on,
+                lf.waived_at, lf.waived_by, lf.waiver_reason
+         FROM late_fees lf
+         JOIN charges c ON c.charge_id = lf.charge_id
+         WHERE ?1 IS NULL OR c.tenant_id = ?1
+         ORDER BY lf.assessed_on DESC, lf.late_fee_id DESC",
+    )
+    .bind(tenant_id)
+    .fetch_all(&pool)
+    .await?;
+    Ok(fees)
+}
+
+/// Runs the late fee job by hand, e.g. after changing a rule.
+#[tauri::command]
+pub async fn run_late_fees(app: AppHandle, as_of: Option<String>) -> Result<LateFeeRun> {
+    let as_of = as_of
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let pool = db::pool(&app).await?;
+    assess_late_fees(&pool, as_of).await
+}
+
+/// Cancels a fee. The fee charge stays on record with status `Waived`.
+#[tauri::command]
+pub async fn waive_late_fee(
+    app: AppHandle,
+    late_fee_id: i64,
+    waived_by: String,
+    reason: String,
+) -> Result<()> {
+    if waived_by.trim().is_empty() || reason.trim().is_empty() {
+        return Err(Error::InvalidInput(
+            "a waiver needs the name of who waived it and a reason".into(),
+        ));
+    }
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let fee: Option<(i64, String)> = sqlx::query_as(
+        "SELECT c.charge_id, c.status FROM late_fees lf
+         JOIN charges c ON c.charge_id = lf.charge_id
+         WHERE lf.late_fee_id = ?1",
+    )
+    .bind(late_fee_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let (charge_id, status) =
+        fee.ok_or_else(|| Error::NotFound(format!("late fee {late_fee_id}")))?;
+    if status != "Open" {
+        return Err(Error::InvalidInput(format!(
+            "late fee {late_fee_id} is already {}",
+            status.to_lowercase()
+        )));
+    }
+
+    sqlx::query(
+        "UPDATE late_fees SET waived_at = CURRENT_TIMESTAMP, waived_by = ?2, waiver_reason = ?3
+         WHERE late_fee_id = ?1",
+    )
+    .bind(late_fee_id)
+    .bind(waived_by.trim())
+    .bind(reason.trim())
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "UPDATE charges SET status = 'Waived', updated_at = CURRENT_TIMESTAMP WHERE charge_id = ?1",
+    )
+    .bind(charge_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn load_rules(pool: &SqlitePool, active_only: bool) -> Result<Vec<LateFeeRule>> {
+    Ok(sqlx::query_as(&format!(
+        "SELECT {RULE_COLUMNS} FROM late_fee_rules
+         WHERE ?1 = 0 OR is_active = 1
+         ORDER BY property_id IS NOT NULL, property_id, category"
+    ))
+    .bind(active_only)
+    .fetch_all(pool)
+    .await?)
+}
+
+fn duplicate_rule(err: sqlx::Error) -> Error {
+    match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => Error::InvalidInput(
+            "a late fee rule already exists for this property and category".into(),
+        ),
+        _ => err.into(),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct OverdueCharge {
+    charge_id: i64,
+    tenant_id: i64,
+    unit_id: Option<i64>,
+    property_id: Option<i64>,
+    category: String,
+    amount: f64,
+    due_date: String,
+    late_fee_id: Option<i64>,
+    fee_charge_id: Option<i64>,
+    fee_amount: Option<f64>,
+    fee_status: Option<String>,
+}
+
+/// Assesses fees on every open charge past its due date as of `as_of`,
+/// based on what is still unpaid after partial payments.
+///
+/// Each overdue charge gets at most one fee charge. Flat and percentage fees
+/// are set once; daily fees are raised on later runs until they hit their cap,
+/// are paid, or are waived. A charge whose due date cannot be read is
+/// skipped and logged to `job_log` rather than stopping the run.
+pub async fn assess_late_fees(pool: &SqlitePool, as_of: NaiveDate) -> Result<LateFeeRun> {
+    let rules = load_rules(pool, true).await?;
+    let overdue: Vec<OverdueCharge> = sqlx::query_as(
+        "SELECT c.charge_id, c.tenant_id, c.unit_id, c.property_id, c.category,
+                CAST(c.outstanding AS REAL) AS amount, c.due_date,
+                lf.late_fee_id, lf.charge_id AS fee_charge_id,
+                CAST(f.amount AS REAL) AS fee_amount, f.status AS fee_status
+         FROM charge_balances c
+         LEFT JOIN late_fees lf ON lf.source_charge_id = c.charge_id
+         LEFT JOIN charges f ON f.charge_id = lf.charge_id
+         WHERE c.status = 'Open' AND c.category <> 'Late Fee' AND c.due_date < ?1
+           AND c.outstanding > 0.005",
+    )
+    .bind(as_of.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    let mut run = LateFeeRun::default();
+    let mut unreadable = Vec::new();
+    let mut tx = pool.begin().await?;
+    for charge in overdue {
+        let Some(rule) = rules
+            .iter()
+            .find(|r| r.property_id == charge.property_id && r.category == charge.category)
+            .or_else(|| {
+                rules
+                    .iter()
+                    .find(|r| r.property_id.is_none() && r.category == charge.category)
+            })
+        else {
+            continue;
+        };
+        let due_date = match period::parse_date(&charge.due_date) {
+            Ok(due_date) => due_date,
+            Err(err) => {
+                unreadable.push(format!("charge {} skipped: {err}", charge.charge_id));
+                continue;
+            }
+        };
+        let days_overdue = (as_of - due_date).num_days();
+        let Some(fee) = rule.fee_for(charge.amount, days_overdue) else {
+            continue;
+        };
+        let days_late = days_overdue - rule.grace_days;
+
+        match (charge.late_fee_id, charge.fee_charge_id) {
+            (Some(late_fee_id), Some(fee_charge_id)) => {
+                let still_open = charge.fee_status.as_deref() == Some("Open");
+                if !still_open || fee <= charge.fee_amount.unwrap_or(0.0) + 0.005 {
+                    continue;
+                }
+                sqlx::query(
+                    "UPDATE charges SET amount = ?2, updated_at = CURRENT_TIMESTAMP WHERE charge_id = ?1",
+                )
+                .bind(fee_charge_id)
+                .bind(fee)
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query(
+                    "UPDATE late_fees SET days_late = ?2, assessed_on = ?3 WHERE late_fee_id = ?1",
+                )
+                .bind(late_fee_id)
+                .bind(days_late)
+                .bind(as_of.to_string())
+                .execute(&mut *tx)
+                .await?;
+                run.updated += 1;
+            }
+            _ => {
+                let fee_charge_id = charges::insert_charge(
+                    &mut *tx,
+                    &NewCharge {
+                        tenant_id: charge.tenant_id,
+                        unit_id: charge.unit_id,
+                        property_id: charge.property_id,
+                        category: "Late Fee".into(),
+                        description: Some(format!(
+                            "Late fee on {} due {}",
+                            charge.category, charge.due_date
+                        )),
+                        amount: fee,
+                        due_date: as_of.to_string(),
+                        parent_charge_id: Some(charge.charge_id),
+                    },
+                )
+                .await?;
+                sqlx::query(
+                    "INSERT INTO late_fees (charge_id, source_charge_id, rule_id, days_late, assessed_on)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                )
+                .bind(fee_charge_id)
+                .bind(charge.charge_id)
+                .bind(rule.rule_id)
+                .bind(days_late)
+                .bind(as_of.to_string())
+                .execute(&mut *tx)
+                .await?;
+                run.created += 1;
+            }
+        }
+    }
+    tx.commit().await?;
+    // Logged after the commit; the transaction holds SQLite's write lock.
+    for message in unreadable {
+        jobs::log_failure(pool, "late_fees", message).await;
+    }
+    Ok(run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(fee_type: FeeType, fee_value: f64, grace_days: i64) -> LateFeeRule {
+        LateFeeRule {
+            rule_id: None,
+            property_id: None,
+            category: "Rent".into(),
+            fee_type,
+            fee_value,
+            grace_days,
+            max_fee: None,
+            max_fee_percent: None,
+            is_active: true,
+        }
+    }
+
+    #[test]
+    fn nothing_within_grace_period() {
+        let flat = rule(FeeType::Flat, 500.0, 5);
+        assert_eq!(flat.fee_for(15_000.0, 0), None);
+        assert_eq!(flat.fee_for(15_000.0, 5), None);
+        assert_eq!(flat.fee_for(15_000.0, 6), Some(500.0));
+    }
+
+    #[test]
+    fn percentage_is_rounded_to_cents() {
+        let percent = rule(FeeType::Percentage, 2.5, 0);
+        assert_eq!(percent.fee_for(12_345.67, 1), Some(308.64));
+        assert_eq!(percent.fee_for(0.1, 1), None);
+    }
+
+    #[test]
+    fn daily_counts_days_after_grace() {
+        let daily = rule(FeeType::Daily, 100.0, 3);
+        assert_eq!(daily.fee_for(10_000.0, 4), Some(100.0));
+        assert_eq!(daily.fee_for(10_000.0, 13), Some(1_000.0));
+    }
+
+    #[test]
+    fn lower_cap_wins() {
+        let mut daily = rule(FeeType::Daily, 100.0, 0);
+        daily.max_fee = Some(1_500.0);
+        daily.max_fee_percent = Some(10.0);
+        // 30 days is 3,000; 10% of 12,000 is 1,200; the amount cap is 1,500.
+        assert_eq!(daily.fee_for(12_000.0, 30), Some(1_200.0));
+        assert_eq!(daily.fee_for(20_000.0, 30), Some(1_500.0));
+        assert_eq!(daily.fee_for(20_000.0, 5), Some(500.0));
+    }
+
+    #[test]
+    fn zero_fee_is_no_fee() {
+        assert_eq!(rule(FeeType::Flat, 0.0, 0).fee_for(5_000.0, 10), None);
+    }
+
+    #[test]
+    fn rejects_bad_rules() {
+        assert!(rule(FeeType::Percentage, 150.0, 0).validate().is_err());
+        assert!(rule(FeeType::Flat, -1.0, 0).validate().is_err());
+        assert!(rule(FeeType::Flat, 100.0, -2).validate().is_err());
+        let mut capped = rule(FeeType::Daily, 50.0, 0);
+        capped.max_fee = Some(-5.0);
+        assert!(capped.validate().is_err());
+        assert!(rule(FeeType::Percentage, 100.0, 0).validate().is_ok());
+    }
+}
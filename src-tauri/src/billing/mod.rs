@@ -0,0 +1,4 @@
+pub mod allocations;
+pub mod charges;
+pub mod late_fees;
+pub mod payments;
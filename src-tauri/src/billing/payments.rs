@@ -0,0 +1,220 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::ledger::periods;
+
+/// Money received, recorded as a `Paid` row in `payments`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewPayment {
+    pub tenant_id: i64,
+    pub unit_id: i64,
+    pub property_id: i64,
+    pub amount: f64,
+    pub payment_date: String,
+    /// Defaults to the payment date.
+    #[serde(default)]
+    pub due_date: Option<String>,
+    pub payment_method: String,
+    /// Defaults to `Rent`.
+    #[serde(default)]
+    pub payment_category: Option<String>,
+    #[serde(default)]
+    pub receipt_number: Option<String>,
+    #[serde(default)]
+    pub transaction_reference: Option<String>,
+    #[serde(default)]
+    pub remarks: Option<String>,
+}
+
+/// Ids in the same `PAY<millis>-<n>` shape the payments screen generates.
+pub fn new_payment_id() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed) % 1000;
+    format!("PAY{millis}-{n}")
+}
+
+/// Every field of a `payments` row as the payments screen edits it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentUpdate {
+    pub tenant_id: i64,
+    pub unit_id: i64,
+    pub property_id: i64,
+    pub amount: f64,
+    pub payment_date: String,
+    pub due_date: String,
+    pub payment_status: String,
+    pub payment_method: String,
+    pub payment_category: String,
+    #[serde(default)]
+    pub receipt_number: Option<String>,
+    #[serde(default)]
+    pub transaction_reference: Option<String>,
+    #[serde(default)]
+    pub remarks: Option<String>,
+}
+
+/// Edits a payment. A payment dated in a closed period (before or after the
+/// edit) is only changed when `reverse_in_open_period` is set; its ledger
+/// entry is then reversed and reposted in the open period.
+#[tauri::command]
+pub async fn update_payment(
+    app: AppHandle,
+    payment_id: String,
+    payment: PaymentUpdate,
+    reverse_in_open_period: Option<bool>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    update(
+        &pool,
+        &payment_id,
+        &payment,
+        reverse_in_open_period.unwrap_or(false),
+    )
+    .await
+}
+
+/// Deletes a payment, under the same closed-period rule as [`update_payment`].
+#[tauri::command]
+pub async fn delete_payment(
+    app: AppHandle,
+    payment_id: String,
+    reverse_in_open_period: Option<bool>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    delete(&pool, &payment_id, reverse_in_open_period.unwrap_or(false)).await
+}
+
+pub async fn insert_payment(conn: &mut SqliteConnection, payment: &NewPayment) -> Result<String> {
+    periods::ensure_open(conn, &payment.payment_date).await?;
+    let payment_id = new_payment_id();
+    let due_date = payment.due_date.as_ref().unwrap_or(&payment.payment_date);
+    sqlx::query(
+        "INSERT INTO payments (
+             payment_id, tenant_id, unit_id, property_id, amount_paid,
+             payment_date, due_date, payment_status, payment_method, payment_category,
+             payment_month, receipt_number, transaction_reference, remarks, created_at, updated_at
+         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 'Paid', ?8, ?9, strftime('%Y-%m', ?7), ?10, ?11, ?12,
+                   CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+    )
+    .bind(&payment_id)
+    .bind(payment.tenant_id.to_string())
+    .bind(payment.unit_id.to_string())
+    .bind(payment.property_id.to_string())
+    .bind(payment.amount)
+    .bind(&payment.payment_date)
+    .bind(due_date)
+    .bind(&payment.payment_method)
+    .bind(payment.payment_category.as_deref().unwrap_or("Rent"))
+    .bind(&payment.receipt_number)
+    .bind(&payment.transaction_reference)
+    .bind(&payment.remarks)
+    .execute(&mut *conn)
+    .await?;
+    Ok(payment_id)
+}
+
+pub async fn update(
+    pool: &SqlitePool,
+    payment_id: &str,
+    payment: &PaymentUpdate,
+    reverse_in_open_period: bool,
+) -> Result<()> {
+    if payment.amount <= 0.0 {
+        return Err(Error::InvalidInput(
+            "payment amount must be positive".into(),
+        ));
+    }
+    let mut tx = pool.begin().await?;
+    let old_date = payment_date(&mut tx, payment_id).await?;
+    periods::ensure_editable(
+        &mut tx,
+        &[&old_date, &payment.payment_date],
+        reverse_in_open_period,
+    )
+    .await?;
+    sqlx::query(
+        "UPDATE payments
+         SET tenant_id = ?2, unit_id = ?3, property_id = ?4, amount_paid = ?5,
+             payment_date = ?6, due_date = ?7, payment_status = ?8, payment_method = ?9,
+             payment_category = ?10, receipt_number = ?11, transaction_reference = ?12,
+             remarks = ?13, payment_month = strftime('%Y-%m', ?7), updated_at = CURRENT_TIMESTAMP
+         WHERE payment_id = ?1",
+    )
+    .bind(payment_id)
+    .bind(payment.tenant_id.to_string())
+    .bind(payment.unit_id.to_string())
+    .bind(payment.property_id.to_string())
+    .bind(payment.amount)
+    .bind(&payment.payment_date)
+    .bind(&payment.due_date)
+    .bind(&payment.payment_status)
+    .bind(&payment.payment_method)
+    .bind(&payment.payment_category)
+    .bind(&payment.receipt_number)
+    .bind(&payment.transaction_reference)
+    .bind(&payment.remarks)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn delete(
+    pool: &SqlitePool,
+    payment_id: &str,
+    reverse_in_open_period: bool,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let date = payment_date(&mut tx, payment_id).await?;
+    periods::ensure_editable(&mut tx, &[&date], reverse_in_open_period).await?;
+    sqlx::query("DELETE FROM payments WHERE payment_id = ?1")
+        .bind(payment_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn payment_date(conn: &mut SqliteConnection, payment_id: &str) -> Result<String> {
+    let row: Option<(String,)> =
+        sqlx::query_as("SELECT payment_date FROM payments WHERE payment_id = ?1")
+            .bind(payment_id)
+            .fetch_optional(conn)
+            .await?;
+    row.map(|(date,)| date)
+        .ok_or_else(|| Error::NotFound(format!("payment {payment_id}")))
+}
+
+/// Unit of the tenant's latest lease, else the unit on their tenant record.
+pub async fn tenant_unit(conn: &mut SqliteConnection, tenant_id: i64) -> Result<Option<i64>> {
+    let (unit_id,): (Option<i64>,) = sqlx::query_as(
+        "SELECT COALESCE(
+             (SELECT unit_id FROM leases WHERE tenant_id = ?1 ORDER BY lease_start_date DESC LIMIT 1),
+             (SELECT unit_id FROM tenants WHERE tenant_id = ?1))",
+    )
+    .bind(tenant_id)
+    .fetch_one(conn)
+    .await?;
+    Ok(unit_id)
+}
+
+pub async fn unit_property(conn: &mut SqliteConnection, unit_id: i64) -> Result<i64> {
+    let row: Option<(i64,)> = sqlx::query_as("SELECT property_id FROM units WHERE unit_id = ?1")
+        .bind(unit_id)
+        .fetch_optional(conn)
+        .await?;
+    row.map(|(property_id,)| property_id)
+        .ok_or_else(|| Error::NotFound(format!("unit {unit_id}")))
+}
@@ -0,0 +1,545 @@
+//! Local listener for M-Pesa Daraja C2B callbacks.
+//!
+//! When `c2b.enabled` is set, the app accepts Safaricom's validation and
+//! confirmation requests on `c2b.bind_address:c2b.port` (default
+//! `127.0.0.1:8787`). Daraja needs a public HTTPS URL, so in production a
+//! tunnel or reverse proxy forwards to this port.
+//!
+//! Daraja cannot send credentials of its own, so the registered URLs carry
+//! `c2b.secret`, generated on first start:
+//! `/mpesa/c2b/<secret>/validation` and `/mpesa/c2b/<secret>/confirmation`.
+//! Anything else gets a 404. `c2b.allowed_ips` (comma separated) can also
+//! limit callers to Safaricom's published addresses; behind a local proxy the
+//! address it forwards in `X-Forwarded-For` is checked instead.
+//!
+//! Locally the callback can be exercised by posting a sample payload:
+//!
+//! ```text
+//! curl -X POST http://127.0.0.1:8787/mpesa/c2b/<secret>/confirmation \
+//!      -H 'Content-Type: application/json' \
+//!      -d '{"TransactionType":"Pay Bill","TransID":"RKTQDM7W6S",
+//!           "TransTime":"20240115143000","TransAmount":"15000.00",
+//!           "BusinessShortCode":"600638","BillRefNumber":"A12",
+//!           "MSISDN":"254712345678","FirstName":"JOHN","LastName":"DOE"}'
+//! ```
+//!
+//! Confirmed payments go through the same matching as statement imports.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::jobs;
+use crate::settings;
+use crate::statements::mpesa::{self, StatementEntry};
+
+const PATH_PREFIX: &str = "/mpesa/c2b/";
+
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const SETTINGS_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_RETRY: Duration = Duration::from_secs(60 * 60);
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+
+/// Listener settings, read once at startup.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind_address: String,
+    pub port: u16,
+    /// Callbacks for any other shortcode are rejected.
+    pub shortcode: Option<String>,
+    pub auto_accept_confidence: f64,
+    /// Path segment every callback URL must carry.
+    pub secret: String,
+    /// Callers allowed to post; empty allows any.
+    pub allowed_ips: Vec<IpAddr>,
+}
+
+impl Config {
+    /// `None` when the listener is switched off.
+    pub async fn load(pool: &SqlitePool) -> Result<Option<Self>> {
+        if !settings::get_or(pool, "c2b.enabled", false).await? {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            bind_address: settings::get_or(pool, "c2b.bind_address", "127.0.0.1".to_string())
+                .await?,
+            port: settings::get_or(pool, "c2b.port", 8787).await?,
+            shortcode: settings::get(pool, "c2b.shortcode")
+                .await?
+                .map(|code| code.trim().to_string())
+                .filter(|code| !code.is_empty()),
+            auto_accept_confidence: settings::get_or(
+                pool,
+                "c2b.auto_accept_confidence",
+                mpesa::AUTO_ACCEPT_CONFIDENCE,
+            )
+            .await?,
+            secret: secret(pool).await?,
+            allowed_ips: allowed_ips(pool).await?,
+        }))
+    }
+}
+
+/// `c2b.secret`, generated and stored the first time it is needed.
+async fn secret(pool: &SqlitePool) -> Result<String> {
+    if let Some(secret) = settings::get(pool, "c2b.secret").await? {
+        let secret = secret.trim();
+        if secret.len() < 16 || !secret.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(Error::InvalidInput(
+                "setting c2b.secret must be at least 16 letters or digits".into(),
+            ));
+        }
+        return Ok(secret.to_string());
+    }
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| Error::InvalidInput(format!("cannot generate c2b.secret: {err}")))?;
+    let generated: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    sqlx::query(
+        "INSERT INTO settings (key, value) VALUES ('c2b.secret', ?1) ON CONFLICT DO NOTHING",
+    )
+    .bind(&generated)
+    .execute(pool)
+    .await?;
+    Ok(settings::get(pool, "c2b.secret")
+        .await?
+        .unwrap_or(generated))
+}
+
+async fn allowed_ips(pool: &SqlitePool) -> Result<Vec<IpAddr>> {
+    let Some(list) = settings::get(pool, "c2b.allowed_ips").await? else {
+        return Ok(Vec::new());
+    };
+    list.split(',')
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+        .map(|ip| {
+            ip.parse().map_err(|_| {
+                Error::InvalidInput(format!(
+                    "setting c2b.allowed_ips has invalid address '{ip}'"
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Runs the listener for as long as the app is open. Settings are checked
+/// again every [`SETTINGS_INTERVAL`] while it is switched off, so turning it
+/// on needs no restart; after a failure it is retried with a growing delay.
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        db::migrated(&app).await;
+        let mut retry = SETTINGS_INTERVAL;
+        loop {
+            // Its own pool, since the frontend closes the shared one.
+            match db::dedicated(&app).await {
+                Ok(pool) => match Config::load(&pool).await {
+                    Ok(Some(config)) => {
+                        if let Err(err) = serve(pool.clone(), config).await {
+                            jobs::log_failure(&pool, "c2b", format!("listener stopped: {err}"))
+                                .await;
+                        }
+                    }
+                    Ok(None) => retry = SETTINGS_INTERVAL,
+                    Err(err) => {
+                        jobs::log_failure(&pool, "c2b", format!("listener not started: {err}"))
+                            .await
+                    }
+                },
+                Err(err) => eprintln!("C2B listener not started: {err}"),
+            }
+            tokio::time::sleep(retry).await;
+            retry = (retry * 2).min(MAX_RETRY);
+        }
+    });
+}
+
+/// Accepts connections until the listener fails; each request is handled on
+/// its own task.
+pub async fn serve(pool: SqlitePool, config: Config) -> Result<()> {
+    let listener = TcpListener::bind((config.bind_address.as_str(), config.port)).await?;
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let pool = pool.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle(stream, peer, &pool, &config).await {
+                jobs::log_failure(&pool, "c2b", format!("request from {peer} failed: {err}")).await;
+            }
+        });
+    }
+}
+
+/// The body Daraja posts to both the validation and confirmation URLs.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct C2bPayload {
+    #[serde(rename = "TransID")]
+    pub trans_id: String,
+    pub trans_time: String,
+    #[serde(deserialize_with = "amount")]
+    pub trans_amount: f64,
+    #[serde(default)]
+    pub business_short_code: Option<String>,
+    #[serde(default)]
+    pub bill_ref_number: Option<String>,
+    /// A plain number on older integrations, a SHA-256 hash on newer ones.
+    #[serde(rename = "MSISDN", default)]
+    pub msisdn: Option<String>,
+    #[serde(default)]
+    pub first_name: Option<String>,
+    #[serde(default)]
+    pub middle_name: Option<String>,
+    #[serde(default)]
+    pub last_name: Option<String>,
+}
+
+impl C2bPayload {
+    pub fn to_entry(&self) -> Result<StatementEntry> {
+        let transaction_code = self.trans_id.trim().to_uppercase();
+        if transaction_code.is_empty() {
+            return Err(Error::InvalidInput("callback has no TransID".into()));
+        }
+        let completed_at = NaiveDateTime::parse_from_str(self.trans_time.trim(), "%Y%m%d%H%M%S")
+            .map_err(|_| Error::InvalidInput(format!("bad TransTime '{}'", self.trans_time)))?;
+        if self.trans_amount <= 0.0 {
+            return Err(Error::InvalidInput(format!(
+                "bad TransAmount {}",
+                self.trans_amount
+            )));
+        }
+        let names: Vec<&str> = [&self.first_name, &self.middle_name, &self.last_name]
+            .into_iter()
+            .flatten()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .collect();
+        let payer_name = (!names.is_empty()).then(|| names.join(" "));
+        let account_reference = non_empty(&self.bill_ref_number);
+        let phone_number = non_empty(&self.msisdn);
+        let details = match &account_reference {
+            Some(reference) => format!("C2B payment, account {reference}"),
+            None => "C2B payment".to_string(),
+        };
+        Ok(StatementEntry {
+            transaction_code,
+            completed_at,
+            details,
+            amount: self.trans_amount,
+            phone_number,
+            payer_name,
+            account_reference,
+        })
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+/// Daraja sends `TransAmount` as a string; sandbox tools often send a number.
+fn amount<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<f64, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::Number(n) => n
+            .as_f64()
+            .ok_or_else(|| de::Error::custom("TransAmount out of range")),
+        Value::String(s) => s
+            .trim()
+            .replace(',', "")
+            .parse()
+            .map_err(|_| de::Error::custom(format!("bad TransAmount '{s}'"))),
+        other => Err(de::Error::custom(format!("bad TransAmount {other}"))),
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    pool: &SqlitePool,
+    config: &Config,
+) -> Result<()> {
+    let (status, body) = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+        Err(_) => (408, json!({ "error": "request timed out" })),
+        Ok(Err(err)) => (400, json!({ "error": err.to_string() })),
+        Ok(Ok(request)) if !allowed(&request, peer.ip(), config) => {
+            (403, json!({ "error": "forbidden" }))
+        }
+        Ok(Ok(request)) => route(&request, pool, config).await,
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        reason(status),
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    /// Last `X-Forwarded-For` address, the one the nearest proxy saw.
+    forwarded_for: Option<IpAddr>,
+    body: Vec<u8>,
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(Error::InvalidInput("connection closed mid-request".into()));
+        }
+        buf.extend_from_slice(&chunk[..read]);
+        if buf.len() > MAX_REQUEST_BYTES {
+            return Err(Error::InvalidInput("request too large".into()));
+        }
+
+        let mut headers = [httparse::EMPTY_HEADER; 32];
+        let mut parsed = httparse::Request::new(&mut headers);
+        let header_len = match parsed.parse(&buf) {
+            Ok(httparse::Status::Complete(len)) => len,
+            Ok(httparse::Status::Partial) => continue,
+            Err(err) => return Err(Error::InvalidInput(format!("malformed request: {err}"))),
+        };
+        let content_length = parsed
+            .headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case("content-length"))
+            .map(|h| {
+                std::str::from_utf8(h.value)
+                    .ok()
+                    .and_then(|v| v.trim().parse::<usize>().ok())
+                    .ok_or_else(|| Error::InvalidInput("bad Content-Length".into()))
+            })
+            .transpose()?
+            .unwrap_or(0);
+        let forwarded_for = parsed
+            .headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case("x-forwarded-for"))
+            .and_then(|h| std::str::from_utf8(h.value).ok())
+            .and_then(|v| v.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        if header_len + content_length > MAX_REQUEST_BYTES {
+            return Err(Error::InvalidInput("request too large".into()));
+        }
+        if buf.len() < header_len + content_length {
+            continue;
+        }
+        return Ok(Request {
+            method: parsed.method.unwrap_or_default().to_string(),
+            path: parsed
+                .path
+                .unwrap_or_default()
+                .split('?')
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches('/')
+                .to_string(),
+            forwarded_for,
+            body: buf[header_len..header_len + content_length].to_vec(),
+        });
+    }
+}
+
+/// Forwarded addresses are only believed from a proxy on this machine.
+fn allowed(request: &Request, peer: IpAddr, config: &Config) -> bool {
+    if config.allowed_ips.is_empty() {
+        return true;
+    }
+    let caller = match request.forwarded_for {
+        Some(forwarded) if peer.is_loopback() => forwarded,
+        _ => peer,
+    };
+    config.allowed_ips.contains(&caller)
+}
+
+/// `validation` or `confirmation` when `path` carries the secret.
+fn endpoint<'a>(path: &'a str, secret: &str) -> Option<&'a str> {
+    let (given, endpoint) = path.strip_prefix(PATH_PREFIX)?.split_once('/')?;
+    let matches = given.len() == secret.len()
+        && given
+            .bytes()
+            .zip(secret.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    (matches && matches!(endpoint, "validation" | "confirmation")).then_some(endpoint)
+}
+
+async fn route(request: &Request, pool: &SqlitePool, config: &Config) -> (u16, Value) {
+    let Some(endpoint) = endpoint(&request.path, &config.secret) else {
+        return (404, json!({ "error": "not found" }));
+    };
+    if request.method != "POST" {
+        return (405, json!({ "error": "use POST" }));
+    }
+    let payload: C2bPayload = match serde_json::from_slice(&request.body) {
+        Ok(payload) => payload,
+        Err(err) => return (400, json!({ "error": format!("bad callback body: {err}") })),
+    };
+
+    if endpoint == "validation" {
+        return (200, validate(&payload, config));
+    }
+    if let Some(code) = rejection(&payload, config) {
+        return (400, json!({ "ResultCode": code, "ResultDesc": "Rejected" }));
+    }
+    let entry = match payload.to_entry() {
+        Ok(entry) => entry,
+        Err(err) => return (400, json!({ "error": err.to_string() })),
+    };
+    match mpesa::record_callback(pool, &entry, config.auto_accept_confidence).await {
+        Ok(_) => (200, json!({ "ResultCode": 0, "ResultDesc": "Success" })),
+        // A 5xx makes Safaricom retry the notification later.
+        Err(err) => {
+            let message = format!("{} not recorded: {err}", entry.transaction_code);
+            jobs::log_failure(pool, "c2b", &message).await;
+            (500, json!({ "error": message }))
+        }
+    }
+}
+
+/// Daraja's answer to a validation request: accept, or reject with one of
+/// its result codes.
+fn validate(payload: &C2bPayload, config: &Config) -> Value {
+    match rejection(payload, config) {
+        None => json!({ "ResultCode": "0", "ResultDesc": "Accepted" }),
+        Some(code) => json!({ "ResultCode": code, "ResultDesc": "Rejected" }),
+    }
+}
+
+fn rejection(payload: &C2bPayload, config: &Config) -> Option<&'static str> {
+    let shortcode = payload.business_short_code.as_deref().map(str::trim);
+    if config.shortcode.is_some() && shortcode != config.shortcode.as_deref() {
+        return Some("C2B00015"); // invalid shortcode
+    }
+    if payload.trans_amount <= 0.0 {
+        return Some("C2B00013"); // invalid amount
+    }
+    None
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "3f9a1c0e5b7d4a2e8c6b1f0d9e7a5c3b";
+
+    fn config(allowed_ips: &[&str]) -> Config {
+        Config {
+            bind_address: "127.0.0.1".into(),
+            port: 8787,
+            shortcode: Some("600638".into()),
+            auto_accept_confidence: mpesa::AUTO_ACCEPT_CONFIDENCE,
+            secret: SECRET.into(),
+            allowed_ips: allowed_ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+        }
+    }
+
+    fn request(forwarded_for: Option<&str>) -> Request {
+        Request {
+            method: "POST".into(),
+            path: format!("/mpesa/c2b/{SECRET}/confirmation"),
+            forwarded_for: forwarded_for.map(|ip| ip.parse().unwrap()),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn endpoints_need_the_secret() {
+        let path = |rest: &str| format!("/mpesa/c2b/{rest}");
+        assert_eq!(
+            endpoint(&path(&format!("{SECRET}/validation")), SECRET),
+            Some("validation")
+        );
+        assert_eq!(
+            endpoint(&path(&format!("{SECRET}/confirmation")), SECRET),
+            Some("confirmation")
+        );
+        assert_eq!(endpoint(&path("confirmation"), SECRET), None);
+        assert_eq!(endpoint(&path(&format!("{SECRET}/other")), SECRET), None);
+        assert_eq!(
+            endpoint(&path(&format!("{}/confirmation", &SECRET[1..])), SECRET),
+            None
+        );
+        let wrong = SECRET.replace('3', "4");
+        assert_eq!(
+            endpoint(&path(&format!("{wrong}/confirmation")), SECRET),
+            None
+        );
+    }
+
+    #[test]
+    fn allowlist() {
+        let safaricom: IpAddr = "196.201.214.200".parse().unwrap();
+        let other: IpAddr = "203.0.113.9".parse().unwrap();
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+
+        assert!(allowed(&request(None), other, &config(&[])));
+        let config = config(&["196.201.214.200"]);
+        assert!(allowed(&request(None), safaricom, &config));
+        assert!(!allowed(&request(None), other, &config));
+        // Behind a local proxy the forwarded address counts ...
+        assert!(allowed(&request(Some("196.201.214.200")), local, &config));
+        assert!(!allowed(&request(Some("203.0.113.9")), local, &config));
+        assert!(!allowed(&request(None), local, &config));
+        // ... but is not taken from anyone else.
+        assert!(!allowed(&request(Some("196.201.214.200")), other, &config));
+    }
+
+    #[test]
+    fn payload_to_entry() {
+        let payload: C2bPayload = serde_json::from_str(
+            r#"{"TransactionType":"Pay Bill","TransID":" rktqdm7w6s ",
+                "TransTime":"20240115143000","TransAmount":"15,000.00",
+                "BusinessShortCode":"600638","BillRefNumber":" A12 ",
+                "MSISDN":"254712345678","FirstName":"JOHN","MiddleName":"","LastName":"DOE"}"#,
+        )
+        .unwrap();
+        assert_eq!(rejection(&payload, &config(&[])), None);
+        let entry = payload.to_entry().unwrap();
+        assert_eq!(entry.transaction_code, "RKTQDM7W6S");
+        assert_eq!(entry.completed_at.to_string(), "2024-01-15 14:30:00");
+        assert_eq!(entry.amount, 15_000.0);
+        assert_eq!(entry.payer_name.as_deref(), Some("JOHN DOE"));
+        assert_eq!(entry.account_reference.as_deref(), Some("A12"));
+
+        let numeric: C2bPayload = serde_json::from_str(
+            r#"{"TransID":"X1","TransTime":"20240115143000","TransAmount":0,
+                "BusinessShortCode":"174379"}"#,
+        )
+        .unwrap();
+        assert_eq!(rejection(&numeric, &config(&[])), Some("C2B00015"));
+        assert!(numeric.to_entry().is_err());
+    }
+}
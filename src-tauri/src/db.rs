@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_sql::{DbInstances, DbPool};
+
+use crate::error::{Error, Result};
+
+/// Connection string shared by the SQL plugin, the frontend and Rust commands.
+pub const DB_URL: &str = "sqlite:productionv1.db";
+
+/// How often [`migrated`] looks again while the schema is behind.
+const MIGRATION_POLL: Duration = Duration::from_secs(2);
+
+/// Latest migration version the app ships, managed as state at startup.
+pub struct SchemaVersion(pub i64);
+
+/// Returns the pool the SQL plugin opened for [`DB_URL`].
+///
+/// The frontend closes the plugin's pool after some loads (the dashboard,
+/// units and managers pages do), which leaves a closed pool registered. When
+/// the pool is missing or closed we reopen the file the plugin uses and
+/// register the new pool in its place.
+pub async fn pool(app: &AppHandle) -> Result<SqlitePool> {
+    open(&app.state::<DbInstances>(), &path(app)?).await
+}
+
+/// A pool of its own on the same file, for tasks that hold one for as long
+/// as the app runs; the frontend cannot close it under them.
+pub async fn dedicated(app: &AppHandle) -> Result<SqlitePool> {
+    connect(&path(app)?).await
+}
+
+/// Waits until the SQL plugin has applied every migration. Background
+/// workers start with this, so their first run after an upgrade does not
+/// meet the previous version's schema.
+pub async fn migrated(app: &AppHandle) {
+    let expected = app.state::<SchemaVersion>().0;
+    loop {
+        if let Ok(pool) = pool(app).await {
+            if schema_version(&pool).await >= expected {
+                return;
+            }
+        }
+        tokio::time::sleep(MIGRATION_POLL).await;
+    }
+}
+
+fn path(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app
+        .path()
+        .app_config_dir()
+        .map_err(|_| Error::DatabaseNotLoaded(DB_URL.into()))?
+        .join(DB_URL.trim_start_matches("sqlite:")))
+}
+
+async fn connect(path: &Path) -> Result<SqlitePool> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true);
+    Ok(SqlitePool::connect_with(options).await?)
+}
+
+async fn open(instances: &DbInstances, path: &Path) -> Result<SqlitePool> {
+    if let Some(DbPool::Sqlite(pool)) = instances.0.read().await.get(DB_URL) {
+        if !pool.is_closed() {
+            return Ok(pool.clone());
+        }
+    }
+
+    let mut instances = instances.0.write().await;
+    // Another command may have reopened it while we waited for the lock.
+    if let Some(DbPool::Sqlite(pool)) = instances.get(DB_URL) {
+        if !pool.is_closed() {
+            return Ok(pool.clone());
+        }
+    }
+    let pool = connect(path).await?;
+    instances.insert(DB_URL.into(), DbPool::Sqlite(pool.clone()));
+    Ok(pool)
+}
+
+/// Highest migration applied, `0` before the plugin has run any.
+async fn schema_version(pool: &SqlitePool) -> i64 {
+    sqlx::query_as::<_, (Option<i64>,)>(
+        "SELECT MAX(version) FROM _sqlx_migrations WHERE success = 1",
+    )
+    .fetch_one(pool)
+    .await
+    .ok()
+    .and_then(|(version,)| version)
+    .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reopens_a_closed_pool() {
+        tauri::async_runtime::block_on(async {
+            let dir = std::env::temp_dir().join(format!("db-test-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("reopen.db");
+            let _ = std::fs::remove_file(&path);
+            let instances = DbInstances::default();
+
+            let first = open(&instances, &path).await.unwrap();
+            sqlx::query("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (7)")
+                .execute(&first)
+                .await
+                .unwrap();
+            assert!(!open(&instances, &path).await.unwrap().is_closed());
+            assert_eq!(schema_version(&first).await, 0);
+
+            // What the plugin's `close` command does.
+            first.close().await;
+            let second = open(&instances, &path).await.unwrap();
+            assert!(!second.is_closed());
+            let (x,): (i64,) = sqlx::query_as("SELECT x FROM t")
+                .fetch_one(&second)
+                .await
+                .unwrap();
+            assert_eq!(x, 7);
+            match instances.0.read().await.get(DB_URL) {
+                Some(DbPool::Sqlite(pool)) => assert!(!pool.is_closed()),
+                _ => panic!("pool not registered"),
+            }
+
+            second.close().await;
+            let _ = std::fs::remove_dir_all(&dir);
+        });
+    }
+}
@@ -0,0 +1,561 @@
+//! Signed leases, tenant KYC (ID copies, KRA PINs) and property papers such
+//! as insurance certificates.
+//!
+//! A document is an attachment with a type, a reference number and an
+//! optional expiry; the file itself lives in the attachment store. Text is
+//! extracted from PDFs and plain-text files when they are added, for search.
+
+use std::path::Path;
+
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::attachments::{self, NewAttachment, OwnerType};
+use crate::db;
+use crate::error::{Error, Result};
+use crate::period;
+use crate::tasks::{self, TaskPriority};
+
+mod pdf_text;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum DocumentType {
+    #[serde(rename = "Lease Agreement")]
+    #[sqlx(rename = "Lease Agreement")]
+    LeaseAgreement,
+    #[serde(rename = "National ID")]
+    #[sqlx(rename = "National ID")]
+    NationalId,
+    Passport,
+    #[serde(rename = "KRA PIN")]
+    #[sqlx(rename = "KRA PIN")]
+    KraPin,
+    #[serde(rename = "Insurance Certificate")]
+    #[sqlx(rename = "Insurance Certificate")]
+    InsuranceCertificate,
+    #[serde(rename = "Title Deed")]
+    #[sqlx(rename = "Title Deed")]
+    TitleDeed,
+    Permit,
+    Other,
+}
+
+/// What the documents screen edits.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDetails {
+    pub document_type: DocumentType,
+    /// Defaults to the file name.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// ID number, KRA PIN, policy number and the like.
+    #[serde(default)]
+    pub reference_number: Option<String>,
+    #[serde(default)]
+    pub issued_on: Option<String>,
+    #[serde(default)]
+    pub expires_on: Option<String>,
+    /// Days before expiry the renewal task is created.
+    #[serde(default = "default_reminder_days")]
+    pub reminder_days: i64,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+fn default_reminder_days() -> i64 {
+    30
+}
+
+impl DocumentDetails {
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+        if self.reminder_days < 0 {
+            return invalid("reminder days cannot be negative");
+        }
+        let issued = self
+            .issued_on
+            .as_deref()
+            .map(period::parse_date)
+            .transpose()?;
+        let expires = self
+            .expires_on
+            .as_deref()
+            .map(period::parse_date)
+            .transpose()?;
+        if let (Some(issued), Some(expires)) = (issued, expires) {
+            if expires < issued {
+                return invalid("a document cannot expire before it was issued");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewDocument {
+    pub owner_type: OwnerType,
+    pub owner_id: i64,
+    /// File to copy into the attachment store.
+    pub path: String,
+    #[serde(default)]
+    pub uploaded_by: Option<String>,
+    #[serde(flatten)]
+    pub details: DocumentDetails,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+    pub document_id: i64,
+    pub attachment_id: i64,
+    pub owner_type: OwnerType,
+    pub owner_id: i64,
+    /// Tenant, property or unit name, or "Lease N - tenant".
+    pub owner_name: Option<String>,
+    pub document_type: DocumentType,
+    pub title: String,
+    pub reference_number: Option<String>,
+    pub issued_on: Option<String>,
+    pub expires_on: Option<String>,
+    pub reminder_days: i64,
+    pub notes: Option<String>,
+    /// Whether any text was found for search.
+    pub text_extracted: bool,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub uploaded_by: Option<String>,
+    pub created_at: Option<String>,
+    #[serde(skip)]
+    pub stored_name: String,
+    /// Absolute path of the stored file.
+    #[sqlx(skip)]
+    pub path: String,
+    /// Negative once expired.
+    #[sqlx(skip)]
+    pub days_to_expiry: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMatch {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub document: Document,
+    /// Matching text with the hits in [brackets].
+    pub snippet: String,
+}
+
+const DOCUMENT_COLUMNS: &str = "d.document_id, d.attachment_id, a.owner_type, a.owner_id,
+            CASE a.owner_type
+                WHEN 'Tenant' THEN (SELECT full_name FROM tenants WHERE tenant_id = a.owner_id)
+                WHEN 'Lease' THEN (SELECT 'Lease ' || l.lease_id || COALESCE(' - ' || t.full_name, '')
+                                   FROM leases l LEFT JOIN tenants t ON t.tenant_id = l.tenant_id
+                                   WHERE l.lease_id = a.owner_id)
+                WHEN 'Property' THEN (SELECT name FROM properties WHERE property_id = a.owner_id)
+                WHEN 'Unit' THEN (SELECT unit_number FROM units WHERE unit_id = a.owner_id)
+                ELSE a.owner_type || ' ' || a.owner_id
+            END AS owner_name,
+            d.document_type, d.title, d.reference_number, d.issued_on, d.expires_on,
+            d.reminder_days, d.notes, d.text_extracted, a.file_name, a.content_type, a.size_bytes,
+            a.uploaded_by, d.created_at, a.stored_name";
+
+/// A later document of the same type for the same owner replaces this one,
+/// e.g. a renewed insurance certificate.
+const NOT_SUPERSEDED: &str = "NOT EXISTS (
+         SELECT 1 FROM documents n JOIN attachments na ON na.attachment_id = n.attachment_id
+         WHERE na.owner_type = a.owner_type AND na.owner_id = a.owner_id
+           AND n.document_type = d.document_type AND n.expires_on > d.expires_on)";
+
+/// Stores the file and records it as a document.
+#[tauri::command]
+pub async fn add_document(app: AppHandle, document: NewDocument) -> Result<Document> {
+    let pool = db::pool(&app).await?;
+    add(&pool, &attachments::store_dir(&app)?, &document).await
+}
+
+#[tauri::command]
+pub async fn save_document_details(
+    app: AppHandle,
+    document_id: i64,
+    details: DocumentDetails,
+) -> Result<Document> {
+    let pool = db::pool(&app).await?;
+    save_details(&pool, &attachments::store_dir(&app)?, document_id, &details).await
+}
+
+/// Documents, optionally of one owner or type; soonest to expire first.
+#[tauri::command]
+pub async fn get_documents(
+    app: AppHandle,
+    owner_type: Option<OwnerType>,
+    owner_id: Option<i64>,
+    document_type: Option<DocumentType>,
+) -> Result<Vec<Document>> {
+    let pool = db::pool(&app).await?;
+    let documents = sqlx::query_as(&format!(
+        "SELECT {DOCUMENT_COLUMNS}
+         FROM documents d JOIN attachments a ON a.attachment_id = d.attachment_id
+         WHERE (?1 IS NULL OR a.owner_type = ?1) AND (?2 IS NULL OR a.owner_id = ?2)
+           AND (?3 IS NULL OR d.document_type = ?3)
+         ORDER BY d.expires_on IS NULL, d.expires_on, d.document_type, d.title"
+    ))
+    .bind(owner_type)
+    .bind(owner_id)
+    .bind(document_type)
+    .fetch_all(&pool)
+    .await?;
+    Ok(finish(
+        &attachments::store_dir(&app)?,
+        documents,
+        period::today(),
+    ))
+}
+
+/// Documents expired or expiring within `within_days` (default 60), leaving
+/// out those already replaced by a later one.
+#[tauri::command]
+pub async fn get_expiring_documents(
+    app: AppHandle,
+    within_days: Option<i64>,
+) -> Result<Vec<Document>> {
+    let pool = db::pool(&app).await?;
+    expiring(
+        &pool,
+        &attachments::store_dir(&app)?,
+        period::today(),
+        within_days.unwrap_or(60),
+    )
+    .await
+}
+
+/// Full-text search over titles, reference numbers and extracted text. Every
+/// word must match, as a word or the start of one.
+#[tauri::command]
+pub async fn search_documents(
+    app: AppHandle,
+    query: String,
+    limit: Option<i64>,
+) -> Result<Vec<DocumentMatch>> {
+    let pool = db::pool(&app).await?;
+    search(
+        &pool,
+        &attachments::store_dir(&app)?,
+        &query,
+        limit.unwrap_or(50),
+    )
+    .await
+}
+
+/// Removes the document and its attachment.
+#[tauri::command]
+pub async fn remove_document(app: AppHandle, document_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let attachment: Option<(i64,)> =
+        sqlx::query_as("SELECT attachment_id FROM documents WHERE document_id = ?1")
+            .bind(document_id)
+            .fetch_optional(&pool)
+            .await?;
+    let Some((attachment_id,)) = attachment else {
+        return Err(Error::NotFound(format!("document {document_id}")));
+    };
+    attachments::remove(&pool, &attachments::store_dir(&app)?, attachment_id).await
+}
+
+/// Extracts the text of every document again and returns how many have
+/// text.
+#[tauri::command]
+pub async fn reindex_documents(app: AppHandle) -> Result<usize> {
+    let pool = db::pool(&app).await?;
+    reindex(&pool, &attachments::store_dir(&app)?).await
+}
+
+pub async fn add(pool: &SqlitePool, store: &Path, new: &NewDocument) -> Result<Document> {
+    new.details.validate()?;
+    let attachment = attachments::add(
+        pool,
+        store,
+        &NewAttachment {
+            owner_type: new.owner_type,
+            owner_id: new.owner_id,
+            path: new.path.clone(),
+            stage: None,
+            caption: None,
+            uploaded_by: new.uploaded_by.clone(),
+        },
+    )
+    .await?;
+    let details = &new.details;
+    let title = details
+        .title
+        .as_deref()
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .unwrap_or(&attachment.file_name);
+    let inserted = sqlx::query(
+        "INSERT INTO documents
+             (attachment_id, document_type, title, reference_number, issued_on, expires_on,
+              reminder_days, notes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )
+    .bind(attachment.attachment_id)
+    .bind(details.document_type)
+    .bind(title)
+    .bind(&details.reference_number)
+    .bind(&details.issued_on)
+    .bind(&details.expires_on)
+    .bind(details.reminder_days)
+    .bind(&details.notes)
+    .execute(pool)
+    .await;
+    let document_id = match inserted {
+        Ok(result) => result.last_insert_rowid(),
+        Err(err) => {
+            attachments::remove(pool, store, attachment.attachment_id).await?;
+            return Err(err.into());
+        }
+    };
+    index(
+        pool,
+        document_id,
+        &attachment.path,
+        &attachment.content_type,
+    )
+    .await?;
+    load(pool, store, document_id).await
+}
+
+pub async fn save_details(
+    pool: &SqlitePool,
+    store: &Path,
+    document_id: i64,
+    details: &DocumentDetails,
+) -> Result<Document> {
+    details.validate()?;
+    let existing = load(pool, store, document_id).await?;
+    let title = details
+        .title
+        .as_deref()
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .unwrap_or(&existing.file_name);
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "UPDATE documents
+         SET document_type = ?2, title = ?3, reference_number = ?4, issued_on = ?5,
+             expires_on = ?6, reminder_days = ?7, notes = ?8, updated_at = CURRENT_TIMESTAMP
+         WHERE document_id = ?1",
+    )
+    .bind(document_id)
+    .bind(details.document_type)
+    .bind(title)
+    .bind(&details.reference_number)
+    .bind(&details.issued_on)
+    .bind(&details.expires_on)
+    .bind(details.reminder_days)
+    .bind(&details.notes)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE document_search SET title = ?2, reference_number = ?3 WHERE rowid = ?1")
+        .bind(document_id)
+        .bind(title)
+        .bind(&details.reference_number)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    load(pool, store, document_id).await
+}
+
+pub async fn expiring(
+    pool: &SqlitePool,
+    store: &Path,
+    as_of: NaiveDate,
+    within_days: i64,
+) -> Result<Vec<Document>> {
+    let documents = sqlx::query_as(&format!(
+        "SELECT {DOCUMENT_COLUMNS}
+         FROM documents d JOIN attachments a ON a.attachment_id = d.attachment_id
+         WHERE d.expires_on <= ?1 AND {NOT_SUPERSEDED}
+         ORDER BY d.expires_on, d.title"
+    ))
+    .bind((as_of + Duration::days(within_days)).to_string())
+    .fetch_all(pool)
+    .await?;
+    Ok(finish(store, documents, as_of))
+}
+
+pub async fn search(
+    pool: &SqlitePool,
+    store: &Path,
+    query: &str,
+    limit: i64,
+) -> Result<Vec<DocumentMatch>> {
+    // Quote each word so FTS5 syntax in the input is searched for literally.
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| word.replace('"', ""))
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{word}\"*"))
+        .collect();
+    if terms.is_empty() {
+        return Err(Error::InvalidInput("enter something to search for".into()));
+    }
+    let mut matches: Vec<DocumentMatch> = sqlx::query_as(&format!(
+        "SELECT {DOCUMENT_COLUMNS},
+                snippet(document_search, -1, '[', ']', '...', 12) AS snippet
+         FROM document_search
+         JOIN documents d ON d.document_id = document_search.rowid
+         JOIN attachments a ON a.attachment_id = d.attachment_id
+         WHERE document_search MATCH ?1
+         ORDER BY document_search.rank
+         LIMIT ?2"
+    ))
+    .bind(terms.join(" "))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    let today = period::today();
+    for found in &mut matches {
+        let document = finish(store, vec![found.document.clone()], today).remove(0);
+        found.document = document;
+    }
+    Ok(matches)
+}
+
+pub async fn reindex(pool: &SqlitePool, store: &Path) -> Result<usize> {
+    let documents: Vec<(i64, String, String)> = sqlx::query_as(
+        "SELECT d.document_id, a.stored_name, a.content_type
+         FROM documents d JOIN attachments a ON a.attachment_id = d.attachment_id",
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut with_text = 0;
+    for (document_id, stored_name, content_type) in documents {
+        let path = store.join(stored_name);
+        if index(pool, document_id, &path.to_string_lossy(), &content_type).await? {
+            with_text += 1;
+        }
+    }
+    Ok(with_text)
+}
+
+/// Adds a renewal task for each document within its reminder period of
+/// `as_of`. Task names carry the expiry date, so a renewed document with a
+/// new date gets a new task.
+pub async fn create_expiry_tasks(pool: &SqlitePool, as_of: NaiveDate) -> Result<usize> {
+    let due: Vec<Document> = sqlx::query_as(&format!(
+        "SELECT {DOCUMENT_COLUMNS}
+         FROM documents d JOIN attachments a ON a.attachment_id = d.attachment_id
+         WHERE d.expires_on IS NOT NULL
+           AND date(d.expires_on, '-' || d.reminder_days || ' days') <= ?1
+           AND {NOT_SUPERSEDED}
+         ORDER BY d.expires_on"
+    ))
+    .bind(as_of.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    let mut created = 0;
+    for document in due {
+        let expires_on = period::parse_date(document.expires_on.as_deref().unwrap_or_default())?;
+        let name = format!(
+            "Renew {}: {} - {} (expires {expires_on})",
+            type_label(document.document_type),
+            document.title,
+            document.owner_name.as_deref().unwrap_or("unknown owner"),
+        );
+        let priority = if expires_on <= as_of + Duration::days(7) {
+            TaskPriority::High
+        } else {
+            TaskPriority::Medium
+        };
+        let due_date = (expires_on - Duration::days(7)).max(as_of);
+        if tasks::create_task(&mut *tx, &name, due_date, priority).await? {
+            created += 1;
+        }
+    }
+    tx.commit().await?;
+    Ok(created)
+}
+
+fn type_label(document_type: DocumentType) -> &'static str {
+    match document_type {
+        DocumentType::LeaseAgreement => "lease agreement",
+        DocumentType::NationalId => "national ID",
+        DocumentType::Passport => "passport",
+        DocumentType::KraPin => "KRA PIN",
+        DocumentType::InsuranceCertificate => "insurance certificate",
+        DocumentType::TitleDeed => "title deed",
+        DocumentType::Permit => "permit",
+        DocumentType::Other => "document",
+    }
+}
+
+/// Extracts the stored file's text into the search index; returns whether
+/// any was found. Titles and reference numbers are searchable either way.
+async fn index(
+    pool: &SqlitePool,
+    document_id: i64,
+    path: &str,
+    content_type: &str,
+) -> Result<bool> {
+    let text = match content_type {
+        "application/pdf" => pdf_text::extract(&std::fs::read(path)?),
+        "text/plain" | "text/csv" => String::from_utf8_lossy(&std::fs::read(path)?).into_owned(),
+        _ => String::new(),
+    };
+    let found = !text.trim().is_empty();
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM document_search WHERE rowid = ?1")
+        .bind(document_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "INSERT INTO document_search (rowid, title, reference_number, content)
+         SELECT document_id, title, COALESCE(reference_number, ''), ?2
+         FROM documents WHERE document_id = ?1",
+    )
+    .bind(document_id)
+    .bind(&text)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE documents SET text_extracted = ?2 WHERE document_id = ?1")
+        .bind(document_id)
+        .bind(found)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(found)
+}
+
+async fn load(pool: &SqlitePool, store: &Path, document_id: i64) -> Result<Document> {
+    let document = sqlx::query_as(&format!(
+        "SELECT {DOCUMENT_COLUMNS}
+         FROM documents d JOIN attachments a ON a.attachment_id = d.attachment_id
+         WHERE d.document_id = ?1"
+    ))
+    .bind(document_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("document {document_id}")))?;
+    Ok(finish(store, vec![document], period::today()).remove(0))
+}
+
+fn finish(store: &Path, mut documents: Vec<Document>, today: NaiveDate) -> Vec<Document> {
+    for document in &mut documents {
+        document.path = store
+            .join(&document.stored_name)
+            .to_string_lossy()
+            .into_owned();
+        document.days_to_expiry = document
+            .expires_on
+            .as_deref()
+            .and_then(|date| period::parse_date(date).ok())
+            .map(|date| (date - today).num_days());
+    }
+    documents
+}
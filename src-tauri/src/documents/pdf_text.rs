@@ -0,0 +1,580 @@
+//! Best-effort text extraction from PDFs, for document search.
+//!
+//! Every content stream in the file is scanned, in file order, for text
+//! drawing operators; `FlateDecode` streams are inflated first and streams
+//! with other filters (images, mostly) are skipped. Strings are mapped
+//! through any `ToUnicode` CMaps in the file, else read as Latin-1. That
+//! covers the PDFs office software and this app produce. Scanned documents
+//! have no text to find, and page order is not guaranteed.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Inflated streams larger than this are cut short.
+const MAX_STREAM_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Text of the PDF in `bytes`, one line per text line found.
+pub fn extract(bytes: &[u8]) -> String {
+    let streams = streams(bytes);
+    let mut cmap = CMap::default();
+    for stream in streams.iter().filter(|s| contains(s, b"begincmap")) {
+        cmap.parse(stream);
+    }
+    let mut text = String::new();
+    for stream in streams.iter().filter(|s| !contains(s, b"begincmap")) {
+        show_text(stream, &cmap, &mut text);
+    }
+    tidy(&text)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    find(haystack, needle, 0).is_some()
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|at| at + from)
+}
+
+/// Decoded data of every stream we can read.
+fn streams(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut streams = Vec::new();
+    let mut at = 0;
+    while let Some(keyword) = find(bytes, b"stream", at) {
+        at = keyword + b"stream".len();
+        // Skip the `stream` inside `endstream`.
+        if bytes[..keyword].ends_with(b"end") {
+            continue;
+        }
+        let start = match bytes.get(at..at + 2) {
+            Some(b"\r\n") => at + 2,
+            Some([b'\n', _]) | Some([b'\r', _]) => at + 1,
+            _ => continue,
+        };
+        let Some(end) = find(bytes, b"endstream", start) else {
+            break;
+        };
+        at = end;
+        let data = bytes[start..end]
+            .strip_suffix(b"\r\n")
+            .or_else(|| bytes[start..end].strip_suffix(b"\n"))
+            .unwrap_or(&bytes[start..end]);
+
+        // The stream's dictionary sits between the object header and here.
+        let dict_start = bytes[..keyword]
+            .windows(3)
+            .rposition(|window| window == b"obj")
+            .unwrap_or(0)
+            .max(keyword.saturating_sub(4096));
+        let dict = &bytes[dict_start..keyword];
+        if contains(dict, b"/Image") || contains(dict, b"/XRef") {
+            continue;
+        }
+        // Filter names end in `Decode`; `/DecodeParms` is not one.
+        let filters = dict
+            .windows(7)
+            .filter(|window| window[0].is_ascii_alphabetic() && window.ends_with(b"Decode"))
+            .count();
+        let flate = contains(dict, b"/FlateDecode");
+        match (filters, flate) {
+            (0, _) => streams.push(data.to_vec()),
+            (1, true) => {
+                let mut inflated = Vec::new();
+                // A damaged stream still yields what inflated before the error.
+                let _ = flate2::read::ZlibDecoder::new(data)
+                    .take(MAX_STREAM_BYTES)
+                    .read_to_end(&mut inflated);
+                streams.push(inflated);
+            }
+            _ => {}
+        }
+    }
+    streams
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Number(f64),
+    String(Vec<u8>),
+    Array(Vec<Operand>),
+    Other,
+}
+
+enum Token<'a> {
+    Operand(Operand),
+    ArrayStart,
+    ArrayEnd,
+    Operator(&'a [u8]),
+}
+
+struct Lexer<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    byte.is_ascii_whitespace() || b"()<>[]{}/%".contains(&byte)
+}
+
+impl<'a> Lexer<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, at: 0 }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.at).copied()
+    }
+
+    fn word(&mut self) -> &'a [u8] {
+        let start = self.at;
+        while self.peek().is_some_and(|byte| !is_delimiter(byte)) {
+            self.at += 1;
+        }
+        &self.data[start..self.at]
+    }
+
+    fn literal(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut depth = 1;
+        while let Some(byte) = self.peek() {
+            self.at += 1;
+            match byte {
+                b'(' => depth += 1,
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                b'\\' => {
+                    let Some(escaped) = self.peek() else { break };
+                    self.at += 1;
+                    match escaped {
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'b' => out.push(8),
+                        b'f' => out.push(12),
+                        b'0'..=b'7' => {
+                            let mut value = u32::from(escaped - b'0');
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(digit @ b'0'..=b'7') => {
+                                        value = value * 8 + u32::from(digit - b'0');
+                                        self.at += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            out.push(value as u8);
+                        }
+                        // A backslash before a line break continues the line.
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.at += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        other => out.push(other),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            out.push(byte);
+        }
+        out
+    }
+
+    fn hex(&mut self) -> Vec<u8> {
+        let mut digits = Vec::new();
+        while let Some(byte) = self.peek() {
+            self.at += 1;
+            match byte {
+                b'>' => break,
+                _ if byte.is_ascii_hexdigit() => digits.push(byte),
+                _ => {}
+            }
+        }
+        if digits.len() % 2 == 1 {
+            digits.push(b'0');
+        }
+        digits
+            .chunks(2)
+            .map(|pair| {
+                let text = std::str::from_utf8(pair).unwrap_or("00");
+                u8::from_str_radix(text, 16).unwrap_or(0)
+            })
+            .collect()
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            let byte = self.peek()?;
+            match byte {
+                _ if byte.is_ascii_whitespace() => self.at += 1,
+                b'%' => {
+                    while self
+                        .peek()
+                        .is_some_and(|byte| byte != b'\n' && byte != b'\r')
+                    {
+                        self.at += 1;
+                    }
+                }
+                b'(' => {
+                    self.at += 1;
+                    return Some(Token::Operand(Operand::String(self.literal())));
+                }
+                b'<' if self.data.get(self.at + 1) == Some(&b'<') => {
+                    self.at += 2;
+                    return Some(Token::Operand(Operand::Other));
+                }
+                b'<' => {
+                    self.at += 1;
+                    return Some(Token::Operand(Operand::String(self.hex())));
+                }
+                b'>' => {
+                    self.at += 1;
+                    if self.peek() == Some(b'>') {
+                        self.at += 1;
+                    }
+                    return Some(Token::Operand(Operand::Other));
+                }
+                b'[' => {
+                    self.at += 1;
+                    return Some(Token::ArrayStart);
+                }
+                b']' => {
+                    self.at += 1;
+                    return Some(Token::ArrayEnd);
+                }
+                b'/' => {
+                    self.at += 1;
+                    self.word();
+                    return Some(Token::Operand(Operand::Other));
+                }
+                b'{' | b'}' | b')' => {
+                    self.at += 1;
+                    return Some(Token::Operand(Operand::Other));
+                }
+                _ => {
+                    let word = self.word();
+                    let number = std::str::from_utf8(word)
+                        .ok()
+                        .and_then(|text| text.parse::<f64>().ok());
+                    return Some(match number {
+                        Some(number) => Token::Operand(Operand::Number(number)),
+                        None => Token::Operator(word),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Runs `operator` with its operands for every operator in `data`.
+fn interpret(data: &[u8], mut operator: impl FnMut(&[u8], &[Operand], &mut Lexer)) {
+    let mut lexer = Lexer::new(data);
+    let mut frames: Vec<Vec<Operand>> = vec![Vec::new()];
+    while let Some(token) = lexer.next() {
+        match token {
+            Token::Operand(operand) => frames.last_mut().unwrap_or(&mut Vec::new()).push(operand),
+            Token::ArrayStart => frames.push(Vec::new()),
+            Token::ArrayEnd if frames.len() > 1 => {
+                let array = frames.pop().unwrap_or_default();
+                if let Some(frame) = frames.last_mut() {
+                    frame.push(Operand::Array(array));
+                }
+            }
+            Token::ArrayEnd => {}
+            Token::Operator(name) => {
+                frames.truncate(1);
+                let operands = std::mem::take(&mut frames[0]);
+                operator(name, &operands, &mut lexer);
+            }
+        }
+    }
+}
+
+/// Character codes to text, from `ToUnicode` CMaps. Codes are keyed with
+/// their byte length, since fonts use one- or two-byte codes.
+#[derive(Default)]
+struct CMap {
+    codes: HashMap<(usize, u32), String>,
+}
+
+fn code(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .take(4)
+        .fold(0, |code, &byte| (code << 8) | u32::from(byte))
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+impl CMap {
+    fn parse(&mut self, data: &[u8]) {
+        interpret(data, |name, operands, _| match name {
+            b"endbfchar" => {
+                for pair in operands.chunks_exact(2) {
+                    if let [Operand::String(from), Operand::String(to)] = pair {
+                        self.codes.insert((from.len(), code(from)), utf16(to));
+                    }
+                }
+            }
+            b"endbfrange" => {
+                for range in operands.chunks_exact(3) {
+                    let [Operand::String(low), Operand::String(high), target] = range else {
+                        continue;
+                    };
+                    let (low_code, high_code) = (code(low), code(high));
+                    // Ignore absurd ranges rather than filling memory.
+                    if high_code < low_code || high_code - low_code > 0xFFFF {
+                        continue;
+                    }
+                    for (offset, value) in (low_code..=high_code).enumerate() {
+                        let text = match target {
+                            Operand::String(start) if start.len() >= 2 => {
+                                let mut units = start.clone();
+                                let last = units.len() - 2;
+                                let unit = u16::from_be_bytes([units[last], units[last + 1]])
+                                    .wrapping_add(offset as u16);
+                                units[last..].copy_from_slice(&unit.to_be_bytes());
+                                utf16(&units)
+                            }
+                            Operand::Array(targets) => match targets.get(offset) {
+                                Some(Operand::String(to)) => utf16(to),
+                                _ => continue,
+                            },
+                            _ => continue,
+                        };
+                        self.codes.insert((low.len(), value), text);
+                    }
+                }
+            }
+            _ => {}
+        });
+    }
+
+    fn has_width(&self, width: usize) -> bool {
+        self.codes.keys().any(|(len, _)| *len == width)
+    }
+
+    fn decode(&self, bytes: &[u8], out: &mut String) {
+        if bytes.len().is_multiple_of(2) && self.has_width(2) {
+            for pair in bytes.chunks(2) {
+                if let Some(text) = self.codes.get(&(2, code(pair))) {
+                    out.push_str(text);
+                }
+            }
+            return;
+        }
+        for &byte in bytes {
+            match self.codes.get(&(1, u32::from(byte))) {
+                Some(text) => out.push_str(text),
+                // Latin-1 is close enough to the standard encodings.
+                None if byte >= 0x20 && byte != 0x7f => out.push(char::from(byte)),
+                // Escaped line breaks and tabs still separate words.
+                None if byte.is_ascii_whitespace() => out.push(' '),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Appends the text shown by a content stream.
+fn show_text(data: &[u8], cmap: &CMap, out: &mut String) {
+    if !contains(data, b"BT") {
+        return;
+    }
+    let string = |operands: &[Operand], out: &mut String| {
+        if let Some(Operand::String(bytes)) = operands.last() {
+            cmap.decode(bytes, out);
+        }
+    };
+    interpret(data, |name, operands, lexer| match name {
+        b"Tj" => string(operands, out),
+        b"'" | b"\"" => {
+            out.push('\n');
+            string(operands, out);
+        }
+        b"TJ" => {
+            if let Some(Operand::Array(parts)) = operands.last() {
+                for part in parts {
+                    match part {
+                        Operand::String(bytes) => cmap.decode(bytes, out),
+                        // A wide negative adjustment is a word gap.
+                        Operand::Number(gap) if *gap < -180.0 => out.push(' '),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        b"Td" | b"TD" => match operands {
+            [.., Operand::Number(_), Operand::Number(y)] if *y != 0.0 => out.push('\n'),
+            _ => out.push(' '),
+        },
+        b"T*" | b"ET" => out.push('\n'),
+        b"Tm" => out.push(' '),
+        // Skip inline image data, which may contain anything.
+        b"ID" => {
+            let rest = &lexer.data[lexer.at..];
+            lexer.at += rest
+                .windows(3)
+                .position(|window| window[0].is_ascii_whitespace() && &window[1..] == b"EI")
+                .map_or(rest.len(), |at| at + 3);
+        }
+        _ => {}
+    });
+}
+
+/// Collapses runs of spaces and drops blank lines.
+fn tidy(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::export::{self, Cell, ExportFormat, Table};
+
+    /// A PDF body holding `streams`, each with its dictionary. Enough for the
+    /// extractor, which does not read the cross-reference table.
+    fn pdf(streams: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        for (i, (dict, data)) in streams.iter().enumerate() {
+            out.extend_from_slice(format!("{} 0 obj\n{dict}\nstream\r\n", i + 1).as_bytes());
+            out.extend_from_slice(data);
+            out.extend_from_slice(b"\r\nendstream\nendobj\n");
+        }
+        out.extend_from_slice(b"trailer\n<< /Root 1 0 R >>\n%%EOF\n");
+        out
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn reads_back_our_own_pdfs() {
+        let mut table = Table::new("Rent Roll (January 2024)", &["Unit", "Tenant"]);
+        table.push(vec![Cell::text("A12"), Cell::text("Jane Wanjiku")]);
+        table.push(vec![Cell::text("B3"), Cell::text(r"Café \ Deli")]);
+        let text = extract(&export::render(&table, ExportFormat::Pdf));
+        assert!(
+            text.starts_with("Rent Roll (January 2024)\nUnit Tenant\n"),
+            "{text}"
+        );
+        assert!(
+            text.contains("\nA12 Jane Wanjiku\nB3 Café \\ Deli"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn compressed_streams_with_a_to_unicode_cmap() {
+        // Two-byte glyph ids as office software writes them for embedded fonts.
+        let cmap = b"/CIDInit /ProcSet findresource begin
+12 dict begin
+begincmap
+1 begincodespacerange <0000> <FFFF> endcodespacerange
+2 beginbfchar
+<0003> <0020>
+<0011> <20AC>
+endbfchar
+2 beginbfrange
+<0024> <003D> <0041>
+<0044> <005D> [<0061> <0062> <0063> <0064> <0065> <0066> <0067> <0068> <0069> <006A>
+ <006B> <006C> <006D> <006E> <006F> <0070> <0071> <0072> <0073> <0074> <0075> <0076>
+ <0077> <0078> <0079> <007A>]
+endbfrange
+endcmap
+CMapName currentdict /CMap defineresource pop
+end
+end";
+        let content = b"BT
+/F1 11 Tf
+72 720 Td
+[<002B0048004F>-20<004F0052>] TJ
+0 -14 Td
+[<0035>30<0048>] TJ
+-250 0 Td
+<00110003>Tj
+ET";
+        let bytes = pdf(&[
+            ("<< /Length 10 >>", cmap),
+            ("<< /Length 99 /Filter /FlateDecode >>", &deflate(content)),
+        ]);
+        assert_eq!(extract(&bytes), "Hello\nRe €");
+    }
+
+    #[test]
+    fn literal_string_escapes_and_word_gaps() {
+        let content = br"BT
+(Tenant \(A12\): Jane) Tj
+T* (Line\nbreak \101\102C \351t\351) Tj
+T* [(Over)-250(due) 120(!)] TJ
+T* (split \
+here (nested)) Tj
+ET";
+        assert_eq!(
+            extract(&pdf(&[("<< /Length 10 >>", content)])),
+            "Tenant (A12): Jane\nLine break ABC été\nOver due!\nsplit here (nested)"
+        );
+    }
+
+    #[test]
+    fn skips_images_and_unknown_filters() {
+        let content = b"q 10 0 0 10 0 0 cm
+BI /W 2 /H 2 /BPC 8 /CS /G ID \x00(Tj)\xff EI Q
+BT (After the image) Tj ET";
+        let bytes = pdf(&[
+            (
+                "<< /Type /XObject /Subtype /Image /Length 4 >>",
+                b"BT (image) Tj ET",
+            ),
+            ("<< /Length 4 /Filter /DCTDecode >>", b"BT (jpeg) Tj ET"),
+            (
+                "<< /Length 4 /Filter [/FlateDecode /ASCII85Decode] >>",
+                b"BT (chained) Tj ET",
+            ),
+            ("<< /Length 4 >>", content),
+        ]);
+        assert_eq!(extract(&bytes), "After the image");
+    }
+
+    #[test]
+    fn damaged_input_gives_what_it_can() {
+        assert_eq!(extract(b""), "");
+        assert_eq!(extract(b"not a pdf at all"), "");
+        // Unterminated stream and string.
+        assert_eq!(extract(b"1 0 obj << >> stream\nBT (cut off"), "");
+
+        let mut truncated = deflate(b"BT (Kept) Tj ET BT (Also kept) Tj ET");
+        truncated.truncate(truncated.len() - 6);
+        let bytes = pdf(&[("<< /Filter /FlateDecode >>", &truncated)]);
+        assert!(extract(&bytes).starts_with("Kept"));
+    }
+}
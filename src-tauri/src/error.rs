@@ -0,0 +1,34 @@
+use serde::{Serialize, Serializer};
+
+/// Errors returned from Rust commands.
+///
+/// Commands surface these to the frontend as plain strings, the same way the
+/// SQL plugin reports its own failures.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Sql(#[from] sqlx::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("database {0} is not loaded")]
+    DatabaseNotLoaded(String),
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    #[error("{0} not found")]
+    NotFound(String),
+    /// An SMS gateway or mail server could not be reached or refused a
+    /// message; the outbox retries these.
+    #[error("delivery failed: {0}")]
+    Delivery(String),
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
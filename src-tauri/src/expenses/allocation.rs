@@ -0,0 +1,559 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::billing::allocations::round_cents;
+use crate::billing::charges::{self, NewCharge};
+use crate::db;
+use crate::error::{Error, Result};
+use crate::period;
+
+/// How an expense's cost is weighted between units.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum AllocationMethod {
+    /// The same share for every unit.
+    Equal,
+    /// In proportion to `units.square_feet`.
+    SquareFootage,
+    /// In proportion to `units.bedroom_count`.
+    Bedrooms,
+    /// Equal shares between units under lease on the expense date; vacant
+    /// units take nothing.
+    Occupancy,
+}
+
+/// Charge categories a recharge may be raised under.
+pub const RECHARGE_CATEGORIES: &[&str] = &["Utilities", "Other"];
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct AllocationLine {
+    pub unit_id: i64,
+    pub unit_number: String,
+    pub block_name: Option<String>,
+    /// Square feet, bedrooms, or 1 per unit, depending on the method.
+    pub basis: f64,
+    pub amount: f64,
+    /// Tenant leasing the unit on the expense date.
+    pub tenant_id: Option<i64>,
+    pub tenant_name: Option<String>,
+    /// Recharge raised for the tenant's share, if any.
+    pub charge_id: Option<i64>,
+    pub recharge_amount: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpenseAllocation {
+    /// `None` for a preview that has not been saved.
+    pub allocation_id: Option<i64>,
+    pub expense_id: i64,
+    pub expense_amount: f64,
+    pub method: AllocationMethod,
+    pub recharge_percent: f64,
+    pub created_by: Option<String>,
+    pub created_at: Option<String>,
+    #[sqlx(skip)]
+    pub lines: Vec<AllocationLine>,
+}
+
+/// Options for passing allocated shares on to tenants.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recharge {
+    /// Part of each unit's share billed to its tenant.
+    pub percent: f64,
+    /// `Utilities` or `Other` (default).
+    #[serde(default)]
+    pub category: Option<String>,
+    pub due_date: String,
+}
+
+/// Shows how the expense would be split without saving anything. Without
+/// `unit_ids` the split covers every unit of the expense's block, or else of
+/// its property.
+#[tauri::command]
+pub async fn preview_expense_allocation(
+    app: AppHandle,
+    expense_id: i64,
+    method: AllocationMethod,
+    unit_ids: Option<Vec<i64>>,
+) -> Result<ExpenseAllocation> {
+    let pool = db::pool(&app).await?;
+    let mut conn = pool.acquire().await?;
+    plan(&mut conn, expense_id, method, unit_ids.as_deref()).await
+}
+
+/// Splits the expense and saves the split, raising a charge for each leased
+/// unit's share when `recharge` is given.
+#[tauri::command]
+pub async fn allocate_expense(
+    app: AppHandle,
+    expense_id: i64,
+    method: AllocationMethod,
+    unit_ids: Option<Vec<i64>>,
+    recharge: Option<Recharge>,
+    created_by: Option<String>,
+) -> Result<ExpenseAllocation> {
+    let pool = db::pool(&app).await?;
+    allocate(
+        &pool,
+        expense_id,
+        method,
+        unit_ids.as_deref(),
+        recharge.as_ref(),
+        created_by,
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn get_expense_allocation(
+    app: AppHandle,
+    expense_id: i64,
+) -> Result<Option<ExpenseAllocation>> {
+    let pool = db::pool(&app).await?;
+    let mut conn = pool.acquire().await?;
+    load(&mut conn, expense_id).await
+}
+
+/// Removes the split and its recharges. Fails once a tenant has paid
+/// towards a recharge.
+#[tauri::command]
+pub async fn delete_expense_allocation(app: AppHandle, expense_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    delete(&pool, expense_id).await
+}
+
+#[derive(sqlx::FromRow)]
+struct ExpenseScope {
+    amount: f64,
+    expense_date: String,
+    unit_id: Option<i64>,
+    block_id: Option<i64>,
+    property_id: Option<i64>,
+}
+
+#[derive(sqlx::FromRow)]
+struct UnitBasis {
+    unit_id: i64,
+    unit_number: String,
+    block_name: Option<String>,
+    square_feet: Option<f64>,
+    bedroom_count: f64,
+    tenant_id: Option<i64>,
+    tenant_name: Option<String>,
+}
+
+pub async fn plan(
+    conn: &mut SqliteConnection,
+    expense_id: i64,
+    method: AllocationMethod,
+    unit_ids: Option<&[i64]>,
+) -> Result<ExpenseAllocation> {
+    let expense: ExpenseScope = sqlx::query_as(
+        "SELECT CAST(amount AS REAL) AS amount, expense_date, unit_id, block_id, property_id
+         FROM expenses WHERE expense_id = ?1",
+    )
+    .bind(expense_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("expense {expense_id}")))?;
+
+    // Explicit units win; otherwise the narrowest scope the expense is tagged with.
+    let units_json = unit_ids.map(|ids| serde_json::to_string(ids).expect("ids serialize"));
+    let (block_id, property_id) = match (&units_json, expense.block_id, expense.property_id) {
+        (Some(_), _, _) => (None, None),
+        (None, Some(block_id), _) => (Some(block_id), None),
+        (None, None, Some(property_id)) => (None, Some(property_id)),
+        (None, None, None) if expense.unit_id.is_some() => {
+            return Err(Error::InvalidInput(format!(
+                "expense {expense_id} belongs to a single unit; choose the units to share it"
+            )))
+        }
+        (None, None, None) => {
+            return Err(Error::InvalidInput(format!(
+                "expense {expense_id} has no block or property; choose the units to share it"
+            )))
+        }
+    };
+    let units: Vec<UnitBasis> = sqlx::query_as(
+        "SELECT u.unit_id, u.unit_number, b.block_name, CAST(u.square_feet AS REAL) AS square_feet,
+                CAST(COALESCE(u.bedroom_count, 0) AS REAL) AS bedroom_count, l.tenant_id,
+                t.full_name AS tenant_name
+         FROM units u
+         LEFT JOIN blocks b ON b.block_id = u.block_id
+         LEFT JOIN leases l ON l.lease_id = (
+             SELECT lease_id FROM leases
+             WHERE unit_id = u.unit_id
+               AND lease_start_date <= ?4 AND lease_end_date >= ?4
+               AND COALESCE(lower(status), 'active') <> 'terminated'
+             ORDER BY lease_start_date DESC
+             LIMIT 1
+         )
+         LEFT JOIN tenants t ON t.tenant_id = l.tenant_id
+         WHERE (?1 IS NOT NULL AND u.unit_id IN (SELECT value FROM json_each(?1)))
+            OR (?2 IS NOT NULL AND CAST(u.block_id AS INTEGER) = ?2)
+            OR (?3 IS NOT NULL AND u.property_id = ?3)
+         ORDER BY b.block_name, u.unit_number",
+    )
+    .bind(&units_json)
+    .bind(block_id)
+    .bind(property_id)
+    .bind(&expense.expense_date)
+    .fetch_all(&mut *conn)
+    .await?;
+    if let Some(ids) = unit_ids {
+        if let Some(missing) = ids
+            .iter()
+            .find(|id| !units.iter().any(|u| u.unit_id == **id))
+        {
+            return Err(Error::NotFound(format!("unit {missing}")));
+        }
+    }
+    if units.is_empty() {
+        return Err(Error::InvalidInput(format!(
+            "no units to share expense {expense_id} between"
+        )));
+    }
+
+    let mut lines: Vec<AllocationLine> = Vec::with_capacity(units.len());
+    let mut unmeasured = Vec::new();
+    for unit in units {
+        let basis = match method {
+            AllocationMethod::Equal => 1.0,
+            AllocationMethod::SquareFootage => match unit.square_feet {
+                Some(area) if area > 0.0 => area,
+                _ => {
+                    unmeasured.push(unit.unit_number.clone());
+                    0.0
+                }
+            },
+            AllocationMethod::Bedrooms => unit.bedroom_count.max(0.0),
+            AllocationMethod::Occupancy => {
+                if unit.tenant_id.is_some() {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        };
+        lines.push(AllocationLine {
+            unit_id: unit.unit_id,
+            unit_number: unit.unit_number,
+            block_name: unit.block_name,
+            basis,
+            amount: 0.0,
+            tenant_id: unit.tenant_id,
+            tenant_name: unit.tenant_name,
+            charge_id: None,
+            recharge_amount: None,
+        });
+    }
+    if !unmeasured.is_empty() {
+        return Err(Error::InvalidInput(format!(
+            "units without a floor area: {}",
+            unmeasured.join(", ")
+        )));
+    }
+    let weights: Vec<f64> = lines.iter().map(|line| line.basis).collect();
+    let amounts = split_cents(expense.amount, &weights).ok_or_else(|| {
+        Error::InvalidInput(match method {
+            AllocationMethod::Occupancy => "none of these units was let on the expense date".into(),
+            _ => "these units have nothing to weight the split by".into(),
+        })
+    })?;
+    for (line, amount) in lines.iter_mut().zip(amounts) {
+        line.amount = amount;
+    }
+
+    Ok(ExpenseAllocation {
+        allocation_id: None,
+        expense_id,
+        expense_amount: expense.amount,
+        method,
+        recharge_percent: 0.0,
+        created_by: None,
+        created_at: None,
+        lines,
+    })
+}
+
+pub async fn allocate(
+    pool: &SqlitePool,
+    expense_id: i64,
+    method: AllocationMethod,
+    unit_ids: Option<&[i64]>,
+    recharge: Option<&Recharge>,
+    created_by: Option<String>,
+) -> Result<ExpenseAllocation> {
+    let recharge_category = match recharge {
+        Some(recharge) => {
+            if !(recharge.percent > 0.0 && recharge.percent <= 100.0) {
+                return Err(Error::InvalidInput(
+                    "recharge percent must be above 0 and at most 100".into(),
+                ));
+            }
+            period::parse_date(&recharge.due_date)?;
+            let category = recharge.category.as_deref().unwrap_or("Other");
+            if !RECHARGE_CATEGORIES.contains(&category) {
+                return Err(Error::InvalidInput(format!(
+                    "recharges are raised as {}",
+                    RECHARGE_CATEGORIES.join(" or ")
+                )));
+            }
+            Some(category)
+        }
+        None => None,
+    };
+
+    let mut tx = pool.begin().await?;
+    ensure_unallocated(&mut tx, expense_id).await?;
+    let plan = plan(&mut tx, expense_id, method, unit_ids).await?;
+    let (category, vendor, expense_date, approval_status): (String, String, String, String) =
+        sqlx::query_as(
+            "SELECT category, vendor, expense_date, approval_status FROM expenses
+             WHERE expense_id = ?1",
+        )
+        .bind(expense_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if approval_status != "Approved" {
+        return Err(Error::InvalidInput(format!(
+            "expense {expense_id} has not been approved; it cannot be recharged or shared yet"
+        )));
+    }
+
+    let allocation_id = sqlx::query(
+        "INSERT INTO expense_allocations (expense_id, method, recharge_percent, created_by)
+         VALUES (?1, ?2, ?3, ?4)",
+    )
+    .bind(expense_id)
+    .bind(method)
+    .bind(recharge.map_or(0.0, |r| r.percent))
+    .bind(created_by)
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+
+    for line in &plan.lines {
+        let charge_id = match (recharge, recharge_category, line.tenant_id) {
+            (Some(recharge), Some(charge_category), Some(tenant_id)) => {
+                let amount = round_cents(line.amount * recharge.percent / 100.0);
+                if amount > 0.0 {
+                    let (property_id,): (i64,) =
+                        sqlx::query_as("SELECT property_id FROM units WHERE unit_id = ?1")
+                            .bind(line.unit_id)
+                            .fetch_one(&mut *tx)
+                            .await?;
+                    Some(
+                        charges::insert_charge(
+                            &mut *tx,
+                            &NewCharge {
+                                tenant_id,
+                                unit_id: Some(line.unit_id),
+                                property_id: Some(property_id),
+                                category: charge_category.into(),
+                                description: Some(format!(
+                                    "Share of {category} - {vendor} ({expense_date})"
+                                )),
+                                amount,
+                                due_date: recharge.due_date.clone(),
+                                parent_charge_id: None,
+                            },
+                        )
+                        .await?,
+                    )
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        sqlx::query(
+            "INSERT INTO expense_allocation_lines
+                 (allocation_id, unit_id, basis, amount, tenant_id, charge_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(allocation_id)
+        .bind(line.unit_id)
+        .bind(line.basis)
+        .bind(line.amount)
+        .bind(line.tenant_id)
+        .bind(charge_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    let allocation = load(&mut tx, expense_id)
+        .await?
+        .expect("allocation was just saved");
+    tx.commit().await?;
+    Ok(allocation)
+}
+
+pub async fn delete(pool: &SqlitePool, expense_id: i64) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let (paid,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (
+             SELECT 1 FROM expense_allocation_lines l
+             JOIN expense_allocations a ON a.allocation_id = l.allocation_id
+             JOIN charge_balances c ON c.charge_id = l.charge_id
+             WHERE a.expense_id = ?1 AND c.paid > 0
+         )",
+    )
+    .bind(expense_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if paid {
+        return Err(Error::InvalidInput(format!(
+            "tenants have paid towards recharges of expense {expense_id}; \
+             waive the unpaid ones instead"
+        )));
+    }
+    sqlx::query(
+        "DELETE FROM charges WHERE charge_id IN (
+             SELECT l.charge_id FROM expense_allocation_lines l
+             JOIN expense_allocations a ON a.allocation_id = l.allocation_id
+             WHERE a.expense_id = ?1
+         )",
+    )
+    .bind(expense_id)
+    .execute(&mut *tx)
+    .await?;
+    let result = sqlx::query("DELETE FROM expense_allocations WHERE expense_id = ?1")
+        .bind(expense_id)
+        .execute(&mut *tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!(
+            "allocation of expense {expense_id}"
+        )));
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Rejects changes to an expense's amount or date, or its deletion, while a
+/// split of it is saved.
+pub async fn ensure_unallocated(conn: &mut SqliteConnection, expense_id: i64) -> Result<()> {
+    let (allocated,): (bool,) =
+        sqlx::query_as("SELECT EXISTS (SELECT 1 FROM expense_allocations WHERE expense_id = ?1)")
+            .bind(expense_id)
+            .fetch_one(&mut *conn)
+            .await?;
+    if allocated {
+        return Err(Error::InvalidInput(format!(
+            "expense {expense_id} has been split across units; remove the allocation first"
+        )));
+    }
+    Ok(())
+}
+
+async fn load(conn: &mut SqliteConnection, expense_id: i64) -> Result<Option<ExpenseAllocation>> {
+    let allocation: Option<ExpenseAllocation> = sqlx::query_as(
+        "SELECT a.allocation_id, a.expense_id, CAST(e.amount AS REAL) AS expense_amount, a.method,
+                a.recharge_percent, a.created_by, a.created_at
+         FROM expense_allocations a
+         JOIN expenses e ON e.expense_id = a.expense_id
+         WHERE a.expense_id = ?1",
+    )
+    .bind(expense_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(mut allocation) = allocation else {
+        return Ok(None);
+    };
+    allocation.lines = sqlx::query_as(
+        "SELECT l.unit_id, u.unit_number, b.block_name, l.basis, CAST(l.amount AS REAL) AS amount,
+                l.tenant_id, t.full_name AS tenant_name, l.charge_id,
+                CAST(c.amount AS REAL) AS recharge_amount
+         FROM expense_allocation_lines l
+         JOIN units u ON u.unit_id = l.unit_id
+         LEFT JOIN blocks b ON b.block_id = u.block_id
+         LEFT JOIN tenants t ON t.tenant_id = l.tenant_id
+         LEFT JOIN charges c ON c.charge_id = l.charge_id
+         WHERE l.allocation_id = ?1
+         ORDER BY b.block_name, u.unit_number",
+    )
+    .bind(allocation.allocation_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(Some(allocation))
+}
+
+/// Splits `total` in proportion to `weights`, to the cent. Cents lost to
+/// rounding go to the largest remainders so the parts add up exactly.
+/// `None` when the weights sum to zero.
+pub fn split_cents(total: f64, weights: &[f64]) -> Option<Vec<f64>> {
+    let sum: f64 = weights.iter().sum();
+    if sum <= 0.0 {
+        return None;
+    }
+    let total_cents = (total * 100.0).round() as i64;
+    let exact: Vec<f64> = weights
+        .iter()
+        .map(|w| total_cents as f64 * w / sum)
+        .collect();
+    let mut cents: Vec<i64> = exact.iter().map(|e| e.floor() as i64).collect();
+    let mut order: Vec<usize> = (0..weights.len()).collect();
+    order.sort_by(|&a, &b| {
+        let ra = exact[a] - exact[a].floor();
+        let rb = exact[b] - exact[b].floor();
+        rb.total_cmp(&ra).then(a.cmp(&b))
+    });
+    let short = total_cents - cents.iter().sum::<i64>();
+    for &i in order.iter().take(short.max(0) as usize) {
+        cents[i] += 1;
+    }
+    Some(cents.into_iter().map(|c| c as f64 / 100.0).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits and checks the parts add up to `total` to the cent.
+    fn split(total: f64, weights: &[f64]) -> Vec<f64> {
+        let parts = split_cents(total, weights).unwrap();
+        assert_eq!(parts.len(), weights.len());
+        let cents: i64 = parts.iter().map(|p| (p * 100.0).round() as i64).sum();
+        assert_eq!(cents, (total * 100.0).round() as i64, "{parts:?}");
+        parts
+    }
+
+    #[test]
+    fn uneven_splits_give_leftover_cents_to_the_largest_remainders() {
+        assert_eq!(split(100.0, &[1.0, 1.0, 1.0]), [33.34, 33.33, 33.33]);
+        assert_eq!(split(0.05, &[1.0, 1.0, 1.0]), [0.02, 0.02, 0.01]);
+        assert_eq!(split(10.0, &[1.0, 2.0]), [3.33, 6.67]);
+        assert_eq!(
+            split(1000.0, &[850.0, 1200.0, 950.0]),
+            [283.33, 400.0, 316.67]
+        );
+        assert_eq!(split(0.01, &[3.0, 1.0]), [0.01, 0.0]);
+    }
+
+    #[test]
+    fn weights_need_not_sum_to_one_hundred() {
+        // Percentages entered as 40/40 share like 50/50.
+        assert_eq!(split(2500.0, &[40.0, 40.0]), [1250.0, 1250.0]);
+        assert_eq!(split(999.99, &[33.3, 33.3, 33.3]), [333.33, 333.33, 333.33]);
+        assert_eq!(split(500.0, &[60.0, 30.0, 30.0]), [250.0, 125.0, 125.0]);
+        assert_eq!(split(77.77, &[0.5, 0.25]), [51.85, 25.92]);
+    }
+
+    #[test]
+    fn zero_weights_take_nothing() {
+        assert_eq!(split(1200.0, &[1.0, 0.0, 1.0]), [600.0, 0.0, 600.0]);
+        assert_eq!(split_cents(1200.0, &[0.0, 0.0]), None);
+        assert_eq!(split_cents(1200.0, &[]), None);
+    }
+
+    #[test]
+    fn many_units_still_add_up() {
+        let weights: Vec<f64> = (1..=37).map(|i| f64::from(i % 5 + 1) * 1.5).collect();
+        for total in [0.01, 12_345.67, 1_000_000.0, 3.0] {
+            split(total, &weights);
+        }
+    }
+}
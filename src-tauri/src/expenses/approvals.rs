@@ -0,0 +1,390 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum ApprovalStatus {
+    #[serde(rename = "Pending Approval")]
+    #[sqlx(rename = "Pending Approval")]
+    PendingApproval,
+    Approved,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum ApproverRole {
+    /// Approves anything up to their limit.
+    Owner,
+    /// Approves expenses on the properties their manager record manages.
+    PropertyManager,
+    /// Approves anything up to their limit.
+    Accountant,
+}
+
+/// Someone who may approve expenses over `expenses.approval_threshold`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Approver {
+    #[serde(default)]
+    pub approver_id: Option<i64>,
+    pub name: String,
+    pub role: ApproverRole,
+    /// The `managers` row of a property manager.
+    #[serde(default)]
+    pub manager_id: Option<i64>,
+    /// Largest expense they may approve; `None` for no limit.
+    #[serde(default)]
+    pub approval_limit: Option<f64>,
+    #[serde(default = "active")]
+    pub is_active: bool,
+}
+
+fn active() -> bool {
+    true
+}
+
+impl Approver {
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+        if self.name.trim().is_empty() {
+            return invalid("approvers need a name");
+        }
+        if self.role == ApproverRole::PropertyManager && self.manager_id.is_none() {
+            return invalid("a property manager approver needs their manager record");
+        }
+        if self.approval_limit.is_some_and(|limit| limit <= 0.0) {
+            return invalid("approval limit must be positive");
+        }
+        Ok(())
+    }
+
+    /// Why this approver may not decide on `expense`, if they may not.
+    fn refusal(&self, expense: &PendingExpense) -> Option<String> {
+        if !self.is_active {
+            return Some(format!("{} is no longer an approver", self.name));
+        }
+        if expense
+            .entered_by
+            .as_deref()
+            .is_some_and(|by| by.trim().eq_ignore_ascii_case(self.name.trim()))
+        {
+            return Some("expenses cannot be approved by whoever entered them".into());
+        }
+        if self.role == ApproverRole::PropertyManager
+            && (expense.manager_id.is_none() || expense.manager_id != self.manager_id)
+        {
+            return Some(format!(
+                "{} does not manage the property this expense is for",
+                self.name
+            ));
+        }
+        if let Some(limit) = self.approval_limit {
+            if expense.amount > limit {
+                return Some(format!(
+                    "{} may approve expenses up to {limit:.2}",
+                    self.name
+                ));
+            }
+        }
+        None
+    }
+}
+
+const APPROVER_COLUMNS: &str = "approver_id, name, role, manager_id,
+     CAST(approval_limit AS REAL) AS approval_limit, is_active";
+
+/// An expense waiting for a decision.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingExpense {
+    pub expense_id: i64,
+    pub expense_date: String,
+    pub amount: f64,
+    pub category: String,
+    pub vendor: String,
+    pub description: Option<String>,
+    pub property_id: Option<i64>,
+    pub property_name: Option<String>,
+    pub manager_id: Option<i64>,
+    pub entered_by: Option<String>,
+    pub approval_status: ApprovalStatus,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalDecision {
+    pub decision_id: i64,
+    pub expense_id: i64,
+    pub approver_id: Option<i64>,
+    pub approver_name: Option<String>,
+    /// `Approved`, `Rejected` or `Resubmitted`.
+    pub decision: String,
+    pub comment: Option<String>,
+    pub decided_at: String,
+}
+
+const EXPENSE_QUERY: &str = "SELECT e.expense_id, e.expense_date, CAST(e.amount AS REAL) AS amount,
+            e.category, e.vendor, e.description, p.property_id, p.name AS property_name,
+            p.manager_id, e.entered_by, e.approval_status
+     FROM expenses e
+     LEFT JOIN properties p ON p.property_id = COALESCE(
+         e.property_id,
+         (SELECT property_id FROM units WHERE unit_id = e.unit_id),
+         (SELECT property_id FROM blocks WHERE block_id = e.block_id))";
+
+#[tauri::command]
+pub async fn get_expense_approvers(app: AppHandle) -> Result<Vec<Approver>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(&format!(
+        "SELECT {APPROVER_COLUMNS} FROM expense_approvers ORDER BY is_active DESC, name"
+    ))
+    .fetch_all(&pool)
+    .await?)
+}
+
+/// Inserts the approver, or updates it when `approverId` is set. Approvers
+/// are deactivated rather than deleted so their decisions stay on record.
+#[tauri::command]
+pub async fn save_expense_approver(app: AppHandle, approver: Approver) -> Result<Approver> {
+    let pool = db::pool(&app).await?;
+    save_approver(&pool, approver).await
+}
+
+/// Expenses waiting for approval, oldest first. With `approverId`, only
+/// those that approver may decide on.
+#[tauri::command]
+pub async fn get_pending_expenses(
+    app: AppHandle,
+    approver_id: Option<i64>,
+) -> Result<Vec<PendingExpense>> {
+    let pool = db::pool(&app).await?;
+    pending(&pool, approver_id).await
+}
+
+/// Approves the expense, which then posts to the ledger and counts in
+/// reports.
+#[tauri::command]
+pub async fn approve_expense(
+    app: AppHandle,
+    expense_id: i64,
+    approver_id: i64,
+    comment: Option<String>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    decide(
+        &pool,
+        expense_id,
+        approver_id,
+        ApprovalStatus::Approved,
+        comment,
+    )
+    .await
+}
+
+/// Rejects the expense; editing it afterwards resubmits it.
+#[tauri::command]
+pub async fn reject_expense(
+    app: AppHandle,
+    expense_id: i64,
+    approver_id: i64,
+    comment: String,
+) -> Result<()> {
+    if comment.trim().is_empty() {
+        return Err(Error::InvalidInput(
+            "give a reason for rejecting the expense".into(),
+        ));
+    }
+    let pool = db::pool(&app).await?;
+    decide(
+        &pool,
+        expense_id,
+        approver_id,
+        ApprovalStatus::Rejected,
+        Some(comment),
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn get_expense_approval_history(
+    app: AppHandle,
+    expense_id: i64,
+) -> Result<Vec<ApprovalDecision>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(
+        "SELECT d.decision_id, d.expense_id, d.approver_id, a.name AS approver_name, d.decision,
+                d.comment, d.decided_at
+         FROM expense_approval_decisions d
+         LEFT JOIN expense_approvers a ON a.approver_id = d.approver_id
+         WHERE d.expense_id = ?1
+         ORDER BY d.decided_at, d.decision_id",
+    )
+    .bind(expense_id)
+    .fetch_all(&pool)
+    .await?)
+}
+
+pub async fn save_approver(pool: &SqlitePool, approver: Approver) -> Result<Approver> {
+    approver.validate()?;
+    let name = approver.name.trim().to_string();
+    let approver_id = match approver.approver_id {
+        Some(approver_id) => {
+            let result = sqlx::query(
+                "UPDATE expense_approvers
+                 SET name = ?2, role = ?3, manager_id = ?4, approval_limit = ?5, is_active = ?6,
+                     updated_at = CURRENT_TIMESTAMP
+                 WHERE approver_id = ?1",
+            )
+            .bind(approver_id)
+            .bind(&name)
+            .bind(approver.role)
+            .bind(approver.manager_id)
+            .bind(approver.approval_limit)
+            .bind(approver.is_active)
+            .execute(pool)
+            .await
+            .map_err(duplicate_approver)?;
+            if result.rows_affected() == 0 {
+                return Err(Error::NotFound(format!("approver {approver_id}")));
+            }
+            approver_id
+        }
+        None => sqlx::query(
+            "INSERT INTO expense_approvers (name, role, manager_id, approval_limit, is_active)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(&name)
+        .bind(approver.role)
+        .bind(approver.manager_id)
+        .bind(approver.approval_limit)
+        .bind(approver.is_active)
+        .execute(pool)
+        .await
+        .map_err(duplicate_approver)?
+        .last_insert_rowid(),
+    };
+    Ok(Approver {
+        approver_id: Some(approver_id),
+        name,
+        ..approver
+    })
+}
+
+pub async fn pending(pool: &SqlitePool, approver_id: Option<i64>) -> Result<Vec<PendingExpense>> {
+    let expenses: Vec<PendingExpense> = sqlx::query_as(&format!(
+        "{EXPENSE_QUERY}
+         WHERE e.approval_status = 'Pending Approval'
+         ORDER BY e.expense_date, e.expense_id"
+    ))
+    .fetch_all(pool)
+    .await?;
+    let Some(approver_id) = approver_id else {
+        return Ok(expenses);
+    };
+    let approver = load_approver(&mut *pool.acquire().await?, approver_id).await?;
+    Ok(expenses
+        .into_iter()
+        .filter(|expense| approver.refusal(expense).is_none())
+        .collect())
+}
+
+pub async fn decide(
+    pool: &SqlitePool,
+    expense_id: i64,
+    approver_id: i64,
+    decision: ApprovalStatus,
+    comment: Option<String>,
+) -> Result<()> {
+    let label = match decision {
+        ApprovalStatus::Approved => "Approved",
+        ApprovalStatus::Rejected => "Rejected",
+        ApprovalStatus::PendingApproval => {
+            return Err(Error::InvalidInput(
+                "an expense can only be approved or rejected".into(),
+            ))
+        }
+    };
+    let mut tx = pool.begin().await?;
+    let approver = load_approver(&mut tx, approver_id).await?;
+    let expense: PendingExpense =
+        sqlx::query_as(&format!("{EXPENSE_QUERY} WHERE e.expense_id = ?1"))
+            .bind(expense_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("expense {expense_id}")))?;
+    if expense.approval_status != ApprovalStatus::PendingApproval {
+        return Err(Error::InvalidInput(format!(
+            "expense {expense_id} is not waiting for approval"
+        )));
+    }
+    if let Some(refusal) = approver.refusal(&expense) {
+        return Err(Error::InvalidInput(refusal));
+    }
+
+    // Triggers post an approved expense to the ledger and the monthly summary.
+    sqlx::query("UPDATE expenses SET approval_status = ?2 WHERE expense_id = ?1")
+        .bind(expense_id)
+        .bind(decision)
+        .execute(&mut *tx)
+        .await?;
+    record(&mut tx, expense_id, Some(approver_id), label, comment).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Puts a rejected expense back in the queue after it has been corrected.
+pub async fn resubmit_if_rejected(conn: &mut SqliteConnection, expense_id: i64) -> Result<()> {
+    let result = sqlx::query(
+        "UPDATE expenses SET approval_status = 'Pending Approval'
+         WHERE expense_id = ?1 AND approval_status = 'Rejected'",
+    )
+    .bind(expense_id)
+    .execute(&mut *conn)
+    .await?;
+    if result.rows_affected() > 0 {
+        record(conn, expense_id, None, "Resubmitted", None).await?;
+    }
+    Ok(())
+}
+
+async fn record(
+    conn: &mut SqliteConnection,
+    expense_id: i64,
+    approver_id: Option<i64>,
+    decision: &str,
+    comment: Option<String>,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO expense_approval_decisions (expense_id, approver_id, decision, comment)
+         VALUES (?1, ?2, ?3, ?4)",
+    )
+    .bind(expense_id)
+    .bind(approver_id)
+    .bind(decision)
+    .bind(comment.as_deref().map(str::trim).filter(|c| !c.is_empty()))
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+async fn load_approver(conn: &mut SqliteConnection, approver_id: i64) -> Result<Approver> {
+    sqlx::query_as(&format!(
+        "SELECT {APPROVER_COLUMNS} FROM expense_approvers WHERE approver_id = ?1"
+    ))
+    .bind(approver_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("approver {approver_id}")))
+}
+
+fn duplicate_approver(err: sqlx::Error) -> Error {
+    match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            Error::InvalidInput("an approver with that name already exists".into())
+        }
+        _ => err.into(),
+    }
+}
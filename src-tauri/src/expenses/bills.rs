@@ -0,0 +1,362 @@
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::billing::allocations::round_cents;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::expenses::{self, vendors, NewExpense};
+use crate::period;
+
+/// Due date of a bill entered without one and whose vendor has no payment
+/// terms.
+const DEFAULT_TERMS_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum BillStatus {
+    Open,
+    Paid,
+    Void,
+}
+
+/// A vendor's invoice, owed until expenses linked to it cover the amount.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorBill {
+    #[serde(default)]
+    pub bill_id: Option<i64>,
+    pub vendor_id: i64,
+    #[serde(default)]
+    pub vendor_name: Option<String>,
+    /// The vendor's invoice number.
+    #[serde(default)]
+    pub bill_number: Option<String>,
+    pub bill_date: String,
+    /// Defaults to the bill date plus the vendor's payment terms.
+    #[serde(default)]
+    pub due_date: Option<String>,
+    pub amount: f64,
+    /// Expense category payments are recorded under.
+    pub category: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub property_id: Option<i64>,
+    #[serde(default)]
+    pub unit_id: Option<i64>,
+    #[serde(default = "open")]
+    pub status: BillStatus,
+    #[serde(default)]
+    pub void_reason: Option<String>,
+    /// Approved payments only.
+    #[serde(default)]
+    pub paid: f64,
+    /// Payments still waiting for approval.
+    #[serde(default)]
+    pub pending: f64,
+    #[serde(default)]
+    pub outstanding: f64,
+}
+
+fn open() -> BillStatus {
+    BillStatus::Open
+}
+
+impl VendorBill {
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+        if self.amount <= 0.0 {
+            return invalid("bill amount must be positive");
+        }
+        if self.category.trim().is_empty() {
+            return invalid("bills need an expense category");
+        }
+        let bill_date = period::parse_date(&self.bill_date)?;
+        if let Some(due_date) = &self.due_date {
+            if period::parse_date(due_date)? < bill_date {
+                return invalid("a bill cannot fall due before its date");
+            }
+        }
+        Ok(())
+    }
+}
+
+const BILL_COLUMNS: &str = "b.bill_id, b.vendor_id, v.name AS vendor_name, b.bill_number,
+     b.bill_date, b.due_date, CAST(b.amount AS REAL) AS amount, b.category, b.description,
+     b.property_id, b.unit_id,
+     CASE WHEN b.status = 'Void' THEN 'Void' WHEN b.outstanding <= 0.005 THEN 'Paid'
+          ELSE 'Open' END AS status,
+     b.void_reason, CAST(b.paid AS REAL) AS paid, CAST(b.pending AS REAL) AS pending,
+     CAST(b.outstanding AS REAL) AS outstanding";
+
+/// A payment towards a bill, recorded as an expense.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BillPayment {
+    pub amount: f64,
+    pub paid_on: String,
+    pub payment_method: String,
+    #[serde(default)]
+    pub paid_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct BillPaymentLine {
+    pub expense_id: i64,
+    pub expense_date: String,
+    pub amount: f64,
+    pub payment_method: String,
+    pub paid_by: Option<String>,
+}
+
+/// Bills, optionally narrowed to a vendor, a property or a status; oldest
+/// due first.
+#[tauri::command]
+pub async fn get_vendor_bills(
+    app: AppHandle,
+    vendor_id: Option<i64>,
+    property_id: Option<i64>,
+    status: Option<BillStatus>,
+) -> Result<Vec<VendorBill>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(&format!(
+        "SELECT * FROM (
+             SELECT {BILL_COLUMNS}
+             FROM vendor_bill_balances b
+             JOIN vendors v ON v.vendor_id = b.vendor_id
+             WHERE (?1 IS NULL OR b.vendor_id = ?1) AND (?2 IS NULL OR b.property_id = ?2)
+         )
+         WHERE ?3 IS NULL OR status = ?3
+         ORDER BY due_date, bill_id"
+    ))
+    .bind(vendor_id)
+    .bind(property_id)
+    .bind(status)
+    .fetch_all(&pool)
+    .await?)
+}
+
+/// Expenses recorded against a bill.
+#[tauri::command]
+pub async fn get_vendor_bill_payments(
+    app: AppHandle,
+    bill_id: i64,
+) -> Result<Vec<BillPaymentLine>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(
+        "SELECT e.expense_id, e.expense_date, CAST(e.amount AS REAL) AS amount,
+                e.payment_method, e.paid_by
+         FROM vendor_bill_payments bp
+         JOIN expenses e ON e.expense_id = bp.expense_id
+         WHERE bp.bill_id = ?1
+         ORDER BY e.expense_date, e.expense_id",
+    )
+    .bind(bill_id)
+    .fetch_all(&pool)
+    .await?)
+}
+
+/// Inserts the bill, or updates it when `billId` is set. Recording a bill
+/// does not touch the ledger; its payments do.
+#[tauri::command]
+pub async fn save_vendor_bill(app: AppHandle, bill: VendorBill) -> Result<VendorBill> {
+    let pool = db::pool(&app).await?;
+    save(&pool, bill).await
+}
+
+/// Voids a bill that has no payments, e.g. one entered twice.
+#[tauri::command]
+pub async fn void_vendor_bill(app: AppHandle, bill_id: i64, reason: String) -> Result<()> {
+    if reason.trim().is_empty() {
+        return Err(Error::InvalidInput(
+            "give a reason for voiding the bill".into(),
+        ));
+    }
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let bill = load(&mut tx, bill_id).await?;
+    if bill.paid > 0.0 {
+        return Err(Error::InvalidInput(
+            "this bill has payments; delete them before voiding it".into(),
+        ));
+    }
+    sqlx::query(
+        "UPDATE vendor_bills SET status = 'Void', void_reason = ?2, updated_at = CURRENT_TIMESTAMP
+         WHERE bill_id = ?1",
+    )
+    .bind(bill_id)
+    .bind(reason.trim())
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Records a payment towards the bill as an expense to its vendor and
+/// returns the expense id. Deleting that expense undoes the payment.
+#[tauri::command]
+pub async fn pay_vendor_bill(app: AppHandle, bill_id: i64, payment: BillPayment) -> Result<i64> {
+    let pool = db::pool(&app).await?;
+    pay(&pool, bill_id, &payment).await
+}
+
+pub async fn save(pool: &SqlitePool, bill: VendorBill) -> Result<VendorBill> {
+    bill.validate()?;
+    let bill_number = bill
+        .bill_number
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(str::to_string);
+
+    let mut tx = pool.begin().await?;
+    let vendor = vendors::load(&mut tx, bill.vendor_id).await?;
+    let due_date = match &bill.due_date {
+        Some(due_date) => due_date.clone(),
+        None => {
+            let terms = vendor.payment_terms_days.unwrap_or(DEFAULT_TERMS_DAYS);
+            (period::parse_date(&bill.bill_date)? + Duration::days(terms)).to_string()
+        }
+    };
+    let bill_id = match bill.bill_id {
+        Some(bill_id) => {
+            let existing = load(&mut tx, bill_id).await?;
+            if existing.status == BillStatus::Void {
+                return Err(Error::InvalidInput("a void bill cannot be edited".into()));
+            }
+            if existing.paid > 0.0 && existing.vendor_id != bill.vendor_id {
+                return Err(Error::InvalidInput(
+                    "this bill has payments; it cannot move to another vendor".into(),
+                ));
+            }
+            if round_cents(bill.amount) < round_cents(existing.paid) {
+                return Err(Error::InvalidInput(format!(
+                    "{:.2} has already been paid on this bill",
+                    existing.paid
+                )));
+            }
+            sqlx::query(
+                "UPDATE vendor_bills
+                 SET vendor_id = ?2, bill_number = ?3, bill_date = ?4, due_date = ?5, amount = ?6,
+                     category = ?7, description = ?8, property_id = ?9, unit_id = ?10,
+                     updated_at = CURRENT_TIMESTAMP
+                 WHERE bill_id = ?1",
+            )
+            .bind(bill_id)
+            .bind(bill.vendor_id)
+            .bind(&bill_number)
+            .bind(&bill.bill_date)
+            .bind(&due_date)
+            .bind(round_cents(bill.amount))
+            .bind(&bill.category)
+            .bind(&bill.description)
+            .bind(bill.property_id)
+            .bind(bill.unit_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(duplicate_bill)?;
+            bill_id
+        }
+        None => sqlx::query(
+            "INSERT INTO vendor_bills
+                 (vendor_id, bill_number, bill_date, due_date, amount, category, description,
+                  property_id, unit_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )
+        .bind(bill.vendor_id)
+        .bind(&bill_number)
+        .bind(&bill.bill_date)
+        .bind(&due_date)
+        .bind(round_cents(bill.amount))
+        .bind(&bill.category)
+        .bind(&bill.description)
+        .bind(bill.property_id)
+        .bind(bill.unit_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(duplicate_bill)?
+        .last_insert_rowid(),
+    };
+    let saved = load(&mut tx, bill_id).await?;
+    tx.commit().await?;
+    Ok(saved)
+}
+
+pub async fn pay(pool: &SqlitePool, bill_id: i64, payment: &BillPayment) -> Result<i64> {
+    let mut tx = pool.begin().await?;
+    let bill = load(&mut tx, bill_id).await?;
+    match bill.status {
+        BillStatus::Void => return Err(Error::InvalidInput("this bill is void".into())),
+        BillStatus::Paid => return Err(Error::InvalidInput("this bill is already paid".into())),
+        BillStatus::Open => {}
+    }
+    // Payments waiting for approval do not count as paid yet, but are not
+    // available to pay again either.
+    let payable = bill.outstanding - bill.pending;
+    if round_cents(payment.amount) > round_cents(payable) {
+        return Err(Error::InvalidInput(if bill.pending > 0.005 {
+            format!(
+                "only {payable:.2} is outstanding on this bill; {:.2} is awaiting approval",
+                bill.pending
+            )
+        } else {
+            format!("only {payable:.2} is outstanding on this bill")
+        }));
+    }
+    let vendor = vendors::load(&mut tx, bill.vendor_id).await?;
+    let description = match (&bill.bill_number, &bill.description) {
+        (Some(number), Some(description)) => format!("Bill {number}: {description}"),
+        (Some(number), None) => format!("Bill {number}"),
+        (None, Some(description)) => description.clone(),
+        (None, None) => format!("Bill of {}", bill.bill_date),
+    };
+    let expense_id = expenses::insert_expense(
+        &mut tx,
+        &NewExpense {
+            amount: round_cents(payment.amount),
+            category: bill.category.clone(),
+            description: Some(description),
+            expense_date: payment.paid_on.clone(),
+            unit_id: bill.unit_id,
+            block_id: None,
+            property_id: bill.property_id,
+            payment_method: payment.payment_method.clone(),
+            vendor: vendor.name,
+            invoice_number: bill.bill_number.clone(),
+            paid_by: payment.paid_by.clone(),
+            entered_by: None,
+        },
+    )
+    .await?;
+    sqlx::query("INSERT INTO vendor_bill_payments (bill_id, expense_id) VALUES (?1, ?2)")
+        .bind(bill_id)
+        .bind(expense_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(expense_id)
+}
+
+async fn load(conn: &mut SqliteConnection, bill_id: i64) -> Result<VendorBill> {
+    sqlx::query_as(&format!(
+        "SELECT {BILL_COLUMNS}
+         FROM vendor_bill_balances b
+         JOIN vendors v ON v.vendor_id = b.vendor_id
+         WHERE b.bill_id = ?1"
+    ))
+    .bind(bill_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("bill {bill_id}")))
+}
+
+fn duplicate_bill(err: sqlx::Error) -> Error {
+    match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            Error::InvalidInput("this vendor already has a bill with that number".into())
+        }
+        _ => err.into(),
+    }
+}
@@ -0,0 +1,192 @@
+//! Expenses entered on the expenses screen, posted from recurring templates
+//! or paid against vendor bills, and how shared ones are split across units.
+//! Expenses over `expenses.approval_threshold` wait for approval before they
+//! reach the ledger and reports.
+
+pub mod allocation;
+pub mod approvals;
+pub mod bills;
+pub mod recurring;
+pub mod vendors;
+
+use serde::Deserialize;
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::ledger::periods;
+
+/// An expense as the expenses screen records it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewExpense {
+    pub amount: f64,
+    pub category: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub expense_date: String,
+    #[serde(default)]
+    pub unit_id: Option<i64>,
+    #[serde(default)]
+    pub block_id: Option<i64>,
+    #[serde(default)]
+    pub property_id: Option<i64>,
+    pub payment_method: String,
+    pub vendor: String,
+    #[serde(default)]
+    pub invoice_number: Option<String>,
+    #[serde(default)]
+    pub paid_by: Option<String>,
+    /// Who recorded the expense; they cannot also approve it.
+    #[serde(default)]
+    pub entered_by: Option<String>,
+}
+
+impl NewExpense {
+    pub fn validate(&self) -> Result<()> {
+        if self.amount <= 0.0 {
+            return Err(Error::InvalidInput(
+                "expense amount must be positive".into(),
+            ));
+        }
+        if self.category.trim().is_empty() || self.vendor.trim().is_empty() {
+            return Err(Error::InvalidInput(
+                "expenses need a category and a vendor".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Edits an expense, resubmitting it if it was rejected. One dated in a
+/// closed period (before or after the edit) is only changed when
+/// `reverse_in_open_period` is set; its ledger entry is then reversed and
+/// reposted in the open period.
+#[tauri::command]
+pub async fn update_expense(
+    app: AppHandle,
+    expense_id: i64,
+    expense: NewExpense,
+    reverse_in_open_period: Option<bool>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    update(
+        &pool,
+        expense_id,
+        &expense,
+        reverse_in_open_period.unwrap_or(false),
+    )
+    .await
+}
+
+/// Deletes an expense, under the same closed-period rule as [`update_expense`].
+#[tauri::command]
+pub async fn delete_expense(
+    app: AppHandle,
+    expense_id: i64,
+    reverse_in_open_period: Option<bool>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    delete(&pool, expense_id, reverse_in_open_period.unwrap_or(false)).await
+}
+
+pub async fn insert_expense(conn: &mut SqliteConnection, expense: &NewExpense) -> Result<i64> {
+    expense.validate()?;
+    periods::ensure_open(conn, &expense.expense_date).await?;
+    let result = sqlx::query(
+        "INSERT INTO expenses (
+             amount, category, description, expense_date, unit_id, block_id, property_id,
+             payment_method, vendor, invoice_number, paid_by, entered_by
+         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+    )
+    .bind(expense.amount)
+    .bind(&expense.category)
+    .bind(&expense.description)
+    .bind(&expense.expense_date)
+    .bind(expense.unit_id)
+    .bind(expense.block_id)
+    .bind(expense.property_id)
+    .bind(&expense.payment_method)
+    .bind(&expense.vendor)
+    .bind(&expense.invoice_number)
+    .bind(&expense.paid_by)
+    .bind(&expense.entered_by)
+    .execute(&mut *conn)
+    .await?;
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn update(
+    pool: &SqlitePool,
+    expense_id: i64,
+    expense: &NewExpense,
+    reverse_in_open_period: bool,
+) -> Result<()> {
+    expense.validate()?;
+    let mut tx = pool.begin().await?;
+    let (old_date, old_amount) = expense_date_and_amount(&mut tx, expense_id).await?;
+    if old_date != expense.expense_date || old_amount != expense.amount {
+        allocation::ensure_unallocated(&mut tx, expense_id).await?;
+    }
+    periods::ensure_editable(
+        &mut tx,
+        &[&old_date, &expense.expense_date],
+        reverse_in_open_period,
+    )
+    .await?;
+    sqlx::query(
+        "UPDATE expenses
+         SET amount = ?2, category = ?3, description = ?4, expense_date = ?5, unit_id = ?6,
+             block_id = ?7, property_id = ?8, payment_method = ?9, vendor = ?10,
+             invoice_number = ?11, paid_by = ?12
+         WHERE expense_id = ?1",
+    )
+    .bind(expense_id)
+    .bind(expense.amount)
+    .bind(&expense.category)
+    .bind(&expense.description)
+    .bind(&expense.expense_date)
+    .bind(expense.unit_id)
+    .bind(expense.block_id)
+    .bind(expense.property_id)
+    .bind(&expense.payment_method)
+    .bind(&expense.vendor)
+    .bind(&expense.invoice_number)
+    .bind(&expense.paid_by)
+    .execute(&mut *tx)
+    .await?;
+    approvals::resubmit_if_rejected(&mut tx, expense_id).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn delete(
+    pool: &SqlitePool,
+    expense_id: i64,
+    reverse_in_open_period: bool,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let (date, _) = expense_date_and_amount(&mut tx, expense_id).await?;
+    allocation::ensure_unallocated(&mut tx, expense_id).await?;
+    periods::ensure_editable(&mut tx, &[&date], reverse_in_open_period).await?;
+    sqlx::query("DELETE FROM expenses WHERE expense_id = ?1")
+        .bind(expense_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn expense_date_and_amount(
+    conn: &mut SqliteConnection,
+    expense_id: i64,
+) -> Result<(String, f64)> {
+    let row: Option<(String, f64)> = sqlx::query_as(
+        "SELECT expense_date, CAST(amount AS REAL) FROM expenses WHERE expense_id = ?1",
+    )
+    .bind(expense_id)
+    .fetch_optional(conn)
+    .await?;
+    row.ok_or_else(|| Error::NotFound(format!("expense {expense_id}")))
+}
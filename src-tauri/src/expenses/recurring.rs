@@ -0,0 +1,790 @@
+use chrono::{Days, Duration, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::billing::allocations::round_cents;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::expenses::{self, NewExpense};
+use crate::period;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum Frequency {
+    Weekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl Frequency {
+    /// The `n`th occurrence counting from `start`. Monthly dates keep the
+    /// start's day where the month has it, e.g. the 31st falls on the 30th in
+    /// April and on the 31st again in May.
+    fn nth(self, start: NaiveDate, n: u32) -> Option<NaiveDate> {
+        match self {
+            Frequency::Weekly => start.checked_add_days(Days::new(7 * u64::from(n))),
+            Frequency::Monthly => start.checked_add_months(Months::new(n)),
+            Frequency::Quarterly => start.checked_add_months(Months::new(3 * n)),
+            Frequency::Yearly => start.checked_add_months(Months::new(12 * n)),
+        }
+    }
+
+    /// First date of the schedule starting at `start` that falls after
+    /// `after`.
+    pub fn next_after(self, start: NaiveDate, after: NaiveDate) -> Option<NaiveDate> {
+        (0..)
+            .map_while(|n| self.nth(start, n))
+            .find(|date| *date > after)
+    }
+}
+
+/// An expense that repeats, e.g. monthly security or caretaker wages.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct RecurringExpense {
+    #[serde(default)]
+    pub template_id: Option<i64>,
+    pub name: String,
+    pub vendor: String,
+    pub amount: f64,
+    pub category: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub unit_id: Option<i64>,
+    #[serde(default)]
+    pub block_id: Option<i64>,
+    #[serde(default)]
+    pub property_id: Option<i64>,
+    pub payment_method: String,
+    pub frequency: Frequency,
+    pub start_date: String,
+    #[serde(default)]
+    pub end_date: Option<String>,
+    /// First occurrence not yet posted or skipped; set by the app.
+    #[serde(default)]
+    pub next_due_date: Option<String>,
+    #[serde(default = "active")]
+    pub is_active: bool,
+}
+
+fn active() -> bool {
+    true
+}
+
+impl RecurringExpense {
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+        if self.name.trim().is_empty() {
+            return invalid("recurring expenses need a name");
+        }
+        if self.amount <= 0.0 {
+            return invalid("expense amount must be positive");
+        }
+        if self.category.trim().is_empty()
+            || self.vendor.trim().is_empty()
+            || self.payment_method.trim().is_empty()
+        {
+            return invalid("recurring expenses need a category, a vendor and a payment method");
+        }
+        let start = period::parse_date(&self.start_date)?;
+        if let Some(end) = &self.end_date {
+            if period::parse_date(end)? < start {
+                return invalid("a recurring expense cannot end before it starts");
+            }
+        }
+        Ok(())
+    }
+
+    /// Scheduled dates from `from` through `through`, within the template's
+    /// start and end dates.
+    fn occurrences(&self, from: NaiveDate, through: NaiveDate) -> Result<Vec<NaiveDate>> {
+        let start = period::parse_date(&self.start_date)?;
+        let end = self
+            .end_date
+            .as_deref()
+            .map(period::parse_date)
+            .transpose()?;
+        let last = end.map_or(through, |end| end.min(through));
+        let mut dates = Vec::new();
+        for n in 0.. {
+            let Some(date) = self.frequency.nth(start, n) else {
+                break;
+            };
+            if date > last {
+                break;
+            }
+            if date >= from {
+                dates.push(date);
+            }
+        }
+        Ok(dates)
+    }
+
+    /// First scheduled date on or after `from`, if the schedule has not ended.
+    fn next_on_or_after(&self, from: NaiveDate) -> Result<Option<NaiveDate>> {
+        // One step of the longest frequency always reaches the next date.
+        let horizon = from + Duration::days(366);
+        Ok(self.occurrences(from, horizon)?.first().copied())
+    }
+
+    fn to_expense(&self, date: NaiveDate, amount: f64, note: Option<&str>) -> NewExpense {
+        let description = self.description.as_deref().unwrap_or(&self.name);
+        NewExpense {
+            amount,
+            category: self.category.clone(),
+            description: Some(match note {
+                Some(note) => format!("{description} - {note}"),
+                None => description.to_string(),
+            }),
+            expense_date: date.to_string(),
+            unit_id: self.unit_id,
+            block_id: self.block_id,
+            property_id: self.property_id,
+            payment_method: self.payment_method.clone(),
+            vendor: self.vendor.clone(),
+            invoice_number: None,
+            paid_by: None,
+            entered_by: None,
+        }
+    }
+}
+
+const TEMPLATE_COLUMNS: &str = "template_id, name, vendor, CAST(amount AS REAL) AS amount,
+     category, description, unit_id, block_id, property_id, payment_method, frequency,
+     start_date, end_date, next_due_date, is_active";
+
+/// One occurrence of a recurring expense, posted or still to come.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledExpense {
+    pub template_id: i64,
+    pub name: String,
+    pub vendor: String,
+    pub category: String,
+    pub property_id: Option<i64>,
+    pub due_date: NaiveDate,
+    pub amount: f64,
+    /// `Upcoming`, `Due` (waiting for the scheduler), `Adjusted`, `Skipped`
+    /// or `Posted`.
+    pub status: String,
+    pub note: Option<String>,
+    pub expense_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecurringExpenseFailure {
+    pub template_id: i64,
+    pub name: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecurringExpenseRun {
+    pub posted: usize,
+    pub skipped: usize,
+    pub total_amount: f64,
+    /// Templates left where they were, e.g. because a due date falls in a
+    /// closed accounting period.
+    pub failures: Vec<RecurringExpenseFailure>,
+}
+
+#[tauri::command]
+pub async fn get_recurring_expenses(app: AppHandle) -> Result<Vec<RecurringExpense>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(&format!(
+        "SELECT {TEMPLATE_COLUMNS} FROM recurring_expenses
+         ORDER BY is_active DESC, next_due_date, name"
+    ))
+    .fetch_all(&pool)
+    .await?)
+}
+
+/// Inserts the template, or updates it when `templateId` is set. Changes
+/// apply to occurrences not yet posted.
+#[tauri::command]
+pub async fn save_recurring_expense(
+    app: AppHandle,
+    template: RecurringExpense,
+) -> Result<RecurringExpense> {
+    let pool = db::pool(&app).await?;
+    save_template(&pool, template).await
+}
+
+/// Deletes the template. Expenses it already posted are kept.
+#[tauri::command]
+pub async fn delete_recurring_expense(app: AppHandle, template_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    sqlx::query("DELETE FROM recurring_expenses WHERE template_id = ?1")
+        .bind(template_id)
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+/// Occurrences of every template dated from `from` (default today) through
+/// `through` (default 90 days on), posted ones included.
+#[tauri::command]
+pub async fn get_expense_schedule(
+    app: AppHandle,
+    from: Option<String>,
+    through: Option<String>,
+) -> Result<Vec<ScheduledExpense>> {
+    let from = from
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let through = through
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(|| from + Duration::days(90));
+    let pool = db::pool(&app).await?;
+    schedule(&pool, from, through).await
+}
+
+/// Leaves one occurrence out; the scheduler moves past it without posting.
+#[tauri::command]
+pub async fn skip_expense_occurrence(
+    app: AppHandle,
+    template_id: i64,
+    due_date: String,
+    note: Option<String>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    set_occurrence(&pool, template_id, &due_date, None, note).await
+}
+
+/// Posts one occurrence at a different amount, e.g. a month with overtime.
+#[tauri::command]
+pub async fn adjust_expense_occurrence(
+    app: AppHandle,
+    template_id: i64,
+    due_date: String,
+    amount: f64,
+    note: Option<String>,
+) -> Result<()> {
+    if amount <= 0.0 {
+        return Err(Error::InvalidInput(
+            "expense amount must be positive".into(),
+        ));
+    }
+    let pool = db::pool(&app).await?;
+    set_occurrence(&pool, template_id, &due_date, Some(amount), note).await
+}
+
+/// Undoes a skip or adjustment of an occurrence not yet posted.
+#[tauri::command]
+pub async fn restore_expense_occurrence(
+    app: AppHandle,
+    template_id: i64,
+    due_date: String,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let result = sqlx::query(
+        "DELETE FROM recurring_expense_occurrences
+         WHERE template_id = ?1 AND due_date = ?2 AND status <> 'Posted'",
+    )
+    .bind(template_id)
+    .bind(&due_date)
+    .execute(&pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!(
+            "a skipped or adjusted occurrence of template {template_id} on {due_date}"
+        )));
+    }
+    Ok(())
+}
+
+/// Posts everything due on or before `as_of` (default today). The daily job
+/// does the same.
+#[tauri::command]
+pub async fn run_recurring_expenses(
+    app: AppHandle,
+    as_of: Option<String>,
+) -> Result<RecurringExpenseRun> {
+    let as_of = as_of
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let pool = db::pool(&app).await?;
+    post_due_expenses(&pool, as_of).await
+}
+
+pub async fn save_template(
+    pool: &SqlitePool,
+    template: RecurringExpense,
+) -> Result<RecurringExpense> {
+    template.validate()?;
+    let start = period::parse_date(&template.start_date)?;
+
+    let mut tx = pool.begin().await?;
+    let template_id = match template.template_id {
+        None => {
+            let next_due = template.next_on_or_after(start)?.unwrap_or(start);
+            sqlx::query(
+                "INSERT INTO recurring_expenses
+                     (name, vendor, amount, category, description, unit_id, block_id, property_id,
+                      payment_method, frequency, start_date, end_date, next_due_date, is_active)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )
+            .bind(template.name.trim())
+            .bind(template.vendor.trim())
+            .bind(round_cents(template.amount))
+            .bind(&template.category)
+            .bind(&template.description)
+            .bind(template.unit_id)
+            .bind(template.block_id)
+            .bind(template.property_id)
+            .bind(&template.payment_method)
+            .bind(template.frequency)
+            .bind(&template.start_date)
+            .bind(&template.end_date)
+            .bind(next_due.to_string())
+            .bind(template.is_active)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid()
+        }
+        Some(template_id) => {
+            let existing = load_template(&mut tx, template_id).await?;
+            let cursor = period::parse_date(existing.next_due_date.as_deref().unwrap_or_default())?;
+            let rescheduled = existing.frequency != template.frequency
+                || existing.start_date != template.start_date;
+            if rescheduled {
+                // Skips and adjustments were for dates of the old schedule.
+                sqlx::query(
+                    "DELETE FROM recurring_expense_occurrences
+                     WHERE template_id = ?1 AND status <> 'Posted'",
+                )
+                .bind(template_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+            let next_due = template
+                .next_on_or_after(cursor.max(start))?
+                .unwrap_or(cursor.max(start));
+            sqlx::query(
+                "UPDATE recurring_expenses
+                 SET name = ?2, vendor = ?3, amount = ?4, category = ?5, description = ?6,
+                     unit_id = ?7, block_id = ?8, property_id = ?9, payment_method = ?10,
+                     frequency = ?11, start_date = ?12, end_date = ?13, next_due_date = ?14,
+                     is_active = ?15, updated_at = CURRENT_TIMESTAMP
+                 WHERE template_id = ?1",
+            )
+            .bind(template_id)
+            .bind(template.name.trim())
+            .bind(template.vendor.trim())
+            .bind(round_cents(template.amount))
+            .bind(&template.category)
+            .bind(&template.description)
+            .bind(template.unit_id)
+            .bind(template.block_id)
+            .bind(template.property_id)
+            .bind(&template.payment_method)
+            .bind(template.frequency)
+            .bind(&template.start_date)
+            .bind(&template.end_date)
+            .bind(next_due.to_string())
+            .bind(template.is_active)
+            .execute(&mut *tx)
+            .await?;
+            template_id
+        }
+    };
+    let saved = load_template(&mut tx, template_id).await?;
+    tx.commit().await?;
+    Ok(saved)
+}
+
+#[derive(sqlx::FromRow)]
+struct RecordedOccurrence {
+    due_date: String,
+    status: String,
+    amount: Option<f64>,
+    note: Option<String>,
+    expense_id: Option<i64>,
+}
+
+pub async fn schedule(
+    pool: &SqlitePool,
+    from: NaiveDate,
+    through: NaiveDate,
+) -> Result<Vec<ScheduledExpense>> {
+    let templates: Vec<RecurringExpense> = sqlx::query_as(&format!(
+        "SELECT {TEMPLATE_COLUMNS} FROM recurring_expenses"
+    ))
+    .fetch_all(pool)
+    .await?;
+    let today = period::today();
+
+    let mut scheduled = Vec::new();
+    for template in templates {
+        let template_id = template.template_id.unwrap_or_default();
+        let recorded: Vec<RecordedOccurrence> = sqlx::query_as(
+            "SELECT o.due_date, o.status,
+                        CAST(COALESCE(o.amount, e.amount) AS REAL) AS amount,
+                        o.note, o.expense_id
+                 FROM recurring_expense_occurrences o
+                 LEFT JOIN expenses e ON e.expense_id = o.expense_id
+                 WHERE o.template_id = ?1 AND o.due_date BETWEEN ?2 AND ?3",
+        )
+        .bind(template_id)
+        .bind(from.to_string())
+        .bind(through.to_string())
+        .fetch_all(pool)
+        .await?;
+        let entry = |due_date: NaiveDate, amount: f64, status: &str| ScheduledExpense {
+            template_id,
+            name: template.name.clone(),
+            vendor: template.vendor.clone(),
+            category: template.category.clone(),
+            property_id: template.property_id,
+            due_date,
+            amount,
+            status: status.to_string(),
+            note: None,
+            expense_id: None,
+        };
+        let mut dates = Vec::new();
+        for occurrence in recorded {
+            let due_date = period::parse_date(&occurrence.due_date)?;
+            let status = match occurrence.status.as_str() {
+                "Scheduled" => "Adjusted",
+                other => other,
+            };
+            dates.push(due_date);
+            scheduled.push(ScheduledExpense {
+                note: occurrence.note.clone(),
+                expense_id: occurrence.expense_id,
+                ..entry(
+                    due_date,
+                    occurrence.amount.unwrap_or(template.amount),
+                    status,
+                )
+            });
+        }
+        if !template.is_active {
+            continue;
+        }
+        let cursor = period::parse_date(template.next_due_date.as_deref().unwrap_or_default())?;
+        for due_date in template.occurrences(from.max(cursor), through)? {
+            if dates.contains(&due_date) {
+                continue;
+            }
+            let status = if due_date <= today { "Due" } else { "Upcoming" };
+            scheduled.push(entry(due_date, template.amount, status));
+        }
+    }
+    scheduled.sort_by(|a, b| {
+        a.due_date
+            .cmp(&b.due_date)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(scheduled)
+}
+
+pub async fn post_due_expenses(pool: &SqlitePool, as_of: NaiveDate) -> Result<RecurringExpenseRun> {
+    let templates: Vec<RecurringExpense> = sqlx::query_as(&format!(
+        "SELECT {TEMPLATE_COLUMNS} FROM recurring_expenses
+         WHERE is_active = 1 AND next_due_date <= ?1
+         ORDER BY next_due_date, template_id"
+    ))
+    .bind(as_of.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    let mut run = RecurringExpenseRun::default();
+    for template in templates {
+        match post_template(pool, &template, as_of).await {
+            Ok((posted, skipped, amount)) => {
+                run.posted += posted;
+                run.skipped += skipped;
+                run.total_amount += amount;
+            }
+            Err(err) => run.failures.push(RecurringExpenseFailure {
+                template_id: template.template_id.unwrap_or_default(),
+                name: template.name.clone(),
+                message: err.to_string(),
+            }),
+        }
+    }
+    run.total_amount = round_cents(run.total_amount);
+    Ok(run)
+}
+
+/// Posts one template's due occurrences in a single transaction, so a
+/// failure leaves it untouched for the next run.
+async fn post_template(
+    pool: &SqlitePool,
+    template: &RecurringExpense,
+    as_of: NaiveDate,
+) -> Result<(usize, usize, f64)> {
+    let template_id = template.template_id.unwrap_or_default();
+    let cursor = period::parse_date(template.next_due_date.as_deref().unwrap_or_default())?;
+    let (mut posted, mut skipped, mut total) = (0, 0, 0.0);
+
+    let mut tx = pool.begin().await?;
+    for due_date in template.occurrences(cursor, as_of)? {
+        let recorded: Option<(String, Option<f64>, Option<String>)> = sqlx::query_as(
+            "SELECT status, CAST(amount AS REAL), note FROM recurring_expense_occurrences
+             WHERE template_id = ?1 AND due_date = ?2",
+        )
+        .bind(template_id)
+        .bind(due_date.to_string())
+        .fetch_optional(&mut *tx)
+        .await?;
+        let (amount, note) = match recorded {
+            Some((status, _, _)) if status != "Scheduled" => {
+                skipped += usize::from(status == "Skipped");
+                continue;
+            }
+            Some((_, amount, note)) => (amount.unwrap_or(template.amount), note),
+            None => (template.amount, None),
+        };
+        let expense_id = expenses::insert_expense(
+            &mut tx,
+            &template.to_expense(due_date, amount, note.as_deref()),
+        )
+        .await?;
+        sqlx::query(
+            "INSERT INTO recurring_expense_occurrences (template_id, due_date, status, amount, note, expense_id)
+             VALUES (?1, ?2, 'Posted', ?3, ?4, ?5)
+             ON CONFLICT (template_id, due_date) DO UPDATE SET status = 'Posted',
+                                                               expense_id = excluded.expense_id,
+                                                               updated_at = CURRENT_TIMESTAMP",
+        )
+        .bind(template_id)
+        .bind(due_date.to_string())
+        .bind(amount)
+        .bind(note)
+        .bind(expense_id)
+        .execute(&mut *tx)
+        .await?;
+        posted += 1;
+        total += amount;
+    }
+
+    let after = as_of + Duration::days(1);
+    let next_due = template.next_on_or_after(after)?;
+    sqlx::query(
+        "UPDATE recurring_expenses
+         SET next_due_date = ?2, is_active = ?3, updated_at = CURRENT_TIMESTAMP
+         WHERE template_id = ?1",
+    )
+    .bind(template_id)
+    .bind(next_due.unwrap_or(after).to_string())
+    // Nothing left to post once the schedule has ended.
+    .bind(next_due.is_some())
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok((posted, skipped, total))
+}
+
+/// Records a skip (`amount` of `None`) or an adjusted amount for an
+/// occurrence that has not been posted yet.
+async fn set_occurrence(
+    pool: &SqlitePool,
+    template_id: i64,
+    due_date: &str,
+    amount: Option<f64>,
+    note: Option<String>,
+) -> Result<()> {
+    let date = period::parse_date(due_date)?;
+    let mut tx = pool.begin().await?;
+    let template = load_template(&mut tx, template_id).await?;
+    let cursor = period::parse_date(template.next_due_date.as_deref().unwrap_or_default())?;
+    if date < cursor {
+        return Err(Error::InvalidInput(format!(
+            "the {due_date} occurrence of {} has already been posted or passed",
+            template.name
+        )));
+    }
+    if !template.occurrences(date, date)?.contains(&date) {
+        return Err(Error::InvalidInput(format!(
+            "{} is not scheduled on {due_date}",
+            template.name
+        )));
+    }
+    sqlx::query(
+        "INSERT INTO recurring_expense_occurrences (template_id, due_date, status, amount, note)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (template_id, due_date) DO UPDATE SET status = excluded.status,
+                                                           amount = excluded.amount,
+                                                           note = excluded.note,
+                                                           updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(template_id)
+    .bind(date.to_string())
+    .bind(if amount.is_some() {
+        "Scheduled"
+    } else {
+        "Skipped"
+    })
+    .bind(amount.map(round_cents))
+    .bind(note.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn load_template(conn: &mut SqliteConnection, template_id: i64) -> Result<RecurringExpense> {
+    sqlx::query_as(&format!(
+        "SELECT {TEMPLATE_COLUMNS} FROM recurring_expenses WHERE template_id = ?1"
+    ))
+    .bind(template_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("recurring expense {template_id}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        period::parse_date(s).unwrap()
+    }
+
+    fn dates(frequency: Frequency, start: &str, count: u32) -> Vec<String> {
+        (0..count)
+            .map(|n| frequency.nth(date(start), n).unwrap().to_string())
+            .collect()
+    }
+
+    fn template(frequency: Frequency, start: &str, end: Option<&str>) -> RecurringExpense {
+        RecurringExpense {
+            template_id: None,
+            name: "Security".into(),
+            vendor: "Guards Ltd".into(),
+            amount: 45_000.0,
+            category: "Security".into(),
+            description: None,
+            unit_id: None,
+            block_id: None,
+            property_id: Some(1),
+            payment_method: "Bank Transfer".into(),
+            frequency,
+            start_date: start.into(),
+            end_date: end.map(Into::into),
+            next_due_date: None,
+            is_active: true,
+        }
+    }
+
+    #[test]
+    fn month_end_days_come_back_after_short_months() {
+        assert_eq!(
+            dates(Frequency::Monthly, "2024-01-31", 5),
+            [
+                "2024-01-31",
+                "2024-02-29",
+                "2024-03-31",
+                "2024-04-30",
+                "2024-05-31"
+            ]
+        );
+        assert_eq!(
+            dates(Frequency::Monthly, "2023-01-31", 3),
+            ["2023-01-31", "2023-02-28", "2023-03-31"]
+        );
+        assert_eq!(
+            dates(Frequency::Monthly, "2024-01-30", 3),
+            ["2024-01-30", "2024-02-29", "2024-03-30"]
+        );
+        assert_eq!(
+            dates(Frequency::Quarterly, "2023-11-30", 4),
+            ["2023-11-30", "2024-02-29", "2024-05-30", "2024-08-30"]
+        );
+    }
+
+    #[test]
+    fn yearly_schedules_keep_leap_days_where_they_exist() {
+        assert_eq!(
+            dates(Frequency::Yearly, "2024-02-29", 5),
+            [
+                "2024-02-29",
+                "2025-02-28",
+                "2026-02-28",
+                "2027-02-28",
+                "2028-02-29"
+            ]
+        );
+        assert_eq!(
+            dates(Frequency::Yearly, "2023-12-31", 2),
+            ["2023-12-31", "2024-12-31"]
+        );
+        assert_eq!(
+            dates(Frequency::Weekly, "2024-02-26", 3),
+            ["2024-02-26", "2024-03-04", "2024-03-11"]
+        );
+    }
+
+    #[test]
+    fn next_date_is_strictly_after() {
+        let next = |frequency: Frequency, start, after| {
+            frequency
+                .next_after(date(start), date(after))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            next(Frequency::Monthly, "2024-01-31", "2024-01-31"),
+            "2024-02-29"
+        );
+        assert_eq!(
+            next(Frequency::Monthly, "2024-01-31", "2024-02-29"),
+            "2024-03-31"
+        );
+        assert_eq!(
+            next(Frequency::Monthly, "2024-01-31", "2023-06-01"),
+            "2024-01-31"
+        );
+        assert_eq!(
+            next(Frequency::Yearly, "2024-02-29", "2024-02-29"),
+            "2025-02-28"
+        );
+        assert_eq!(
+            next(Frequency::Yearly, "2024-02-29", "2027-03-01"),
+            "2028-02-29"
+        );
+    }
+
+    #[test]
+    fn occurrences_stop_at_the_end_date() {
+        let template = template(Frequency::Monthly, "2024-01-31", Some("2024-04-29"));
+        let listed: Vec<String> = template
+            .occurrences(date("2024-02-01"), date("2024-12-31"))
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(listed, ["2024-02-29", "2024-03-31"]);
+        assert_eq!(
+            template.next_on_or_after(date("2024-03-31")).unwrap(),
+            Some(date("2024-03-31"))
+        );
+        assert_eq!(template.next_on_or_after(date("2024-04-01")).unwrap(), None);
+    }
+
+    #[test]
+    fn next_yearly_date_is_found_across_a_leap_year() {
+        let template = template(Frequency::Yearly, "2020-02-29", None);
+        assert_eq!(
+            template.next_on_or_after(date("2023-03-01")).unwrap(),
+            Some(date("2024-02-29"))
+        );
+        assert_eq!(
+            template.next_on_or_after(date("2024-03-01")).unwrap(),
+            Some(date("2025-02-28"))
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+
+/// Someone expenses are paid to. `expenses.vendor` keeps the name as typed;
+/// expenses are tied to the vendor through `vendor_id`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Vendor {
+    #[serde(default)]
+    pub vendor_id: Option<i64>,
+    pub name: String,
+    #[serde(default)]
+    pub contact_person: Option<String>,
+    #[serde(default)]
+    pub phone_number: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub address: Option<String>,
+    /// e.g. KRA PIN.
+    #[serde(default)]
+    pub tax_id: Option<String>,
+    #[serde(default)]
+    pub payment_method: Option<String>,
+    #[serde(default)]
+    pub bank_name: Option<String>,
+    #[serde(default)]
+    pub bank_account: Option<String>,
+    /// Till, paybill or phone number.
+    #[serde(default)]
+    pub mpesa_number: Option<String>,
+    /// Days from a bill's date to its due date when the bill gives none.
+    #[serde(default)]
+    pub payment_terms_days: Option<i64>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default = "active")]
+    pub is_active: bool,
+}
+
+fn active() -> bool {
+    true
+}
+
+impl Vendor {
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+        if name_key(&self.name).is_empty() {
+            return invalid("vendors need a name");
+        }
+        if self.payment_terms_days.is_some_and(|days| days < 0) {
+            return invalid("payment terms cannot be negative");
+        }
+        Ok(())
+    }
+}
+
+const VENDOR_COLUMNS: &str = "vendor_id, name, contact_person, phone_number, email, address,
+     tax_id, payment_method, bank_name, bank_account, mpesa_number, payment_terms_days, notes,
+     is_active";
+
+#[tauri::command]
+pub async fn get_vendors(app: AppHandle, include_inactive: Option<bool>) -> Result<Vec<Vendor>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(&format!(
+        "SELECT {VENDOR_COLUMNS} FROM vendors
+         WHERE ?1 OR is_active = 1
+         ORDER BY name COLLATE NOCASE"
+    ))
+    .bind(include_inactive.unwrap_or(false))
+    .fetch_all(&pool)
+    .await?)
+}
+
+/// Inserts the vendor, or updates it when `vendorId` is set. A renamed
+/// vendor keeps its old name as an alias, so expenses entered under it
+/// still link here.
+#[tauri::command]
+pub async fn save_vendor(app: AppHandle, vendor: Vendor) -> Result<Vendor> {
+    let pool = db::pool(&app).await?;
+    save(&pool, vendor).await
+}
+
+/// Deletes a vendor nothing refers to; others can only be deactivated.
+#[tauri::command]
+pub async fn delete_vendor(app: AppHandle, vendor_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let (used,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM expenses WHERE vendor_id = ?1)
+             OR EXISTS (SELECT 1 FROM vendor_bills WHERE vendor_id = ?1)",
+    )
+    .bind(vendor_id)
+    .fetch_one(&pool)
+    .await?;
+    if used {
+        return Err(Error::InvalidInput(
+            "this vendor has expenses or bills; deactivate it instead".into(),
+        ));
+    }
+    sqlx::query("DELETE FROM vendors WHERE vendor_id = ?1")
+        .bind(vendor_id)
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+/// Folds `duplicate_id` into `vendor_id`: its expenses and bills move over
+/// and its names become aliases. Expense descriptions are left as entered.
+#[tauri::command]
+pub async fn merge_vendors(app: AppHandle, vendor_id: i64, duplicate_id: i64) -> Result<Vendor> {
+    let pool = db::pool(&app).await?;
+    merge(&pool, vendor_id, duplicate_id).await
+}
+
+/// Groups of active vendors whose names may be spellings of the same one,
+/// e.g. "Kamau Plumbers" and "kamau plumbing": they differ only in word
+/// endings or a trailing "Ltd", "Limited" or "Co".
+#[tauri::command]
+pub async fn get_possible_duplicate_vendors(app: AppHandle) -> Result<Vec<Vec<Vendor>>> {
+    let pool = db::pool(&app).await?;
+    let vendors: Vec<Vendor> = sqlx::query_as(&format!(
+        "SELECT {VENDOR_COLUMNS} FROM vendors WHERE is_active = 1 ORDER BY vendor_id"
+    ))
+    .fetch_all(&pool)
+    .await?;
+
+    let mut groups: BTreeMap<String, Vec<Vendor>> = BTreeMap::new();
+    for vendor in vendors {
+        groups
+            .entry(similarity_key(&vendor.name))
+            .or_default()
+            .push(vendor);
+    }
+    Ok(groups
+        .into_values()
+        .filter(|vendors| vendors.len() > 1)
+        .collect())
+}
+
+/// The name vendors are matched on: lowercase, without dots or commas and
+/// with single spaces. Migration 31 and the expense triggers compute the
+/// same key in SQL.
+pub fn name_key(name: &str) -> String {
+    name.to_lowercase()
+        .replace(['.', ','], "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Looser than [`name_key`]: company suffixes dropped and each word cut to
+/// its first five letters, so "Kamau Plumbers Ltd" and "kamau plumbing"
+/// agree.
+fn similarity_key(name: &str) -> String {
+    let key = name_key(name).replace('&', "and");
+    let mut words: Vec<&str> = key.split(' ').collect();
+    while words.len() > 1 && matches!(words.last(), Some(&("ltd" | "limited" | "co"))) {
+        words.pop();
+    }
+    words
+        .iter()
+        .map(|word| word.chars().take(5).collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub async fn save(pool: &SqlitePool, vendor: Vendor) -> Result<Vendor> {
+    vendor.validate()?;
+    let name = vendor.name.trim().to_string();
+    let key = name_key(&name);
+
+    let mut tx = pool.begin().await?;
+    let taken: Option<(i64,)> = sqlx::query_as(
+        "SELECT vendor_id FROM vendors WHERE name_key = ?1
+         UNION ALL
+         SELECT vendor_id FROM vendor_aliases WHERE name_key = ?1",
+    )
+    .bind(&key)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if taken.is_some_and(|(id,)| Some(id) != vendor.vendor_id) {
+        return Err(Error::InvalidInput(format!(
+            "a vendor named {name} already exists"
+        )));
+    }
+
+    let vendor_id = match vendor.vendor_id {
+        Some(vendor_id) => {
+            let old: Option<(String,)> =
+                sqlx::query_as("SELECT name_key FROM vendors WHERE vendor_id = ?1")
+                    .bind(vendor_id)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+            let Some((old_key,)) = old else {
+                return Err(Error::NotFound(format!("vendor {vendor_id}")));
+            };
+            if old_key != key {
+                sqlx::query(
+                    "INSERT OR REPLACE INTO vendor_aliases (name_key, vendor_id) VALUES (?1, ?2)",
+                )
+                .bind(&old_key)
+                .bind(vendor_id)
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query("DELETE FROM vendor_aliases WHERE name_key = ?1")
+                    .bind(&key)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query(
+                "UPDATE vendors
+                 SET name = ?2, name_key = ?3, contact_person = ?4, phone_number = ?5, email = ?6,
+                     address = ?7, tax_id = ?8, payment_method = ?9, bank_name = ?10,
+                     bank_account = ?11, mpesa_number = ?12, payment_terms_days = ?13, notes = ?14,
+                     is_active = ?15, updated_at = CURRENT_TIMESTAMP
+                 WHERE vendor_id = ?1",
+            )
+            .bind(vendor_id)
+            .bind(&name)
+            .bind(&key)
+            .bind(&vendor.contact_person)
+            .bind(&vendor.phone_number)
+            .bind(&vendor.email)
+            .bind(&vendor.address)
+            .bind(&vendor.tax_id)
+            .bind(&vendor.payment_method)
+            .bind(&vendor.bank_name)
+            .bind(&vendor.bank_account)
+            .bind(&vendor.mpesa_number)
+            .bind(vendor.payment_terms_days)
+            .bind(&vendor.notes)
+            .bind(vendor.is_active)
+            .execute(&mut *tx)
+            .await?;
+            vendor_id
+        }
+        None => sqlx::query(
+            "INSERT INTO vendors
+                 (name, name_key, contact_person, phone_number, email, address, tax_id,
+                  payment_method, bank_name, bank_account, mpesa_number, payment_terms_days, notes,
+                  is_active)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        )
+        .bind(&name)
+        .bind(&key)
+        .bind(&vendor.contact_person)
+        .bind(&vendor.phone_number)
+        .bind(&vendor.email)
+        .bind(&vendor.address)
+        .bind(&vendor.tax_id)
+        .bind(&vendor.payment_method)
+        .bind(&vendor.bank_name)
+        .bind(&vendor.bank_account)
+        .bind(&vendor.mpesa_number)
+        .bind(vendor.payment_terms_days)
+        .bind(&vendor.notes)
+        .bind(vendor.is_active)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid(),
+    };
+    tx.commit().await?;
+    Ok(Vendor {
+        vendor_id: Some(vendor_id),
+        name,
+        ..vendor
+    })
+}
+
+pub async fn merge(pool: &SqlitePool, vendor_id: i64, duplicate_id: i64) -> Result<Vendor> {
+    if vendor_id == duplicate_id {
+        return Err(Error::InvalidInput(
+            "a vendor cannot be merged into itself".into(),
+        ));
+    }
+    let mut tx = pool.begin().await?;
+    let vendor = load(&mut tx, vendor_id).await?;
+    load(&mut tx, duplicate_id).await?;
+
+    sqlx::query("UPDATE expenses SET vendor_id = ?1 WHERE vendor_id = ?2")
+        .bind(vendor_id)
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "UPDATE vendor_bills SET vendor_id = ?1, updated_at = CURRENT_TIMESTAMP
+         WHERE vendor_id = ?2",
+    )
+    .bind(vendor_id)
+    .bind(duplicate_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|err| match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => Error::InvalidInput(
+            "both vendors have a bill with the same number; renumber or void one first".into(),
+        ),
+        _ => err.into(),
+    })?;
+    sqlx::query("UPDATE vendor_aliases SET vendor_id = ?1 WHERE vendor_id = ?2")
+        .bind(vendor_id)
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "INSERT INTO vendor_aliases (name_key, vendor_id)
+         SELECT name_key, ?1 FROM vendors WHERE vendor_id = ?2",
+    )
+    .bind(vendor_id)
+    .bind(duplicate_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM vendors WHERE vendor_id = ?1")
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(vendor)
+}
+
+pub async fn load(conn: &mut SqliteConnection, vendor_id: i64) -> Result<Vendor> {
+    sqlx::query_as(&format!(
+        "SELECT {VENDOR_COLUMNS} FROM vendors WHERE vendor_id = ?1"
+    ))
+    .bind(vendor_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("vendor {vendor_id}")))
+}
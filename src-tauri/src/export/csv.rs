@@ -0,0 +1,30 @@
+use super::{Cell, Table};
+
+pub fn render(table: &Table) -> String {
+    let mut out = String::new();
+    push_line(&mut out, table.columns.iter().map(String::as_str));
+    for row in &table.rows {
+        let values: Vec<String> = row.iter().map(Cell::display).collect();
+        push_line(&mut out, values.iter().map(String::as_str));
+    }
+    out
+}
+
+fn push_line<'a>(out: &mut String, values: impl Iterator<Item = &'a str>) {
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&escape(value));
+    }
+    out.push_str("\r\n");
+}
+
+/// Quotes a field when it contains a delimiter, quote or line break.
+pub fn escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,56 @@
+//! Minimal DOCX writer for letters.
+//!
+//! The package holds only the main document part. Word, LibreOffice and
+//! Google Docs fill in default styles, so headings are plain bold runs rather
+//! than named styles.
+
+use super::xlsx::escape;
+use super::zip::ZipWriter;
+use super::{Block, Letter};
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/></Relationships>"#;
+
+/// Heading size in half-points.
+const HEADING_HALF_POINTS: u32 = 28;
+
+pub fn render(letter: &Letter) -> Vec<u8> {
+    let mut body = String::new();
+    for block in &letter.blocks {
+        match block {
+            Block::Heading(text) => body.push_str(&format!(
+                "<w:p><w:pPr><w:spacing w:after=\"240\"/></w:pPr><w:r><w:rPr><w:b/>\
+                 <w:sz w:val=\"{HEADING_HALF_POINTS}\"/></w:rPr>{}</w:r></w:p>",
+                runs(text)
+            )),
+            Block::Paragraph(text) => body.push_str(&format!(
+                "<w:p><w:pPr><w:spacing w:after=\"200\"/></w:pPr><w:r>{}</w:r></w:p>",
+                runs(text)
+            )),
+        }
+    }
+    let document = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <w:document xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">\
+         <w:body>{body}<w:sectPr><w:pgSz w:w=\"11906\" w:h=\"16838\"/>\
+         <w:pgMar w:top=\"1280\" w:right=\"1280\" w:bottom=\"1280\" w:left=\"1280\" \
+         w:header=\"708\" w:footer=\"708\" w:gutter=\"0\"/></w:sectPr></w:body></w:document>"
+    );
+
+    let mut zip = ZipWriter::default();
+    zip.add("[Content_Types].xml", CONTENT_TYPES.as_bytes());
+    zip.add("_rels/.rels", ROOT_RELS.as_bytes());
+    zip.add("word/document.xml", document.as_bytes());
+    zip.finish()
+}
+
+/// The text of a run, with embedded newlines as line breaks.
+fn runs(text: &str) -> String {
+    text.split('\n')
+        .map(|line| format!("<w:t xml:space=\"preserve\">{}</w:t>", escape(line)))
+        .collect::<Vec<_>>()
+        .join("<w:br/>")
+}
@@ -0,0 +1,113 @@
+//! Tabular exports shared by the reports.
+//!
+//! Reports build a [`Table`] once and [`write`] renders it in whichever
+//! [`ExportFormat`] the user picked. Letters are a [`Letter`] of headings and
+//! paragraphs, written as PDF or DOCX.
+
+mod csv;
+mod docx;
+mod pdf;
+mod xlsx;
+mod zip;
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+    Pdf,
+}
+
+#[derive(Debug, Clone)]
+pub enum Cell {
+    Empty,
+    Text(String),
+    /// Rendered with two decimals.
+    Money(f64),
+}
+
+impl Cell {
+    pub fn text(value: impl Into<String>) -> Self {
+        Cell::Text(value.into())
+    }
+
+    pub fn opt_text(value: Option<impl Into<String>>) -> Self {
+        value.map_or(Cell::Empty, Cell::text)
+    }
+
+    fn display(&self) -> String {
+        match self {
+            Cell::Empty => String::new(),
+            Cell::Text(s) => s.clone(),
+            Cell::Money(n) => format!("{n:.2}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub title: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(title: impl Into<String>, columns: &[&str]) -> Self {
+        Self {
+            title: title.into(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<Cell>) {
+        debug_assert_eq!(row.len(), self.columns.len());
+        self.rows.push(row);
+    }
+}
+
+pub fn render(table: &Table, format: ExportFormat) -> Vec<u8> {
+    match format {
+        ExportFormat::Csv => csv::render(table).into_bytes(),
+        ExportFormat::Xlsx => xlsx::render(table),
+        ExportFormat::Pdf => pdf::render(table),
+    }
+}
+
+pub fn write(table: &Table, format: ExportFormat, path: &Path) -> Result<()> {
+    std::fs::write(path, render(table, format))?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum LetterFormat {
+    Pdf,
+    Docx,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading(String),
+    /// Newlines inside a paragraph are kept as line breaks.
+    Paragraph(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Letter {
+    pub blocks: Vec<Block>,
+}
+
+pub fn render_letter(letter: &Letter, format: LetterFormat) -> Vec<u8> {
+    match format {
+        LetterFormat::Pdf => pdf::render_letter(letter),
+        LetterFormat::Docx => docx::render(letter),
+    }
+}
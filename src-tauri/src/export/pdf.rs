@@ -0,0 +1,294 @@
+//! Plain-text PDF writer using the built-in Courier and Helvetica fonts.
+//!
+//! Good enough for reports and letters: text flows line by line and breaks
+//! onto a new page when the current one is full. Characters outside Latin-1
+//! are replaced with `?` because the standard fonts cannot show them.
+
+use super::{Block, Cell, Letter, Table};
+
+const MARGIN: f32 = 36.0;
+const BODY_SIZE: f32 = 8.0;
+/// Letters are read rather than scanned, so they get larger type and margins.
+const LETTER_SIZE: f32 = 10.5;
+const LETTER_MARGIN: f32 = 64.0;
+/// Every Courier glyph is 600/1000 of the font size wide.
+const COURIER_WIDTH: f32 = 0.6;
+const HEADING_SIZE: f32 = 12.0;
+const LEADING: f32 = 1.3;
+/// Widest a table column is allowed to grow before values are truncated.
+const MAX_COLUMN_CHARS: usize = 28;
+
+#[derive(Clone, Copy)]
+enum Font {
+    Body,
+    Heading,
+}
+
+pub struct TextPdf {
+    width: f32,
+    height: f32,
+    margin: f32,
+    body_size: f32,
+    pages: Vec<String>,
+    current: String,
+    y: f32,
+}
+
+impl TextPdf {
+    /// A4 page, landscape when `landscape` is set.
+    pub fn new(landscape: bool) -> Self {
+        let (width, height) = if landscape {
+            (842.0, 595.0)
+        } else {
+            (595.0, 842.0)
+        };
+        Self {
+            width,
+            height,
+            margin: MARGIN,
+            body_size: BODY_SIZE,
+            pages: Vec::new(),
+            current: String::new(),
+            y: height - MARGIN,
+        }
+    }
+
+    /// Portrait A4 with the larger type and margins used for letters.
+    pub fn letter() -> Self {
+        Self {
+            margin: LETTER_MARGIN,
+            body_size: LETTER_SIZE,
+            y: 842.0 - LETTER_MARGIN,
+            ..Self::new(false)
+        }
+    }
+
+    pub fn heading(&mut self, text: &str) {
+        self.emit(Font::Heading, HEADING_SIZE, text);
+    }
+
+    pub fn line(&mut self, text: &str) {
+        self.emit(Font::Body, self.body_size, text);
+    }
+
+    /// Body text wrapped at word boundaries to the page width. Embedded
+    /// newlines start a new line; long words are split where they must be.
+    pub fn paragraph(&mut self, text: &str) {
+        let usable = self.width - 2.0 * self.margin;
+        let columns = ((usable / (self.body_size * COURIER_WIDTH)) as usize).max(1);
+        for line in text.lines() {
+            for wrapped in wrap(line, columns) {
+                self.line(&wrapped);
+            }
+        }
+    }
+
+    pub fn blank(&mut self) {
+        self.line("");
+    }
+
+    fn emit(&mut self, font: Font, size: f32, text: &str) {
+        let step = size * LEADING;
+        if self.y - step < self.margin {
+            self.break_page();
+        }
+        self.y -= step;
+        if text.is_empty() {
+            return;
+        }
+        let name = match font {
+            Font::Body => "F1",
+            Font::Heading => "F2",
+        };
+        self.current.push_str(&format!(
+            "BT /{name} {size} Tf {} {:.1} Td ({}) Tj ET\n",
+            self.margin,
+            self.y,
+            escape(text)
+        ));
+    }
+
+    fn break_page(&mut self) {
+        self.pages.push(std::mem::take(&mut self.current));
+        self.y = self.height - self.margin;
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        if !self.current.is_empty() || self.pages.is_empty() {
+            self.break_page();
+        }
+
+        // Objects: 1 catalog, 2 page tree, 3 Courier, 4 Helvetica-Bold, then a
+        // page and its content stream for every page.
+        let page_ids: Vec<usize> = (0..self.pages.len()).map(|i| 5 + 2 * i).collect();
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                page_ids
+                    .iter()
+                    .map(|id| format!("{id} 0 R"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                page_ids.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>"
+                .to_string(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+                .to_string(),
+        ];
+        for (content, id) in self.pages.iter().zip(&page_ids) {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                self.width,
+                self.height,
+                id + 1
+            ));
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{content}endstream",
+                content.len()
+            ));
+        }
+
+        let mut out: Vec<u8> = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+        }
+        let xref = out.len();
+        out.extend_from_slice(
+            format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+        );
+        for offset in offsets {
+            out.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+        }
+        out.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+                objects.len() + 1
+            )
+            .as_bytes(),
+        );
+        out
+    }
+}
+
+/// Splits `line` into pieces of at most `columns` characters, breaking at
+/// spaces where possible.
+fn wrap(line: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in line.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        let used = current.chars().count();
+        if used > 0 && used + 1 + word.len() <= columns {
+            current.push(' ');
+            current.extend(&word);
+            continue;
+        }
+        if used > 0 {
+            lines.push(std::mem::take(&mut current));
+        }
+        while word.len() > columns {
+            lines.push(word.drain(..columns).collect());
+        }
+        current.extend(&word);
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Escapes a PDF literal string, mapping text to single-byte Latin-1.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if (c as u32) < 0x20 => out.push(' '),
+            c if (c as u32) < 0x80 => out.push(c),
+            c if (c as u32) <= 0xFF => out.push_str(&format!("\\{:03o}", c as u32)),
+            _ => out.push('?'),
+        }
+    }
+    out
+}
+
+pub fn render(table: &Table) -> Vec<u8> {
+    let cells: Vec<Vec<String>> = table
+        .rows
+        .iter()
+        .map(|row| row.iter().map(Cell::display).collect())
+        .collect();
+    let widths: Vec<usize> = table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .chain([name.chars().count()])
+                .max()
+                .unwrap_or(0)
+                .min(MAX_COLUMN_CHARS)
+        })
+        .collect();
+    let numeric: Vec<bool> = (0..table.columns.len())
+        .map(|i| {
+            table
+                .rows
+                .iter()
+                .any(|row| matches!(row[i], Cell::Money(_)))
+        })
+        .collect();
+
+    let format_row = |values: &[String]| {
+        values
+            .iter()
+            .zip(&widths)
+            .zip(&numeric)
+            .map(|((value, &width), &right)| {
+                let value: String = value.chars().take(width).collect();
+                if right {
+                    format!("{value:>width$}")
+                } else {
+                    format!("{value:<width$}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+
+    let landscape = widths.iter().sum::<usize>() + 2 * widths.len() > 100;
+    let mut pdf = TextPdf::new(landscape);
+    pdf.heading(&table.title);
+    pdf.blank();
+    let header = format_row(&table.columns);
+    pdf.line(&header);
+    pdf.line(&"-".repeat(header.chars().count()));
+    for row in &cells {
+        pdf.line(&format_row(row));
+    }
+    pdf.finish()
+}
+
+pub fn render_letter(letter: &Letter) -> Vec<u8> {
+    let mut pdf = TextPdf::letter();
+    for (i, block) in letter.blocks.iter().enumerate() {
+        if i > 0 {
+            pdf.blank();
+        }
+        match block {
+            Block::Heading(text) => pdf.heading(text),
+            Block::Paragraph(text) => pdf.paragraph(text),
+        }
+    }
+    pdf.finish()
+}
@@ -0,0 +1,219 @@
+//! Background jobs that keep derived data current while the app is open.
+//!
+//! Everything here runs once the database is migrated at startup and then
+//! once a day. Jobs are
+//! idempotent, so a restart or a manual run from the UI never double-books.
+//! Failures go to `job_log`, since nobody is watching stderr in a packaged
+//! app.
+
+use std::fmt::Display;
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::attachments;
+use crate::billing::late_fees;
+use crate::db;
+use crate::documents;
+use crate::error::Result;
+use crate::expenses::recurring;
+use crate::maintenance::schedules;
+use crate::notifications::{digest, email, reminders, statements};
+use crate::period;
+use crate::settings;
+use crate::utilities;
+
+const INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long `job_log` entries are kept.
+const LOG_DAYS: i64 = 90;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct JobLogEntry {
+    pub log_id: i64,
+    /// e.g. `late_fees`, `c2b`.
+    pub job: String,
+    pub message: String,
+    pub created_at: Option<String>,
+}
+
+/// Recent background job failures, newest first.
+#[tauri::command]
+pub async fn get_job_log(app: AppHandle, limit: Option<i64>) -> Result<Vec<JobLogEntry>> {
+    let pool = db::pool(&app).await?;
+    let entries = sqlx::query_as(
+        "SELECT log_id, job, message, created_at FROM job_log
+         ORDER BY log_id DESC
+         LIMIT ?1",
+    )
+    .bind(limit.unwrap_or(100))
+    .fetch_all(&pool)
+    .await?;
+    Ok(entries)
+}
+
+#[tauri::command]
+pub async fn clear_job_log(app: AppHandle) -> Result<u64> {
+    let pool = db::pool(&app).await?;
+    let result = sqlx::query("DELETE FROM job_log").execute(&pool).await?;
+    Ok(result.rows_affected())
+}
+
+/// Records a failure of `job`. Falls back to stderr when the log itself
+/// cannot be written.
+pub async fn log_failure(pool: &SqlitePool, job: &str, message: impl Display) {
+    let message = message.to_string();
+    let logged = sqlx::query("INSERT INTO job_log (job, message) VALUES (?1, ?2)")
+        .bind(job)
+        .bind(&message)
+        .execute(pool)
+        .await;
+    if let Err(err) = logged {
+        eprintln!("{job}: {message} (not logged: {err})");
+    }
+}
+
+/// Logs `result` under `job` when it is an error.
+async fn check<T>(pool: &SqlitePool, job: &str, result: Result<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(err) => {
+            log_failure(pool, job, err).await;
+            None
+        }
+    }
+}
+
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        db::migrated(&app).await;
+        loop {
+            run_daily(&app).await;
+            tokio::time::sleep(INTERVAL).await;
+        }
+    });
+}
+
+async fn run_daily(app: &AppHandle) {
+    let pool = match db::pool(app).await {
+        Ok(pool) => pool,
+        Err(err) => {
+            // Without the database there is nowhere else to put it.
+            eprintln!("background jobs skipped: {err}");
+            return;
+        }
+    };
+    let today = period::today();
+
+    check(&pool, "job_log", prune_log(&pool, today).await).await;
+
+    check(
+        &pool,
+        "late_fees",
+        late_fees::assess_late_fees(&pool, today).await,
+    )
+    .await;
+
+    let run = recurring::post_due_expenses(&pool, today).await;
+    if let Some(run) = check(&pool, "recurring_expenses", run).await {
+        for failure in run.failures {
+            let message = format!(
+                "template {} ({}) not posted: {}",
+                failure.template_id, failure.name, failure.message
+            );
+            log_failure(&pool, "recurring_expenses", message).await;
+        }
+    }
+
+    check(
+        &pool,
+        "maintenance_schedules",
+        schedules::create_due_tasks(&pool, today).await,
+    )
+    .await;
+
+    check(
+        &pool,
+        "document_expiry",
+        documents::create_expiry_tasks(&pool, today).await,
+    )
+    .await;
+
+    if let Some(store) = check(&pool, "attachment_cleanup", attachments::store_dir(app)).await {
+        check(
+            &pool,
+            "attachment_cleanup",
+            attachments::prune(&pool, &store).await,
+        )
+        .await;
+    }
+
+    // Off unless `utilities.auto_bill` is set, so readings can be reviewed first.
+    let auto_bill = settings::get_or(&pool, "utilities.auto_bill", false).await;
+    if check(&pool, "utility_billing", auto_bill).await == Some(true) {
+        check(
+            &pool,
+            "utility_billing",
+            utilities::generate_charges(&pool, today, None).await,
+        )
+        .await;
+    }
+
+    // Only with a gateway set up, so a backlog of stale reminders is not
+    // sent the day one is.
+    let sms_enabled = sms_reminders_enabled(&pool).await;
+    if check(&pool, "sms_reminders", sms_enabled).await == Some(true) {
+        check(
+            &pool,
+            "sms_reminders",
+            reminders::queue_reminders(&pool, today).await,
+        )
+        .await;
+    }
+
+    if let Some(Some(_)) = check(&pool, "email", email::mailer(&pool).await).await {
+        email_jobs(&pool, today).await;
+    }
+}
+
+/// Last month's statements once `email.statement_day` comes round, and the
+/// manager digests. `email.digest_days` of 0 turns digests off.
+async fn email_jobs(pool: &SqlitePool, today: NaiveDate) {
+    let month = statements::statement_month(pool, today).await;
+    if let Some(Some(month)) = check(pool, "tenant_statements", month).await {
+        check(
+            pool,
+            "tenant_statements",
+            statements::queue_statements(pool, month, None).await,
+        )
+        .await;
+    }
+    let every = settings::get_or(pool, "email.digest_days", 7).await;
+    if check(pool, "manager_digests", every)
+        .await
+        .is_some_and(|days: i64| days != 0)
+    {
+        check(
+            pool,
+            "manager_digests",
+            digest::queue_digests(pool, today).await,
+        )
+        .await;
+    }
+}
+
+async fn prune_log(pool: &SqlitePool, today: NaiveDate) -> Result<()> {
+    sqlx::query("DELETE FROM job_log WHERE created_at < ?1")
+        .bind((today - chrono::Duration::days(LOG_DAYS)).to_string())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn sms_reminders_enabled(pool: &SqlitePool) -> Result<bool> {
+    Ok(settings::get(pool, "sms.provider").await?.is_some()
+        && settings::get_or(pool, "sms.reminders", true).await?)
+}
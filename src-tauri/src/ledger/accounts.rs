@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+
+pub const ACCOUNT_TYPES: &[&str] = &["Asset", "Liability", "Equity", "Income", "Expense"];
+
+/// Values of `ledger_account_map.source`.
+pub const MAPPING_SOURCES: &[&str] = &["PaymentMethod", "PaymentCategory", "ExpenseCategory"];
+
+/// Mapping value that catches anything without its own row.
+pub const FALLBACK: &str = "*";
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    pub account_id: i64,
+    pub code: String,
+    pub name: String,
+    pub account_type: String,
+    pub is_active: bool,
+}
+
+/// A new account, or changes to an existing one when `account_id` is set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountInput {
+    #[serde(default)]
+    pub account_id: Option<i64>,
+    pub code: String,
+    pub name: String,
+    pub account_type: String,
+    #[serde(default = "active")]
+    pub is_active: bool,
+}
+
+fn active() -> bool {
+    true
+}
+
+/// Which account a payment method or category posts to.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountMapping {
+    pub source: String,
+    pub match_value: String,
+    pub account_id: i64,
+    pub account_code: String,
+    pub account_name: String,
+}
+
+/// The chart of accounts, ordered by code.
+#[tauri::command]
+pub async fn get_ledger_accounts(app: AppHandle) -> Result<Vec<Account>> {
+    let pool = db::pool(&app).await?;
+    accounts(&pool).await
+}
+
+#[tauri::command]
+pub async fn save_ledger_account(app: AppHandle, account: AccountInput) -> Result<Account> {
+    let pool = db::pool(&app).await?;
+    save_account(&pool, &account).await
+}
+
+#[tauri::command]
+pub async fn get_account_mappings(app: AppHandle) -> Result<Vec<AccountMapping>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(
+        "SELECT m.source, m.match_value, m.account_id,
+                a.code AS account_code, a.name AS account_name
+         FROM ledger_account_map m
+         JOIN ledger_accounts a ON a.account_id = m.account_id
+         ORDER BY m.source, m.match_value = '*', m.match_value",
+    )
+    .fetch_all(&pool)
+    .await?)
+}
+
+/// Points a payment method or category at an account; `None` removes the
+/// mapping so the value falls back to the `*` row. Applies to postings made
+/// from now on.
+#[tauri::command]
+pub async fn set_account_mapping(
+    app: AppHandle,
+    source: String,
+    match_value: String,
+    account_id: Option<i64>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    set_mapping(&pool, &source, &match_value, account_id).await
+}
+
+pub async fn accounts(pool: &SqlitePool) -> Result<Vec<Account>> {
+    Ok(sqlx::query_as(
+        "SELECT account_id, code, name, account_type, is_active
+         FROM ledger_accounts ORDER BY code",
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn save_account(pool: &SqlitePool, input: &AccountInput) -> Result<Account> {
+    let code = input.code.trim();
+    let name = input.name.trim();
+    if code.is_empty() || name.is_empty() {
+        return Err(Error::InvalidInput(
+            "accounts need a code and a name".into(),
+        ));
+    }
+    if !ACCOUNT_TYPES.contains(&input.account_type.as_str()) {
+        return Err(Error::InvalidInput(format!(
+            "account type must be one of {}",
+            ACCOUNT_TYPES.join(", ")
+        )));
+    }
+
+    let mut tx = pool.begin().await?;
+    let account_id = match input.account_id {
+        None => sqlx::query(
+            "INSERT INTO ledger_accounts (code, name, account_type, is_active)
+             VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(code)
+        .bind(name)
+        .bind(&input.account_type)
+        .bind(input.is_active)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid(),
+        Some(account_id) => {
+            let existing: Option<(String, bool)> = sqlx::query_as(
+                "SELECT account_type,
+                        EXISTS (SELECT 1 FROM journal_lines WHERE account_id = ?1)
+                 FROM ledger_accounts WHERE account_id = ?1",
+            )
+            .bind(account_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let (account_type, posted) =
+                existing.ok_or_else(|| Error::NotFound(format!("account {account_id}")))?;
+            if posted && account_type != input.account_type {
+                return Err(Error::InvalidInput(format!(
+                    "account {code} has postings; its type cannot change"
+                )));
+            }
+            if !input.is_active {
+                let (mapped,): (bool,) = sqlx::query_as(
+                    "SELECT EXISTS (SELECT 1 FROM ledger_account_map WHERE account_id = ?1)",
+                )
+                .bind(account_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                if mapped {
+                    return Err(Error::InvalidInput(format!(
+                        "account {code} is still mapped; remap it before deactivating"
+                    )));
+                }
+            }
+            sqlx::query(
+                "UPDATE ledger_accounts
+                 SET code = ?2, name = ?3, account_type = ?4, is_active = ?5
+                 WHERE account_id = ?1",
+            )
+            .bind(account_id)
+            .bind(code)
+            .bind(name)
+            .bind(&input.account_type)
+            .bind(input.is_active)
+            .execute(&mut *tx)
+            .await?;
+            account_id
+        }
+    };
+    let account = sqlx::query_as(
+        "SELECT account_id, code, name, account_type, is_active
+         FROM ledger_accounts WHERE account_id = ?1",
+    )
+    .bind(account_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(account)
+}
+
+pub async fn set_mapping(
+    pool: &SqlitePool,
+    source: &str,
+    match_value: &str,
+    account_id: Option<i64>,
+) -> Result<()> {
+    if !MAPPING_SOURCES.contains(&source) {
+        return Err(Error::InvalidInput(format!(
+            "mapping source must be one of {}",
+            MAPPING_SOURCES.join(", ")
+        )));
+    }
+    // Triggers compare against the lower-cased, trimmed column value.
+    let match_value = match_value.trim().to_lowercase();
+    if match_value.is_empty() {
+        return Err(Error::InvalidInput("mapping value cannot be empty".into()));
+    }
+
+    match account_id {
+        None if match_value == FALLBACK => Err(Error::InvalidInput(format!(
+            "the {FALLBACK} mapping for {source} can be changed but not removed"
+        ))),
+        None => {
+            sqlx::query("DELETE FROM ledger_account_map WHERE source = ?1 AND match_value = ?2")
+                .bind(source)
+                .bind(&match_value)
+                .execute(pool)
+                .await?;
+            Ok(())
+        }
+        Some(account_id) => {
+            let active: Option<(bool,)> =
+                sqlx::query_as("SELECT is_active FROM ledger_accounts WHERE account_id = ?1")
+                    .bind(account_id)
+                    .fetch_optional(pool)
+                    .await?;
+            match active {
+                None => return Err(Error::NotFound(format!("account {account_id}"))),
+                Some((false,)) => {
+                    return Err(Error::InvalidInput(format!(
+                        "account {account_id} is inactive"
+                    )))
+                }
+                Some((true,)) => {}
+            }
+            sqlx::query(
+                "INSERT INTO ledger_account_map (source, match_value, account_id)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT (source, match_value) DO UPDATE SET account_id = excluded.account_id",
+            )
+            .bind(source)
+            .bind(&match_value)
+            .bind(account_id)
+            .execute(pool)
+            .await?;
+            Ok(())
+        }
+    }
+}
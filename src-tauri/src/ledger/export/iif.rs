@@ -0,0 +1,111 @@
+use super::ExportRow;
+
+const HEADER: &str = "!TRNS\tTRNSID\tTRNSTYPE\tDATE\tACCNT\tNAME\tAMOUNT\tDOCNUM\tMEMO\r\n\
+                      !SPL\tSPLID\tTRNSTYPE\tDATE\tACCNT\tNAME\tAMOUNT\tDOCNUM\tMEMO\r\n\
+                      !ENDTRNS\r\n";
+
+/// QuickBooks Desktop IIF: payments as deposits into the money account,
+/// expenses as cheques out of it, each split against its income or expense
+/// account.
+pub fn render(rows: &[ExportRow]) -> String {
+    let mut out = String::from(HEADER);
+    for row in rows {
+        let (kind, account, split_account, amount) = match row.source_type {
+            "Payment" => (
+                "DEPOSIT",
+                &row.debit_account,
+                &row.credit_account,
+                row.amount,
+            ),
+            _ => (
+                "CHECK",
+                &row.credit_account,
+                &row.debit_account,
+                -row.amount,
+            ),
+        };
+        let date = row.date.format("%m/%d/%Y").to_string();
+        let name = field(row.name.as_deref().unwrap_or_default());
+        let docnum = field(row.reference.as_deref().unwrap_or(&row.source_id));
+        let memo = field(&row.description());
+        out.push_str(&format!(
+            "TRNS\t\t{kind}\t{date}\t{}\t{name}\t{amount:.2}\t{docnum}\t{memo}\r\n",
+            field(account)
+        ));
+        out.push_str(&format!(
+            "SPL\t\t{kind}\t{date}\t{}\t{name}\t{:.2}\t{docnum}\t{memo}\r\n",
+            field(split_account),
+            -amount
+        ));
+        out.push_str("ENDTRNS\r\n");
+    }
+    out
+}
+
+/// IIF has no quoting, so tabs and line breaks become spaces.
+fn field(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if matches!(c, '\t' | '\r' | '\n') {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn row(source_type: &'static str, debit: &str, credit: &str, amount: f64) -> ExportRow {
+        ExportRow {
+            source_type,
+            source_id: "17".into(),
+            date: NaiveDate::from_ymd_opt(2024, 5, 3).unwrap(),
+            amount,
+            category: "Rent".into(),
+            name: Some("Jane\tWanjiku".into()),
+            reference: None,
+            memo: Some("May\r\nrent".into()),
+            debit_account: debit.into(),
+            credit_account: credit.into(),
+        }
+    }
+
+    #[test]
+    fn payments_deposit_and_expenses_write_cheques() {
+        let expense = ExportRow {
+            category: "Repairs".into(),
+            name: None,
+            reference: Some("INV-9".into()),
+            memo: None,
+            ..row("Expense", "Repairs", "Bank", 1200.5)
+        };
+        let out = render(&[row("Payment", "Bank", "Rent Income", 25000.0), expense]);
+        assert_eq!(
+            out,
+            format!(
+                "{HEADER}\
+                 TRNS\t\tDEPOSIT\t05/03/2024\tBank\tJane Wanjiku\t25000.00\t17\tRent: May  rent\r\n\
+                 SPL\t\tDEPOSIT\t05/03/2024\tRent Income\tJane Wanjiku\t-25000.00\t17\tRent: May  rent\r\n\
+                 ENDTRNS\r\n\
+                 TRNS\t\tCHECK\t05/03/2024\tBank\t\t-1200.50\tINV-9\tRepairs\r\n\
+                 SPL\t\tCHECK\t05/03/2024\tRepairs\t\t1200.50\tINV-9\tRepairs\r\n\
+                 ENDTRNS\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn header_declares_the_columns_once() {
+        assert_eq!(render(&[]), HEADER);
+        assert!(HEADER.starts_with("!TRNS\tTRNSID\tTRNSTYPE\tDATE\tACCNT\tNAME\tAMOUNT"));
+    }
+}
@@ -0,0 +1,480 @@
+//! Journals for QuickBooks (IIF, QBO) and Xero (manual journal CSV), built
+//! from `payments` and `expenses`.
+//!
+//! Accounts are resolved per target: an `export_account_map` row for the
+//! exact value, then the ledger's own mapping for it, then the `*` rows in
+//! the same order. The ledger mapping names accounts by name for QuickBooks
+//! and by code for Xero.
+
+mod iif;
+mod qbo;
+mod xero;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::ledger::accounts::{FALLBACK, MAPPING_SOURCES};
+use crate::period;
+use crate::settings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AccountingFormat {
+    /// QuickBooks Desktop import file.
+    Iif,
+    /// QuickBooks Web Connect bank file.
+    Qbo,
+    /// Xero manual journal import.
+    XeroCsv,
+}
+
+impl AccountingFormat {
+    pub fn target(self) -> &'static str {
+        match self {
+            AccountingFormat::Iif | AccountingFormat::Qbo => "QuickBooks",
+            AccountingFormat::XeroCsv => "Xero",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            AccountingFormat::Iif => "IIF",
+            AccountingFormat::Qbo => "QBO",
+            AccountingFormat::XeroCsv => "Xero CSV",
+        }
+    }
+}
+
+/// One payment or expense, with both sides resolved to target accounts.
+/// Money comes in to `debit_account` for payments and goes out of
+/// `credit_account` for expenses.
+#[derive(Debug, Clone)]
+pub struct ExportRow {
+    pub source_type: &'static str,
+    pub source_id: String,
+    pub date: NaiveDate,
+    pub amount: f64,
+    pub category: String,
+    /// Tenant for payments, vendor for expenses.
+    pub name: Option<String>,
+    pub reference: Option<String>,
+    pub memo: Option<String>,
+    pub debit_account: String,
+    pub credit_account: String,
+}
+
+impl ExportRow {
+    /// Stable per-record id, e.g. for QBO's `FITID`.
+    pub fn key(&self) -> String {
+        format!("{}-{}", self.source_type, self.source_id)
+    }
+
+    pub fn description(&self) -> String {
+        [Some(self.category.as_str()), self.memo.as_deref()]
+            .into_iter()
+            .flatten()
+            .filter(|s| !s.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join(": ")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountingExport {
+    pub export_id: i64,
+    pub target: String,
+    pub format: String,
+    pub from_date: String,
+    pub to_date: String,
+    pub file_name: Option<String>,
+    pub row_count: i64,
+    pub exported_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResult {
+    pub export: AccountingExport,
+    /// Records in the range left out because an earlier export sent them.
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportAccountMapping {
+    pub target: String,
+    pub source: String,
+    pub match_value: String,
+    pub external_account: String,
+}
+
+/// Writes payments and expenses dated within the range to `path`. Records
+/// already exported to the same target are skipped unless
+/// `include_exported` is set.
+#[tauri::command]
+pub async fn export_accounting(
+    app: AppHandle,
+    format: AccountingFormat,
+    from: String,
+    to: String,
+    path: PathBuf,
+    include_exported: Option<bool>,
+) -> Result<ExportResult> {
+    let from = period::parse_date(&from)?;
+    let to = period::parse_date(&to)?;
+    let pool = db::pool(&app).await?;
+    export(
+        &pool,
+        format,
+        from,
+        to,
+        &path,
+        include_exported.unwrap_or(false),
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn get_accounting_exports(app: AppHandle) -> Result<Vec<AccountingExport>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(
+        "SELECT export_id, target, format, from_date, to_date, file_name, row_count, exported_at
+         FROM accounting_exports ORDER BY export_id DESC",
+    )
+    .fetch_all(&pool)
+    .await?)
+}
+
+/// Forgets an export, e.g. after the import failed on the other side, so
+/// its records are exported again next time.
+#[tauri::command]
+pub async fn delete_accounting_export(app: AppHandle, export_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let result = sqlx::query("DELETE FROM accounting_exports WHERE export_id = ?1")
+        .bind(export_id)
+        .execute(&pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!("export {export_id}")));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_export_account_mappings(
+    app: AppHandle,
+    target: String,
+) -> Result<Vec<ExportAccountMapping>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(
+        "SELECT target, source, match_value, external_account FROM export_account_map
+         WHERE target = ?1
+         ORDER BY source, match_value = '*', match_value",
+    )
+    .bind(target)
+    .fetch_all(&pool)
+    .await?)
+}
+
+/// Sets the account a value exports to; `None` removes the row so the
+/// ledger mapping applies again.
+#[tauri::command]
+pub async fn set_export_account_mapping(
+    app: AppHandle,
+    target: String,
+    source: String,
+    match_value: String,
+    external_account: Option<String>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    set_mapping(
+        &pool,
+        &target,
+        &source,
+        &match_value,
+        external_account.as_deref(),
+    )
+    .await
+}
+
+pub async fn set_mapping(
+    pool: &SqlitePool,
+    target: &str,
+    source: &str,
+    match_value: &str,
+    external_account: Option<&str>,
+) -> Result<()> {
+    if !["QuickBooks", "Xero"].contains(&target) {
+        return Err(Error::InvalidInput(
+            "export target must be QuickBooks or Xero".into(),
+        ));
+    }
+    if !MAPPING_SOURCES.contains(&source) {
+        return Err(Error::InvalidInput(format!(
+            "mapping source must be one of {}",
+            MAPPING_SOURCES.join(", ")
+        )));
+    }
+    let match_value = match_value.trim().to_lowercase();
+    if match_value.is_empty() {
+        return Err(Error::InvalidInput("mapping value cannot be empty".into()));
+    }
+    match external_account.map(str::trim).filter(|a| !a.is_empty()) {
+        Some(account) => {
+            sqlx::query(
+                "INSERT INTO export_account_map (target, source, match_value, external_account)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (target, source, match_value)
+                 DO UPDATE SET external_account = excluded.external_account",
+            )
+            .bind(target)
+            .bind(source)
+            .bind(&match_value)
+            .bind(account)
+            .execute(pool)
+            .await?;
+        }
+        None => {
+            sqlx::query(
+                "DELETE FROM export_account_map
+                 WHERE target = ?1 AND source = ?2 AND match_value = ?3",
+            )
+            .bind(target)
+            .bind(source)
+            .bind(&match_value)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn export(
+    pool: &SqlitePool,
+    format: AccountingFormat,
+    from: NaiveDate,
+    to: NaiveDate,
+    path: &std::path::Path,
+    include_exported: bool,
+) -> Result<ExportResult> {
+    if to < from {
+        return Err(Error::InvalidInput(format!(
+            "export range ends ({to}) before it starts ({from})"
+        )));
+    }
+    let target = format.target();
+    let (rows, skipped) = rows(pool, target, from, to, include_exported).await?;
+    if rows.is_empty() {
+        return Err(Error::InvalidInput(format!(
+            "nothing to export between {from} and {to}"
+        )));
+    }
+
+    let content = match format {
+        AccountingFormat::Iif => iif::render(&rows).into_bytes(),
+        AccountingFormat::Qbo => {
+            let bank_id = settings::get_or(pool, "export.qbo.bank_id", "3000".to_string()).await?;
+            let account_id =
+                settings::get_or(pool, "export.qbo.account_id", "0000000000".to_string()).await?;
+            qbo::render(&rows, &bank_id, &account_id, from, to).into_bytes()
+        }
+        AccountingFormat::XeroCsv => {
+            let tax_rate =
+                settings::get_or(pool, "export.xero.tax_rate", "Tax Exempt".to_string()).await?;
+            xero::render(&rows, &tax_rate)
+        }
+    };
+
+    let mut tx = pool.begin().await?;
+    let export_id = sqlx::query(
+        "INSERT INTO accounting_exports (target, format, from_date, to_date, file_name, row_count)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )
+    .bind(target)
+    .bind(format.label())
+    .bind(from.to_string())
+    .bind(to.to_string())
+    .bind(
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned()),
+    )
+    .bind(rows.len() as i64)
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    for row in &rows {
+        // Re-exported records keep pointing at the export that first sent them.
+        sqlx::query(
+            "INSERT OR IGNORE INTO accounting_export_rows (export_id, target, source_type, source_id)
+             VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(export_id)
+        .bind(target)
+        .bind(row.source_type)
+        .bind(&row.source_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    std::fs::write(path, &content)?;
+    let export = sqlx::query_as(
+        "SELECT export_id, target, format, from_date, to_date, file_name, row_count, exported_at
+         FROM accounting_exports WHERE export_id = ?1",
+    )
+    .bind(export_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(ExportResult { export, skipped })
+}
+
+#[derive(sqlx::FromRow)]
+struct SourceRow {
+    source_type: String,
+    source_id: String,
+    date: String,
+    amount: f64,
+    category: String,
+    method: String,
+    name: Option<String>,
+    reference: Option<String>,
+    memo: Option<String>,
+    exported: bool,
+}
+
+/// Paid payments and expenses in the range with accounts resolved, and how
+/// many were left out as already exported.
+async fn rows(
+    pool: &SqlitePool,
+    target: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+    include_exported: bool,
+) -> Result<(Vec<ExportRow>, usize)> {
+    let sources: Vec<SourceRow> = sqlx::query_as(
+        "SELECT 'Payment' AS source_type, p.payment_id AS source_id, p.payment_date AS date,
+                CAST(p.amount_paid AS REAL) AS amount, p.payment_category AS category,
+                p.payment_method AS method, t.full_name AS name,
+                COALESCE(p.receipt_number, p.transaction_reference) AS reference,
+                p.remarks AS memo,
+                EXISTS (SELECT 1 FROM accounting_export_rows x
+                        WHERE x.target = ?3 AND x.source_type = 'Payment'
+                          AND x.source_id = p.payment_id) AS exported
+         FROM payments p
+         LEFT JOIN tenants t ON t.tenant_id = CAST(p.tenant_id AS INTEGER)
+         WHERE p.payment_status = 'Paid' AND p.amount_paid > 0
+           AND p.payment_date BETWEEN ?1 AND ?2
+         UNION ALL
+         SELECT 'Expense', CAST(e.expense_id AS TEXT), e.expense_date,
+                CAST(e.amount AS REAL), e.category, e.payment_method, e.vendor,
+                e.invoice_number, e.description,
+                EXISTS (SELECT 1 FROM accounting_export_rows x
+                        WHERE x.target = ?3 AND x.source_type = 'Expense'
+                          AND x.source_id = CAST(e.expense_id AS TEXT))
+         FROM expenses e
+         WHERE e.amount > 0 AND e.approval_status = 'Approved'
+           AND e.expense_date BETWEEN ?1 AND ?2
+         ORDER BY 3, 1, 2",
+    )
+    .bind(from.to_string())
+    .bind(to.to_string())
+    .bind(target)
+    .fetch_all(pool)
+    .await?;
+
+    let accounts = AccountResolver::load(pool, target).await?;
+    let mut rows = Vec::new();
+    let mut skipped = 0;
+    for source in sources {
+        if source.exported && !include_exported {
+            skipped += 1;
+            continue;
+        }
+        let method = accounts.resolve("PaymentMethod", &source.method)?;
+        let (source_type, debit_account, credit_account) = match source.source_type.as_str() {
+            "Payment" => (
+                "Payment",
+                method,
+                accounts.resolve("PaymentCategory", &source.category)?,
+            ),
+            _ => (
+                "Expense",
+                accounts.resolve("ExpenseCategory", &source.category)?,
+                method,
+            ),
+        };
+        rows.push(ExportRow {
+            source_type,
+            source_id: source.source_id,
+            date: period::parse_date(&source.date)?,
+            amount: source.amount,
+            category: source.category,
+            name: source.name,
+            reference: source.reference,
+            memo: source.memo,
+            debit_account,
+            credit_account,
+        });
+    }
+    Ok((rows, skipped))
+}
+
+struct AccountResolver {
+    /// `(source, value)` to the target's account, from `export_account_map`.
+    external: HashMap<(String, String), String>,
+    /// The same from the ledger mapping, as name or code.
+    ledger: HashMap<(String, String), String>,
+}
+
+impl AccountResolver {
+    async fn load(pool: &SqlitePool, target: &str) -> Result<Self> {
+        let external: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT source, match_value, external_account FROM export_account_map
+             WHERE target = ?1",
+        )
+        .bind(target)
+        .fetch_all(pool)
+        .await?;
+        let ledger: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT m.source, m.match_value,
+                    CASE WHEN ?1 = 'Xero' THEN a.code ELSE a.name END
+             FROM ledger_account_map m
+             JOIN ledger_accounts a ON a.account_id = m.account_id",
+        )
+        .bind(target)
+        .fetch_all(pool)
+        .await?;
+        let index = |rows: Vec<(String, String, String)>| {
+            rows.into_iter()
+                .map(|(source, value, account)| ((source, value), account))
+                .collect()
+        };
+        Ok(Self {
+            external: index(external),
+            ledger: index(ledger),
+        })
+    }
+
+    fn resolve(&self, source: &str, value: &str) -> Result<String> {
+        let value = value.trim().to_lowercase();
+        let account = [value.as_str(), FALLBACK]
+            .into_iter()
+            .flat_map(|value| {
+                let key = (source.to_string(), value.to_string());
+                [self.external.get(&key), self.ledger.get(&key)]
+            })
+            .flatten()
+            .next()
+            .cloned();
+        account.ok_or_else(|| {
+            Error::InvalidInput(format!("no account is mapped for {source} '{value}'"))
+        })
+    }
+}
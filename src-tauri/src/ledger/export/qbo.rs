@@ -0,0 +1,123 @@
+use chrono::{Local, NaiveDate};
+
+use super::ExportRow;
+
+/// QuickBooks Web Connect (OFX 1.0.2 SGML) bank file: payments as credits,
+/// expenses as debits. QuickBooks asks which account to import into and
+/// categorises lines itself; the mapped account goes in the memo as a hint.
+pub fn render(
+    rows: &[ExportRow],
+    bank_id: &str,
+    account_id: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> String {
+    let now = Local::now().format("%Y%m%d%H%M%S").to_string();
+    let mut out = format!(
+        "OFXHEADER:100\r\nDATA:OFXSGML\r\nVERSION:102\r\nSECURITY:NONE\r\nENCODING:USASCII\r\n\
+         CHARSET:1252\r\nCOMPRESSION:NONE\r\nOLDFILEUID:NONE\r\nNEWFILEUID:NONE\r\n\r\n\
+         <OFX>\r\n<SIGNONMSGSRSV1>\r\n<SONRS>\r\n<STATUS>\r\n<CODE>0\r\n<SEVERITY>INFO\r\n</STATUS>\r\n\
+         <DTSERVER>{now}\r\n<LANGUAGE>ENG\r\n<INTU.BID>{bank_id}\r\n</SONRS>\r\n</SIGNONMSGSRSV1>\r\n\
+         <BANKMSGSRSV1>\r\n<STMTTRNRS>\r\n<TRNUID>1\r\n<STATUS>\r\n<CODE>0\r\n<SEVERITY>INFO\r\n</STATUS>\r\n\
+         <STMTRS>\r\n<CURDEF>KES\r\n<BANKACCTFROM>\r\n<BANKID>{bank_id}\r\n<ACCTID>{}\r\n\
+         <ACCTTYPE>CHECKING\r\n</BANKACCTFROM>\r\n<BANKTRANLIST>\r\n<DTSTART>{}\r\n<DTEND>{}\r\n",
+        text(account_id),
+        from.format("%Y%m%d"),
+        to.format("%Y%m%d"),
+    );
+    let mut balance = 0.0;
+    for row in rows {
+        let (kind, amount, category) = match row.source_type {
+            "Payment" => ("CREDIT", row.amount, &row.credit_account),
+            _ => ("DEBIT", -row.amount, &row.debit_account),
+        };
+        balance += amount;
+        out.push_str(&format!(
+            "<STMTTRN>\r\n<TRNTYPE>{kind}\r\n<DTPOSTED>{}\r\n<TRNAMT>{amount:.2}\r\n<FITID>{}\r\n",
+            row.date.format("%Y%m%d"),
+            text(&row.key()),
+        ));
+        if let Some(name) = &row.name {
+            // OFX 1.x caps NAME at 32 characters.
+            out.push_str(&format!(
+                "<NAME>{}\r\n",
+                text(&name.chars().take(32).collect::<String>())
+            ));
+        }
+        if let Some(reference) = &row.reference {
+            out.push_str(&format!("<REFNUM>{}\r\n", text(reference)));
+        }
+        out.push_str(&format!(
+            "<MEMO>{}\r\n</STMTTRN>\r\n",
+            text(&format!("{} [{category}]", row.description()))
+        ));
+    }
+    out.push_str(&format!(
+        "</BANKTRANLIST>\r\n<LEDGERBAL>\r\n<BALAMT>{balance:.2}\r\n<DTASOF>{}\r\n</LEDGERBAL>\r\n\
+         </STMTRS>\r\n</STMTTRNRS>\r\n</BANKMSGSRSV1>\r\n</OFX>\r\n",
+        to.format("%Y%m%d"),
+    ));
+    out
+}
+
+/// Escapes SGML markup characters and flattens line breaks.
+fn text(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(source_type: &'static str, debit: &str, credit: &str, amount: f64) -> ExportRow {
+        ExportRow {
+            source_type,
+            source_id: "17".into(),
+            date: NaiveDate::from_ymd_opt(2024, 5, 3).unwrap(),
+            amount,
+            category: "Rent".into(),
+            name: None,
+            reference: None,
+            memo: None,
+            debit_account: debit.into(),
+            credit_account: credit.into(),
+        }
+    }
+
+    #[test]
+    fn payments_are_credits_and_expenses_debits() {
+        let payment = ExportRow {
+            name: Some("Mwangi & Sons Properties Holdings Ltd".into()),
+            reference: Some("QK7<1>".into()),
+            memo: Some("May\nrent".into()),
+            ..row("Payment", "Bank", "Rent Income", 25000.0)
+        };
+        let expense = ExportRow {
+            source_id: "4".into(),
+            category: "Repairs".into(),
+            ..row("Expense", "Repairs", "Bank", 1200.5)
+        };
+        let from = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2024, 5, 31).unwrap();
+        let out = render(&[payment, expense], "KCB", "0123", from, to);
+
+        assert!(out.starts_with("OFXHEADER:100\r\nDATA:OFXSGML\r\nVERSION:102\r\n"));
+        assert!(out.contains("<BANKID>KCB\r\n<ACCTID>0123\r\n"));
+        assert!(out.contains("<DTSTART>20240501\r\n<DTEND>20240531\r\n"));
+        assert!(out.contains(
+            "<STMTTRN>\r\n<TRNTYPE>CREDIT\r\n<DTPOSTED>20240503\r\n<TRNAMT>25000.00\r\n\
+             <FITID>Payment-17\r\n<NAME>Mwangi &amp; Sons Properties Holding\r\n\
+             <REFNUM>QK7&lt;1&gt;\r\n<MEMO>Rent: May rent [Rent Income]\r\n</STMTTRN>\r\n"
+        ));
+        assert!(out.contains(
+            "<STMTTRN>\r\n<TRNTYPE>DEBIT\r\n<DTPOSTED>20240503\r\n<TRNAMT>-1200.50\r\n\
+             <FITID>Expense-4\r\n<MEMO>Repairs [Repairs]\r\n</STMTTRN>\r\n"
+        ));
+        assert!(out.contains("<LEDGERBAL>\r\n<BALAMT>23799.50\r\n<DTASOF>20240531\r\n"));
+        assert!(out.ends_with("</BANKMSGSRSV1>\r\n</OFX>\r\n"));
+    }
+}
@@ -0,0 +1,94 @@
+use super::ExportRow;
+use crate::export::{self, Cell, ExportFormat, Table};
+
+/// Xero manual journal import: one journal per record, narrated with the
+/// record id, debit lines positive and credit lines negative.
+pub fn render(rows: &[ExportRow], tax_rate: &str) -> Vec<u8> {
+    let mut table = Table::new(
+        "Xero Manual Journal",
+        &[
+            "*Narration",
+            "*Date",
+            "Description",
+            "*AccountCode",
+            "*TaxRate",
+            "*Amount",
+        ],
+    );
+    for row in rows {
+        let narration = match &row.name {
+            Some(name) => format!("{} {} - {name}", row.source_type, row.source_id),
+            None => format!("{} {}", row.source_type, row.source_id),
+        };
+        let date = row.date.format("%d/%m/%Y").to_string();
+        for (account, amount) in [
+            (&row.debit_account, row.amount),
+            (&row.credit_account, -row.amount),
+        ] {
+            table.push(vec![
+                Cell::text(&narration),
+                Cell::text(&date),
+                Cell::text(row.description()),
+                Cell::text(account),
+                Cell::text(tax_rate),
+                Cell::Money(amount),
+            ]);
+        }
+    }
+    export::render(&table, ExportFormat::Csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn writes_balanced_journal_lines() {
+        let row = ExportRow {
+            source_type: "Payment",
+            source_id: "17".into(),
+            date: NaiveDate::from_ymd_opt(2024, 5, 3).unwrap(),
+            amount: 25000.0,
+            category: "Rent".into(),
+            name: Some("Wanjiku, Jane".into()),
+            reference: None,
+            memo: Some("the \"May\" invoice".into()),
+            debit_account: "090".into(),
+            credit_account: "200".into(),
+        };
+        let out = String::from_utf8(render(&[row], "Tax Exempt")).unwrap();
+        assert_eq!(
+            out,
+            "*Narration,*Date,Description,*AccountCode,*TaxRate,*Amount\r\n\
+             \"Payment 17 - Wanjiku, Jane\",03/05/2024,\"Rent: the \"\"May\"\" invoice\",090,Tax Exempt,25000.00\r\n\
+             \"Payment 17 - Wanjiku, Jane\",03/05/2024,\"Rent: the \"\"May\"\" invoice\",200,Tax Exempt,-25000.00\r\n"
+        );
+    }
+
+    #[test]
+    fn narrates_without_a_name() {
+        let row = ExportRow {
+            source_type: "Expense",
+            source_id: "4".into(),
+            date: NaiveDate::from_ymd_opt(2024, 12, 31).unwrap(),
+            amount: 1200.5,
+            category: "Repairs".into(),
+            name: None,
+            reference: None,
+            memo: None,
+            debit_account: "473".into(),
+            credit_account: "090".into(),
+        };
+        let out = String::from_utf8(render(&[row], "")).unwrap();
+        let lines: Vec<&str> = out.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                "Expense 4,31/12/2024,Repairs,473,,1200.50",
+                "Expense 4,31/12/2024,Repairs,090,,-1200.50",
+            ]
+        );
+    }
+}
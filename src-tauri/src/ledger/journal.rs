@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::billing::allocations::round_cents;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::ledger::periods;
+use crate::period;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub entry_id: i64,
+    pub entry_date: String,
+    pub memo: Option<String>,
+    /// `Payment` and `Expense` entries are posted by the database; `Manual`
+    /// ones come from [`post_journal_entry`].
+    pub source_type: String,
+    pub source_id: Option<String>,
+    pub property_id: Option<i64>,
+    pub reverses_entry_id: Option<i64>,
+    pub reversed_by_entry_id: Option<i64>,
+    #[sqlx(skip)]
+    pub lines: Vec<JournalLine>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalLine {
+    pub line_id: i64,
+    pub entry_id: i64,
+    pub account_id: i64,
+    pub account_code: String,
+    pub account_name: String,
+    pub debit: f64,
+    pub credit: f64,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewJournalLine {
+    pub account_id: i64,
+    #[serde(default)]
+    pub debit: f64,
+    #[serde(default)]
+    pub credit: f64,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// A manual journal entry, e.g. an opening balance or a bank charge.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewJournalEntry {
+    pub entry_date: String,
+    #[serde(default)]
+    pub memo: Option<String>,
+    #[serde(default)]
+    pub property_id: Option<i64>,
+    pub lines: Vec<NewJournalLine>,
+}
+
+impl NewJournalEntry {
+    /// Every line debits or credits a positive amount, and the two sides
+    /// agree to the cent.
+    pub fn validate(&self) -> Result<()> {
+        period::parse_date(&self.entry_date)?;
+        if self.lines.len() < 2 {
+            return Err(Error::InvalidInput(
+                "a journal entry needs at least two lines".into(),
+            ));
+        }
+        for line in &self.lines {
+            let one_sided = (line.debit > 0.0 && line.credit == 0.0)
+                || (line.credit > 0.0 && line.debit == 0.0);
+            if !one_sided {
+                return Err(Error::InvalidInput(
+                    "each line must have either a debit or a credit".into(),
+                ));
+            }
+        }
+        let debits: f64 = self.lines.iter().map(|l| l.debit).sum();
+        let credits: f64 = self.lines.iter().map(|l| l.credit).sum();
+        if round_cents(debits) != round_cents(credits) {
+            return Err(Error::InvalidInput(format!(
+                "debits {debits:.2} do not equal credits {credits:.2}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+const ENTRY_COLUMNS: &str = "e.entry_id, e.entry_date, e.memo, e.source_type, e.source_id,
+     e.property_id, e.reverses_entry_id,
+     (SELECT r.entry_id FROM journal_entries r WHERE r.reverses_entry_id = e.entry_id)
+         AS reversed_by_entry_id";
+
+/// Entries dated within the range, optionally only those touching an account
+/// or from one source, each with its lines.
+#[tauri::command]
+pub async fn get_journal_entries(
+    app: AppHandle,
+    from: Option<String>,
+    to: Option<String>,
+    account_id: Option<i64>,
+    source_type: Option<String>,
+) -> Result<Vec<JournalEntry>> {
+    for date in [&from, &to].into_iter().flatten() {
+        period::parse_date(date)?;
+    }
+    let pool = db::pool(&app).await?;
+    let mut entries: Vec<JournalEntry> = sqlx::query_as(&format!(
+        "SELECT {ENTRY_COLUMNS} FROM journal_entries e
+         WHERE (?1 IS NULL OR e.entry_date >= ?1)
+           AND (?2 IS NULL OR e.entry_date <= ?2)
+           AND (?3 IS NULL OR EXISTS (
+                   SELECT 1 FROM journal_lines l WHERE l.entry_id = e.entry_id AND l.account_id = ?3))
+           AND (?4 IS NULL OR e.source_type = ?4)
+         ORDER BY e.entry_date, e.entry_id"
+    ))
+    .bind(from)
+    .bind(to)
+    .bind(account_id)
+    .bind(source_type)
+    .fetch_all(&pool)
+    .await?;
+
+    let mut conn = pool.acquire().await?;
+    for entry in &mut entries {
+        entry.lines = lines(&mut conn, entry.entry_id).await?;
+    }
+    Ok(entries)
+}
+
+#[tauri::command]
+pub async fn post_journal_entry(app: AppHandle, entry: NewJournalEntry) -> Result<JournalEntry> {
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let entry_id = insert_entry(&mut tx, &entry).await?;
+    let entry = load_entry(&mut tx, entry_id).await?;
+    tx.commit().await?;
+    Ok(entry)
+}
+
+/// Cancels a manual entry with an equal and opposite one dated `entry_date`
+/// (default today), which must fall in an open period. Automatic postings are
+/// corrected by editing or deleting the payment or expense instead.
+#[tauri::command]
+pub async fn reverse_journal_entry(
+    app: AppHandle,
+    entry_id: i64,
+    entry_date: Option<String>,
+) -> Result<JournalEntry> {
+    let entry_date = entry_date
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let pool = db::pool(&app).await?;
+    let reversal_id = reverse_entry(&pool, entry_id, &entry_date.to_string()).await?;
+    let mut conn = pool.acquire().await?;
+    load_entry(&mut conn, reversal_id).await
+}
+
+pub async fn insert_entry(conn: &mut SqliteConnection, entry: &NewJournalEntry) -> Result<i64> {
+    entry.validate()?;
+    let entry_date = period::parse_date(&entry.entry_date)?;
+    periods::ensure_open(conn, &entry.entry_date).await?;
+    let entry_id = sqlx::query(
+        "INSERT INTO journal_entries (entry_date, memo, source_type, property_id)
+         VALUES (?1, ?2, 'Manual', ?3)",
+    )
+    .bind(entry_date.to_string())
+    .bind(&entry.memo)
+    .bind(entry.property_id)
+    .execute(&mut *conn)
+    .await?
+    .last_insert_rowid();
+
+    for line in &entry.lines {
+        let active: Option<(bool,)> =
+            sqlx::query_as("SELECT is_active FROM ledger_accounts WHERE account_id = ?1")
+                .bind(line.account_id)
+                .fetch_optional(&mut *conn)
+                .await?;
+        if active != Some((true,)) {
+            return Err(Error::InvalidInput(format!(
+                "account {} does not exist or is inactive",
+                line.account_id
+            )));
+        }
+        sqlx::query(
+            "INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(entry_id)
+        .bind(line.account_id)
+        .bind(round_cents(line.debit))
+        .bind(round_cents(line.credit))
+        .bind(&line.description)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(entry_id)
+}
+
+pub async fn reverse_entry(pool: &SqlitePool, entry_id: i64, entry_date: &str) -> Result<i64> {
+    let mut tx = pool.begin().await?;
+    periods::ensure_open(&mut tx, entry_date).await?;
+    let original = load_entry(&mut tx, entry_id).await?;
+    if original.source_type != "Manual" {
+        return Err(Error::InvalidInput(format!(
+            "entry {entry_id} was posted from {} {}; edit or delete that record instead",
+            original.source_type.to_lowercase(),
+            original.source_id.unwrap_or_default()
+        )));
+    }
+    if original.reverses_entry_id.is_some() {
+        return Err(Error::InvalidInput(format!(
+            "entry {entry_id} is itself a reversal"
+        )));
+    }
+    if let Some(reversal) = original.reversed_by_entry_id {
+        return Err(Error::InvalidInput(format!(
+            "entry {entry_id} was already reversed by entry {reversal}"
+        )));
+    }
+
+    let memo = format!(
+        "Reversal: {}",
+        original.memo.unwrap_or_else(|| format!("entry {entry_id}"))
+    );
+    let reversal_id = sqlx::query(
+        "INSERT INTO journal_entries (entry_date, memo, source_type, property_id, reverses_entry_id)
+         VALUES (?1, ?2, 'Manual', ?3, ?4)",
+    )
+    .bind(entry_date)
+    .bind(memo)
+    .bind(original.property_id)
+    .bind(entry_id)
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    sqlx::query(
+        "INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+         SELECT ?1, account_id, credit, debit, description
+         FROM journal_lines WHERE entry_id = ?2",
+    )
+    .bind(reversal_id)
+    .bind(entry_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(reversal_id)
+}
+
+pub async fn load_entry(conn: &mut SqliteConnection, entry_id: i64) -> Result<JournalEntry> {
+    let mut entry: JournalEntry = sqlx::query_as(&format!(
+        "SELECT {ENTRY_COLUMNS} FROM journal_entries e WHERE e.entry_id = ?1"
+    ))
+    .bind(entry_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("journal entry {entry_id}")))?;
+    entry.lines = lines(conn, entry_id).await?;
+    Ok(entry)
+}
+
+async fn lines(conn: &mut SqliteConnection, entry_id: i64) -> Result<Vec<JournalLine>> {
+    Ok(sqlx::query_as(
+        "SELECT l.line_id, l.entry_id, l.account_id, a.code AS account_code,
+                a.name AS account_name, CAST(l.debit AS REAL) AS debit,
+                CAST(l.credit AS REAL) AS credit, l.description
+         FROM journal_lines l
+         JOIN ledger_accounts a ON a.account_id = l.account_id
+         WHERE l.entry_id = ?1
+         ORDER BY l.credit > 0, l.line_id",
+    )
+    .bind(entry_id)
+    .fetch_all(&mut *conn)
+    .await?)
+}
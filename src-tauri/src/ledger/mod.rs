@@ -0,0 +1,151 @@
+//! Double-entry general ledger.
+//!
+//! Paid payments and approved expenses are posted by database triggers
+//! (migrations 24 and 32), so the journal stays complete whichever screen
+//! wrote the row. This module manages the chart of accounts and its mapping,
+//! manual entries, the trial balance, month-end close and exports to
+//! accounting software.
+
+pub mod accounts;
+pub mod export;
+pub mod journal;
+pub mod periods;
+
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::billing::allocations::round_cents;
+use crate::db;
+use crate::error::Result;
+use crate::export::{Cell, ExportFormat, Table};
+use crate::period;
+
+/// An account's net balance, in the debit or the credit column.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TrialBalanceRow {
+    pub account_id: i64,
+    pub code: String,
+    pub name: String,
+    pub account_type: String,
+    pub debit: f64,
+    pub credit: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrialBalance {
+    pub as_of: NaiveDate,
+    pub property_id: Option<i64>,
+    pub rows: Vec<TrialBalanceRow>,
+    pub total_debit: f64,
+    pub total_credit: f64,
+    pub is_balanced: bool,
+}
+
+/// Balances of every account with postings up to `as_of` (default today),
+/// for one property or the whole portfolio.
+#[tauri::command]
+pub async fn get_trial_balance(
+    app: AppHandle,
+    as_of: Option<String>,
+    property_id: Option<i64>,
+) -> Result<TrialBalance> {
+    let as_of = as_of
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let pool = db::pool(&app).await?;
+    trial_balance(&pool, as_of, property_id).await
+}
+
+/// Writes the trial balance to `path` and returns the path back.
+#[tauri::command]
+pub async fn export_trial_balance(
+    app: AppHandle,
+    as_of: Option<String>,
+    property_id: Option<i64>,
+    format: ExportFormat,
+    path: PathBuf,
+) -> Result<PathBuf> {
+    let balance = get_trial_balance(app, as_of, property_id).await?;
+    crate::export::write(&balance.to_table(), format, &path)?;
+    Ok(path)
+}
+
+pub async fn trial_balance(
+    pool: &SqlitePool,
+    as_of: NaiveDate,
+    property_id: Option<i64>,
+) -> Result<TrialBalance> {
+    let mut rows: Vec<TrialBalanceRow> = sqlx::query_as(
+        "SELECT a.account_id, a.code, a.name, a.account_type,
+                CAST(SUM(l.debit) AS REAL) AS debit, CAST(SUM(l.credit) AS REAL) AS credit
+         FROM journal_lines l
+         JOIN journal_entries e ON e.entry_id = l.entry_id
+         JOIN ledger_accounts a ON a.account_id = l.account_id
+         WHERE e.entry_date <= ?1 AND (?2 IS NULL OR e.property_id = ?2)
+         GROUP BY a.account_id
+         ORDER BY a.code",
+    )
+    .bind(as_of.to_string())
+    .bind(property_id)
+    .fetch_all(pool)
+    .await?;
+
+    for row in &mut rows {
+        let net = round_cents(row.debit - row.credit);
+        (row.debit, row.credit) = if net >= 0.0 { (net, 0.0) } else { (0.0, -net) };
+    }
+    // Accounts whose postings cancel out (e.g. a reversed payment) add nothing.
+    rows.retain(|row| row.debit != 0.0 || row.credit != 0.0);
+
+    let total_debit = round_cents(rows.iter().map(|r| r.debit).sum());
+    let total_credit = round_cents(rows.iter().map(|r| r.credit).sum());
+    Ok(TrialBalance {
+        as_of,
+        property_id,
+        rows,
+        total_debit,
+        total_credit,
+        is_balanced: total_debit == total_credit,
+    })
+}
+
+impl TrialBalance {
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new(
+            format!("Trial Balance as of {}", self.as_of),
+            &["Code", "Account", "Type", "Debit", "Credit"],
+        );
+        let money = |amount: f64| {
+            if amount == 0.0 {
+                Cell::Empty
+            } else {
+                Cell::Money(amount)
+            }
+        };
+        for row in &self.rows {
+            table.push(vec![
+                Cell::text(&row.code),
+                Cell::text(&row.name),
+                Cell::text(&row.account_type),
+                money(row.debit),
+                money(row.credit),
+            ]);
+        }
+        table.push(vec![
+            Cell::text("Total"),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Money(self.total_debit),
+            Cell::Money(self.total_credit),
+        ]);
+        table
+    }
+}
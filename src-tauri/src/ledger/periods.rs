@@ -0,0 +1,191 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::period::{self, Month};
+
+/// A month that has been closed, or closed and reopened. Months without a
+/// row have never been closed.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountingPeriod {
+    pub period: String,
+    pub status: String,
+    pub closed_at: Option<String>,
+    pub closed_by: Option<String>,
+    pub reopened_at: Option<String>,
+    pub reopen_reason: Option<String>,
+}
+
+const PERIOD_COLUMNS: &str = "period, status, closed_at, closed_by, reopened_at, reopen_reason";
+
+#[tauri::command]
+pub async fn get_accounting_periods(app: AppHandle) -> Result<Vec<AccountingPeriod>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(&format!(
+        "SELECT {PERIOD_COLUMNS} FROM accounting_periods ORDER BY period DESC"
+    ))
+    .fetch_all(&pool)
+    .await?)
+}
+
+/// Closes a finished month. Months close in order, so every earlier month
+/// with postings must already be closed.
+#[tauri::command]
+pub async fn close_accounting_period(
+    app: AppHandle,
+    period: Month,
+    closed_by: Option<String>,
+) -> Result<AccountingPeriod> {
+    let pool = db::pool(&app).await?;
+    close(&pool, period, closed_by).await
+}
+
+/// Reopens the latest closed month, e.g. to post a late adjustment. Earlier
+/// months stay closed.
+#[tauri::command]
+pub async fn reopen_accounting_period(
+    app: AppHandle,
+    period: Month,
+    reason: String,
+) -> Result<AccountingPeriod> {
+    let pool = db::pool(&app).await?;
+    reopen(&pool, period, &reason).await
+}
+
+pub async fn close(
+    pool: &SqlitePool,
+    period: Month,
+    closed_by: Option<String>,
+) -> Result<AccountingPeriod> {
+    let mut tx = pool.begin().await?;
+    if period >= Month::current() {
+        return Err(Error::InvalidInput(format!(
+            "{period} has not ended yet and cannot be closed"
+        )));
+    }
+    let lock = lock_date(&mut tx).await?;
+    if lock.is_some_and(|lock| period.last_day() <= lock) {
+        return Err(Error::InvalidInput(format!("{period} is already closed")));
+    }
+    let (earlier,): (Option<String>,) = sqlx::query_as(
+        "SELECT MIN(entry_date) FROM journal_entries
+         WHERE entry_date < ?1 AND (?2 IS NULL OR entry_date > ?2)",
+    )
+    .bind(period.first_day().to_string())
+    .bind(lock.map(|d| d.to_string()))
+    .fetch_one(&mut *tx)
+    .await?;
+    if let Some(earlier) = earlier {
+        let earlier = Month::of(period::parse_date(&earlier)?);
+        return Err(Error::InvalidInput(format!(
+            "close {earlier} before {period}"
+        )));
+    }
+
+    sqlx::query(
+        "INSERT INTO accounting_periods (period, status, closed_at, closed_by)
+         VALUES (?1, 'Closed', CURRENT_TIMESTAMP, ?2)
+         ON CONFLICT (period) DO UPDATE SET status = 'Closed',
+                                            closed_at = CURRENT_TIMESTAMP,
+                                            closed_by = excluded.closed_by",
+    )
+    .bind(period.to_string())
+    .bind(closed_by)
+    .execute(&mut *tx)
+    .await?;
+    let closed = load(&mut tx, period).await?;
+    tx.commit().await?;
+    Ok(closed)
+}
+
+pub async fn reopen(pool: &SqlitePool, period: Month, reason: &str) -> Result<AccountingPeriod> {
+    if reason.trim().is_empty() {
+        return Err(Error::InvalidInput(
+            "give a reason for reopening a period".into(),
+        ));
+    }
+    let mut tx = pool.begin().await?;
+    match lock_date(&mut tx).await? {
+        Some(lock) if Month::of(lock) == period => {}
+        Some(lock) if period.last_day() < lock => {
+            return Err(Error::InvalidInput(format!(
+                "reopen {} first; only the latest closed period can be reopened",
+                Month::of(lock)
+            )))
+        }
+        _ => return Err(Error::InvalidInput(format!("{period} is not closed"))),
+    }
+    sqlx::query(
+        "UPDATE accounting_periods
+         SET status = 'Open', reopened_at = CURRENT_TIMESTAMP, reopen_reason = ?2
+         WHERE period = ?1",
+    )
+    .bind(period.to_string())
+    .bind(reason.trim())
+    .execute(&mut *tx)
+    .await?;
+    let reopened = load(&mut tx, period).await?;
+    tx.commit().await?;
+    Ok(reopened)
+}
+
+/// Last day of the latest closed period; everything up to it is locked.
+pub async fn lock_date(conn: &mut SqliteConnection) -> Result<Option<NaiveDate>> {
+    let (lock,): (Option<String>,) = sqlx::query_as("SELECT lock_date FROM ledger_lock")
+        .fetch_one(&mut *conn)
+        .await?;
+    lock.as_deref().map(period::parse_date).transpose()
+}
+
+/// Rejects writes dated in a closed period.
+pub async fn ensure_open(conn: &mut SqliteConnection, date: &str) -> Result<()> {
+    match closed_month(conn, date).await? {
+        Some(month) => Err(Error::InvalidInput(format!(
+            "{month} is a closed accounting period"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Rejects edits and deletions of records dated in a closed period, unless
+/// the caller asked for the correction to be posted as a reversing entry in
+/// the open period; the ledger triggers then date it there.
+pub async fn ensure_editable(
+    conn: &mut SqliteConnection,
+    dates: &[&str],
+    reverse_in_open_period: bool,
+) -> Result<()> {
+    if reverse_in_open_period {
+        return Ok(());
+    }
+    for date in dates {
+        if let Some(month) = closed_month(conn, date).await? {
+            return Err(Error::InvalidInput(format!(
+                "{month} is a closed accounting period; post the change as a \
+                 reversing entry in the open period instead"
+            )));
+        }
+    }
+    Ok(())
+}
+
+async fn closed_month(conn: &mut SqliteConnection, date: &str) -> Result<Option<Month>> {
+    let date = period::parse_date(date)?;
+    Ok(lock_date(conn)
+        .await?
+        .filter(|lock| date <= *lock)
+        .map(|_| Month::of(date)))
+}
+
+async fn load(conn: &mut SqliteConnection, period: Month) -> Result<AccountingPeriod> {
+    Ok(sqlx::query_as(&format!(
+        "SELECT {PERIOD_COLUMNS} FROM accounting_periods WHERE period = ?1"
+    ))
+    .bind(period.to_string())
+    .fetch_one(&mut *conn)
+    .await?)
+}
@@ -1,7 +1,30 @@
 use serde::{Deserialize, Serialize};
 
+use tauri::Manager;
 use tauri_plugin_sql::{Builder as SqlBuilder, Migration, MigrationKind};
 
+mod attachments;
+mod billing;
+mod c2b;
+mod db;
+mod documents;
+mod error;
+mod expenses;
+mod export;
+mod jobs;
+mod ledger;
+mod maintenance;
+mod net;
+mod notifications;
+mod period;
+mod reports;
+mod settings;
+mod statements;
+mod stats;
+mod tasks;
+mod templates;
+mod utilities;
+
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -373,15 +396,2537 @@ pub fn run() {
             ",
             kind: MigrationKind::Up, // This is an "Up" migration to apply changes
 },
+
+        // ---------------------------------------------------------------------
+        // Migration 18: Monthly summary tables for dashboard stats
+        // Title: Create Payment and Expense Summaries
+        // Table Name: payment_monthly_summary, expense_monthly_summary
+        // Note: kept in sync by triggers on payments/expenses so the dashboard never
+        // aggregates the raw tables. period is strftime('%Y-%m') of payment_date /
+        // expense_date; property_id 0 collects rows with no resolvable property.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 18,
+            description: "create_monthly_summary_tables",
+            sql: "
+                CREATE TABLE IF NOT EXISTS payment_monthly_summary (
+                    property_id INTEGER NOT NULL,
+                    period TEXT NOT NULL,
+                    payment_category TEXT NOT NULL,
+                    payment_status TEXT NOT NULL,
+                    total_amount DECIMAL(12, 2) NOT NULL DEFAULT 0,
+                    payment_count INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY (property_id, period, payment_category, payment_status)
+                );
+
+                CREATE TABLE IF NOT EXISTS expense_monthly_summary (
+                    property_id INTEGER NOT NULL,
+                    period TEXT NOT NULL,
+                    total_amount DECIMAL(12, 2) NOT NULL DEFAULT 0,
+                    expense_count INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY (property_id, period)
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_payments_status_due ON payments(payment_status, due_date);
+
+                INSERT INTO payment_monthly_summary
+                    (property_id, period, payment_category, payment_status, total_amount, payment_count)
+                SELECT COALESCE(CAST(property_id AS INTEGER), 0), COALESCE(strftime('%Y-%m', payment_date), ''),
+                       payment_category, payment_status, SUM(amount_paid), COUNT(*)
+                FROM payments
+                GROUP BY 1, 2, 3, 4;
+
+                INSERT INTO expense_monthly_summary (property_id, period, total_amount, expense_count)
+                SELECT COALESCE(e.property_id, u.property_id, b.property_id, 0),
+                       COALESCE(strftime('%Y-%m', e.expense_date), ''), SUM(e.amount), COUNT(*)
+                FROM expenses e
+                LEFT JOIN units u ON u.unit_id = e.unit_id
+                LEFT JOIN blocks b ON b.block_id = e.block_id
+                GROUP BY 1, 2;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_summary_insert AFTER INSERT ON payments
+                BEGIN
+                    INSERT INTO payment_monthly_summary
+                        (property_id, period, payment_category, payment_status, total_amount, payment_count)
+                    VALUES (COALESCE(CAST(NEW.property_id AS INTEGER), 0), COALESCE(strftime('%Y-%m', NEW.payment_date), ''),
+                            NEW.payment_category, NEW.payment_status, NEW.amount_paid, 1)
+                    ON CONFLICT (property_id, period, payment_category, payment_status) DO UPDATE
+                    SET total_amount = total_amount + excluded.total_amount,
+                        payment_count = payment_count + 1;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_summary_delete AFTER DELETE ON payments
+                BEGIN
+                    UPDATE payment_monthly_summary
+                    SET total_amount = total_amount - OLD.amount_paid, payment_count = payment_count - 1
+                    WHERE property_id = COALESCE(CAST(OLD.property_id AS INTEGER), 0)
+                      AND period = COALESCE(strftime('%Y-%m', OLD.payment_date), '')
+                      AND payment_category = OLD.payment_category
+                      AND payment_status = OLD.payment_status;
+                    DELETE FROM payment_monthly_summary WHERE payment_count <= 0;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_summary_update
+                AFTER UPDATE OF property_id, payment_date, payment_category, payment_status, amount_paid ON payments
+                BEGIN
+                    UPDATE payment_monthly_summary
+                    SET total_amount = total_amount - OLD.amount_paid, payment_count = payment_count - 1
+                    WHERE property_id = COALESCE(CAST(OLD.property_id AS INTEGER), 0)
+                      AND period = COALESCE(strftime('%Y-%m', OLD.payment_date), '')
+                      AND payment_category = OLD.payment_category
+                      AND payment_status = OLD.payment_status;
+                    INSERT INTO payment_monthly_summary
+                        (property_id, period, payment_category, payment_status, total_amount, payment_count)
+                    VALUES (COALESCE(CAST(NEW.property_id AS INTEGER), 0), COALESCE(strftime('%Y-%m', NEW.payment_date), ''),
+                            NEW.payment_category, NEW.payment_status, NEW.amount_paid, 1)
+                    ON CONFLICT (property_id, period, payment_category, payment_status) DO UPDATE
+                    SET total_amount = total_amount + excluded.total_amount,
+                        payment_count = payment_count + 1;
+                    DELETE FROM payment_monthly_summary WHERE payment_count <= 0;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_expenses_summary_insert AFTER INSERT ON expenses
+                BEGIN
+                    INSERT INTO expense_monthly_summary (property_id, period, total_amount, expense_count)
+                    VALUES (COALESCE(NEW.property_id,
+                                     (SELECT property_id FROM units WHERE unit_id = NEW.unit_id),
+                                     (SELECT property_id FROM blocks WHERE block_id = NEW.block_id), 0),
+                            COALESCE(strftime('%Y-%m', NEW.expense_date), ''), NEW.amount, 1)
+                    ON CONFLICT (property_id, period) DO UPDATE
+                    SET total_amount = total_amount + excluded.total_amount,
+                        expense_count = expense_count + 1;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_expenses_summary_delete AFTER DELETE ON expenses
+                BEGIN
+                    UPDATE expense_monthly_summary
+                    SET total_amount = total_amount - OLD.amount, expense_count = expense_count - 1
+                    WHERE property_id = COALESCE(OLD.property_id,
+                                                 (SELECT property_id FROM units WHERE unit_id = OLD.unit_id),
+                                                 (SELECT property_id FROM blocks WHERE block_id = OLD.block_id), 0)
+                      AND period = COALESCE(strftime('%Y-%m', OLD.expense_date), '');
+                    DELETE FROM expense_monthly_summary WHERE expense_count <= 0;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_expenses_summary_update
+                AFTER UPDATE OF amount, expense_date, unit_id, block_id, property_id ON expenses
+                BEGIN
+                    UPDATE expense_monthly_summary
+                    SET total_amount = total_amount - OLD.amount, expense_count = expense_count - 1
+                    WHERE property_id = COALESCE(OLD.property_id,
+                                                 (SELECT property_id FROM units WHERE unit_id = OLD.unit_id),
+                                                 (SELECT property_id FROM blocks WHERE block_id = OLD.block_id), 0)
+                      AND period = COALESCE(strftime('%Y-%m', OLD.expense_date), '');
+                    INSERT INTO expense_monthly_summary (property_id, period, total_amount, expense_count)
+                    VALUES (COALESCE(NEW.property_id,
+                                     (SELECT property_id FROM units WHERE unit_id = NEW.unit_id),
+                                     (SELECT property_id FROM blocks WHERE block_id = NEW.block_id), 0),
+                            COALESCE(strftime('%Y-%m', NEW.expense_date), ''), NEW.amount, 1)
+                    ON CONFLICT (property_id, period) DO UPDATE
+                    SET total_amount = total_amount + excluded.total_amount,
+                        expense_count = expense_count + 1;
+                    DELETE FROM expense_monthly_summary WHERE expense_count <= 0;
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 19: Create charges and late fee tables
+        // Title: Create Charges, Late Fee Rules and Late Fees Tables
+        // Table Name: charges, late_fee_rules, late_fees
+        // Note: charges are what a tenant owes. Pending/Overdue payments rows entered
+        // from the payments screen are mirrored into charges (source_payment_id) by
+        // triggers, so existing bills keep working. A late fee is itself a charge whose
+        // parent_charge_id points at the overdue charge it was assessed on.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 19,
+            description: "create_charges_and_late_fee_tables",
+            sql: "
+                CREATE TABLE IF NOT EXISTS charges (
+                    charge_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    tenant_id INTEGER NOT NULL,
+                    unit_id INTEGER,
+                    property_id INTEGER,
+                    category TEXT NOT NULL CHECK (category IN ('Rent', 'Utilities', 'Deposit', 'Late Fee', 'Other')),
+                    description TEXT,
+                    amount DECIMAL(10, 2) NOT NULL,
+                    due_date DATE NOT NULL,
+                    status TEXT NOT NULL DEFAULT 'Open' CHECK (status IN ('Open', 'Paid', 'Waived')),
+                    source_payment_id TEXT UNIQUE,          -- legacy pending payments row this mirrors
+                    parent_charge_id INTEGER,               -- originating charge for late fees
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (tenant_id) REFERENCES tenants(tenant_id),
+                    FOREIGN KEY (unit_id) REFERENCES units(unit_id),
+                    FOREIGN KEY (property_id) REFERENCES properties(property_id),
+                    FOREIGN KEY (parent_charge_id) REFERENCES charges(charge_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_charges_tenant ON charges(tenant_id, status);
+                CREATE INDEX IF NOT EXISTS idx_charges_status_due ON charges(status, due_date);
+                CREATE INDEX IF NOT EXISTS idx_charges_parent ON charges(parent_charge_id);
+
+                INSERT INTO charges (tenant_id, unit_id, property_id, category, description, amount, due_date, source_payment_id)
+                SELECT CAST(tenant_id AS INTEGER), CAST(unit_id AS INTEGER), CAST(property_id AS INTEGER),
+                       payment_category, remarks, amount_paid, due_date, payment_id
+                FROM payments
+                WHERE payment_status IN ('Pending', 'Overdue');
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_charge_insert AFTER INSERT ON payments
+                WHEN NEW.payment_status IN ('Pending', 'Overdue')
+                BEGIN
+                    INSERT INTO charges (tenant_id, unit_id, property_id, category, description, amount, due_date, source_payment_id)
+                    VALUES (CAST(NEW.tenant_id AS INTEGER), CAST(NEW.unit_id AS INTEGER), CAST(NEW.property_id AS INTEGER),
+                            NEW.payment_category, NEW.remarks, NEW.amount_paid, NEW.due_date, NEW.payment_id);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_charge_update
+                AFTER UPDATE OF payment_status, amount_paid, due_date ON payments
+                BEGIN
+                    UPDATE charges
+                    SET status = CASE WHEN NEW.payment_status = 'Paid' THEN 'Paid' ELSE 'Open' END,
+                        amount = NEW.amount_paid,
+                        due_date = NEW.due_date,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE source_payment_id = NEW.payment_id AND status <> 'Waived';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_charge_delete AFTER DELETE ON payments
+                BEGIN
+                    DELETE FROM charges WHERE source_payment_id = OLD.payment_id;
+                END;
+
+                CREATE TABLE IF NOT EXISTS late_fee_rules (
+                    rule_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    property_id INTEGER,                    -- NULL: default for properties without a rule
+                    category TEXT NOT NULL DEFAULT 'Rent',  -- charge category the rule applies to
+                    fee_type TEXT NOT NULL CHECK (fee_type IN ('Flat', 'Percentage', 'Daily')),
+                    fee_value DECIMAL(10, 2) NOT NULL,      -- amount, percent of the charge, or amount per day
+                    grace_days INTEGER NOT NULL DEFAULT 0,
+                    max_fee DECIMAL(10, 2),                 -- optional cap as an amount
+                    max_fee_percent REAL,                   -- optional cap as percent of the charge
+                    is_active INTEGER NOT NULL DEFAULT 1,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (property_id) REFERENCES properties(property_id)
+                );
+
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_late_fee_rules_scope
+                    ON late_fee_rules(COALESCE(property_id, 0), category);
+
+                CREATE TABLE IF NOT EXISTS late_fees (
+                    late_fee_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    charge_id INTEGER NOT NULL UNIQUE,      -- the fee charge
+                    source_charge_id INTEGER NOT NULL UNIQUE, -- the overdue charge it was assessed on
+                    rule_id INTEGER,
+                    days_late INTEGER NOT NULL,
+                    assessed_on DATE NOT NULL,              -- last time the amount was (re)computed
+                    waived_at DATETIME,
+                    waived_by TEXT,
+                    waiver_reason TEXT,
+                    FOREIGN KEY (charge_id) REFERENCES charges(charge_id) ON DELETE CASCADE,
+                    FOREIGN KEY (source_charge_id) REFERENCES charges(charge_id) ON DELETE CASCADE,
+                    FOREIGN KEY (rule_id) REFERENCES late_fee_rules(rule_id) ON DELETE SET NULL
+                );
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 20: Payment allocations and tenant credit
+        // Title: Create Payment Allocations and Tenant Credits Tables
+        // Table Name: payment_allocations, tenant_credits, charge_balances (view)
+        // Note: a payment can be split across several charges. Whatever is left over
+        // becomes tenant credit (positive tenant_credits rows); spending credit on a
+        // charge is a negative row carrying that charge_id. Triggers keep
+        // charges.status in step with what has been applied.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 20,
+            description: "create_payment_allocations_and_tenant_credits",
+            sql: "
+                CREATE TABLE IF NOT EXISTS payment_allocations (
+                    allocation_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    payment_id TEXT NOT NULL,
+                    charge_id INTEGER NOT NULL,
+                    amount DECIMAL(10, 2) NOT NULL CHECK (amount > 0),
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (payment_id) REFERENCES payments(payment_id) ON DELETE CASCADE,
+                    FOREIGN KEY (charge_id) REFERENCES charges(charge_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_payment_allocations_payment ON payment_allocations(payment_id);
+                CREATE INDEX IF NOT EXISTS idx_payment_allocations_charge ON payment_allocations(charge_id);
+
+                CREATE TABLE IF NOT EXISTS tenant_credits (
+                    credit_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    tenant_id INTEGER NOT NULL,
+                    amount DECIMAL(10, 2) NOT NULL,         -- positive: credit added, negative: credit used
+                    payment_id TEXT,                        -- overpayment that created the credit
+                    charge_id INTEGER,                      -- charge the credit was spent on
+                    note TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (tenant_id) REFERENCES tenants(tenant_id),
+                    FOREIGN KEY (payment_id) REFERENCES payments(payment_id) ON DELETE CASCADE,
+                    FOREIGN KEY (charge_id) REFERENCES charges(charge_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_tenant_credits_tenant ON tenant_credits(tenant_id);
+                CREATE INDEX IF NOT EXISTS idx_tenant_credits_charge ON tenant_credits(charge_id);
+
+                CREATE VIEW IF NOT EXISTS charge_balances AS
+                SELECT c.charge_id, c.tenant_id, c.unit_id, c.property_id, c.category, c.description,
+                       c.amount, c.due_date, c.status, c.parent_charge_id,
+                       COALESCE(a.allocated, 0) + COALESCE(cr.applied, 0) AS paid,
+                       c.amount - COALESCE(a.allocated, 0) - COALESCE(cr.applied, 0) AS outstanding
+                FROM charges c
+                LEFT JOIN (
+                    SELECT charge_id, SUM(amount) AS allocated FROM payment_allocations GROUP BY charge_id
+                ) a ON a.charge_id = c.charge_id
+                LEFT JOIN (
+                    SELECT charge_id, -SUM(amount) AS applied FROM tenant_credits
+                    WHERE charge_id IS NOT NULL GROUP BY charge_id
+                ) cr ON cr.charge_id = c.charge_id;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payment_allocations_insert AFTER INSERT ON payment_allocations
+                BEGIN
+                    UPDATE charges
+                    SET status = CASE WHEN (SELECT outstanding FROM charge_balances WHERE charge_id = NEW.charge_id) <= 0.005
+                                      THEN 'Paid' ELSE 'Open' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE charge_id = NEW.charge_id AND status <> 'Waived';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payment_allocations_delete AFTER DELETE ON payment_allocations
+                BEGIN
+                    UPDATE charges
+                    SET status = CASE WHEN (SELECT outstanding FROM charge_balances WHERE charge_id = OLD.charge_id) <= 0.005
+                                      THEN 'Paid' ELSE 'Open' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE charge_id = OLD.charge_id AND status <> 'Waived';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_tenant_credits_insert AFTER INSERT ON tenant_credits
+                WHEN NEW.charge_id IS NOT NULL
+                BEGIN
+                    UPDATE charges
+                    SET status = CASE WHEN (SELECT outstanding FROM charge_balances WHERE charge_id = NEW.charge_id) <= 0.005
+                                      THEN 'Paid' ELSE 'Open' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE charge_id = NEW.charge_id AND status <> 'Waived';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_tenant_credits_delete AFTER DELETE ON tenant_credits
+                WHEN OLD.charge_id IS NOT NULL
+                BEGIN
+                    UPDATE charges
+                    SET status = CASE WHEN (SELECT outstanding FROM charge_balances WHERE charge_id = OLD.charge_id) <= 0.005
+                                      THEN 'Paid' ELSE 'Open' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE charge_id = OLD.charge_id AND status <> 'Waived';
+                END;
+
+                -- Editing a legacy pending row must not reopen a charge that allocations already settled.
+                DROP TRIGGER IF EXISTS trg_payments_charge_update;
+                CREATE TRIGGER trg_payments_charge_update
+                AFTER UPDATE OF payment_status, amount_paid, due_date ON payments
+                BEGIN
+                    UPDATE charges
+                    SET amount = NEW.amount_paid,
+                        due_date = NEW.due_date,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE source_payment_id = NEW.payment_id;
+
+                    UPDATE charges
+                    SET status = CASE
+                            WHEN NEW.payment_status = 'Paid' THEN 'Paid'
+                            WHEN (SELECT outstanding FROM charge_balances WHERE charge_id = charges.charge_id) <= 0.005 THEN 'Paid'
+                            ELSE 'Open' END
+                    WHERE source_payment_id = NEW.payment_id AND status <> 'Waived';
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 21: M-Pesa statement imports
+        // Title: Create M-Pesa Imports and Transactions Tables
+        // Table Name: mpesa_imports, mpesa_transactions
+        // Note: one row per incoming statement line with the proposed tenant and a
+        // 0-1 confidence. Accepting a line records it in payments; deleting that
+        // payment puts the line back up for matching.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 21,
+            description: "create_mpesa_import_tables",
+            sql: "
+                CREATE TABLE IF NOT EXISTS mpesa_imports (
+                    import_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    file_name TEXT,
+                    transaction_count INTEGER NOT NULL DEFAULT 0,
+                    imported_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+
+                CREATE TABLE IF NOT EXISTS mpesa_transactions (
+                    transaction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    import_id INTEGER NOT NULL,
+                    transaction_code TEXT NOT NULL,         -- M-Pesa receipt number, e.g. QBC1XYZ123
+                    completed_at DATETIME NOT NULL,
+                    details TEXT,
+                    amount DECIMAL(10, 2) NOT NULL,
+                    phone_number TEXT,                      -- may be masked, e.g. 2547******678
+                    payer_name TEXT,
+                    account_reference TEXT,
+                    tenant_id INTEGER,                      -- proposed, then confirmed, tenant
+                    unit_id INTEGER,
+                    property_id INTEGER,
+                    confidence REAL NOT NULL DEFAULT 0,
+                    match_reason TEXT,
+                    status TEXT NOT NULL DEFAULT 'Unmatched'
+                        CHECK (status IN ('Unmatched', 'Proposed', 'Accepted', 'Ignored', 'Duplicate')),
+                    duplicate_of TEXT,                      -- payment or earlier transaction with the same code
+                    payment_id TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (import_id) REFERENCES mpesa_imports(import_id) ON DELETE CASCADE,
+                    FOREIGN KEY (tenant_id) REFERENCES tenants(tenant_id) ON DELETE SET NULL,
+                    FOREIGN KEY (payment_id) REFERENCES payments(payment_id) ON DELETE SET NULL
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_mpesa_transactions_code ON mpesa_transactions(transaction_code);
+                CREATE INDEX IF NOT EXISTS idx_mpesa_transactions_import ON mpesa_transactions(import_id, status);
+                CREATE INDEX IF NOT EXISTS idx_payments_transaction_reference ON payments(transaction_reference);
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_mpesa_delete BEFORE DELETE ON payments
+                BEGIN
+                    UPDATE mpesa_transactions
+                    SET status = CASE WHEN tenant_id IS NULL THEN 'Unmatched' ELSE 'Proposed' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE payment_id = OLD.payment_id;
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 22: Bank statement reconciliation
+        // Title: Create Bank Statement and Reconciliation Tables
+        // Table Name: bank_statements, bank_statement_lines, bank_line_matches
+        // Note: amounts on statement lines are signed (credits positive). A match ties
+        // part of a line to one payment (credits) or expense (debits); unconfirmed
+        // matches are the matcher's suggestions. Triggers keep the line status in
+        // step with its matches.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 22,
+            description: "create_bank_reconciliation_tables",
+            sql: "
+                CREATE TABLE IF NOT EXISTS bank_statements (
+                    statement_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    account TEXT,
+                    file_name TEXT,
+                    format TEXT NOT NULL CHECK (format IN ('CSV', 'OFX', 'MT940')),
+                    period_start DATE,
+                    period_end DATE,
+                    imported_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+
+                CREATE TABLE IF NOT EXISTS bank_statement_lines (
+                    line_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    statement_id INTEGER NOT NULL,
+                    posted_on DATE NOT NULL,
+                    amount DECIMAL(10, 2) NOT NULL,         -- credits positive, debits negative
+                    description TEXT,
+                    reference TEXT,
+                    check_number TEXT,
+                    external_id TEXT,                       -- OFX FITID or MT940 bank reference
+                    status TEXT NOT NULL DEFAULT 'Unreconciled'
+                        CHECK (status IN ('Unreconciled', 'Suggested', 'Reconciled', 'Ignored')),
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (statement_id) REFERENCES bank_statements(statement_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_bank_statement_lines_statement ON bank_statement_lines(statement_id, status);
+                CREATE INDEX IF NOT EXISTS idx_bank_statement_lines_external ON bank_statement_lines(external_id);
+
+                CREATE TABLE IF NOT EXISTS bank_line_matches (
+                    match_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    line_id INTEGER NOT NULL,
+                    payment_id TEXT,
+                    expense_id INTEGER,
+                    amount DECIMAL(10, 2) NOT NULL CHECK (amount > 0),
+                    confidence REAL NOT NULL DEFAULT 0,
+                    is_confirmed INTEGER NOT NULL DEFAULT 0,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    CHECK ((payment_id IS NULL) <> (expense_id IS NULL)),
+                    FOREIGN KEY (line_id) REFERENCES bank_statement_lines(line_id) ON DELETE CASCADE,
+                    FOREIGN KEY (payment_id) REFERENCES payments(payment_id) ON DELETE CASCADE,
+                    FOREIGN KEY (expense_id) REFERENCES expenses(expense_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_bank_line_matches_line ON bank_line_matches(line_id);
+                CREATE INDEX IF NOT EXISTS idx_bank_line_matches_payment ON bank_line_matches(payment_id);
+                CREATE INDEX IF NOT EXISTS idx_bank_line_matches_expense ON bank_line_matches(expense_id);
+
+                CREATE TRIGGER IF NOT EXISTS trg_bank_line_matches_insert AFTER INSERT ON bank_line_matches
+                BEGIN
+                    UPDATE bank_statement_lines
+                    SET status = CASE
+                            WHEN (SELECT COALESCE(SUM(amount), 0) FROM bank_line_matches
+                                  WHERE line_id = NEW.line_id AND is_confirmed = 1) >= ABS(amount) - 0.005 THEN 'Reconciled'
+                            WHEN EXISTS (SELECT 1 FROM bank_line_matches
+                                         WHERE line_id = NEW.line_id AND is_confirmed = 0) THEN 'Suggested'
+                            ELSE 'Unreconciled' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE line_id = NEW.line_id AND status <> 'Ignored';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_bank_line_matches_delete AFTER DELETE ON bank_line_matches
+                BEGIN
+                    UPDATE bank_statement_lines
+                    SET status = CASE
+                            WHEN (SELECT COALESCE(SUM(amount), 0) FROM bank_line_matches
+                                  WHERE line_id = OLD.line_id AND is_confirmed = 1) >= ABS(amount) - 0.005 THEN 'Reconciled'
+                            WHEN EXISTS (SELECT 1 FROM bank_line_matches
+                                         WHERE line_id = OLD.line_id AND is_confirmed = 0) THEN 'Suggested'
+                            ELSE 'Unreconciled' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE line_id = OLD.line_id AND status <> 'Ignored';
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 23: Application settings and M-Pesa callback imports
+        // Title: Create Settings Table
+        // Table Name: settings, mpesa_imports
+        // Note: settings hold key/value pairs the app reads at startup (e.g. the
+        // C2B listener's port). Callback transactions share a single mpesa_imports
+        // row, told apart from statement uploads by the source column.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 23,
+            description: "create_settings_and_mpesa_import_source",
+            sql: "
+                CREATE TABLE IF NOT EXISTS settings (
+                    key TEXT PRIMARY KEY NOT NULL,
+                    value TEXT NOT NULL,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+
+                ALTER TABLE mpesa_imports ADD COLUMN source TEXT NOT NULL DEFAULT 'Statement'
+                    CHECK (source IN ('Statement', 'Callback'));
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 24: Double-entry general ledger
+        // Title: Create Chart of Accounts and Journal Tables
+        // Table Name: ledger_accounts, ledger_account_map, journal_entries, journal_lines
+        // Note: paid payments and expenses are posted automatically by triggers, so
+        // rows entered from any screen reach the ledger. ledger_account_map picks the
+        // accounts by payment method and payment/expense category (lower-cased); the
+        // '*' row is the fallback. Editing or deleting a posted row never rewrites
+        // its entry: the entry is reversed and, for edits, posted again.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 24,
+            description: "create_general_ledger_tables",
+            sql: "
+                CREATE TABLE IF NOT EXISTS ledger_accounts (
+                    account_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    code TEXT UNIQUE NOT NULL,
+                    name TEXT NOT NULL,
+                    account_type TEXT NOT NULL CHECK (account_type IN ('Asset', 'Liability', 'Equity', 'Income', 'Expense')),
+                    is_active INTEGER NOT NULL DEFAULT 1,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+
+                INSERT OR IGNORE INTO ledger_accounts (code, name, account_type) VALUES
+                    ('1000', 'Cash on Hand', 'Asset'),
+                    ('1010', 'Bank', 'Asset'),
+                    ('1020', 'Mobile Money (M-Pesa)', 'Asset'),
+                    ('1030', 'Undeposited Funds', 'Asset'),
+                    ('2000', 'Tenant Deposits Held', 'Liability'),
+                    ('3000', 'Owner Equity', 'Equity'),
+                    ('4000', 'Rent Income', 'Income'),
+                    ('4100', 'Utilities Income', 'Income'),
+                    ('4900', 'Other Income', 'Income'),
+                    ('5000', 'Maintenance and Repairs', 'Expense'),
+                    ('5100', 'Utilities Expense', 'Expense'),
+                    ('5900', 'Other Expenses', 'Expense');
+
+                CREATE TABLE IF NOT EXISTS ledger_account_map (
+                    source TEXT NOT NULL CHECK (source IN ('PaymentMethod', 'PaymentCategory', 'ExpenseCategory')),
+                    match_value TEXT NOT NULL,              -- lower-cased value, or '*' for anything else
+                    account_id INTEGER NOT NULL,
+                    PRIMARY KEY (source, match_value),
+                    FOREIGN KEY (account_id) REFERENCES ledger_accounts(account_id)
+                );
+
+                INSERT OR IGNORE INTO ledger_account_map (source, match_value, account_id)
+                SELECT m.source, m.match_value, a.account_id
+                FROM (
+                    SELECT 'PaymentMethod' AS source, 'cash' AS match_value, '1000' AS code
+                    UNION ALL SELECT 'PaymentMethod', 'bank transfer', '1010'
+                    UNION ALL SELECT 'PaymentMethod', 'bank', '1010'
+                    UNION ALL SELECT 'PaymentMethod', 'check', '1010'
+                    UNION ALL SELECT 'PaymentMethod', 'cheque', '1010'
+                    UNION ALL SELECT 'PaymentMethod', 'mobile money', '1020'
+                    UNION ALL SELECT 'PaymentMethod', 'm-pesa', '1020'
+                    UNION ALL SELECT 'PaymentMethod', 'mpesa', '1020'
+                    UNION ALL SELECT 'PaymentMethod', '*', '1030'
+                    UNION ALL SELECT 'PaymentCategory', 'rent', '4000'
+                    UNION ALL SELECT 'PaymentCategory', 'utilities', '4100'
+                    UNION ALL SELECT 'PaymentCategory', 'deposit', '2000'
+                    UNION ALL SELECT 'PaymentCategory', '*', '4900'
+                    UNION ALL SELECT 'ExpenseCategory', 'maintenance', '5000'
+                    UNION ALL SELECT 'ExpenseCategory', 'repairs', '5000'
+                    UNION ALL SELECT 'ExpenseCategory', 'utility', '5100'
+                    UNION ALL SELECT 'ExpenseCategory', 'utilities', '5100'
+                    UNION ALL SELECT 'ExpenseCategory', '*', '5900'
+                ) m
+                JOIN ledger_accounts a ON a.code = m.code;
+
+                CREATE TABLE IF NOT EXISTS journal_entries (
+                    entry_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    entry_date DATE NOT NULL,
+                    memo TEXT,
+                    source_type TEXT NOT NULL CHECK (source_type IN ('Payment', 'Expense', 'Manual')),
+                    source_id TEXT,                         -- payment_id or expense_id for automatic postings
+                    property_id INTEGER,
+                    reverses_entry_id INTEGER UNIQUE,       -- set on the entry that cancels another
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (reverses_entry_id) REFERENCES journal_entries(entry_id)
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_journal_entries_source ON journal_entries(source_type, source_id);
+                CREATE INDEX IF NOT EXISTS idx_journal_entries_date ON journal_entries(entry_date);
+
+                CREATE TABLE IF NOT EXISTS journal_lines (
+                    line_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    entry_id INTEGER NOT NULL,
+                    account_id INTEGER NOT NULL,
+                    debit DECIMAL(12, 2) NOT NULL DEFAULT 0,
+                    credit DECIMAL(12, 2) NOT NULL DEFAULT 0,
+                    description TEXT,
+                    CHECK (debit >= 0 AND credit >= 0 AND (debit = 0) <> (credit = 0)),
+                    FOREIGN KEY (entry_id) REFERENCES journal_entries(entry_id) ON DELETE CASCADE,
+                    FOREIGN KEY (account_id) REFERENCES ledger_accounts(account_id)
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_journal_lines_entry ON journal_lines(entry_id);
+                CREATE INDEX IF NOT EXISTS idx_journal_lines_account ON journal_lines(account_id);
+
+                -- What each payment or expense should post right now. Rows that post
+                -- nothing (unpaid or zero amounts) are absent.
+                CREATE VIEW IF NOT EXISTS ledger_postings AS
+                SELECT 'Payment' AS source_type,
+                       p.payment_id AS source_id,
+                       p.payment_date AS entry_date,
+                       p.payment_category || ' payment ' || p.payment_id AS memo,
+                       CAST(p.property_id AS INTEGER) AS property_id,
+                       p.amount_paid AS amount,
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = lower(trim(p.payment_method))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = '*')) AS debit_account_id,
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentCategory' AND match_value = lower(trim(p.payment_category))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentCategory' AND match_value = '*')) AS credit_account_id
+                FROM payments p
+                WHERE p.payment_status = 'Paid' AND p.amount_paid > 0
+                UNION ALL
+                SELECT 'Expense',
+                       CAST(e.expense_id AS TEXT),
+                       e.expense_date,
+                       e.category || ' - ' || e.vendor,
+                       COALESCE(e.property_id, (SELECT property_id FROM units WHERE unit_id = e.unit_id)),
+                       e.amount,
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'ExpenseCategory' AND match_value = lower(trim(e.category))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'ExpenseCategory' AND match_value = '*')),
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = lower(trim(e.payment_method))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = '*'))
+                FROM expenses e
+                WHERE e.amount > 0;
+
+                -- Existing records are posted on their own dates.
+                INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id)
+                SELECT entry_date, memo, source_type, source_id, property_id FROM ledger_postings;
+                INSERT INTO journal_lines (entry_id, account_id, debit, credit)
+                SELECT j.entry_id, p.debit_account_id, p.amount, 0
+                FROM journal_entries j
+                JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                WHERE j.source_type IN ('Payment', 'Expense')
+                  AND j.reverses_entry_id IS NULL
+                  AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id)
+                UNION ALL
+                SELECT j.entry_id, p.credit_account_id, 0, p.amount
+                FROM journal_entries j
+                JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                WHERE j.source_type IN ('Payment', 'Expense')
+                  AND j.reverses_entry_id IS NULL
+                  AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id);
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_ledger_insert AFTER INSERT ON payments
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id)
+                    SELECT entry_date, memo, source_type, source_id, property_id FROM ledger_postings
+                    WHERE source_type = 'Payment' AND source_id = NEW.payment_id;
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit)
+                    SELECT j.entry_id, p.debit_account_id, p.amount, 0
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Payment' AND j.source_id = NEW.payment_id
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id)
+                    UNION ALL
+                    SELECT j.entry_id, p.credit_account_id, 0, p.amount
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Payment' AND j.source_id = NEW.payment_id
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_ledger_update
+                AFTER UPDATE OF payment_status, amount_paid, payment_date, payment_method, payment_category, property_id ON payments
+                WHEN OLD.payment_status IS NOT NEW.payment_status OR OLD.amount_paid IS NOT NEW.amount_paid
+                  OR OLD.payment_date IS NOT NEW.payment_date OR OLD.payment_method IS NOT NEW.payment_method
+                  OR OLD.payment_category IS NOT NEW.payment_category OR OLD.property_id IS NOT NEW.property_id
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id, reverses_entry_id)
+                    SELECT e.entry_date, 'Reversal: ' || COALESCE(e.memo, 'entry ' || e.entry_id),
+                           e.source_type, e.source_id, e.property_id, e.entry_id
+                    FROM journal_entries e
+                    WHERE e.source_type = 'Payment' AND e.source_id = OLD.payment_id AND e.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_entries r WHERE r.reverses_entry_id = e.entry_id);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+                    SELECT r.entry_id, l.account_id, l.credit, l.debit, l.description
+                    FROM journal_entries r
+                    JOIN journal_lines l ON l.entry_id = r.reverses_entry_id
+                    WHERE r.source_type = 'Payment' AND r.source_id = OLD.payment_id
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines x WHERE x.entry_id = r.entry_id);
+
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id)
+                    SELECT entry_date, memo, source_type, source_id, property_id FROM ledger_postings
+                    WHERE source_type = 'Payment' AND source_id = NEW.payment_id;
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit)
+                    SELECT j.entry_id, p.debit_account_id, p.amount, 0
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Payment' AND j.source_id = NEW.payment_id
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id)
+                    UNION ALL
+                    SELECT j.entry_id, p.credit_account_id, 0, p.amount
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Payment' AND j.source_id = NEW.payment_id
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_ledger_delete AFTER DELETE ON payments
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id, reverses_entry_id)
+                    SELECT e.entry_date, 'Reversal: ' || COALESCE(e.memo, 'entry ' || e.entry_id),
+                           e.source_type, e.source_id, e.property_id, e.entry_id
+                    FROM journal_entries e
+                    WHERE e.source_type = 'Payment' AND e.source_id = OLD.payment_id AND e.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_entries r WHERE r.reverses_entry_id = e.entry_id);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+                    SELECT r.entry_id, l.account_id, l.credit, l.debit, l.description
+                    FROM journal_entries r
+                    JOIN journal_lines l ON l.entry_id = r.reverses_entry_id
+                    WHERE r.source_type = 'Payment' AND r.source_id = OLD.payment_id
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines x WHERE x.entry_id = r.entry_id);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_expenses_ledger_insert AFTER INSERT ON expenses
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id)
+                    SELECT entry_date, memo, source_type, source_id, property_id FROM ledger_postings
+                    WHERE source_type = 'Expense' AND source_id = CAST(NEW.expense_id AS TEXT);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit)
+                    SELECT j.entry_id, p.debit_account_id, p.amount, 0
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Expense' AND j.source_id = CAST(NEW.expense_id AS TEXT)
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id)
+                    UNION ALL
+                    SELECT j.entry_id, p.credit_account_id, 0, p.amount
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Expense' AND j.source_id = CAST(NEW.expense_id AS TEXT)
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_expenses_ledger_update
+                AFTER UPDATE OF amount, category, vendor, expense_date, payment_method, property_id, unit_id ON expenses
+                WHEN OLD.amount IS NOT NEW.amount OR OLD.category IS NOT NEW.category OR OLD.vendor IS NOT NEW.vendor
+                  OR OLD.expense_date IS NOT NEW.expense_date OR OLD.payment_method IS NOT NEW.payment_method
+                  OR OLD.property_id IS NOT NEW.property_id OR OLD.unit_id IS NOT NEW.unit_id
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id, reverses_entry_id)
+                    SELECT e.entry_date, 'Reversal: ' || COALESCE(e.memo, 'entry ' || e.entry_id),
+                           e.source_type, e.source_id, e.property_id, e.entry_id
+                    FROM journal_entries e
+                    WHERE e.source_type = 'Expense' AND e.source_id = CAST(OLD.expense_id AS TEXT) AND e.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_entries r WHERE r.reverses_entry_id = e.entry_id);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+                    SELECT r.entry_id, l.account_id, l.credit, l.debit, l.description
+                    FROM journal_entries r
+                    JOIN journal_lines l ON l.entry_id = r.reverses_entry_id
+                    WHERE r.source_type = 'Expense' AND r.source_id = CAST(OLD.expense_id AS TEXT)
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines x WHERE x.entry_id = r.entry_id);
+
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id)
+                    SELECT entry_date, memo, source_type, source_id, property_id FROM ledger_postings
+                    WHERE source_type = 'Expense' AND source_id = CAST(NEW.expense_id AS TEXT);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit)
+                    SELECT j.entry_id, p.debit_account_id, p.amount, 0
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Expense' AND j.source_id = CAST(NEW.expense_id AS TEXT)
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id)
+                    UNION ALL
+                    SELECT j.entry_id, p.credit_account_id, 0, p.amount
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Expense' AND j.source_id = CAST(NEW.expense_id AS TEXT)
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_expenses_ledger_delete AFTER DELETE ON expenses
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id, reverses_entry_id)
+                    SELECT e.entry_date, 'Reversal: ' || COALESCE(e.memo, 'entry ' || e.entry_id),
+                           e.source_type, e.source_id, e.property_id, e.entry_id
+                    FROM journal_entries e
+                    WHERE e.source_type = 'Expense' AND e.source_id = CAST(OLD.expense_id AS TEXT) AND e.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_entries r WHERE r.reverses_entry_id = e.entry_id);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+                    SELECT r.entry_id, l.account_id, l.credit, l.debit, l.description
+                    FROM journal_entries r
+                    JOIN journal_lines l ON l.entry_id = r.reverses_entry_id
+                    WHERE r.source_type = 'Expense' AND r.source_id = CAST(OLD.expense_id AS TEXT)
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines x WHERE x.entry_id = r.entry_id);
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 25: Accounting period close
+        // Title: Create Accounting Periods Table
+        // Table Name: accounting_periods
+        // Note: one row per month that has been closed (or closed and reopened).
+        // Periods close in order, so everything up to ledger_lock.lock_date is
+        // locked. The ledger view and the update/delete triggers are recreated so
+        // that a posting or reversal dated in a closed period lands on the first
+        // open date instead: closed balances never change.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 25,
+            description: "create_accounting_periods",
+            sql: "
+                CREATE TABLE IF NOT EXISTS accounting_periods (
+                    period TEXT PRIMARY KEY NOT NULL,       -- YYYY-MM
+                    status TEXT NOT NULL DEFAULT 'Closed' CHECK (status IN ('Open', 'Closed')),
+                    closed_at DATETIME,
+                    closed_by TEXT,
+                    reopened_at DATETIME,
+                    reopen_reason TEXT
+                );
+
+                -- Last day of the latest closed period; NULL while nothing is closed.
+                CREATE VIEW IF NOT EXISTS ledger_lock AS
+                SELECT date(MAX(period) || '-01', '+1 month', '-1 day') AS lock_date
+                FROM accounting_periods
+                WHERE status = 'Closed';
+
+                -- As in migration 24, but postings dated in a closed period land on the
+                -- first open date instead.
+                DROP VIEW IF EXISTS ledger_postings;
+                CREATE VIEW ledger_postings AS
+                SELECT 'Payment' AS source_type,
+                       p.payment_id AS source_id,
+                       CASE WHEN p.payment_date <= (SELECT lock_date FROM ledger_lock)
+                            THEN MAX(date('now', 'localtime'), date((SELECT lock_date FROM ledger_lock), '+1 day'))
+                            ELSE p.payment_date END AS entry_date,
+                       p.payment_category || ' payment ' || p.payment_id AS memo,
+                       CAST(p.property_id AS INTEGER) AS property_id,
+                       p.amount_paid AS amount,
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = lower(trim(p.payment_method))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = '*')) AS debit_account_id,
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentCategory' AND match_value = lower(trim(p.payment_category))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentCategory' AND match_value = '*')) AS credit_account_id
+                FROM payments p
+                WHERE p.payment_status = 'Paid' AND p.amount_paid > 0
+                UNION ALL
+                SELECT 'Expense',
+                       CAST(e.expense_id AS TEXT),
+                       CASE WHEN e.expense_date <= (SELECT lock_date FROM ledger_lock)
+                            THEN MAX(date('now', 'localtime'), date((SELECT lock_date FROM ledger_lock), '+1 day'))
+                            ELSE e.expense_date END,
+                       e.category || ' - ' || e.vendor,
+                       COALESCE(e.property_id, (SELECT property_id FROM units WHERE unit_id = e.unit_id)),
+                       e.amount,
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'ExpenseCategory' AND match_value = lower(trim(e.category))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'ExpenseCategory' AND match_value = '*')),
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = lower(trim(e.payment_method))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = '*'))
+                FROM expenses e
+                WHERE e.amount > 0;
+
+                DROP TRIGGER IF EXISTS trg_payments_ledger_update;
+                DROP TRIGGER IF EXISTS trg_payments_ledger_delete;
+                DROP TRIGGER IF EXISTS trg_expenses_ledger_update;
+                DROP TRIGGER IF EXISTS trg_expenses_ledger_delete;
+
+                CREATE TRIGGER trg_payments_ledger_update
+                AFTER UPDATE OF payment_status, amount_paid, payment_date, payment_method, payment_category, property_id ON payments
+                WHEN OLD.payment_status IS NOT NEW.payment_status OR OLD.amount_paid IS NOT NEW.amount_paid
+                  OR OLD.payment_date IS NOT NEW.payment_date OR OLD.payment_method IS NOT NEW.payment_method
+                  OR OLD.payment_category IS NOT NEW.payment_category OR OLD.property_id IS NOT NEW.property_id
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id, reverses_entry_id)
+                    SELECT CASE WHEN e.entry_date <= (SELECT lock_date FROM ledger_lock)
+                                THEN MAX(date('now', 'localtime'), date((SELECT lock_date FROM ledger_lock), '+1 day'))
+                                ELSE e.entry_date END,
+                           'Reversal: ' || COALESCE(e.memo, 'entry ' || e.entry_id),
+                           e.source_type, e.source_id, e.property_id, e.entry_id
+                    FROM journal_entries e
+                    WHERE e.source_type = 'Payment' AND e.source_id = OLD.payment_id AND e.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_entries r WHERE r.reverses_entry_id = e.entry_id);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+                    SELECT r.entry_id, l.account_id, l.credit, l.debit, l.description
+                    FROM journal_entries r
+                    JOIN journal_lines l ON l.entry_id = r.reverses_entry_id
+                    WHERE r.source_type = 'Payment' AND r.source_id = OLD.payment_id
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines x WHERE x.entry_id = r.entry_id);
+
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id)
+                    SELECT entry_date, memo, source_type, source_id, property_id FROM ledger_postings
+                    WHERE source_type = 'Payment' AND source_id = NEW.payment_id;
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit)
+                    SELECT j.entry_id, p.debit_account_id, p.amount, 0
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Payment' AND j.source_id = NEW.payment_id
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id)
+                    UNION ALL
+                    SELECT j.entry_id, p.credit_account_id, 0, p.amount
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Payment' AND j.source_id = NEW.payment_id
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id);
+                END;
+
+                CREATE TRIGGER trg_payments_ledger_delete AFTER DELETE ON payments
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id, reverses_entry_id)
+                    SELECT CASE WHEN e.entry_date <= (SELECT lock_date FROM ledger_lock)
+                                THEN MAX(date('now', 'localtime'), date((SELECT lock_date FROM ledger_lock), '+1 day'))
+                                ELSE e.entry_date END,
+                           'Reversal: ' || COALESCE(e.memo, 'entry ' || e.entry_id),
+                           e.source_type, e.source_id, e.property_id, e.entry_id
+                    FROM journal_entries e
+                    WHERE e.source_type = 'Payment' AND e.source_id = OLD.payment_id AND e.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_entries r WHERE r.reverses_entry_id = e.entry_id);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+                    SELECT r.entry_id, l.account_id, l.credit, l.debit, l.description
+                    FROM journal_entries r
+                    JOIN journal_lines l ON l.entry_id = r.reverses_entry_id
+                    WHERE r.source_type = 'Payment' AND r.source_id = OLD.payment_id
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines x WHERE x.entry_id = r.entry_id);
+                END;
+
+                CREATE TRIGGER trg_expenses_ledger_update
+                AFTER UPDATE OF amount, category, vendor, expense_date, payment_method, property_id, unit_id ON expenses
+                WHEN OLD.amount IS NOT NEW.amount OR OLD.category IS NOT NEW.category OR OLD.vendor IS NOT NEW.vendor
+                  OR OLD.expense_date IS NOT NEW.expense_date OR OLD.payment_method IS NOT NEW.payment_method
+                  OR OLD.property_id IS NOT NEW.property_id OR OLD.unit_id IS NOT NEW.unit_id
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id, reverses_entry_id)
+                    SELECT CASE WHEN e.entry_date <= (SELECT lock_date FROM ledger_lock)
+                                THEN MAX(date('now', 'localtime'), date((SELECT lock_date FROM ledger_lock), '+1 day'))
+                                ELSE e.entry_date END,
+                           'Reversal: ' || COALESCE(e.memo, 'entry ' || e.entry_id),
+                           e.source_type, e.source_id, e.property_id, e.entry_id
+                    FROM journal_entries e
+                    WHERE e.source_type = 'Expense' AND e.source_id = CAST(OLD.expense_id AS TEXT) AND e.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_entries r WHERE r.reverses_entry_id = e.entry_id);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+                    SELECT r.entry_id, l.account_id, l.credit, l.debit, l.description
+                    FROM journal_entries r
+                    JOIN journal_lines l ON l.entry_id = r.reverses_entry_id
+                    WHERE r.source_type = 'Expense' AND r.source_id = CAST(OLD.expense_id AS TEXT)
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines x WHERE x.entry_id = r.entry_id);
+
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id)
+                    SELECT entry_date, memo, source_type, source_id, property_id FROM ledger_postings
+                    WHERE source_type = 'Expense' AND source_id = CAST(NEW.expense_id AS TEXT);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit)
+                    SELECT j.entry_id, p.debit_account_id, p.amount, 0
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Expense' AND j.source_id = CAST(NEW.expense_id AS TEXT)
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id)
+                    UNION ALL
+                    SELECT j.entry_id, p.credit_account_id, 0, p.amount
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Expense' AND j.source_id = CAST(NEW.expense_id AS TEXT)
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id);
+                END;
+
+                CREATE TRIGGER trg_expenses_ledger_delete AFTER DELETE ON expenses
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id, reverses_entry_id)
+                    SELECT CASE WHEN e.entry_date <= (SELECT lock_date FROM ledger_lock)
+                                THEN MAX(date('now', 'localtime'), date((SELECT lock_date FROM ledger_lock), '+1 day'))
+                                ELSE e.entry_date END,
+                           'Reversal: ' || COALESCE(e.memo, 'entry ' || e.entry_id),
+                           e.source_type, e.source_id, e.property_id, e.entry_id
+                    FROM journal_entries e
+                    WHERE e.source_type = 'Expense' AND e.source_id = CAST(OLD.expense_id AS TEXT) AND e.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_entries r WHERE r.reverses_entry_id = e.entry_id);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+                    SELECT r.entry_id, l.account_id, l.credit, l.debit, l.description
+                    FROM journal_entries r
+                    JOIN journal_lines l ON l.entry_id = r.reverses_entry_id
+                    WHERE r.source_type = 'Expense' AND r.source_id = CAST(OLD.expense_id AS TEXT)
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines x WHERE x.entry_id = r.entry_id);
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 26: Accounting software exports
+        // Title: Create Accounting Export Tables
+        // Table Name: export_account_map, accounting_exports, accounting_export_rows
+        // Note: export_account_map names the QuickBooks account (or Xero account code)
+        // for a payment method or payment/expense category; values without a row use
+        // the ledger mapping. accounting_export_rows holds every payment and expense
+        // sent to a target, so the next export skips them; deleting an export frees
+        // its rows.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 26,
+            description: "create_accounting_export_tables",
+            sql: "
+                CREATE TABLE IF NOT EXISTS export_account_map (
+                    target TEXT NOT NULL CHECK (target IN ('QuickBooks', 'Xero')),
+                    source TEXT NOT NULL CHECK (source IN ('PaymentMethod', 'PaymentCategory', 'ExpenseCategory')),
+                    match_value TEXT NOT NULL,              -- lower-cased value, or '*' for anything else
+                    external_account TEXT NOT NULL,         -- QuickBooks account name or Xero account code
+                    PRIMARY KEY (target, source, match_value)
+                );
+
+                CREATE TABLE IF NOT EXISTS accounting_exports (
+                    export_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    target TEXT NOT NULL CHECK (target IN ('QuickBooks', 'Xero')),
+                    format TEXT NOT NULL CHECK (format IN ('IIF', 'QBO', 'Xero CSV')),
+                    from_date DATE NOT NULL,
+                    to_date DATE NOT NULL,
+                    file_name TEXT,
+                    row_count INTEGER NOT NULL DEFAULT 0,
+                    exported_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+
+                CREATE TABLE IF NOT EXISTS accounting_export_rows (
+                    export_id INTEGER NOT NULL,
+                    target TEXT NOT NULL,
+                    source_type TEXT NOT NULL CHECK (source_type IN ('Payment', 'Expense')),
+                    source_id TEXT NOT NULL,                -- payment_id or expense_id
+                    UNIQUE (target, source_type, source_id),
+                    FOREIGN KEY (export_id) REFERENCES accounting_exports(export_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_accounting_export_rows_export ON accounting_export_rows(export_id);
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 27: Rental income tax rules and withholdings
+        // Title: Tax Rules
+        // Table Name: properties, tax_rules, tax_withholdings
+        // Note: a property's owner files the tax, so properties gain the owner's name and
+        // tax PIN, plus the jurisdiction whose rules apply (NULL: the tax.jurisdiction
+        // setting). Rules are dated so a rate change keeps earlier months correct; Kenya's
+        // Monthly Rental Income tax went from 10% to 7.5% in 2024. Rent a tenant withheld
+        // and paid to the tax authority is recorded against the payment it reduced.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 27,
+            description: "create_tax_rule_tables",
+            sql: "
+                ALTER TABLE properties ADD COLUMN owner_name TEXT;
+                ALTER TABLE properties ADD COLUMN owner_tax_id TEXT;
+                ALTER TABLE properties ADD COLUMN tax_jurisdiction TEXT;
+
+                CREATE TABLE IF NOT EXISTS tax_rules (
+                    rule_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    jurisdiction TEXT NOT NULL,             -- e.g. 'KE'
+                    tax_name TEXT NOT NULL,
+                    rate REAL NOT NULL CHECK (rate >= 0 AND rate <= 100), -- percent of gross rent
+                    min_annual_gross DECIMAL(12, 2),        -- owners below this are not liable
+                    max_annual_gross DECIMAL(12, 2),        -- owners above this file under other rules
+                    filing_due_day INTEGER NOT NULL DEFAULT 20 CHECK (filing_due_day BETWEEN 1 AND 31), -- day of the following month
+                    effective_from DATE NOT NULL,
+                    effective_to DATE,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (jurisdiction, effective_from)
+                );
+
+                INSERT OR IGNORE INTO tax_rules
+                    (jurisdiction, tax_name, rate, min_annual_gross, max_annual_gross, filing_due_day, effective_from, effective_to)
+                VALUES
+                    ('KE', 'Monthly Rental Income Tax', 10.0, 288000, 15000000, 20, '2021-01-01', '2023-12-31'),
+                    ('KE', 'Monthly Rental Income Tax', 7.5, 288000, 15000000, 20, '2024-01-01', NULL);
+
+                CREATE TABLE IF NOT EXISTS tax_withholdings (
+                    withholding_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    payment_id TEXT NOT NULL UNIQUE,
+                    amount DECIMAL(10, 2) NOT NULL CHECK (amount > 0),
+                    certificate_number TEXT,
+                    withheld_on DATE NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (payment_id) REFERENCES payments(payment_id) ON DELETE CASCADE
+                );
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 28: Utility meters, readings and tariffs
+        // Title: Utility Metering
+        // Table Name: utility_meters, meter_readings, utility_tariffs, utility_tariff_tiers
+        // Note: consumption is the difference from the meter's previous reading (or its
+        // initial reading), worked out when a reading is saved. Readings with negative or
+        // unusually high consumption are Flagged and not billed until accepted or corrected.
+        // Billed readings keep the charge they produced. Tariffs with a NULL property_id are
+        // the default for properties without their own; tiers are priced progressively.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 28,
+            description: "create_utility_metering_tables",
+            sql: "
+                CREATE TABLE IF NOT EXISTS utility_meters (
+                    meter_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    unit_id INTEGER NOT NULL,
+                    utility_type TEXT NOT NULL CHECK (utility_type IN ('Water', 'Electricity')),
+                    meter_number TEXT,
+                    initial_reading REAL NOT NULL DEFAULT 0,
+                    installed_on DATE,
+                    is_active INTEGER NOT NULL DEFAULT 1,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (unit_id) REFERENCES units(unit_id)
+                );
+
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_utility_meters_number
+                    ON utility_meters(utility_type, meter_number) WHERE meter_number IS NOT NULL;
+                CREATE INDEX IF NOT EXISTS idx_utility_meters_unit ON utility_meters(unit_id);
+
+                CREATE TABLE IF NOT EXISTS meter_readings (
+                    reading_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    meter_id INTEGER NOT NULL,
+                    reading_date DATE NOT NULL,
+                    reading REAL NOT NULL,
+                    is_meter_reset INTEGER NOT NULL DEFAULT 0, -- meter replaced or rolled over; counts from zero
+                    consumption REAL NOT NULL DEFAULT 0,
+                    anomaly TEXT CHECK (anomaly IN ('Negative', 'Extreme')),
+                    anomaly_accepted INTEGER NOT NULL DEFAULT 0,
+                    status TEXT NOT NULL DEFAULT 'Pending' CHECK (status IN ('Pending', 'Flagged', 'Billed')),
+                    charge_id INTEGER,                      -- NULL when billed at zero or not yet billed
+                    recorded_by TEXT,
+                    notes TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (meter_id, reading_date),
+                    FOREIGN KEY (meter_id) REFERENCES utility_meters(meter_id) ON DELETE CASCADE,
+                    FOREIGN KEY (charge_id) REFERENCES charges(charge_id) ON DELETE SET NULL
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_meter_readings_status ON meter_readings(status, reading_date);
+
+                CREATE TABLE IF NOT EXISTS utility_tariffs (
+                    tariff_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    property_id INTEGER,                    -- NULL: default for properties without a tariff
+                    utility_type TEXT NOT NULL CHECK (utility_type IN ('Water', 'Electricity')),
+                    tariff_type TEXT NOT NULL CHECK (tariff_type IN ('Flat', 'Tiered')),
+                    rate DECIMAL(10, 4),                    -- price per unit for Flat tariffs
+                    fixed_charge DECIMAL(10, 2) NOT NULL DEFAULT 0, -- standing charge per bill
+                    effective_from DATE NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (property_id) REFERENCES properties(property_id)
+                );
+
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_utility_tariffs_scope
+                    ON utility_tariffs(COALESCE(property_id, 0), utility_type, effective_from);
+
+                CREATE TABLE IF NOT EXISTS utility_tariff_tiers (
+                    tariff_id INTEGER NOT NULL,
+                    up_to REAL,                             -- upper bound of the tier; NULL for the last
+                    rate DECIMAL(10, 4) NOT NULL,
+                    FOREIGN KEY (tariff_id) REFERENCES utility_tariffs(tariff_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_utility_tariff_tiers_tariff ON utility_tariff_tiers(tariff_id);
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 29: Shared expense allocation
+        // Title: Expense Allocations
+        // Table Name: units, expense_allocations, expense_allocation_lines
+        // Note: splits one expense (e.g. a building water bill) across units. Units gain a
+        // floor area for square-footage splits. A line's charge_id is the recharge raised
+        // for the tenant leasing the unit on the expense date, if the cost was passed on.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 29,
+            description: "create_expense_allocation_tables",
+            sql: "
+                ALTER TABLE units ADD COLUMN square_feet REAL;
+
+                CREATE TABLE IF NOT EXISTS expense_allocations (
+                    allocation_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    expense_id INTEGER NOT NULL UNIQUE,
+                    method TEXT NOT NULL CHECK (method IN ('Equal', 'SquareFootage', 'Bedrooms', 'Occupancy')),
+                    recharge_percent REAL NOT NULL DEFAULT 0 CHECK (recharge_percent BETWEEN 0 AND 100),
+                    created_by TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (expense_id) REFERENCES expenses(expense_id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS expense_allocation_lines (
+                    line_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    allocation_id INTEGER NOT NULL,
+                    unit_id INTEGER NOT NULL,
+                    basis REAL NOT NULL,                    -- square feet, bedrooms, or 1 per unit
+                    amount DECIMAL(10, 2) NOT NULL,
+                    tenant_id INTEGER,
+                    charge_id INTEGER,
+                    FOREIGN KEY (allocation_id) REFERENCES expense_allocations(allocation_id) ON DELETE CASCADE,
+                    FOREIGN KEY (unit_id) REFERENCES units(unit_id),
+                    FOREIGN KEY (tenant_id) REFERENCES tenants(tenant_id),
+                    FOREIGN KEY (charge_id) REFERENCES charges(charge_id) ON DELETE SET NULL
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_expense_allocation_lines_allocation
+                    ON expense_allocation_lines(allocation_id);
+                CREATE INDEX IF NOT EXISTS idx_expense_allocation_lines_unit
+                    ON expense_allocation_lines(unit_id);
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 30: Recurring expenses
+        // Title: Recurring Expense Templates
+        // Table Name: recurring_expenses, recurring_expense_occurrences
+        // Note: a template repeats from start_date by frequency; next_due_date is the first
+        // occurrence not yet posted or skipped. An occurrence row is written when one is
+        // skipped or adjusted ahead of time (Scheduled with an amount), and when the
+        // scheduler posts it to expenses.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 30,
+            description: "create_recurring_expense_tables",
+            sql: "
+                CREATE TABLE IF NOT EXISTS recurring_expenses (
+                    template_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    vendor TEXT NOT NULL,
+                    amount DECIMAL(10, 2) NOT NULL CHECK (amount > 0),
+                    category TEXT NOT NULL,
+                    description TEXT,
+                    unit_id INTEGER,
+                    block_id INTEGER,
+                    property_id INTEGER,
+                    payment_method TEXT NOT NULL,
+                    frequency TEXT NOT NULL CHECK (frequency IN ('Weekly', 'Monthly', 'Quarterly', 'Yearly')),
+                    start_date DATE NOT NULL,
+                    end_date DATE,
+                    next_due_date DATE NOT NULL,
+                    is_active INTEGER NOT NULL DEFAULT 1,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (unit_id) REFERENCES units(unit_id),
+                    FOREIGN KEY (block_id) REFERENCES blocks(block_id),
+                    FOREIGN KEY (property_id) REFERENCES properties(property_id)
+                );
+
+                CREATE TABLE IF NOT EXISTS recurring_expense_occurrences (
+                    occurrence_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    template_id INTEGER NOT NULL,
+                    due_date DATE NOT NULL,
+                    status TEXT NOT NULL CHECK (status IN ('Scheduled', 'Skipped', 'Posted')),
+                    amount DECIMAL(10, 2),                  -- adjusted amount; NULL uses the template's
+                    note TEXT,
+                    expense_id INTEGER,                     -- set once posted
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (template_id, due_date),
+                    FOREIGN KEY (template_id) REFERENCES recurring_expenses(template_id) ON DELETE CASCADE,
+                    FOREIGN KEY (expense_id) REFERENCES expenses(expense_id) ON DELETE SET NULL
+                );
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 31: Vendors and vendor bills
+        // Title: Vendor Registry And Accounts Payable
+        // Table Name: vendors, vendor_aliases, vendor_bills, vendor_bill_payments
+        // Note: existing expenses.vendor strings are folded into one vendor per name_key
+        // (lowercased, trimmed, without dots or commas), named after its most used spelling.
+        // expenses.vendor stays as entered; triggers keep expenses.vendor_id in step with it,
+        // creating the vendor when the name is new. vendor_aliases maps the keys of vendors
+        // merged into another. A bill is paid by expenses linked to it.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 31,
+            description: "create_vendor_tables",
+            sql: "
+                CREATE TABLE IF NOT EXISTS vendors (
+                    vendor_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    name_key TEXT NOT NULL UNIQUE,
+                    contact_person TEXT,
+                    phone_number TEXT,
+                    email TEXT,
+                    address TEXT,
+                    tax_id TEXT,                            -- e.g. KRA PIN
+                    payment_method TEXT,                    -- preferred, e.g. Bank, M-Pesa
+                    bank_name TEXT,
+                    bank_account TEXT,
+                    mpesa_number TEXT,                      -- till, paybill or phone number
+                    payment_terms_days INTEGER,             -- default bill due date, from the bill date
+                    notes TEXT,
+                    is_active INTEGER NOT NULL DEFAULT 1,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+
+                CREATE TABLE IF NOT EXISTS vendor_aliases (
+                    name_key TEXT PRIMARY KEY,
+                    vendor_id INTEGER NOT NULL,
+                    FOREIGN KEY (vendor_id) REFERENCES vendors(vendor_id) ON DELETE CASCADE
+                );
+
+                ALTER TABLE expenses ADD COLUMN vendor_id INTEGER REFERENCES vendors(vendor_id);
+
+                INSERT INTO vendors (name, name_key)
+                SELECT name, name_key FROM (
+                    SELECT name, name_key,
+                           ROW_NUMBER() OVER (PARTITION BY name_key ORDER BY uses DESC, last_used DESC, name) AS pick
+                    FROM (
+                        SELECT trim(vendor) AS name, lower(trim(replace(replace(replace(replace(vendor, '.', ''), ',', ''), '  ', ' '), '  ', ' '))) AS name_key,
+                               COUNT(*) AS uses, MAX(expense_date) AS last_used
+                        FROM expenses
+                        WHERE trim(vendor) <> ''
+                        GROUP BY trim(vendor)
+                    )
+                )
+                WHERE pick = 1;
+
+                UPDATE expenses
+                SET vendor_id = (SELECT vendor_id FROM vendors v WHERE v.name_key = lower(trim(replace(replace(replace(replace(expenses.vendor, '.', ''), ',', ''), '  ', ' '), '  ', ' '))));
+
+                CREATE INDEX IF NOT EXISTS idx_expenses_vendor ON expenses(vendor_id);
+
+                CREATE TRIGGER IF NOT EXISTS trg_expenses_vendor_insert AFTER INSERT ON expenses
+                WHEN NEW.vendor_id IS NULL AND trim(NEW.vendor) <> ''
+                BEGIN
+                    INSERT OR IGNORE INTO vendors (name, name_key)
+                    SELECT trim(NEW.vendor), lower(trim(replace(replace(replace(replace(NEW.vendor, '.', ''), ',', ''), '  ', ' '), '  ', ' ')))
+                    WHERE NOT EXISTS (SELECT 1 FROM vendor_aliases WHERE name_key = lower(trim(replace(replace(replace(replace(NEW.vendor, '.', ''), ',', ''), '  ', ' '), '  ', ' '))));
+                    UPDATE expenses
+                    SET vendor_id = COALESCE((SELECT vendor_id FROM vendor_aliases WHERE name_key = lower(trim(replace(replace(replace(replace(NEW.vendor, '.', ''), ',', ''), '  ', ' '), '  ', ' ')))),
+                                             (SELECT vendor_id FROM vendors WHERE name_key = lower(trim(replace(replace(replace(replace(NEW.vendor, '.', ''), ',', ''), '  ', ' '), '  ', ' ')))))
+                    WHERE expense_id = NEW.expense_id;
+                END;
+
+                -- Only when the caller did not pick the vendor itself.
+                CREATE TRIGGER IF NOT EXISTS trg_expenses_vendor_update AFTER UPDATE OF vendor ON expenses
+                WHEN OLD.vendor IS NOT NEW.vendor AND NEW.vendor_id IS OLD.vendor_id AND trim(NEW.vendor) <> ''
+                BEGIN
+                    INSERT OR IGNORE INTO vendors (name, name_key)
+                    SELECT trim(NEW.vendor), lower(trim(replace(replace(replace(replace(NEW.vendor, '.', ''), ',', ''), '  ', ' '), '  ', ' ')))
+                    WHERE NOT EXISTS (SELECT 1 FROM vendor_aliases WHERE name_key = lower(trim(replace(replace(replace(replace(NEW.vendor, '.', ''), ',', ''), '  ', ' '), '  ', ' '))));
+                    UPDATE expenses
+                    SET vendor_id = COALESCE((SELECT vendor_id FROM vendor_aliases WHERE name_key = lower(trim(replace(replace(replace(replace(NEW.vendor, '.', ''), ',', ''), '  ', ' '), '  ', ' ')))),
+                                             (SELECT vendor_id FROM vendors WHERE name_key = lower(trim(replace(replace(replace(replace(NEW.vendor, '.', ''), ',', ''), '  ', ' '), '  ', ' ')))))
+                    WHERE expense_id = NEW.expense_id;
+                END;
+
+                CREATE TABLE IF NOT EXISTS vendor_bills (
+                    bill_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    vendor_id INTEGER NOT NULL,
+                    bill_number TEXT,                       -- the vendor's invoice number
+                    bill_date DATE NOT NULL,
+                    due_date DATE NOT NULL,
+                    amount DECIMAL(10, 2) NOT NULL CHECK (amount > 0),
+                    category TEXT NOT NULL,                 -- expense category its payments post to
+                    description TEXT,
+                    property_id INTEGER,
+                    unit_id INTEGER,
+                    status TEXT NOT NULL DEFAULT 'Open' CHECK (status IN ('Open', 'Void')),
+                    void_reason TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (vendor_id, bill_number),
+                    FOREIGN KEY (vendor_id) REFERENCES vendors(vendor_id),
+                    FOREIGN KEY (property_id) REFERENCES properties(property_id),
+                    FOREIGN KEY (unit_id) REFERENCES units(unit_id)
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_vendor_bills_due ON vendor_bills(status, due_date);
+
+                CREATE TABLE IF NOT EXISTS vendor_bill_payments (
+                    bill_id INTEGER NOT NULL,
+                    expense_id INTEGER NOT NULL UNIQUE,     -- the payment; its amount counts against the bill
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (bill_id) REFERENCES vendor_bills(bill_id),
+                    FOREIGN KEY (expense_id) REFERENCES expenses(expense_id) ON DELETE CASCADE
+                );
+
+                CREATE VIEW IF NOT EXISTS vendor_bill_balances AS
+                SELECT b.bill_id, b.vendor_id, b.bill_number, b.bill_date, b.due_date, b.amount, b.category,
+                       b.description, b.property_id, b.unit_id, b.status, b.void_reason,
+                       COALESCE(p.paid, 0) AS paid,
+                       CASE WHEN b.status = 'Void' THEN 0 ELSE b.amount - COALESCE(p.paid, 0) END AS outstanding
+                FROM vendor_bills b
+                LEFT JOIN (
+                    SELECT bp.bill_id, SUM(e.amount) AS paid
+                    FROM vendor_bill_payments bp
+                    JOIN expenses e ON e.expense_id = bp.expense_id
+                    GROUP BY bp.bill_id
+                ) p ON p.bill_id = b.bill_id;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 32: Expense approvals
+        // Title: Expense Approval Workflow
+        // Table Name: expense_approvers, expense_approval_decisions
+        // Note: expenses gain approval_status and entered_by (paid_by stays as entered).
+        // New expenses are classified by trigger: above expenses.approval_threshold
+        // (default 50000) they wait as 'Pending Approval', otherwise they are 'Approved';
+        // an approved expense edited above the threshold and its old amount waits again.
+        // Only approved expenses post to the ledger and count in expense_monthly_summary,
+        // so the ledger view, the expense ledger insert/update triggers and the summary
+        // triggers are recreated. Existing expenses are approved.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 32,
+            description: "create_expense_approvals",
+            sql: "
+                ALTER TABLE expenses ADD COLUMN approval_status TEXT
+                    CHECK (approval_status IN ('Pending Approval', 'Approved', 'Rejected'));
+                ALTER TABLE expenses ADD COLUMN entered_by TEXT;
+                UPDATE expenses SET approval_status = 'Approved';
+                CREATE INDEX IF NOT EXISTS idx_expenses_approval ON expenses(approval_status);
+
+                CREATE TABLE IF NOT EXISTS expense_approvers (
+                    approver_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE,
+                    role TEXT NOT NULL CHECK (role IN ('Owner', 'PropertyManager', 'Accountant')),
+                    manager_id INTEGER,                     -- a property manager approves for their properties
+                    approval_limit DECIMAL(10, 2),          -- NULL: no limit
+                    is_active INTEGER NOT NULL DEFAULT 1,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (manager_id) REFERENCES managers(manager_id)
+                );
+
+                CREATE TABLE IF NOT EXISTS expense_approval_decisions (
+                    decision_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    expense_id INTEGER NOT NULL,
+                    approver_id INTEGER,                    -- NULL when the expense was resubmitted
+                    decision TEXT NOT NULL CHECK (decision IN ('Approved', 'Rejected', 'Resubmitted')),
+                    comment TEXT,
+                    decided_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (expense_id) REFERENCES expenses(expense_id) ON DELETE CASCADE,
+                    FOREIGN KEY (approver_id) REFERENCES expense_approvers(approver_id)
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_expense_approval_decisions ON expense_approval_decisions(expense_id);
+
+                CREATE TRIGGER IF NOT EXISTS trg_expenses_approval_insert AFTER INSERT ON expenses
+                WHEN NEW.approval_status IS NULL
+                BEGIN
+                    UPDATE expenses
+                    SET approval_status = CASE WHEN NEW.amount > COALESCE((SELECT CAST(value AS REAL) FROM settings WHERE key = 'expenses.approval_threshold'), 50000)
+                                               THEN 'Pending Approval' ELSE 'Approved' END
+                    WHERE expense_id = NEW.expense_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_expenses_approval_amount AFTER UPDATE OF amount ON expenses
+                WHEN NEW.approval_status = 'Approved' AND NEW.amount > OLD.amount
+                  AND NEW.amount > COALESCE((SELECT CAST(value AS REAL) FROM settings WHERE key = 'expenses.approval_threshold'), 50000)
+                BEGIN
+                    UPDATE expenses SET approval_status = 'Pending Approval' WHERE expense_id = NEW.expense_id;
+                END;
+
+                -- As in migration 25, but only approved expenses post.
+                DROP VIEW IF EXISTS ledger_postings;
+                CREATE VIEW ledger_postings AS
+                SELECT 'Payment' AS source_type,
+                       p.payment_id AS source_id,
+                       CASE WHEN p.payment_date <= (SELECT lock_date FROM ledger_lock)
+                            THEN MAX(date('now', 'localtime'), date((SELECT lock_date FROM ledger_lock), '+1 day'))
+                            ELSE p.payment_date END AS entry_date,
+                       p.payment_category || ' payment ' || p.payment_id AS memo,
+                       CAST(p.property_id AS INTEGER) AS property_id,
+                       p.amount_paid AS amount,
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = lower(trim(p.payment_method))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = '*')) AS debit_account_id,
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentCategory' AND match_value = lower(trim(p.payment_category))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentCategory' AND match_value = '*')) AS credit_account_id
+                FROM payments p
+                WHERE p.payment_status = 'Paid' AND p.amount_paid > 0
+                UNION ALL
+                SELECT 'Expense',
+                       CAST(e.expense_id AS TEXT),
+                       CASE WHEN e.expense_date <= (SELECT lock_date FROM ledger_lock)
+                            THEN MAX(date('now', 'localtime'), date((SELECT lock_date FROM ledger_lock), '+1 day'))
+                            ELSE e.expense_date END,
+                       e.category || ' - ' || e.vendor,
+                       COALESCE(e.property_id, (SELECT property_id FROM units WHERE unit_id = e.unit_id)),
+                       e.amount,
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'ExpenseCategory' AND match_value = lower(trim(e.category))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'ExpenseCategory' AND match_value = '*')),
+                       COALESCE(
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = lower(trim(e.payment_method))),
+                           (SELECT account_id FROM ledger_account_map
+                            WHERE source = 'PaymentMethod' AND match_value = '*'))
+                FROM expenses e
+                WHERE e.amount > 0 AND e.approval_status = 'Approved';
+
+                DROP TRIGGER IF EXISTS trg_expenses_ledger_insert;
+                CREATE TRIGGER trg_expenses_ledger_insert AFTER INSERT ON expenses
+                -- Others post once the insert trigger above classifies them.
+                WHEN NEW.approval_status = 'Approved'
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id)
+                    SELECT entry_date, memo, source_type, source_id, property_id FROM ledger_postings
+                    WHERE source_type = 'Expense' AND source_id = CAST(NEW.expense_id AS TEXT);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit)
+                    SELECT j.entry_id, p.debit_account_id, p.amount, 0
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Expense' AND j.source_id = CAST(NEW.expense_id AS TEXT)
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id)
+                    UNION ALL
+                    SELECT j.entry_id, p.credit_account_id, 0, p.amount
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Expense' AND j.source_id = CAST(NEW.expense_id AS TEXT)
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id);
+                END;
+
+                DROP TRIGGER IF EXISTS trg_expenses_ledger_update;
+                CREATE TRIGGER trg_expenses_ledger_update
+                AFTER UPDATE OF amount, category, vendor, expense_date, payment_method, property_id, unit_id,
+                                approval_status ON expenses
+                WHEN OLD.amount IS NOT NEW.amount OR OLD.category IS NOT NEW.category OR OLD.vendor IS NOT NEW.vendor
+                  OR OLD.expense_date IS NOT NEW.expense_date OR OLD.payment_method IS NOT NEW.payment_method
+                  OR OLD.property_id IS NOT NEW.property_id OR OLD.unit_id IS NOT NEW.unit_id
+                  OR OLD.approval_status IS NOT NEW.approval_status
+                BEGIN
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id, reverses_entry_id)
+                    SELECT CASE WHEN e.entry_date <= (SELECT lock_date FROM ledger_lock)
+                                THEN MAX(date('now', 'localtime'), date((SELECT lock_date FROM ledger_lock), '+1 day'))
+                                ELSE e.entry_date END,
+                           'Reversal: ' || COALESCE(e.memo, 'entry ' || e.entry_id),
+                           e.source_type, e.source_id, e.property_id, e.entry_id
+                    FROM journal_entries e
+                    WHERE e.source_type = 'Expense' AND e.source_id = CAST(OLD.expense_id AS TEXT) AND e.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_entries r WHERE r.reverses_entry_id = e.entry_id);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit, description)
+                    SELECT r.entry_id, l.account_id, l.credit, l.debit, l.description
+                    FROM journal_entries r
+                    JOIN journal_lines l ON l.entry_id = r.reverses_entry_id
+                    WHERE r.source_type = 'Expense' AND r.source_id = CAST(OLD.expense_id AS TEXT)
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines x WHERE x.entry_id = r.entry_id);
+
+                    INSERT INTO journal_entries (entry_date, memo, source_type, source_id, property_id)
+                    SELECT entry_date, memo, source_type, source_id, property_id FROM ledger_postings
+                    WHERE source_type = 'Expense' AND source_id = CAST(NEW.expense_id AS TEXT);
+                    INSERT INTO journal_lines (entry_id, account_id, debit, credit)
+                    SELECT j.entry_id, p.debit_account_id, p.amount, 0
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Expense' AND j.source_id = CAST(NEW.expense_id AS TEXT)
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id)
+                    UNION ALL
+                    SELECT j.entry_id, p.credit_account_id, 0, p.amount
+                    FROM journal_entries j
+                    JOIN ledger_postings p ON p.source_type = j.source_type AND p.source_id = j.source_id
+                    WHERE j.source_type = 'Expense' AND j.source_id = CAST(NEW.expense_id AS TEXT)
+                      AND j.reverses_entry_id IS NULL
+                      AND NOT EXISTS (SELECT 1 FROM journal_lines l WHERE l.entry_id = j.entry_id);
+                END;
+
+                DROP TRIGGER IF EXISTS trg_expenses_summary_insert;
+                DROP TRIGGER IF EXISTS trg_expenses_summary_delete;
+                DROP TRIGGER IF EXISTS trg_expenses_summary_update;
+
+                CREATE TRIGGER trg_expenses_summary_insert AFTER INSERT ON expenses
+                WHEN NEW.approval_status = 'Approved'
+                BEGIN
+                    INSERT INTO expense_monthly_summary (property_id, period, total_amount, expense_count)
+                    VALUES (COALESCE(NEW.property_id,
+                                   (SELECT property_id FROM units WHERE unit_id = NEW.unit_id),
+                                   (SELECT property_id FROM blocks WHERE block_id = NEW.block_id), 0),
+                            COALESCE(strftime('%Y-%m', NEW.expense_date), ''), NEW.amount, 1)
+                    ON CONFLICT (property_id, period) DO UPDATE
+                    SET total_amount = total_amount + excluded.total_amount,
+                        expense_count = expense_count + 1;
+                END;
+
+                CREATE TRIGGER trg_expenses_summary_delete AFTER DELETE ON expenses
+                WHEN OLD.approval_status = 'Approved'
+                BEGIN
+                    UPDATE expense_monthly_summary
+                    SET total_amount = total_amount - OLD.amount, expense_count = expense_count - 1
+                    WHERE property_id = COALESCE(OLD.property_id,
+                                   (SELECT property_id FROM units WHERE unit_id = OLD.unit_id),
+                                   (SELECT property_id FROM blocks WHERE block_id = OLD.block_id), 0)
+                      AND period = COALESCE(strftime('%Y-%m', OLD.expense_date), '');
+                    DELETE FROM expense_monthly_summary WHERE expense_count <= 0;
+                END;
+
+                CREATE TRIGGER trg_expenses_summary_update
+                AFTER UPDATE OF amount, expense_date, unit_id, block_id, property_id, approval_status ON expenses
+                WHEN OLD.approval_status = 'Approved' OR NEW.approval_status = 'Approved'
+                BEGIN
+                    UPDATE expense_monthly_summary
+                    SET total_amount = total_amount - OLD.amount, expense_count = expense_count - 1
+                    WHERE OLD.approval_status = 'Approved'
+                      AND property_id = COALESCE(OLD.property_id,
+                                   (SELECT property_id FROM units WHERE unit_id = OLD.unit_id),
+                                   (SELECT property_id FROM blocks WHERE block_id = OLD.block_id), 0)
+                      AND period = COALESCE(strftime('%Y-%m', OLD.expense_date), '');
+                    INSERT INTO expense_monthly_summary (property_id, period, total_amount, expense_count)
+                    SELECT COALESCE(NEW.property_id,
+                                   (SELECT property_id FROM units WHERE unit_id = NEW.unit_id),
+                                   (SELECT property_id FROM blocks WHERE block_id = NEW.block_id), 0),
+                           COALESCE(strftime('%Y-%m', NEW.expense_date), ''), NEW.amount, 1
+                    WHERE NEW.approval_status = 'Approved'
+                    ON CONFLICT (property_id, period) DO UPDATE
+                    SET total_amount = total_amount + excluded.total_amount,
+                        expense_count = expense_count + 1;
+                    DELETE FROM expense_monthly_summary WHERE expense_count <= 0;
+                END;
+
+                -- As in migration 31, but rejected payments do not count.
+                DROP VIEW IF EXISTS vendor_bill_balances;
+                CREATE VIEW vendor_bill_balances AS
+                SELECT b.bill_id, b.vendor_id, b.bill_number, b.bill_date, b.due_date, b.amount, b.category,
+                       b.description, b.property_id, b.unit_id, b.status, b.void_reason,
+                       COALESCE(p.paid, 0) AS paid,
+                       CASE WHEN b.status = 'Void' THEN 0 ELSE b.amount - COALESCE(p.paid, 0) END AS outstanding
+                FROM vendor_bills b
+                LEFT JOIN (
+                    SELECT bp.bill_id, SUM(e.amount) AS paid
+                    FROM vendor_bill_payments bp
+                    JOIN expenses e ON e.expense_id = bp.expense_id
+                    WHERE e.approval_status <> 'Rejected'
+                    GROUP BY bp.bill_id
+                ) p ON p.bill_id = b.bill_id;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 33: Maintenance work orders
+        // Title: Work Orders On Complaints
+        // Table Name: work_orders, work_order_costs
+        // Note: one work order per complaint, created by trigger for new complaints and
+        // backfilled for existing ones. due_at is the SLA target (created_at plus the
+        // priority's maintenance.sla_hours.* setting); started_at and resolved_at follow
+        // complaints.status. Each cost is an expense; deleting the expense drops the cost.
+        // Times are UTC like complaints.created_at.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 33,
+            description: "create_work_orders",
+            sql: "
+                CREATE TABLE IF NOT EXISTS work_orders (
+                    work_order_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    complaint_id INTEGER NOT NULL UNIQUE,
+                    priority TEXT NOT NULL DEFAULT 'Medium' CHECK (priority IN ('Low', 'Medium', 'High', 'Emergency')),
+                    category TEXT NOT NULL DEFAULT 'General'
+                        CHECK (category IN ('Plumbing', 'Electrical', 'Carpentry', 'Painting', 'Roofing',
+                                            'Appliance', 'Pest Control', 'Cleaning', 'Security', 'General')),
+                    assigned_vendor_id INTEGER,
+                    assigned_staff TEXT,
+                    scheduled_date DATE,
+                    due_at DATETIME,                        -- SLA target
+                    started_at DATETIME,
+                    resolved_at DATETIME,
+                    notes TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    CHECK (assigned_vendor_id IS NULL OR assigned_staff IS NULL),
+                    FOREIGN KEY (complaint_id) REFERENCES complaints(complaint_id) ON DELETE CASCADE,
+                    FOREIGN KEY (assigned_vendor_id) REFERENCES vendors(vendor_id)
+                );
+
+                INSERT INTO work_orders (complaint_id, due_at, resolved_at, created_at)
+                SELECT complaint_id, datetime(created_at, '+168 hours'),
+                       CASE WHEN status = 'Resolved' THEN updated_at END, created_at
+                FROM complaints;
+
+                CREATE TRIGGER IF NOT EXISTS trg_complaints_work_order AFTER INSERT ON complaints
+                BEGIN
+                    INSERT OR IGNORE INTO work_orders (complaint_id, due_at, created_at)
+                    VALUES (NEW.complaint_id,
+                            datetime(COALESCE(NEW.created_at, datetime('now')), '+' ||
+                                     COALESCE((SELECT CAST(value AS INTEGER) FROM settings
+                                               WHERE key = 'maintenance.sla_hours.Medium'), 168) || ' hours'),
+                            COALESCE(NEW.created_at, datetime('now')));
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_complaints_work_order_status AFTER UPDATE OF status ON complaints
+                WHEN OLD.status IS NOT NEW.status
+                BEGIN
+                    UPDATE work_orders
+                    SET started_at = CASE WHEN NEW.status = 'Open' THEN NULL
+                                          ELSE COALESCE(started_at, datetime('now')) END,
+                        resolved_at = CASE WHEN NEW.status = 'Resolved' THEN COALESCE(resolved_at, datetime('now')) END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE complaint_id = NEW.complaint_id;
+                END;
+
+                CREATE TABLE IF NOT EXISTS work_order_costs (
+                    cost_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    work_order_id INTEGER NOT NULL,
+                    cost_type TEXT NOT NULL CHECK (cost_type IN ('Labour', 'Material')),
+                    description TEXT NOT NULL,
+                    quantity REAL,
+                    unit_cost DECIMAL(10, 2),
+                    expense_id INTEGER NOT NULL UNIQUE,     -- the amount lives on the expense
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (work_order_id) REFERENCES work_orders(work_order_id),
+                    FOREIGN KEY (expense_id) REFERENCES expenses(expense_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_work_order_costs ON work_order_costs(work_order_id);
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 34: Preventive maintenance and inspections
+        // Title: Maintenance Schedules And Inspections
+        // Table Name: maintenance_schedules, maintenance_checklist_items, inspections, inspection_results
+        // Note: a schedule covers a property, one of its blocks or one unit. The daily job
+        // adds a task lead_days before next_due_date; recording an inspection against the
+        // schedule moves next_due_date on. Results keep the checklist item's text so editing
+        // a checklist leaves past inspections as they were. Inspections (not preventive
+        // visits) keep properties.last_inspection at the latest inspected_on.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 34,
+            description: "create_maintenance_schedules_and_inspections",
+            sql: "
+                CREATE TABLE IF NOT EXISTS maintenance_schedules (
+                    schedule_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    kind TEXT NOT NULL CHECK (kind IN ('Inspection', 'Preventive')),
+                    category TEXT NOT NULL DEFAULT 'General'
+                        CHECK (category IN ('Plumbing', 'Electrical', 'Carpentry', 'Painting', 'Roofing',
+                                            'Appliance', 'Pest Control', 'Cleaning', 'Security', 'General')),
+                    property_id INTEGER NOT NULL,
+                    block_id INTEGER,
+                    unit_id INTEGER,
+                    frequency TEXT NOT NULL CHECK (frequency IN ('Weekly', 'Monthly', 'Quarterly', 'Yearly')),
+                    start_date DATE NOT NULL,
+                    next_due_date DATE NOT NULL,            -- first visit not yet recorded
+                    lead_days INTEGER NOT NULL DEFAULT 7 CHECK (lead_days >= 0),
+                    assigned_vendor_id INTEGER,
+                    assigned_staff TEXT,
+                    notes TEXT,
+                    is_active BOOLEAN NOT NULL DEFAULT 1,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (property_id) REFERENCES properties(property_id),
+                    FOREIGN KEY (block_id) REFERENCES blocks(block_id),
+                    FOREIGN KEY (unit_id) REFERENCES units(unit_id),
+                    FOREIGN KEY (assigned_vendor_id) REFERENCES vendors(vendor_id)
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_maintenance_schedules_due ON maintenance_schedules(is_active, next_due_date);
+
+                CREATE TABLE IF NOT EXISTS maintenance_checklist_items (
+                    item_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    schedule_id INTEGER NOT NULL,
+                    position INTEGER NOT NULL,
+                    item TEXT NOT NULL,
+                    FOREIGN KEY (schedule_id) REFERENCES maintenance_schedules(schedule_id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS inspections (
+                    inspection_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    schedule_id INTEGER,
+                    kind TEXT NOT NULL DEFAULT 'Inspection' CHECK (kind IN ('Inspection', 'Preventive')),
+                    property_id INTEGER NOT NULL,
+                    block_id INTEGER,
+                    unit_id INTEGER,
+                    inspected_on DATE NOT NULL,
+                    inspector TEXT,
+                    notes TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (schedule_id) REFERENCES maintenance_schedules(schedule_id) ON DELETE SET NULL,
+                    FOREIGN KEY (property_id) REFERENCES properties(property_id),
+                    FOREIGN KEY (block_id) REFERENCES blocks(block_id),
+                    FOREIGN KEY (unit_id) REFERENCES units(unit_id)
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_inspections_property ON inspections(property_id, inspected_on);
+
+                CREATE TABLE IF NOT EXISTS inspection_results (
+                    result_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    inspection_id INTEGER NOT NULL,
+                    item TEXT NOT NULL,
+                    result TEXT NOT NULL CHECK (result IN ('Pass', 'Fail', 'N/A')),
+                    notes TEXT,
+                    FOREIGN KEY (inspection_id) REFERENCES inspections(inspection_id) ON DELETE CASCADE
+                );
+
+                CREATE TRIGGER IF NOT EXISTS trg_inspections_last_inspection AFTER INSERT ON inspections
+                WHEN NEW.kind = 'Inspection'
+                BEGIN
+                    UPDATE properties
+                    SET last_inspection = NEW.inspected_on, updated_at = CURRENT_TIMESTAMP
+                    WHERE property_id = NEW.property_id
+                      AND (last_inspection IS NULL OR last_inspection < NEW.inspected_on);
+                END;
+
+                -- Falls back to the latest remaining inspection; a hand-entered date stays
+                -- when none remain.
+                CREATE TRIGGER IF NOT EXISTS trg_inspections_last_inspection_delete AFTER DELETE ON inspections
+                WHEN OLD.kind = 'Inspection'
+                BEGIN
+                    UPDATE properties
+                    SET last_inspection = COALESCE((SELECT MAX(inspected_on) FROM inspections
+                                                    WHERE property_id = OLD.property_id AND kind = 'Inspection'),
+                                                   last_inspection),
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE property_id = OLD.property_id AND last_inspection = OLD.inspected_on;
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 35: Attachments
+        // Title: Attachments On Units, Leases, Complaints And Inspections
+        // Table Name: attachments
+        // Note: files live in the app data directory under attachments/, named by their
+        // SHA-256, so the same photo attached twice is stored once. stored_name and
+        // thumbnail_name are relative to that directory. Deleting the record a file is
+        // attached to deletes the attachment rows; files left unreferenced are pruned by
+        // the app.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 35,
+            description: "create_attachments",
+            sql: "
+                CREATE TABLE IF NOT EXISTS attachments (
+                    attachment_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    owner_type TEXT NOT NULL CHECK (owner_type IN ('Unit', 'Lease', 'Complaint', 'Inspection')),
+                    owner_id INTEGER NOT NULL,
+                    stage TEXT CHECK (stage IN ('Move-in', 'Move-out', 'Routine')),  -- condition reports
+                    file_name TEXT NOT NULL,                -- as uploaded
+                    content_type TEXT NOT NULL,
+                    size_bytes INTEGER NOT NULL,
+                    sha256 TEXT NOT NULL,
+                    stored_name TEXT NOT NULL,
+                    thumbnail_name TEXT,
+                    caption TEXT,
+                    uploaded_by TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (owner_type, owner_id, sha256)
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_attachments_owner ON attachments(owner_type, owner_id);
+                CREATE INDEX IF NOT EXISTS idx_attachments_sha256 ON attachments(sha256);
+
+                CREATE TRIGGER IF NOT EXISTS trg_units_attachments_delete AFTER DELETE ON units
+                BEGIN
+                    DELETE FROM attachments WHERE owner_type = 'Unit' AND owner_id = OLD.unit_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_leases_attachments_delete AFTER DELETE ON leases
+                BEGIN
+                    DELETE FROM attachments WHERE owner_type = 'Lease' AND owner_id = OLD.lease_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_complaints_attachments_delete AFTER DELETE ON complaints
+                BEGIN
+                    DELETE FROM attachments WHERE owner_type = 'Complaint' AND owner_id = OLD.complaint_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_inspections_attachments_delete AFTER DELETE ON inspections
+                BEGIN
+                    DELETE FROM attachments WHERE owner_type = 'Inspection' AND owner_id = OLD.inspection_id;
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 36: Documents
+        // Title: Tenant, Lease And Property Documents
+        // Table Name: attachments, documents, document_search
+        // Note: a document is an attachment with a type, reference number and expiry, so
+        // attachments is rebuilt to also belong to tenants and properties. document_search
+        // is an FTS5 index keyed by document_id holding the title, reference number and
+        // text extracted from PDFs by the app. The daily job creates renewal tasks
+        // reminder_days before expires_on.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 36,
+            description: "create_documents",
+            sql: "
+                CREATE TABLE attachments_rebuilt (
+                    attachment_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    owner_type TEXT NOT NULL
+                        CHECK (owner_type IN ('Unit', 'Lease', 'Complaint', 'Inspection', 'Tenant', 'Property')),
+                    owner_id INTEGER NOT NULL,
+                    stage TEXT CHECK (stage IN ('Move-in', 'Move-out', 'Routine')),  -- condition reports
+                    file_name TEXT NOT NULL,                -- as uploaded
+                    content_type TEXT NOT NULL,
+                    size_bytes INTEGER NOT NULL,
+                    sha256 TEXT NOT NULL,
+                    stored_name TEXT NOT NULL,
+                    thumbnail_name TEXT,
+                    caption TEXT,
+                    uploaded_by TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (owner_type, owner_id, sha256)
+                );
+                INSERT INTO attachments_rebuilt SELECT * FROM attachments;
+
+                -- The rename fails while triggers still name the dropped table.
+                DROP TRIGGER IF EXISTS trg_units_attachments_delete;
+                DROP TRIGGER IF EXISTS trg_leases_attachments_delete;
+                DROP TRIGGER IF EXISTS trg_complaints_attachments_delete;
+                DROP TRIGGER IF EXISTS trg_inspections_attachments_delete;
+                DROP TABLE attachments;
+                ALTER TABLE attachments_rebuilt RENAME TO attachments;
+
+                CREATE INDEX IF NOT EXISTS idx_attachments_owner ON attachments(owner_type, owner_id);
+                CREATE INDEX IF NOT EXISTS idx_attachments_sha256 ON attachments(sha256);
+
+                CREATE TRIGGER IF NOT EXISTS trg_units_attachments_delete AFTER DELETE ON units
+                BEGIN
+                    DELETE FROM attachments WHERE owner_type = 'Unit' AND owner_id = OLD.unit_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_leases_attachments_delete AFTER DELETE ON leases
+                BEGIN
+                    DELETE FROM attachments WHERE owner_type = 'Lease' AND owner_id = OLD.lease_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_complaints_attachments_delete AFTER DELETE ON complaints
+                BEGIN
+                    DELETE FROM attachments WHERE owner_type = 'Complaint' AND owner_id = OLD.complaint_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_inspections_attachments_delete AFTER DELETE ON inspections
+                BEGIN
+                    DELETE FROM attachments WHERE owner_type = 'Inspection' AND owner_id = OLD.inspection_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_tenants_attachments_delete AFTER DELETE ON tenants
+                BEGIN
+                    DELETE FROM attachments WHERE owner_type = 'Tenant' AND owner_id = OLD.tenant_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_properties_attachments_delete AFTER DELETE ON properties
+                BEGIN
+                    DELETE FROM attachments WHERE owner_type = 'Property' AND owner_id = OLD.property_id;
+                END;
+
+                CREATE TABLE IF NOT EXISTS documents (
+                    document_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    attachment_id INTEGER NOT NULL UNIQUE,
+                    document_type TEXT NOT NULL
+                        CHECK (document_type IN ('Lease Agreement', 'National ID', 'Passport', 'KRA PIN',
+                                                 'Insurance Certificate', 'Title Deed', 'Permit', 'Other')),
+                    title TEXT NOT NULL,
+                    reference_number TEXT,                  -- ID number, PIN, policy number
+                    issued_on DATE,
+                    expires_on DATE,
+                    reminder_days INTEGER NOT NULL DEFAULT 30 CHECK (reminder_days >= 0),
+                    notes TEXT,
+                    text_extracted BOOLEAN NOT NULL DEFAULT 0,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (attachment_id) REFERENCES attachments(attachment_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_documents_expiry ON documents(expires_on);
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS document_search USING fts5(
+                    title, reference_number, content, tokenize = 'porter unicode61'
+                );
+
+                CREATE TRIGGER IF NOT EXISTS trg_documents_search_delete AFTER DELETE ON documents
+                BEGIN
+                    DELETE FROM document_search WHERE rowid = OLD.document_id;
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 37: Letter Templates
+        // Title: Lease, Notice And Rent Increase Templates
+        // Table Name: document_templates, document_template_versions, generated_documents
+        // Note: template bodies are never updated; saving a changed body adds a version, and
+        // generated_documents records the version each letter came from and when it was
+        // signed. Default templates are seeded as version 1 and their bodies start at
+        // column 0 so the letters have no stray indentation.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 37,
+            description: "create_document_templates",
+            sql: "
+                CREATE TABLE IF NOT EXISTS document_templates (
+                    template_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    kind TEXT NOT NULL CHECK (kind IN ('Lease', 'Notice to Vacate', 'Rent Increase')),
+                    name TEXT NOT NULL,
+                    is_active INTEGER NOT NULL DEFAULT 1,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (kind, name)
+                );
+
+                CREATE TABLE IF NOT EXISTS document_template_versions (
+                    version_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    template_id INTEGER NOT NULL,
+                    version INTEGER NOT NULL,
+                    body TEXT NOT NULL,
+                    notes TEXT,
+                    created_by TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (template_id, version),
+                    FOREIGN KEY (template_id) REFERENCES document_templates(template_id) ON DELETE CASCADE
+                );
+
+                CREATE TRIGGER IF NOT EXISTS trg_template_versions_immutable
+                BEFORE UPDATE ON document_template_versions
+                BEGIN
+                    SELECT RAISE(ABORT, 'template versions cannot be changed; save a new version');
+                END;
+
+                CREATE TABLE IF NOT EXISTS generated_documents (
+                    generated_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    version_id INTEGER NOT NULL,
+                    lease_id INTEGER NOT NULL,
+                    format TEXT NOT NULL CHECK (format IN ('pdf', 'docx')),
+                    path TEXT NOT NULL,
+                    sha256 TEXT NOT NULL,
+                    generated_by TEXT,
+                    generated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    signed_on DATE,
+                    FOREIGN KEY (version_id) REFERENCES document_template_versions(version_id) ON DELETE RESTRICT,
+                    FOREIGN KEY (lease_id) REFERENCES leases(lease_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_generated_documents_lease ON generated_documents(lease_id);
+
+                INSERT INTO document_templates (kind, name) VALUES
+                    ('Lease', 'Standard Tenancy Agreement'),
+                    ('Notice to Vacate', 'Notice to Vacate'),
+                    ('Rent Increase', 'Rent Increase Letter');
+
+                INSERT INTO document_template_versions (template_id, version, body, notes)
+                SELECT template_id, 1,
+                       CASE kind
+                           WHEN 'Lease' THEN '# Tenancy Agreement
+
+This agreement is made on {{today}} between {{landlord.name}} (the Landlord) and {{tenant.name}}, ID number {{tenant.id_number}} (the Tenant).
+
+# 1. Premises
+
+The Landlord lets to the Tenant unit {{unit.number}}{{#if block.name}}, block {{block.name}}{{/if}}, {{property.name}}, {{property.address}} (the Premises).
+
+# 2. Term
+
+The tenancy runs for {{lease.term_months}} months from {{lease.start_date}} to {{lease.end_date}}.
+
+# 3. Rent and deposit
+
+The rent is KES {{lease.rent}} a month, payable in advance on or before the 5th day of each month. The Tenant has paid a deposit of KES {{lease.deposit}}, refundable at the end of the tenancy less the cost of any damage beyond fair wear and tear and any unpaid rent or charges.
+
+# 4. Tenant''s obligations
+
+The Tenant shall keep the Premises clean and in good repair, shall not sublet or assign the Premises without the Landlord''s written consent, shall not make alterations without consent, and shall give one month''s written notice before vacating.
+
+# 5. Landlord''s obligations
+
+The Landlord shall keep the structure and common areas in good repair and allow the Tenant quiet enjoyment of the Premises while the rent is paid and the terms of this agreement are kept.
+
+# 6. Contact
+
+Questions about this tenancy may be directed to {{manager.name}} on {{manager.phone}}.
+
+Signed by the Landlord: ____________________  Date: ____________
+
+Signed by the Tenant: ____________________  Date: ____________
+'
+                           WHEN 'Notice to Vacate' THEN '# Notice to Vacate
+
+{{today}}
+
+To: {{tenant.name}}
+Unit {{unit.number}}, {{property.name}}
+{{property.address}}
+
+Dear {{tenant.name}},
+
+This letter gives you notice to vacate unit {{unit.number}} at {{property.name}} on or before {{notice.vacate_date}}.
+{{#if notice.reason}}
+
+Reason: {{notice.reason}}
+{{/if}}
+
+Please hand over the keys and arrange a move-out inspection with {{manager.name}} on {{manager.phone}}. Your deposit of KES {{lease.deposit}} will be refunded after the inspection, less any deductions allowed under your tenancy agreement.
+
+Yours faithfully,
+
+{{landlord.name}}
+'
+                           ELSE '# Notice of Rent Increase
+
+{{today}}
+
+To: {{tenant.name}}
+Unit {{unit.number}}, {{property.name}}
+
+Dear {{tenant.name}},
+
+We write to inform you that the rent for unit {{unit.number}} will change from KES {{lease.rent}} to KES {{increase.new_rent}} a month, an increase of KES {{increase.amount}} ({{increase.percent}}%), with effect from {{increase.effective_date}}.
+
+All other terms of your tenancy remain the same. If you have any questions, please contact {{manager.name}} on {{manager.phone}}.
+
+Yours faithfully,
+
+{{landlord.name}}
+'
+                       END,
+                       'Default template'
+                FROM document_templates;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 38: SMS Outbox
+        // Title: SMS Reminders, Receipts And Opt-Outs
+        // Table Name: notification_opt_outs, sms_outbox
+        // Note: every message is written to sms_outbox before it is sent, and a worker sends
+        // queued rows with retries. dedupe_key stops a reminder or receipt from being queued
+        // twice. Messages to opted-out tenants or without a usable number are kept as Skipped
+        // so the outbox shows why nothing went out.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 38,
+            description: "create_sms_outbox",
+            sql: "
+                CREATE TABLE IF NOT EXISTS notification_opt_outs (
+                    tenant_id INTEGER NOT NULL,
+                    channel TEXT NOT NULL,
+                    reason TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (tenant_id, channel),
+                    FOREIGN KEY (tenant_id) REFERENCES tenants(tenant_id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS sms_outbox (
+                    message_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    tenant_id INTEGER,
+                    phone_number TEXT NOT NULL,
+                    kind TEXT NOT NULL CHECK (kind IN ('Rent Due', 'Overdue', 'Receipt', 'Custom')),
+                    body TEXT NOT NULL,
+                    dedupe_key TEXT UNIQUE,
+                    status TEXT NOT NULL DEFAULT 'Queued'
+                        CHECK (status IN ('Queued', 'Sent', 'Delivered', 'Failed', 'Skipped')),
+                    provider TEXT,
+                    provider_message_id TEXT,
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    last_error TEXT,
+                    cost TEXT,
+                    next_attempt_at DATETIME,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    sent_at DATETIME,
+                    delivered_at DATETIME,
+                    FOREIGN KEY (tenant_id) REFERENCES tenants(tenant_id) ON DELETE SET NULL
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_sms_outbox_status ON sms_outbox(status, next_attempt_at);
+                CREATE INDEX IF NOT EXISTS idx_sms_outbox_provider_id ON sms_outbox(provider_message_id);
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 39: Email Outbox
+        // Title: Statements, Receipts And Manager Digests By Email
+        // Table Name: email_outbox, email_attachments
+        // Note: same lifecycle as sms_outbox without a delivered state, since SMTP only
+        // reports acceptance. A message goes to a tenant or a manager; attachments are
+        // stored with it so a retry sends exactly what was queued.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 39,
+            description: "create_email_outbox",
+            sql: "
+                CREATE TABLE IF NOT EXISTS email_outbox (
+                    email_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    tenant_id INTEGER,
+                    manager_id INTEGER,
+                    to_address TEXT NOT NULL,
+                    kind TEXT NOT NULL CHECK (kind IN ('Statement', 'Receipt', 'Digest', 'Custom')),
+                    subject TEXT NOT NULL,
+                    body TEXT NOT NULL,
+                    dedupe_key TEXT UNIQUE,
+                    status TEXT NOT NULL DEFAULT 'Queued'
+                        CHECK (status IN ('Queued', 'Sent', 'Failed', 'Skipped')),
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    last_error TEXT,
+                    next_attempt_at DATETIME,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    sent_at DATETIME,
+                    FOREIGN KEY (tenant_id) REFERENCES tenants(tenant_id) ON DELETE SET NULL,
+                    FOREIGN KEY (manager_id) REFERENCES managers(manager_id) ON DELETE SET NULL
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_email_outbox_status ON email_outbox(status, next_attempt_at);
+
+                CREATE TABLE IF NOT EXISTS email_attachments (
+                    attachment_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    email_id INTEGER NOT NULL,
+                    file_name TEXT NOT NULL,
+                    content_type TEXT NOT NULL,
+                    content BLOB NOT NULL,
+                    FOREIGN KEY (email_id) REFERENCES email_outbox(email_id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_email_attachments_email ON email_attachments(email_id);
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 40: Broadcasts
+        // Title: Bulk Messages To Tenants
+        // Table Name: broadcasts, broadcast_recipients
+        // Note: the audience filters are kept as sent. Each recipient row points at the
+        // sms_outbox or email_outbox copy it was queued as, which carries the delivery
+        // status; unit_number is copied so the log reads the same after a move-out.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 40,
+            description: "create_broadcasts",
+            sql: "
+                CREATE TABLE IF NOT EXISTS broadcasts (
+                    broadcast_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    subject TEXT,
+                    body TEXT NOT NULL,
+                    channels TEXT NOT NULL,
+                    property_id INTEGER,
+                    block_id INTEGER,
+                    unit_status TEXT,
+                    min_balance DECIMAL(10, 2),
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (property_id) REFERENCES properties(property_id) ON DELETE SET NULL,
+                    FOREIGN KEY (block_id) REFERENCES blocks(block_id) ON DELETE SET NULL
+                );
+
+                CREATE TABLE IF NOT EXISTS broadcast_recipients (
+                    recipient_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    broadcast_id INTEGER NOT NULL,
+                    tenant_id INTEGER,
+                    unit_number TEXT,
+                    channel TEXT NOT NULL CHECK (channel IN ('sms', 'email')),
+                    sms_message_id INTEGER,
+                    email_id INTEGER,
+                    FOREIGN KEY (broadcast_id) REFERENCES broadcasts(broadcast_id) ON DELETE CASCADE,
+                    FOREIGN KEY (tenant_id) REFERENCES tenants(tenant_id) ON DELETE SET NULL,
+                    FOREIGN KEY (sms_message_id) REFERENCES sms_outbox(message_id) ON DELETE SET NULL,
+                    FOREIGN KEY (email_id) REFERENCES email_outbox(email_id) ON DELETE SET NULL
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_broadcast_recipients_broadcast ON broadcast_recipients(broadcast_id);
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 41: Stored charge balances
+        // Title: Keep Paid Totals On Charges
+        // Table Name: charges, charge_balances (view)
+        // Note: charges.paid is what allocations and spent credit have covered, kept in
+        // step by the triggers that already set charges.status. charge_balances becomes a
+        // plain projection of charges, so dashboard and report queries on open charges are
+        // served by the status and due date indexes instead of aggregating every allocation.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 41,
+            description: "store_charge_paid_totals",
+            sql: "
+                ALTER TABLE charges ADD COLUMN paid DECIMAL(10, 2) NOT NULL DEFAULT 0;
+
+                UPDATE charges
+                SET paid = COALESCE((SELECT SUM(amount) FROM payment_allocations a
+                                     WHERE a.charge_id = charges.charge_id), 0)
+                         - COALESCE((SELECT SUM(amount) FROM tenant_credits cr
+                                     WHERE cr.charge_id = charges.charge_id), 0);
+
+                DROP VIEW IF EXISTS charge_balances;
+                CREATE VIEW charge_balances AS
+                SELECT charge_id, tenant_id, unit_id, property_id, category, description,
+                       amount, due_date, status, parent_charge_id, paid, amount - paid AS outstanding
+                FROM charges;
+
+                DROP TRIGGER IF EXISTS trg_payment_allocations_insert;
+                CREATE TRIGGER trg_payment_allocations_insert AFTER INSERT ON payment_allocations
+                BEGIN
+                    UPDATE charges
+                    SET paid = paid + NEW.amount,
+                        status = CASE WHEN status = 'Waived' THEN 'Waived'
+                                      WHEN amount - (paid + NEW.amount) <= 0.005 THEN 'Paid' ELSE 'Open' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE charge_id = NEW.charge_id;
+                END;
+
+                DROP TRIGGER IF EXISTS trg_payment_allocations_delete;
+                CREATE TRIGGER trg_payment_allocations_delete AFTER DELETE ON payment_allocations
+                BEGIN
+                    UPDATE charges
+                    SET paid = paid - OLD.amount,
+                        status = CASE WHEN status = 'Waived' THEN 'Waived'
+                                      WHEN amount - (paid - OLD.amount) <= 0.005 THEN 'Paid' ELSE 'Open' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE charge_id = OLD.charge_id;
+                END;
+
+                DROP TRIGGER IF EXISTS trg_tenant_credits_insert;
+                CREATE TRIGGER trg_tenant_credits_insert AFTER INSERT ON tenant_credits
+                WHEN NEW.charge_id IS NOT NULL
+                BEGIN
+                    UPDATE charges
+                    SET paid = paid - NEW.amount,
+                        status = CASE WHEN status = 'Waived' THEN 'Waived'
+                                      WHEN amount - (paid - NEW.amount) <= 0.005 THEN 'Paid' ELSE 'Open' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE charge_id = NEW.charge_id;
+                END;
+
+                DROP TRIGGER IF EXISTS trg_tenant_credits_delete;
+                CREATE TRIGGER trg_tenant_credits_delete AFTER DELETE ON tenant_credits
+                WHEN OLD.charge_id IS NOT NULL
+                BEGIN
+                    UPDATE charges
+                    SET paid = paid + OLD.amount,
+                        status = CASE WHEN status = 'Waived' THEN 'Waived'
+                                      WHEN amount - (paid + OLD.amount) <= 0.005 THEN 'Paid' ELSE 'Open' END,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE charge_id = OLD.charge_id;
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 42: Background job log
+        // Title: Record Background Job Failures
+        // Table Name: job_log
+        // Note: the daily jobs and the C2B listener run with no window to report to, so
+        // failures are written here for the app to show. Rows older than 90 days are
+        // pruned by the daily run.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 42,
+            description: "create_job_log",
+            sql: "
+                CREATE TABLE IF NOT EXISTS job_log (
+                    log_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    job TEXT NOT NULL,                      -- e.g. late_fees, c2b
+                    message TEXT NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_job_log_created ON job_log(created_at);
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 43: Pending payments settled by allocation
+        // Title: Guard Payment Status Changes
+        // Table Name: payments, charges
+        // Note: a Pending or Overdue payments row is a bill, mirrored as a charge. Once
+        // another payment has been allocated to that charge, the charge is the record
+        // of what was paid, so marking the bill Paid as well would count the money twice
+        // and is refused. The other way, a Paid row with allocations or credit of its own
+        // cannot be marked unpaid; one without becomes a bill again and gets its charge.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 43,
+            description: "guard_payment_status_changes",
+            sql: "
+                CREATE TRIGGER IF NOT EXISTS trg_payments_settled_guard
+                BEFORE UPDATE OF payment_status ON payments
+                WHEN NEW.payment_status = 'Paid' AND OLD.payment_status <> 'Paid'
+                  AND EXISTS (SELECT 1 FROM charges WHERE source_payment_id = NEW.payment_id AND paid > 0.005)
+                BEGIN
+                    SELECT RAISE(ABORT, 'this bill has payments allocated to it; record any balance as a new payment instead of marking it Paid');
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_unpaid_guard
+                BEFORE UPDATE OF payment_status ON payments
+                WHEN OLD.payment_status = 'Paid' AND NEW.payment_status <> 'Paid'
+                  AND (EXISTS (SELECT 1 FROM payment_allocations WHERE payment_id = NEW.payment_id)
+                       OR EXISTS (SELECT 1 FROM tenant_credits WHERE payment_id = NEW.payment_id))
+                BEGIN
+                    SELECT RAISE(ABORT, 'this payment has been allocated to charges; delete it instead of marking it unpaid');
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS trg_payments_charge_reopen
+                AFTER UPDATE OF payment_status ON payments
+                WHEN NEW.payment_status IN ('Pending', 'Overdue')
+                BEGIN
+                    INSERT OR IGNORE INTO charges (tenant_id, unit_id, property_id, category, description, amount, due_date, source_payment_id)
+                    VALUES (CAST(NEW.tenant_id AS INTEGER), CAST(NEW.unit_id AS INTEGER), CAST(NEW.property_id AS INTEGER),
+                            NEW.payment_category, NEW.remarks, NEW.amount_paid, NEW.due_date, NEW.payment_id);
+                END;
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 44: One live row per M-Pesa transaction code
+        // Title: Unique M-Pesa Transaction Codes
+        // Table Name: mpesa_transactions
+        // Note: the duplicate check on import and on C2B callbacks reads before it
+        // writes, so two callbacks for the same code arriving together could both be
+        // recorded. Any such rows already stored become duplicates of the earliest one;
+        // their payment link is dropped so deleting the extra payment (found by its
+        // transaction reference) cannot bring them back.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 44,
+            description: "unique_mpesa_transaction_codes",
+            sql: "
+                UPDATE mpesa_transactions
+                SET status = 'Duplicate',
+                    duplicate_of = 'transaction ' || (
+                        SELECT MIN(o.transaction_id) FROM mpesa_transactions o
+                        WHERE o.transaction_code = mpesa_transactions.transaction_code
+                          AND o.status <> 'Duplicate'
+                    ),
+                    payment_id = NULL,
+                    updated_at = CURRENT_TIMESTAMP
+                WHERE status <> 'Duplicate'
+                  AND transaction_id > (
+                      SELECT MIN(o.transaction_id) FROM mpesa_transactions o
+                      WHERE o.transaction_code = mpesa_transactions.transaction_code
+                        AND o.status <> 'Duplicate'
+                  );
+
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_mpesa_transactions_code_unique
+                ON mpesa_transactions(transaction_code) WHERE status <> 'Duplicate';
+            ",
+            kind: MigrationKind::Up,
+        },
+        // ---------------------------------------------------------------------
+        // Migration 45: Only approved bill payments count
+        // Title: Recreate Vendor Bill Balances
+        // Table Name: vendor_bill_balances
+        // Note: migration 32 left payments waiting for approval counting against the bill.
+        // Like the ledger and the expense summary, paid now only counts approved payments;
+        // those still waiting are shown as pending so the bill is not paid twice meanwhile.
+        // ---------------------------------------------------------------------
+        Migration {
+            version: 45,
+            description: "count_approved_bill_payments",
+            sql: "
+                DROP VIEW IF EXISTS vendor_bill_balances;
+                CREATE VIEW vendor_bill_balances AS
+                SELECT b.bill_id, b.vendor_id, b.bill_number, b.bill_date, b.due_date, b.amount, b.category,
+                       b.description, b.property_id, b.unit_id, b.status, b.void_reason,
+                       COALESCE(p.paid, 0) AS paid,
+                       COALESCE(p.pending, 0) AS pending,
+                       CASE WHEN b.status = 'Void' THEN 0 ELSE b.amount - COALESCE(p.paid, 0) END AS outstanding
+                FROM vendor_bills b
+                LEFT JOIN (
+                    SELECT bp.bill_id,
+                           SUM(CASE WHEN e.approval_status = 'Approved' THEN e.amount ELSE 0 END) AS paid,
+                           SUM(CASE WHEN e.approval_status = 'Pending Approval' THEN e.amount ELSE 0 END) AS pending
+                    FROM vendor_bill_payments bp
+                    JOIN expenses e ON e.expense_id = bp.expense_id
+                    GROUP BY bp.bill_id
+                ) p ON p.bill_id = b.bill_id;
+            ",
+            kind: MigrationKind::Up,
+        },
 ];
+    let schema_version = migrations.iter().map(|m| m.version).max().unwrap_or_default();
     tauri::Builder::default()
         .plugin(tauri_plugin_sql::Builder::new().build())
         .plugin(
             SqlBuilder::default() // Use our aliased Builder
-                .add_migrations("sqlite:productionv1.db", migrations) // 'test4.db' is our database file
+                .add_migrations(db::DB_URL, migrations) // 'test4.db' is our database file
                 .build(),
         )
         .plugin(tauri_plugin_opener::init())
+        .setup(move |app| {
+            // Background workers wait for the plugin to reach this version.
+            app.manage(db::SchemaVersion(schema_version));
+            jobs::start(app.handle().clone());
+            c2b::start(app.handle().clone());
+            notifications::start(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             // greet,
             // get_mock_units,
@@ -391,10 +2936,173 @@ pub fn run() {
             // get_all_payments,
             // get_expense_categories,
             // get_all_expenses,
-            // get_stats_cards,
+            stats::get_stats_cards,
             // get_recent_activities,
             // get_upcoming_tasks,
             // get_building_blocks
+            reports::rent_roll::get_rent_roll,
+            reports::rent_roll::export_rent_roll,
+            reports::aging::get_arrears_aging,
+            reports::aging::get_collections_worklist,
+            reports::aging::create_collection_tasks,
+            billing::charges::get_tenant_charges,
+            billing::late_fees::get_late_fee_rules,
+            billing::late_fees::save_late_fee_rule,
+            billing::late_fees::delete_late_fee_rule,
+            billing::late_fees::get_late_fees,
+            billing::late_fees::run_late_fees,
+            billing::late_fees::waive_late_fee,
+            billing::allocations::record_payment,
+            billing::allocations::allocate_payment,
+            billing::allocations::apply_tenant_credit,
+            billing::allocations::get_tenant_balance,
+            billing::allocations::get_payment_allocations,
+            statements::mpesa::import_mpesa_statement,
+            statements::mpesa::get_mpesa_transactions,
+            statements::mpesa::accept_mpesa_transaction,
+            statements::mpesa::accept_mpesa_matches,
+            statements::mpesa::ignore_mpesa_transaction,
+            statements::bank::import_bank_statement,
+            statements::bank::get_bank_statements,
+            statements::bank::get_bank_statement_lines,
+            statements::bank::accept_bank_match,
+            statements::bank::split_bank_line,
+            statements::bank::create_from_bank_line,
+            statements::bank::ignore_bank_line,
+            statements::bank::reset_bank_line,
+            jobs::get_job_log,
+            jobs::clear_job_log,
+            settings::get_settings,
+            settings::save_settings,
+            ledger::get_trial_balance,
+            ledger::export_trial_balance,
+            ledger::accounts::get_ledger_accounts,
+            ledger::accounts::save_ledger_account,
+            ledger::accounts::get_account_mappings,
+            ledger::accounts::set_account_mapping,
+            ledger::journal::get_journal_entries,
+            ledger::journal::post_journal_entry,
+            ledger::journal::reverse_journal_entry,
+            ledger::periods::get_accounting_periods,
+            ledger::periods::close_accounting_period,
+            ledger::periods::reopen_accounting_period,
+            billing::payments::update_payment,
+            billing::payments::delete_payment,
+            expenses::update_expense,
+            expenses::delete_expense,
+            ledger::export::export_accounting,
+            ledger::export::get_accounting_exports,
+            ledger::export::delete_accounting_export,
+            ledger::export::get_export_account_mappings,
+            ledger::export::set_export_account_mapping,
+            reports::tax::get_tax_rules,
+            reports::tax::save_tax_rule,
+            reports::tax::delete_tax_rule,
+            reports::tax::set_property_tax_profile,
+            reports::tax::get_tax_withholdings,
+            reports::tax::record_tax_withholding,
+            reports::tax::delete_tax_withholding,
+            reports::tax::get_rental_income_tax,
+            reports::tax::export_rental_income_tax,
+            utilities::meters::get_utility_meters,
+            utilities::meters::save_utility_meter,
+            utilities::meters::get_meter_readings,
+            utilities::meters::record_meter_reading,
+            utilities::meters::update_meter_reading,
+            utilities::meters::delete_meter_reading,
+            utilities::meters::accept_meter_reading,
+            utilities::tariffs::get_utility_tariffs,
+            utilities::tariffs::save_utility_tariff,
+            utilities::tariffs::delete_utility_tariff,
+            utilities::generate_utility_charges,
+            expenses::allocation::preview_expense_allocation,
+            expenses::allocation::allocate_expense,
+            expenses::allocation::get_expense_allocation,
+            expenses::allocation::delete_expense_allocation,
+            expenses::recurring::get_recurring_expenses,
+            expenses::recurring::save_recurring_expense,
+            expenses::recurring::delete_recurring_expense,
+            expenses::recurring::get_expense_schedule,
+            expenses::recurring::skip_expense_occurrence,
+            expenses::recurring::adjust_expense_occurrence,
+            expenses::recurring::restore_expense_occurrence,
+            expenses::recurring::run_recurring_expenses,
+            expenses::vendors::get_vendors,
+            expenses::vendors::save_vendor,
+            expenses::vendors::delete_vendor,
+            expenses::vendors::merge_vendors,
+            expenses::vendors::get_possible_duplicate_vendors,
+            expenses::bills::get_vendor_bills,
+            expenses::bills::get_vendor_bill_payments,
+            expenses::bills::save_vendor_bill,
+            expenses::bills::void_vendor_bill,
+            expenses::bills::pay_vendor_bill,
+            reports::aging::get_payables_aging,
+            expenses::approvals::get_expense_approvers,
+            expenses::approvals::save_expense_approver,
+            expenses::approvals::get_pending_expenses,
+            expenses::approvals::approve_expense,
+            expenses::approvals::reject_expense,
+            expenses::approvals::get_expense_approval_history,
+            maintenance::work_orders::get_work_orders,
+            maintenance::work_orders::get_work_order,
+            maintenance::work_orders::save_work_order,
+            maintenance::work_orders::set_work_order_status,
+            maintenance::work_orders::add_work_order_cost,
+            maintenance::work_orders::delete_work_order_cost,
+            maintenance::work_orders::get_maintenance_sla,
+            maintenance::schedules::get_maintenance_schedules,
+            maintenance::schedules::save_maintenance_schedule,
+            maintenance::schedules::delete_maintenance_schedule,
+            maintenance::schedules::get_inspections,
+            maintenance::schedules::record_inspection,
+            maintenance::schedules::delete_inspection,
+            maintenance::schedules::run_maintenance_scheduler,
+            attachments::add_attachment,
+            attachments::get_attachments,
+            attachments::get_unit_attachments,
+            attachments::remove_attachment,
+            attachments::prune_attachment_files,
+            documents::add_document,
+            documents::save_document_details,
+            documents::get_documents,
+            documents::get_expiring_documents,
+            documents::search_documents,
+            documents::remove_document,
+            documents::reindex_documents,
+            templates::get_document_templates,
+            templates::get_template_versions,
+            templates::save_document_template,
+            templates::get_template_fields,
+            templates::preview_letter,
+            templates::generate_letter,
+            templates::get_generated_documents,
+            templates::mark_document_signed,
+            notifications::get_notification_opt_outs,
+            notifications::set_notification_opt_out,
+            notifications::sms::get_sms_outbox,
+            notifications::sms::send_sms,
+            notifications::sms::send_pending_sms,
+            notifications::sms::retry_sms,
+            notifications::sms::cancel_sms,
+            notifications::reminders::get_sms_templates,
+            notifications::reminders::save_sms_template,
+            notifications::reminders::run_sms_reminders,
+            notifications::email::get_email_outbox,
+            notifications::email::send_email,
+            notifications::email::send_test_email,
+            notifications::email::send_pending_email,
+            notifications::email::retry_email,
+            notifications::email::cancel_email,
+            notifications::statements::get_tenant_statement,
+            notifications::statements::email_tenant_statements,
+            notifications::statements::email_payment_receipt,
+            notifications::digest::get_manager_digest,
+            notifications::digest::email_manager_digests,
+            notifications::broadcasts::preview_broadcast,
+            notifications::broadcasts::send_broadcast,
+            notifications::broadcasts::get_broadcasts,
+            notifications::broadcasts::get_broadcast_deliveries,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
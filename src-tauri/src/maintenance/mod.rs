@@ -0,0 +1,9 @@
+//! Maintenance of units and properties.
+//!
+//! Every complaint carries a work order with its priority, trade, assignee
+//! and SLA target; labour and materials spent on it are recorded as expenses.
+//! Inspections and servicing that repeat are scheduled per property, block
+//! or unit, and the daily job turns upcoming visits into tasks.
+
+pub mod schedules;
+pub mod work_orders;
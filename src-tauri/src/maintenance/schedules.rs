@@ -0,0 +1,658 @@
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::expenses::recurring::Frequency;
+use crate::maintenance::work_orders::WorkCategory;
+use crate::period;
+use crate::tasks::{self, TaskPriority};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum ScheduleKind {
+    /// Updates the property's `last_inspection` when recorded.
+    Inspection,
+    /// Servicing such as fire extinguishers or water tank cleaning.
+    Preventive,
+}
+
+impl ScheduleKind {
+    fn task_prefix(self) -> &'static str {
+        match self {
+            ScheduleKind::Inspection => "Inspection due",
+            ScheduleKind::Preventive => "Maintenance due",
+        }
+    }
+}
+
+/// An inspection or servicing that repeats for a property, a block or a
+/// unit.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceSchedule {
+    #[serde(default)]
+    pub schedule_id: Option<i64>,
+    pub name: String,
+    pub kind: ScheduleKind,
+    #[serde(default = "general")]
+    pub category: WorkCategory,
+    /// Taken from the block or unit when either is given.
+    #[serde(default)]
+    pub property_id: Option<i64>,
+    #[serde(default)]
+    pub block_id: Option<i64>,
+    #[serde(default)]
+    pub unit_id: Option<i64>,
+    pub frequency: Frequency,
+    pub start_date: String,
+    /// First visit not yet recorded; set by the app.
+    #[serde(default)]
+    pub next_due_date: Option<String>,
+    /// Days before the due date the reminder task is created.
+    #[serde(default = "default_lead_days")]
+    pub lead_days: i64,
+    #[serde(default)]
+    pub assigned_vendor_id: Option<i64>,
+    #[serde(default)]
+    pub assigned_staff: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default = "active")]
+    pub is_active: bool,
+    /// Items each visit is checked against, in order.
+    #[serde(default)]
+    #[sqlx(skip)]
+    pub checklist: Vec<String>,
+}
+
+fn general() -> WorkCategory {
+    WorkCategory::General
+}
+
+fn default_lead_days() -> i64 {
+    7
+}
+
+fn active() -> bool {
+    true
+}
+
+impl MaintenanceSchedule {
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+        if self.name.trim().is_empty() {
+            return invalid("maintenance schedules need a name");
+        }
+        if self.lead_days < 0 {
+            return invalid("lead days cannot be negative");
+        }
+        if self.assigned_vendor_id.is_some()
+            && self
+                .assigned_staff
+                .as_deref()
+                .is_some_and(|s| !s.trim().is_empty())
+        {
+            return invalid("assign the schedule to a vendor or to staff, not both");
+        }
+        if self.checklist.iter().any(|item| item.trim().is_empty()) {
+            return invalid("checklist items cannot be blank");
+        }
+        period::parse_date(&self.start_date)?;
+        Ok(())
+    }
+}
+
+const SCHEDULE_COLUMNS: &str = "schedule_id, name, kind, category, property_id, block_id, unit_id,
+     frequency, start_date, next_due_date, lead_days, assigned_vendor_id, assigned_staff, notes,
+     is_active";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum CheckResult {
+    Pass,
+    Fail,
+    #[serde(rename = "N/A")]
+    #[sqlx(rename = "N/A")]
+    NotApplicable,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct InspectionResult {
+    pub item: String,
+    pub result: CheckResult,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+/// A visit to record. With `scheduleId` set, the kind and scope default to
+/// the schedule's and the schedule moves on to its next due date.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewInspection {
+    #[serde(default)]
+    pub schedule_id: Option<i64>,
+    #[serde(default)]
+    pub kind: Option<ScheduleKind>,
+    #[serde(default)]
+    pub property_id: Option<i64>,
+    #[serde(default)]
+    pub block_id: Option<i64>,
+    #[serde(default)]
+    pub unit_id: Option<i64>,
+    pub inspected_on: String,
+    #[serde(default)]
+    pub inspector: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub results: Vec<InspectionResult>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Inspection {
+    pub inspection_id: i64,
+    pub schedule_id: Option<i64>,
+    pub schedule_name: Option<String>,
+    pub kind: ScheduleKind,
+    pub property_id: i64,
+    pub property_name: Option<String>,
+    pub block_id: Option<i64>,
+    pub block_name: Option<String>,
+    pub unit_id: Option<i64>,
+    pub unit_number: Option<String>,
+    pub inspected_on: String,
+    pub inspector: Option<String>,
+    pub notes: Option<String>,
+    pub failed_items: i64,
+    #[sqlx(skip)]
+    pub results: Vec<InspectionResult>,
+}
+
+const INSPECTION_QUERY: &str = "SELECT i.inspection_id, i.schedule_id, s.name AS schedule_name,
+            i.kind, i.property_id, p.name AS property_name, i.block_id, b.block_name, i.unit_id,
+            u.unit_number, i.inspected_on, i.inspector, i.notes,
+            (SELECT COUNT(*) FROM inspection_results r
+             WHERE r.inspection_id = i.inspection_id AND r.result = 'Fail') AS failed_items
+     FROM inspections i
+     LEFT JOIN maintenance_schedules s ON s.schedule_id = i.schedule_id
+     LEFT JOIN properties p ON p.property_id = i.property_id
+     LEFT JOIN blocks b ON b.block_id = i.block_id
+     LEFT JOIN units u ON u.unit_id = i.unit_id";
+
+/// Schedules with their checklists, optionally only those for a property or
+/// due on or before `due_by`.
+#[tauri::command]
+pub async fn get_maintenance_schedules(
+    app: AppHandle,
+    property_id: Option<i64>,
+    due_by: Option<String>,
+) -> Result<Vec<MaintenanceSchedule>> {
+    if let Some(date) = &due_by {
+        period::parse_date(date)?;
+    }
+    let pool = db::pool(&app).await?;
+    let mut schedules: Vec<MaintenanceSchedule> = sqlx::query_as(&format!(
+        "SELECT {SCHEDULE_COLUMNS} FROM maintenance_schedules
+         WHERE (?1 IS NULL OR property_id = ?1)
+           AND (?2 IS NULL OR (is_active = 1 AND next_due_date <= ?2))
+         ORDER BY is_active DESC, next_due_date, name"
+    ))
+    .bind(property_id)
+    .bind(due_by)
+    .fetch_all(&pool)
+    .await?;
+    let mut conn = pool.acquire().await?;
+    for schedule in &mut schedules {
+        schedule.checklist = checklist(&mut conn, schedule.schedule_id.unwrap_or_default()).await?;
+    }
+    Ok(schedules)
+}
+
+/// Inserts the schedule, or updates it when `scheduleId` is set. A changed
+/// frequency or start date recomputes the next due date from the later of
+/// the start and the current due date.
+#[tauri::command]
+pub async fn save_maintenance_schedule(
+    app: AppHandle,
+    schedule: MaintenanceSchedule,
+) -> Result<MaintenanceSchedule> {
+    let pool = db::pool(&app).await?;
+    save_schedule(&pool, schedule).await
+}
+
+/// Deletes the schedule. Inspections recorded against it are kept.
+#[tauri::command]
+pub async fn delete_maintenance_schedule(app: AppHandle, schedule_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    sqlx::query("DELETE FROM maintenance_schedules WHERE schedule_id = ?1")
+        .bind(schedule_id)
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+/// Inspections and servicing visits, newest first.
+#[tauri::command]
+pub async fn get_inspections(
+    app: AppHandle,
+    property_id: Option<i64>,
+    unit_id: Option<i64>,
+    schedule_id: Option<i64>,
+) -> Result<Vec<Inspection>> {
+    let pool = db::pool(&app).await?;
+    let mut inspections: Vec<Inspection> = sqlx::query_as(&format!(
+        "{INSPECTION_QUERY}
+         WHERE (?1 IS NULL OR i.property_id = ?1) AND (?2 IS NULL OR i.unit_id = ?2)
+           AND (?3 IS NULL OR i.schedule_id = ?3)
+         ORDER BY i.inspected_on DESC, i.inspection_id DESC"
+    ))
+    .bind(property_id)
+    .bind(unit_id)
+    .bind(schedule_id)
+    .fetch_all(&pool)
+    .await?;
+    let mut conn = pool.acquire().await?;
+    for inspection in &mut inspections {
+        inspection.results = results(&mut conn, inspection.inspection_id).await?;
+    }
+    Ok(inspections)
+}
+
+/// Records a visit with its checklist results.
+#[tauri::command]
+pub async fn record_inspection(app: AppHandle, inspection: NewInspection) -> Result<Inspection> {
+    let pool = db::pool(&app).await?;
+    record(&pool, &inspection).await
+}
+
+/// Deletes an inspection; the property's `last_inspection` falls back to the
+/// latest one left. Its schedule keeps its next due date.
+#[tauri::command]
+pub async fn delete_inspection(app: AppHandle, inspection_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    sqlx::query("DELETE FROM inspections WHERE inspection_id = ?1")
+        .bind(inspection_id)
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+/// Creates reminder tasks for schedules coming due by `as_of` (default
+/// today) and returns how many were new. The daily job does the same.
+#[tauri::command]
+pub async fn run_maintenance_scheduler(app: AppHandle, as_of: Option<String>) -> Result<usize> {
+    let as_of = as_of
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let pool = db::pool(&app).await?;
+    create_due_tasks(&pool, as_of).await
+}
+
+pub async fn save_schedule(
+    pool: &SqlitePool,
+    schedule: MaintenanceSchedule,
+) -> Result<MaintenanceSchedule> {
+    schedule.validate()?;
+    let start = period::parse_date(&schedule.start_date)?;
+    let staff = schedule
+        .assigned_staff
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+
+    let mut tx = pool.begin().await?;
+    let (property_id, block_id, unit_id) = scope(
+        &mut tx,
+        schedule.property_id,
+        schedule.block_id,
+        schedule.unit_id,
+    )
+    .await?;
+    let schedule_id = match schedule.schedule_id {
+        None => sqlx::query(
+            "INSERT INTO maintenance_schedules
+                 (name, kind, category, property_id, block_id, unit_id, frequency, start_date,
+                  next_due_date, lead_days, assigned_vendor_id, assigned_staff, notes, is_active)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8, ?9, ?10, ?11, ?12, ?13)",
+        )
+        .bind(schedule.name.trim())
+        .bind(schedule.kind)
+        .bind(schedule.category)
+        .bind(property_id)
+        .bind(block_id)
+        .bind(unit_id)
+        .bind(schedule.frequency)
+        .bind(start.to_string())
+        .bind(schedule.lead_days)
+        .bind(schedule.assigned_vendor_id)
+        .bind(staff)
+        .bind(&schedule.notes)
+        .bind(schedule.is_active)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid(),
+        Some(schedule_id) => {
+            let existing = load_schedule(&mut tx, schedule_id).await?;
+            let mut next_due =
+                period::parse_date(existing.next_due_date.as_deref().unwrap_or_default())?;
+            if existing.frequency != schedule.frequency
+                || existing.start_date != schedule.start_date
+            {
+                // The first date of the new schedule not before the old due date.
+                let cursor = next_due.max(start);
+                next_due = schedule
+                    .frequency
+                    .next_after(start, cursor - Duration::days(1))
+                    .unwrap_or(cursor);
+            }
+            sqlx::query(
+                "UPDATE maintenance_schedules
+                 SET name = ?2, kind = ?3, category = ?4, property_id = ?5, block_id = ?6,
+                     unit_id = ?7, frequency = ?8, start_date = ?9, next_due_date = ?10,
+                     lead_days = ?11, assigned_vendor_id = ?12, assigned_staff = ?13, notes = ?14,
+                     is_active = ?15, updated_at = CURRENT_TIMESTAMP
+                 WHERE schedule_id = ?1",
+            )
+            .bind(schedule_id)
+            .bind(schedule.name.trim())
+            .bind(schedule.kind)
+            .bind(schedule.category)
+            .bind(property_id)
+            .bind(block_id)
+            .bind(unit_id)
+            .bind(schedule.frequency)
+            .bind(start.to_string())
+            .bind(next_due.to_string())
+            .bind(schedule.lead_days)
+            .bind(schedule.assigned_vendor_id)
+            .bind(staff)
+            .bind(&schedule.notes)
+            .bind(schedule.is_active)
+            .execute(&mut *tx)
+            .await?;
+            schedule_id
+        }
+    };
+
+    sqlx::query("DELETE FROM maintenance_checklist_items WHERE schedule_id = ?1")
+        .bind(schedule_id)
+        .execute(&mut *tx)
+        .await?;
+    for (position, item) in schedule.checklist.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO maintenance_checklist_items (schedule_id, position, item)
+             VALUES (?1, ?2, ?3)",
+        )
+        .bind(schedule_id)
+        .bind(position as i64)
+        .bind(item.trim())
+        .execute(&mut *tx)
+        .await?;
+    }
+    let saved = load_schedule(&mut tx, schedule_id).await?;
+    tx.commit().await?;
+    Ok(saved)
+}
+
+pub async fn record(pool: &SqlitePool, inspection: &NewInspection) -> Result<Inspection> {
+    let inspected_on = period::parse_date(&inspection.inspected_on)?;
+    if inspection
+        .results
+        .iter()
+        .any(|result| result.item.trim().is_empty())
+    {
+        return Err(Error::InvalidInput(
+            "checklist results need the item checked".into(),
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+    let schedule = match inspection.schedule_id {
+        Some(schedule_id) => Some(load_schedule(&mut tx, schedule_id).await?),
+        None => None,
+    };
+    let (property_id, block_id, unit_id) = match &schedule {
+        Some(schedule)
+            if inspection.property_id.is_none()
+                && inspection.block_id.is_none()
+                && inspection.unit_id.is_none() =>
+        {
+            (
+                schedule.property_id.unwrap_or_default(),
+                schedule.block_id,
+                schedule.unit_id,
+            )
+        }
+        _ => {
+            scope(
+                &mut tx,
+                inspection.property_id,
+                inspection.block_id,
+                inspection.unit_id,
+            )
+            .await?
+        }
+    };
+    let kind = inspection
+        .kind
+        .or(schedule.as_ref().map(|schedule| schedule.kind))
+        .unwrap_or(ScheduleKind::Inspection);
+
+    let inspection_id = sqlx::query(
+        "INSERT INTO inspections
+             (schedule_id, kind, property_id, block_id, unit_id, inspected_on, inspector, notes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )
+    .bind(inspection.schedule_id)
+    .bind(kind)
+    .bind(property_id)
+    .bind(block_id)
+    .bind(unit_id)
+    .bind(inspected_on.to_string())
+    .bind(&inspection.inspector)
+    .bind(&inspection.notes)
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    for result in &inspection.results {
+        sqlx::query(
+            "INSERT INTO inspection_results (inspection_id, item, result, notes)
+             VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(inspection_id)
+        .bind(result.item.trim())
+        .bind(result.result)
+        .bind(&result.notes)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // The visit covers the occurrence that was due, however early or late.
+    if let Some(schedule) = &schedule {
+        let start = period::parse_date(&schedule.start_date)?;
+        let due = period::parse_date(schedule.next_due_date.as_deref().unwrap_or_default())?;
+        if let Some(next_due) = schedule.frequency.next_after(start, due.max(inspected_on)) {
+            sqlx::query(
+                "UPDATE maintenance_schedules
+                 SET next_due_date = ?2, updated_at = CURRENT_TIMESTAMP
+                 WHERE schedule_id = ?1",
+            )
+            .bind(schedule.schedule_id)
+            .bind(next_due.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    let mut recorded: Inspection =
+        sqlx::query_as(&format!("{INSPECTION_QUERY} WHERE i.inspection_id = ?1"))
+            .bind(inspection_id)
+            .fetch_one(&mut *tx)
+            .await?;
+    recorded.results = results(&mut tx, inspection_id).await?;
+    tx.commit().await?;
+    Ok(recorded)
+}
+
+#[derive(sqlx::FromRow)]
+struct DueSchedule {
+    name: String,
+    kind: ScheduleKind,
+    next_due_date: String,
+    property_name: Option<String>,
+    block_name: Option<String>,
+    unit_number: Option<String>,
+}
+
+/// Adds a task for each active schedule within its lead time of `as_of`.
+/// Task names carry the due date, so each occurrence gets one task however
+/// often this runs; it is high priority once the date has arrived.
+pub async fn create_due_tasks(pool: &SqlitePool, as_of: NaiveDate) -> Result<usize> {
+    let due: Vec<DueSchedule> = sqlx::query_as(
+        "SELECT s.name, s.kind, s.next_due_date, p.name AS property_name, b.block_name,
+                u.unit_number
+         FROM maintenance_schedules s
+         LEFT JOIN properties p ON p.property_id = s.property_id
+         LEFT JOIN blocks b ON b.block_id = s.block_id
+         LEFT JOIN units u ON u.unit_id = s.unit_id
+         WHERE s.is_active = 1 AND date(s.next_due_date, '-' || s.lead_days || ' days') <= ?1
+         ORDER BY s.next_due_date, s.schedule_id",
+    )
+    .bind(as_of.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    let mut created = 0;
+    for schedule in due {
+        let due_date = period::parse_date(&schedule.next_due_date)?;
+        let place = [
+            schedule.property_name.as_deref(),
+            schedule.block_name.as_deref(),
+            schedule.unit_number.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+        let name = format!(
+            "{}: {} - {} ({})",
+            schedule.kind.task_prefix(),
+            schedule.name,
+            place,
+            due_date
+        );
+        let priority = if due_date <= as_of {
+            TaskPriority::High
+        } else {
+            TaskPriority::Medium
+        };
+        if tasks::create_task(&mut *tx, &name, due_date, priority).await? {
+            created += 1;
+        }
+    }
+    tx.commit().await?;
+    Ok(created)
+}
+
+/// The property a schedule or inspection belongs to, taken from the unit or
+/// block when one is given, after checking the three agree.
+async fn scope(
+    conn: &mut SqliteConnection,
+    property_id: Option<i64>,
+    block_id: Option<i64>,
+    unit_id: Option<i64>,
+) -> Result<(i64, Option<i64>, Option<i64>)> {
+    let mismatch = || {
+        Err(Error::InvalidInput(
+            "the unit, block and property given do not match".into(),
+        ))
+    };
+    if let Some(unit_id) = unit_id {
+        let unit: Option<(i64, Option<i64>)> = sqlx::query_as(
+            "SELECT property_id, CAST(NULLIF(block_id, '') AS INTEGER) FROM units
+             WHERE unit_id = ?1",
+        )
+        .bind(unit_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+        let Some((unit_property, unit_block)) = unit else {
+            return Err(Error::NotFound(format!("unit {unit_id}")));
+        };
+        if property_id.is_some_and(|id| id != unit_property)
+            || block_id.is_some_and(|id| Some(id) != unit_block)
+        {
+            return mismatch();
+        }
+        return Ok((unit_property, unit_block, Some(unit_id)));
+    }
+    if let Some(block_id) = block_id {
+        let block: Option<(i64,)> =
+            sqlx::query_as("SELECT property_id FROM blocks WHERE block_id = ?1")
+                .bind(block_id)
+                .fetch_optional(&mut *conn)
+                .await?;
+        let Some((block_property,)) = block else {
+            return Err(Error::NotFound(format!("block {block_id}")));
+        };
+        if property_id.is_some_and(|id| id != block_property) {
+            return mismatch();
+        }
+        return Ok((block_property, Some(block_id), None));
+    }
+    let Some(property_id) = property_id else {
+        return Err(Error::InvalidInput(
+            "choose a property, block or unit".into(),
+        ));
+    };
+    let exists: Option<(i64,)> =
+        sqlx::query_as("SELECT property_id FROM properties WHERE property_id = ?1")
+            .bind(property_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+    if exists.is_none() {
+        return Err(Error::NotFound(format!("property {property_id}")));
+    }
+    Ok((property_id, None, None))
+}
+
+async fn checklist(conn: &mut SqliteConnection, schedule_id: i64) -> Result<Vec<String>> {
+    let items: Vec<(String,)> = sqlx::query_as(
+        "SELECT item FROM maintenance_checklist_items WHERE schedule_id = ?1
+         ORDER BY position, item_id",
+    )
+    .bind(schedule_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(items.into_iter().map(|(item,)| item).collect())
+}
+
+async fn results(conn: &mut SqliteConnection, inspection_id: i64) -> Result<Vec<InspectionResult>> {
+    Ok(sqlx::query_as(
+        "SELECT item, result, notes FROM inspection_results WHERE inspection_id = ?1
+         ORDER BY result_id",
+    )
+    .bind(inspection_id)
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+async fn load_schedule(
+    conn: &mut SqliteConnection,
+    schedule_id: i64,
+) -> Result<MaintenanceSchedule> {
+    let mut schedule: MaintenanceSchedule = sqlx::query_as(&format!(
+        "SELECT {SCHEDULE_COLUMNS} FROM maintenance_schedules WHERE schedule_id = ?1"
+    ))
+    .bind(schedule_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("maintenance schedule {schedule_id}")))?;
+    schedule.checklist = checklist(conn, schedule_id).await?;
+    Ok(schedule)
+}
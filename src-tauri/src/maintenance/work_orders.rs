@@ -0,0 +1,537 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::billing::allocations::round_cents;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::expenses::{self, NewExpense};
+use crate::period;
+use crate::settings;
+
+/// Expense category work order costs are recorded under.
+const EXPENSE_CATEGORY: &str = "maintenance";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum WorkOrderPriority {
+    Low,
+    Medium,
+    High,
+    Emergency,
+}
+
+impl WorkOrderPriority {
+    pub fn label(self) -> &'static str {
+        match self {
+            WorkOrderPriority::Low => "Low",
+            WorkOrderPriority::Medium => "Medium",
+            WorkOrderPriority::High => "High",
+            WorkOrderPriority::Emergency => "Emergency",
+        }
+    }
+
+    /// Hours to resolve, unless `maintenance.sla_hours.<Priority>` says
+    /// otherwise.
+    fn default_sla_hours(self) -> i64 {
+        match self {
+            WorkOrderPriority::Low => 336,
+            WorkOrderPriority::Medium => 168,
+            WorkOrderPriority::High => 72,
+            WorkOrderPriority::Emergency => 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum WorkCategory {
+    Plumbing,
+    Electrical,
+    Carpentry,
+    Painting,
+    Roofing,
+    Appliance,
+    #[serde(rename = "Pest Control")]
+    #[sqlx(rename = "Pest Control")]
+    PestControl,
+    Cleaning,
+    Security,
+    General,
+}
+
+/// `complaints.status`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum ComplaintStatus {
+    Open,
+    #[serde(rename = "In Progress")]
+    #[sqlx(rename = "In Progress")]
+    InProgress,
+    Resolved,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum CostType {
+    Labour,
+    Material,
+}
+
+/// A complaint with its work order.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkOrder {
+    pub work_order_id: i64,
+    pub complaint_id: i64,
+    pub unit_id: i64,
+    pub unit_number: Option<String>,
+    pub property_id: Option<i64>,
+    pub property_name: Option<String>,
+    pub tenant_id: Option<i64>,
+    pub tenant_name: Option<String>,
+    pub description: String,
+    pub status: ComplaintStatus,
+    pub priority: WorkOrderPriority,
+    pub category: WorkCategory,
+    pub assigned_vendor_id: Option<i64>,
+    pub assigned_vendor_name: Option<String>,
+    pub assigned_staff: Option<String>,
+    pub scheduled_date: Option<String>,
+    /// SLA target, UTC.
+    pub due_at: Option<String>,
+    pub started_at: Option<String>,
+    pub resolved_at: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: Option<String>,
+    /// Resolved after `due_at`, or still open past it.
+    pub sla_breached: bool,
+    /// Approved costs only, like every other expense total.
+    pub labour_cost: f64,
+    pub material_cost: f64,
+    #[sqlx(skip)]
+    pub costs: Vec<WorkOrderCost>,
+}
+
+const WORK_ORDER_QUERY: &str = "SELECT w.work_order_id, c.complaint_id, c.unit_id, u.unit_number,
+            u.property_id, p.name AS property_name, c.tenant_id, t.full_name AS tenant_name,
+            c.description, c.status, w.priority, w.category, w.assigned_vendor_id,
+            v.name AS assigned_vendor_name, w.assigned_staff, w.scheduled_date, w.due_at,
+            w.started_at, w.resolved_at, w.notes, w.created_at,
+            w.due_at IS NOT NULL
+                AND julianday(COALESCE(w.resolved_at, datetime('now'))) > julianday(w.due_at)
+                AS sla_breached,
+            CAST(COALESCE((SELECT SUM(e.amount) FROM work_order_costs k
+                           JOIN expenses e ON e.expense_id = k.expense_id
+                           WHERE k.work_order_id = w.work_order_id AND k.cost_type = 'Labour'
+                             AND e.approval_status = 'Approved'), 0)
+                 AS REAL) AS labour_cost,
+            CAST(COALESCE((SELECT SUM(e.amount) FROM work_order_costs k
+                           JOIN expenses e ON e.expense_id = k.expense_id
+                           WHERE k.work_order_id = w.work_order_id AND k.cost_type = 'Material'
+                             AND e.approval_status = 'Approved'), 0)
+                 AS REAL) AS material_cost
+     FROM work_orders w
+     JOIN complaints c ON c.complaint_id = w.complaint_id
+     LEFT JOIN units u ON u.unit_id = c.unit_id
+     LEFT JOIN properties p ON p.property_id = u.property_id
+     LEFT JOIN tenants t ON t.tenant_id = c.tenant_id
+     LEFT JOIN vendors v ON v.vendor_id = w.assigned_vendor_id";
+
+/// What the maintenance screen edits on a work order.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkOrderDetails {
+    pub priority: WorkOrderPriority,
+    pub category: WorkCategory,
+    #[serde(default)]
+    pub assigned_vendor_id: Option<i64>,
+    #[serde(default)]
+    pub assigned_staff: Option<String>,
+    #[serde(default)]
+    pub scheduled_date: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkOrderCost {
+    pub cost_id: i64,
+    pub cost_type: CostType,
+    pub description: String,
+    pub quantity: Option<f64>,
+    pub unit_cost: Option<f64>,
+    pub amount: f64,
+    pub expense_id: i64,
+    pub expense_date: String,
+    pub approval_status: Option<String>,
+}
+
+/// Labour or materials spent on a work order.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewWorkOrderCost {
+    pub cost_type: CostType,
+    pub description: String,
+    #[serde(default)]
+    pub quantity: Option<f64>,
+    #[serde(default)]
+    pub unit_cost: Option<f64>,
+    /// Required unless both `quantity` and `unitCost` are given.
+    #[serde(default)]
+    pub amount: Option<f64>,
+    pub incurred_on: String,
+    pub payment_method: String,
+    /// Who was paid; defaults to the assigned vendor or staff member.
+    #[serde(default)]
+    pub vendor: Option<String>,
+    #[serde(default)]
+    pub paid_by: Option<String>,
+    #[serde(default)]
+    pub entered_by: Option<String>,
+}
+
+impl NewWorkOrderCost {
+    fn amount(&self) -> Result<f64> {
+        let amount = match (self.quantity, self.unit_cost, self.amount) {
+            (_, _, Some(amount)) => amount,
+            (Some(quantity), Some(unit_cost), None) => quantity * unit_cost,
+            _ => {
+                return Err(Error::InvalidInput(
+                    "give the cost's amount, or its quantity and unit cost".into(),
+                ))
+            }
+        };
+        if self.description.trim().is_empty() {
+            return Err(Error::InvalidInput("describe the cost".into()));
+        }
+        Ok(round_cents(amount))
+    }
+}
+
+/// Time to resolve for one property's work orders.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertySla {
+    pub property_id: Option<i64>,
+    pub property_name: Option<String>,
+    pub work_orders: i64,
+    pub resolved: i64,
+    pub resolved_within_sla: i64,
+    pub open: i64,
+    /// Open and already past the SLA target.
+    pub overdue: i64,
+    /// Share of resolved work orders resolved in time; `None` before any are.
+    pub compliance_percent: Option<f64>,
+    pub average_hours_to_resolve: Option<f64>,
+    pub total_cost: f64,
+}
+
+/// Work orders, optionally narrowed to a property, a complaint status or an
+/// assigned vendor; most urgent first.
+#[tauri::command]
+pub async fn get_work_orders(
+    app: AppHandle,
+    property_id: Option<i64>,
+    status: Option<ComplaintStatus>,
+    assigned_vendor_id: Option<i64>,
+) -> Result<Vec<WorkOrder>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(&format!(
+        "{WORK_ORDER_QUERY}
+         WHERE (?1 IS NULL OR u.property_id = ?1) AND (?2 IS NULL OR c.status = ?2)
+           AND (?3 IS NULL OR w.assigned_vendor_id = ?3)
+         ORDER BY c.status = 'Resolved',
+                  CASE w.priority WHEN 'Emergency' THEN 0 WHEN 'High' THEN 1
+                                  WHEN 'Medium' THEN 2 ELSE 3 END,
+                  w.due_at, w.work_order_id"
+    ))
+    .bind(property_id)
+    .bind(status)
+    .bind(assigned_vendor_id)
+    .fetch_all(&pool)
+    .await?)
+}
+
+/// The complaint's work order with its costs.
+#[tauri::command]
+pub async fn get_work_order(app: AppHandle, complaint_id: i64) -> Result<WorkOrder> {
+    let pool = db::pool(&app).await?;
+    load(&mut *pool.acquire().await?, complaint_id).await
+}
+
+/// Sets priority, trade, assignee and schedule. A new priority moves the SLA
+/// target to the complaint's creation time plus that priority's hours.
+#[tauri::command]
+pub async fn save_work_order(
+    app: AppHandle,
+    complaint_id: i64,
+    details: WorkOrderDetails,
+) -> Result<WorkOrder> {
+    let pool = db::pool(&app).await?;
+    save(&pool, complaint_id, &details).await
+}
+
+/// Moves the complaint to `status`; the work order records when work started
+/// and when it was resolved.
+#[tauri::command]
+pub async fn set_work_order_status(
+    app: AppHandle,
+    complaint_id: i64,
+    status: ComplaintStatus,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let result = sqlx::query(
+        "UPDATE complaints SET status = ?2, updated_at = datetime('now') WHERE complaint_id = ?1",
+    )
+    .bind(complaint_id)
+    .bind(status)
+    .execute(&pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!("complaint {complaint_id}")));
+    }
+    Ok(())
+}
+
+/// Records labour or materials as a maintenance expense on the complaint's
+/// unit and links it to the work order.
+#[tauri::command]
+pub async fn add_work_order_cost(
+    app: AppHandle,
+    complaint_id: i64,
+    cost: NewWorkOrderCost,
+) -> Result<WorkOrder> {
+    let pool = db::pool(&app).await?;
+    add_cost(&pool, complaint_id, &cost).await
+}
+
+/// Deletes the cost's expense, under the same closed-period rule as
+/// deleting any expense.
+#[tauri::command]
+pub async fn delete_work_order_cost(
+    app: AppHandle,
+    cost_id: i64,
+    reverse_in_open_period: Option<bool>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let expense: Option<(i64,)> =
+        sqlx::query_as("SELECT expense_id FROM work_order_costs WHERE cost_id = ?1")
+            .bind(cost_id)
+            .fetch_optional(&pool)
+            .await?;
+    let Some((expense_id,)) = expense else {
+        return Err(Error::NotFound(format!("work order cost {cost_id}")));
+    };
+    expenses::delete(&pool, expense_id, reverse_in_open_period.unwrap_or(false)).await
+}
+
+/// SLA compliance and time to resolve per property, for work orders raised
+/// from `from` through `to` (default the last 90 days).
+#[tauri::command]
+pub async fn get_maintenance_sla(
+    app: AppHandle,
+    from: Option<String>,
+    to: Option<String>,
+    property_id: Option<i64>,
+) -> Result<Vec<PropertySla>> {
+    let to = to
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let from = from
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or(to - chrono::Duration::days(90));
+    let pool = db::pool(&app).await?;
+    sla_report(&pool, from, to, property_id).await
+}
+
+pub async fn sla_report(
+    pool: &SqlitePool,
+    from: NaiveDate,
+    to: NaiveDate,
+    property_id: Option<i64>,
+) -> Result<Vec<PropertySla>> {
+    Ok(sqlx::query_as(
+        "SELECT property_id, property_name,
+                COUNT(*) AS work_orders,
+                COALESCE(SUM(resolved_at IS NOT NULL), 0) AS resolved,
+                COALESCE(SUM(resolved_at IS NOT NULL AND NOT breached), 0) AS resolved_within_sla,
+                COALESCE(SUM(resolved_at IS NULL), 0) AS open,
+                COALESCE(SUM(resolved_at IS NULL AND breached), 0) AS overdue,
+                CAST(100.0 * SUM(resolved_at IS NOT NULL AND NOT breached)
+                     / NULLIF(SUM(resolved_at IS NOT NULL), 0) AS REAL) AS compliance_percent,
+                CAST(AVG(CASE WHEN resolved_at IS NOT NULL
+                              THEN (julianday(resolved_at) - julianday(created_at)) * 24 END)
+                     AS REAL) AS average_hours_to_resolve,
+                CAST(COALESCE(SUM(cost), 0) AS REAL) AS total_cost
+         FROM (
+             SELECT u.property_id, p.name AS property_name, w.created_at, w.resolved_at,
+                    w.due_at IS NOT NULL
+                        AND julianday(COALESCE(w.resolved_at, datetime('now'))) > julianday(w.due_at)
+                        AS breached,
+                    (SELECT SUM(e.amount) FROM work_order_costs k
+                     JOIN expenses e ON e.expense_id = k.expense_id
+                     WHERE k.work_order_id = w.work_order_id
+                       AND e.approval_status = 'Approved') AS cost
+             FROM work_orders w
+             JOIN complaints c ON c.complaint_id = w.complaint_id
+             LEFT JOIN units u ON u.unit_id = c.unit_id
+             LEFT JOIN properties p ON p.property_id = u.property_id
+             WHERE date(w.created_at) BETWEEN ?1 AND ?2
+               AND (?3 IS NULL OR u.property_id = ?3)
+         )
+         GROUP BY property_id
+         ORDER BY property_name",
+    )
+    .bind(from.to_string())
+    .bind(to.to_string())
+    .bind(property_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn save(
+    pool: &SqlitePool,
+    complaint_id: i64,
+    details: &WorkOrderDetails,
+) -> Result<WorkOrder> {
+    let staff = details
+        .assigned_staff
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+    if details.assigned_vendor_id.is_some() && staff.is_some() {
+        return Err(Error::InvalidInput(
+            "assign the work order to a vendor or to staff, not both".into(),
+        ));
+    }
+    if let Some(date) = &details.scheduled_date {
+        period::parse_date(date)?;
+    }
+    let key = format!("maintenance.sla_hours.{}", details.priority.label());
+    let sla_hours: i64 = settings::get_or(pool, &key, details.priority.default_sla_hours()).await?;
+
+    let mut tx = pool.begin().await?;
+    let created: Option<(String,)> =
+        sqlx::query_as("SELECT created_at FROM complaints WHERE complaint_id = ?1")
+            .bind(complaint_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let Some((created_at,)) = created else {
+        return Err(Error::NotFound(format!("complaint {complaint_id}")));
+    };
+    sqlx::query(
+        "INSERT INTO work_orders
+             (complaint_id, priority, category, assigned_vendor_id, assigned_staff, scheduled_date,
+              notes, due_at, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, datetime(?8, '+' || ?9 || ' hours'), ?8)
+         ON CONFLICT (complaint_id) DO UPDATE
+         SET priority = excluded.priority, category = excluded.category,
+             assigned_vendor_id = excluded.assigned_vendor_id,
+             assigned_staff = excluded.assigned_staff, scheduled_date = excluded.scheduled_date,
+             notes = excluded.notes,
+             due_at = CASE WHEN priority IS excluded.priority THEN due_at ELSE excluded.due_at END,
+             updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(complaint_id)
+    .bind(details.priority)
+    .bind(details.category)
+    .bind(details.assigned_vendor_id)
+    .bind(staff)
+    .bind(&details.scheduled_date)
+    .bind(&details.notes)
+    .bind(&created_at)
+    .bind(sla_hours)
+    .execute(&mut *tx)
+    .await?;
+    let order = load(&mut tx, complaint_id).await?;
+    tx.commit().await?;
+    Ok(order)
+}
+
+pub async fn add_cost(
+    pool: &SqlitePool,
+    complaint_id: i64,
+    cost: &NewWorkOrderCost,
+) -> Result<WorkOrder> {
+    let amount = cost.amount()?;
+    let mut tx = pool.begin().await?;
+    let order = load(&mut tx, complaint_id).await?;
+    let vendor = cost
+        .vendor
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .or(order.assigned_vendor_name.as_deref())
+        .or(order.assigned_staff.as_deref())
+        .ok_or_else(|| {
+            Error::InvalidInput("say who was paid; the work order has no assignee".into())
+        })?
+        .to_string();
+    let expense_id = expenses::insert_expense(
+        &mut tx,
+        &NewExpense {
+            amount,
+            category: EXPENSE_CATEGORY.into(),
+            description: Some(format!(
+                "Work order {}: {} - {}",
+                order.work_order_id,
+                match cost.cost_type {
+                    CostType::Labour => "labour",
+                    CostType::Material => "materials",
+                },
+                cost.description.trim()
+            )),
+            expense_date: cost.incurred_on.clone(),
+            unit_id: Some(order.unit_id),
+            block_id: None,
+            property_id: order.property_id,
+            payment_method: cost.payment_method.clone(),
+            vendor,
+            invoice_number: None,
+            paid_by: cost.paid_by.clone(),
+            entered_by: cost.entered_by.clone(),
+        },
+    )
+    .await?;
+    sqlx::query(
+        "INSERT INTO work_order_costs
+             (work_order_id, cost_type, description, quantity, unit_cost, expense_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )
+    .bind(order.work_order_id)
+    .bind(cost.cost_type)
+    .bind(cost.description.trim())
+    .bind(cost.quantity)
+    .bind(cost.unit_cost)
+    .bind(expense_id)
+    .execute(&mut *tx)
+    .await?;
+    let order = load(&mut tx, complaint_id).await?;
+    tx.commit().await?;
+    Ok(order)
+}
+
+async fn load(conn: &mut SqliteConnection, complaint_id: i64) -> Result<WorkOrder> {
+    let mut order: WorkOrder =
+        sqlx::query_as(&format!("{WORK_ORDER_QUERY} WHERE c.complaint_id = ?1"))
+            .bind(complaint_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("work order for complaint {complaint_id}")))?;
+    order.costs = sqlx::query_as(
+        "SELECT k.cost_id, k.cost_type, k.description, k.quantity,
+                CAST(k.unit_cost AS REAL) AS unit_cost, CAST(e.amount AS REAL) AS amount,
+                k.expense_id, e.expense_date, e.approval_status
+         FROM work_order_costs k
+         JOIN expenses e ON e.expense_id = k.expense_id
+         WHERE k.work_order_id = ?1
+         ORDER BY e.expense_date, k.cost_id",
+    )
+    .bind(order.work_order_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(order)
+}
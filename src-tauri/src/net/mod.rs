@@ -0,0 +1,51 @@
+//! Outbound connections to SMS gateways and mail servers.
+//!
+//! There is no HTTP client or mailer dependency: requests are written by
+//! hand over a `TcpStream`, wrapped in TLS with the Mozilla root
+//! certificates when the server needs it.
+
+pub mod http;
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use crate::error::{Error, Result};
+
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Opens a TCP connection, giving up after [`CONNECT_TIMEOUT`].
+pub async fn connect(host: &str, port: u16) -> Result<TcpStream> {
+    match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(stream) => Ok(stream?),
+        Err(_) => Err(Error::Delivery(format!(
+            "timed out connecting to {host}:{port}"
+        ))),
+    }
+}
+
+/// Starts TLS on an open connection, checking the certificate against
+/// `host`.
+pub async fn tls(stream: TcpStream, host: &str) -> Result<TlsStream<TcpStream>> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    let config = CONFIG.get_or_init(|| {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        Arc::new(
+            ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        )
+    });
+    let name = ServerName::try_from(host.to_string())
+        .map_err(|_| Error::InvalidInput(format!("'{host}' is not a valid host name")))?;
+    Ok(TlsConnector::from(config.clone())
+        .connect(name, stream)
+        .await?)
+}
@@ -0,0 +1,423 @@
+//! One message to many tenants, e.g. a water shutdown notice to Block B.
+//!
+//! Recipients are the current leaseholders of the units that match an
+//! [`Audience`]. Each copy goes through the SMS or email outbox like any
+//! other message, and `broadcast_recipients` links it back to the broadcast
+//! so delivery can be followed per tenant.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use super::email::{self, EmailKind, NewEmail};
+use super::sms::{self, MessageKind, NewSms};
+use super::Channel;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::period;
+use crate::settings;
+use crate::templates::{money, Template};
+
+/// Fields a broadcast body can use.
+const FIELDS: &[&str] = &[
+    "tenant.name",
+    "tenant.first_name",
+    "unit.number",
+    "block.name",
+    "property.name",
+    "balance",
+];
+
+/// Which tenants a broadcast goes to. Every filter that is set must match;
+/// with none set it goes to every current tenant.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Audience {
+    pub property_id: Option<i64>,
+    pub block_id: Option<i64>,
+    /// Matched without regard to case, e.g. `occupied`.
+    pub unit_status: Option<String>,
+    /// Only tenants owing at least this much on charges already due.
+    pub min_balance: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Recipient {
+    pub tenant_id: i64,
+    pub tenant_name: String,
+    pub unit_number: String,
+    pub block_name: Option<String>,
+    pub property_name: String,
+    pub phone_number: Option<String>,
+    pub email: Option<String>,
+    pub balance: f64,
+    /// Set when the channel was chosen and the tenant can be reached on it:
+    /// a usable number or address and no opt-out.
+    #[sqlx(skip)]
+    pub by_sms: bool,
+    #[sqlx(skip)]
+    pub by_email: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BroadcastInput {
+    /// Email subject; required when sending by email.
+    #[serde(default)]
+    pub subject: Option<String>,
+    pub body: String,
+    pub channels: Vec<Channel>,
+    #[serde(default)]
+    pub audience: Audience,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Broadcast {
+    pub broadcast_id: i64,
+    pub subject: Option<String>,
+    pub body: String,
+    /// `sms`, `email` or `sms,email`.
+    pub channels: String,
+    pub property_id: Option<i64>,
+    pub block_id: Option<i64>,
+    pub unit_status: Option<String>,
+    pub min_balance: Option<f64>,
+    pub created_at: Option<String>,
+    pub recipients: i64,
+    pub queued: i64,
+    pub sent: i64,
+    pub delivered: i64,
+    pub failed: i64,
+    pub skipped: i64,
+}
+
+const BROADCAST_COLUMNS: &str = "b.broadcast_id, b.subject, b.body, b.channels, b.property_id,
+            b.block_id, b.unit_status, CAST(b.min_balance AS REAL) AS min_balance, b.created_at,
+            COUNT(r.recipient_id) AS recipients,
+            COUNT(CASE WHEN COALESCE(s.status, e.status) = 'Queued' THEN 1 END) AS queued,
+            COUNT(CASE WHEN COALESCE(s.status, e.status) = 'Sent' THEN 1 END) AS sent,
+            COUNT(CASE WHEN COALESCE(s.status, e.status) = 'Delivered' THEN 1 END) AS delivered,
+            COUNT(CASE WHEN COALESCE(s.status, e.status) = 'Failed' THEN 1 END) AS failed,
+            COUNT(CASE WHEN COALESCE(s.status, e.status) = 'Skipped' THEN 1 END) AS skipped
+         FROM broadcasts b
+         LEFT JOIN broadcast_recipients r ON r.broadcast_id = b.broadcast_id
+         LEFT JOIN sms_outbox s ON s.message_id = r.sms_message_id
+         LEFT JOIN email_outbox e ON e.email_id = r.email_id";
+
+/// How one copy of a broadcast fared.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Delivery {
+    pub recipient_id: i64,
+    pub tenant_id: Option<i64>,
+    pub tenant_name: Option<String>,
+    pub unit_number: Option<String>,
+    pub channel: Channel,
+    /// The phone number or email address it went to.
+    pub address: Option<String>,
+    /// Outbox status: Queued, Sent, Delivered, Failed or Skipped.
+    pub status: Option<String>,
+    pub last_error: Option<String>,
+    pub sent_at: Option<String>,
+    pub delivered_at: Option<String>,
+}
+
+/// The tenants a broadcast would reach, and by which channels.
+#[tauri::command]
+pub async fn preview_broadcast(
+    app: AppHandle,
+    audience: Audience,
+    channels: Vec<Channel>,
+) -> Result<Vec<Recipient>> {
+    let pool = db::pool(&app).await?;
+    recipients(&pool, &audience, &channels).await
+}
+
+/// Queues a copy for every recipient on every chosen channel and sends what
+/// it can straight away; the worker sends the rest.
+#[tauri::command]
+pub async fn send_broadcast(app: AppHandle, input: BroadcastInput) -> Result<Broadcast> {
+    let pool = db::pool(&app).await?;
+    let broadcast_id = create(&pool, &input).await?;
+    if input.channels.contains(&Channel::Sms) {
+        if let Some(provider) = sms::provider(&pool, &super::data_dir(&app)?).await? {
+            sms::dispatch(&pool, provider.as_ref()).await?;
+        }
+    }
+    if input.channels.contains(&Channel::Email) {
+        if let Some(mailer) = email::mailer(&pool).await? {
+            email::dispatch(&pool, &mailer).await?;
+        }
+    }
+    load(&pool, broadcast_id).await
+}
+
+/// Past broadcasts, newest first, with delivery counts.
+#[tauri::command]
+pub async fn get_broadcasts(app: AppHandle, limit: Option<i64>) -> Result<Vec<Broadcast>> {
+    let pool = db::pool(&app).await?;
+    let broadcasts = sqlx::query_as(&format!(
+        "SELECT {BROADCAST_COLUMNS}
+         GROUP BY b.broadcast_id
+         ORDER BY b.broadcast_id DESC
+         LIMIT ?1"
+    ))
+    .bind(limit.unwrap_or(100))
+    .fetch_all(&pool)
+    .await?;
+    Ok(broadcasts)
+}
+
+#[tauri::command]
+pub async fn get_broadcast_deliveries(app: AppHandle, broadcast_id: i64) -> Result<Vec<Delivery>> {
+    let pool = db::pool(&app).await?;
+    deliveries(&pool, broadcast_id).await
+}
+
+pub async fn recipients(
+    pool: &SqlitePool,
+    audience: &Audience,
+    channels: &[Channel],
+) -> Result<Vec<Recipient>> {
+    let today = period::today().to_string();
+    let mut recipients: Vec<Recipient> = sqlx::query_as(
+        "SELECT t.tenant_id, t.full_name AS tenant_name, u.unit_number, b.block_name,
+                p.name AS property_name, t.phone_number, t.email,
+                (SELECT CAST(COALESCE(SUM(c.outstanding), 0) AS REAL) FROM charge_balances c
+                 WHERE c.tenant_id = t.tenant_id AND c.status = 'Open'
+                   AND c.due_date <= ?1) AS balance
+         FROM units u
+         JOIN properties p ON p.property_id = u.property_id
+         LEFT JOIN blocks b ON b.block_id = u.block_id
+         JOIN leases l ON l.lease_id = (
+             SELECT lease_id FROM leases
+             WHERE unit_id = u.unit_id
+               AND lease_start_date <= ?1 AND lease_end_date >= ?1
+               AND COALESCE(lower(status), 'active') <> 'terminated'
+             ORDER BY lease_start_date DESC
+             LIMIT 1
+         )
+         JOIN tenants t ON t.tenant_id = l.tenant_id
+         WHERE (?2 IS NULL OR u.property_id = ?2)
+           AND (?3 IS NULL OR CAST(u.block_id AS INTEGER) = ?3)
+           AND (?4 IS NULL OR lower(u.unit_status) = lower(?4))
+         ORDER BY p.name, b.block_name, u.unit_number",
+    )
+    .bind(&today)
+    .bind(audience.property_id)
+    .bind(audience.block_id)
+    .bind(audience.unit_status.as_deref().map(str::trim))
+    .fetch_all(pool)
+    .await?;
+
+    // A tenant with two units gets one copy, listed under the first.
+    let mut seen = std::collections::HashSet::new();
+    recipients.retain(|r| seen.insert(r.tenant_id));
+    if let Some(min_balance) = audience.min_balance {
+        recipients.retain(|r| r.balance >= min_balance - 0.005);
+    }
+
+    let country_code = settings::get_or(pool, "sms.country_code", "254".to_string()).await?;
+    for recipient in &mut recipients {
+        if channels.contains(&Channel::Sms) {
+            recipient.by_sms = recipient
+                .phone_number
+                .as_deref()
+                .and_then(|phone| super::normalize_phone(phone, &country_code))
+                .is_some()
+                && !super::opted_out(pool, recipient.tenant_id, Channel::Sms).await?;
+        }
+        if channels.contains(&Channel::Email) {
+            recipient.by_email = recipient
+                .email
+                .as_deref()
+                .and_then(email::address)
+                .is_some()
+                && !super::opted_out(pool, recipient.tenant_id, Channel::Email).await?;
+        }
+    }
+    Ok(recipients)
+}
+
+/// Records the broadcast and queues its copies. Tenants who cannot be
+/// reached on a channel get a skipped copy, so the log shows why.
+pub async fn create(pool: &SqlitePool, input: &BroadcastInput) -> Result<i64> {
+    let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+    let body = input.body.trim();
+    if body.is_empty() {
+        return invalid("the message is empty");
+    }
+    if input.channels.is_empty() {
+        return invalid("choose SMS, email or both");
+    }
+    let subject = input
+        .subject
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+    if input.channels.contains(&Channel::Email) && subject.is_none() {
+        return invalid("an email needs a subject");
+    }
+    if input.audience.min_balance.is_some_and(|b| b < 0.0) {
+        return invalid("the minimum balance cannot be negative");
+    }
+    let template = Template::parse(body)?;
+    for name in template.fields() {
+        if !FIELDS.contains(&name) {
+            return Err(Error::InvalidInput(format!(
+                "{{{{{name}}}}} is not a field of a broadcast"
+            )));
+        }
+    }
+    let recipients = recipients(pool, &input.audience, &input.channels).await?;
+    if recipients.is_empty() {
+        return invalid("no current tenants match");
+    }
+
+    let channels: Vec<&str> = [Channel::Sms, Channel::Email]
+        .into_iter()
+        .filter(|c| input.channels.contains(c))
+        .map(|c| match c {
+            Channel::Sms => "sms",
+            Channel::Email => "email",
+        })
+        .collect();
+    let audience = &input.audience;
+    let broadcast_id = sqlx::query(
+        "INSERT INTO broadcasts
+            (subject, body, channels, property_id, block_id, unit_status, min_balance)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )
+    .bind(subject)
+    .bind(body)
+    .bind(channels.join(","))
+    .bind(audience.property_id)
+    .bind(audience.block_id)
+    .bind(audience.unit_status.as_deref())
+    .bind(audience.min_balance)
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    for recipient in &recipients {
+        let text = template.render(&values(recipient));
+        let key = format!("broadcast:{broadcast_id}:{}", recipient.tenant_id);
+        if input.channels.contains(&Channel::Sms) {
+            let message_id = sms::queue(
+                pool,
+                &NewSms {
+                    tenant_id: Some(recipient.tenant_id),
+                    phone_number: None,
+                    kind: MessageKind::Custom,
+                    body: text.clone(),
+                    dedupe_key: Some(key.clone()),
+                },
+            )
+            .await?;
+            log(pool, broadcast_id, recipient, Channel::Sms, message_id).await?;
+        }
+        if let (true, Some(subject)) = (input.channels.contains(&Channel::Email), subject) {
+            let email_id = email::queue(
+                pool,
+                &NewEmail {
+                    tenant_id: Some(recipient.tenant_id),
+                    manager_id: None,
+                    to_address: None,
+                    kind: EmailKind::Custom,
+                    subject: subject.to_string(),
+                    body: text,
+                    attachments: Vec::new(),
+                    dedupe_key: Some(key),
+                },
+            )
+            .await?;
+            log(pool, broadcast_id, recipient, Channel::Email, email_id).await?;
+        }
+    }
+    Ok(broadcast_id)
+}
+
+pub async fn deliveries(pool: &SqlitePool, broadcast_id: i64) -> Result<Vec<Delivery>> {
+    load(pool, broadcast_id).await?;
+    let deliveries = sqlx::query_as(
+        "SELECT r.recipient_id, r.tenant_id, t.full_name AS tenant_name, r.unit_number,
+                r.channel, COALESCE(s.phone_number, e.to_address) AS address,
+                COALESCE(s.status, e.status) AS status,
+                COALESCE(s.last_error, e.last_error) AS last_error,
+                COALESCE(s.sent_at, e.sent_at) AS sent_at, s.delivered_at
+         FROM broadcast_recipients r
+         LEFT JOIN tenants t ON t.tenant_id = r.tenant_id
+         LEFT JOIN sms_outbox s ON s.message_id = r.sms_message_id
+         LEFT JOIN email_outbox e ON e.email_id = r.email_id
+         WHERE r.broadcast_id = ?1
+         ORDER BY t.full_name, r.channel",
+    )
+    .bind(broadcast_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(deliveries)
+}
+
+pub async fn load(pool: &SqlitePool, broadcast_id: i64) -> Result<Broadcast> {
+    let broadcast = sqlx::query_as(&format!(
+        "SELECT {BROADCAST_COLUMNS}
+         WHERE b.broadcast_id = ?1
+         GROUP BY b.broadcast_id"
+    ))
+    .bind(broadcast_id)
+    .fetch_optional(pool)
+    .await?;
+    broadcast.ok_or_else(|| Error::NotFound(format!("broadcast {broadcast_id}")))
+}
+
+/// Links a queued copy to the broadcast. `outbox_id` is `None` only if the
+/// copy was already queued, which a new broadcast never is.
+async fn log(
+    pool: &SqlitePool,
+    broadcast_id: i64,
+    recipient: &Recipient,
+    channel: Channel,
+    outbox_id: Option<i64>,
+) -> Result<()> {
+    let (sms_message_id, email_id) = match channel {
+        Channel::Sms => (outbox_id, None),
+        Channel::Email => (None, outbox_id),
+    };
+    sqlx::query(
+        "INSERT INTO broadcast_recipients
+            (broadcast_id, tenant_id, unit_number, channel, sms_message_id, email_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )
+    .bind(broadcast_id)
+    .bind(recipient.tenant_id)
+    .bind(&recipient.unit_number)
+    .bind(channel)
+    .bind(sms_message_id)
+    .bind(email_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+fn values(recipient: &Recipient) -> HashMap<String, String> {
+    let first_name = recipient
+        .tenant_name
+        .split_whitespace()
+        .next()
+        .unwrap_or(&recipient.tenant_name);
+    let mut values = HashMap::from([
+        ("tenant.name".to_string(), recipient.tenant_name.clone()),
+        ("tenant.first_name".to_string(), first_name.to_string()),
+        ("unit.number".to_string(), recipient.unit_number.clone()),
+        ("property.name".to_string(), recipient.property_name.clone()),
+        ("balance".to_string(), money(recipient.balance)),
+    ]);
+    if let Some(block) = &recipient.block_name {
+        values.insert("block.name".into(), block.clone());
+    }
+    values
+}
@@ -0,0 +1,222 @@
+//! A periodic email to each manager listing the overdue accounts and open
+//! complaints on their properties.
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use super::email::{self, EmailKind, NewEmail};
+use crate::db;
+use crate::error::{Error, Result};
+use crate::period;
+use crate::reports::aging::{self, TenantAging};
+use crate::settings;
+use crate::templates::money;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenComplaint {
+    pub complaint_id: i64,
+    pub description: String,
+    pub status: String,
+    pub unit_number: String,
+    pub property_name: String,
+    pub priority: Option<String>,
+    pub due_at: Option<String>,
+    /// Past its SLA target.
+    pub overdue: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagerDigest {
+    pub manager_id: i64,
+    pub manager_name: String,
+    pub as_of: NaiveDate,
+    /// Tenants with anything past due, largest amount first.
+    pub overdue_accounts: Vec<TenantAging>,
+    pub total_overdue: f64,
+    pub open_complaints: Vec<OpenComplaint>,
+}
+
+impl ManagerDigest {
+    pub fn is_empty(&self) -> bool {
+        self.overdue_accounts.is_empty() && self.open_complaints.is_empty()
+    }
+}
+
+#[tauri::command]
+pub async fn get_manager_digest(
+    app: AppHandle,
+    manager_id: i64,
+    as_of: Option<String>,
+) -> Result<ManagerDigest> {
+    let as_of = as_of
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let pool = db::pool(&app).await?;
+    digest(&pool, manager_id, as_of).await
+}
+
+/// Queues today's digest for every manager with an email address and
+/// something to report.
+#[tauri::command]
+pub async fn email_manager_digests(app: AppHandle) -> Result<usize> {
+    let pool = db::pool(&app).await?;
+    queue_digests(&pool, period::today()).await
+}
+
+pub async fn digest(pool: &SqlitePool, manager_id: i64, as_of: NaiveDate) -> Result<ManagerDigest> {
+    let manager: Option<(String,)> =
+        sqlx::query_as("SELECT name FROM managers WHERE manager_id = ?1")
+            .bind(manager_id)
+            .fetch_optional(pool)
+            .await?;
+    let Some((manager_name,)) = manager else {
+        return Err(Error::NotFound(format!("manager {manager_id}")));
+    };
+    let report = aging::arrears_aging(pool, as_of, None, Some(manager_id)).await?;
+    let mut overdue_accounts: Vec<TenantAging> = report
+        .tenants
+        .into_iter()
+        .filter(|tenant| tenant.buckets.overdue() > 0.005)
+        .collect();
+    overdue_accounts.sort_by(|a, b| b.buckets.overdue().total_cmp(&a.buckets.overdue()));
+    let open_complaints = sqlx::query_as(
+        "SELECT c.complaint_id, c.description, c.status, u.unit_number, p.name AS property_name,
+                w.priority, w.due_at, COALESCE(w.due_at < ?2, 0) AS overdue
+         FROM complaints c
+         JOIN units u ON u.unit_id = c.unit_id
+         JOIN properties p ON p.property_id = u.property_id
+         LEFT JOIN work_orders w ON w.complaint_id = c.complaint_id
+         WHERE c.status <> 'Resolved' AND p.manager_id = ?1
+         ORDER BY w.due_at IS NULL, w.due_at, c.complaint_id",
+    )
+    .bind(manager_id)
+    .bind(as_of.to_string())
+    .fetch_all(pool)
+    .await?;
+    Ok(ManagerDigest {
+        manager_id,
+        manager_name,
+        as_of,
+        total_overdue: overdue_accounts.iter().map(|t| t.buckets.overdue()).sum(),
+        overdue_accounts,
+        open_complaints,
+    })
+}
+
+/// One digest per manager every `email.digest_days` (default 7).
+pub async fn queue_digests(pool: &SqlitePool, as_of: NaiveDate) -> Result<usize> {
+    let every: i64 = settings::get_or(pool, "email.digest_days", 7).await?;
+    let window = (as_of - NaiveDate::default()).num_days() / every.max(1);
+    let managers: Vec<(i64,)> = sqlx::query_as(
+        "SELECT manager_id FROM managers
+         WHERE email IS NOT NULL AND TRIM(email) <> ''
+         ORDER BY manager_id",
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut queued = 0;
+    for (manager_id,) in managers {
+        let digest = digest(pool, manager_id, as_of).await?;
+        if digest.is_empty() {
+            continue;
+        }
+        let inserted = email::queue(
+            pool,
+            &NewEmail {
+                tenant_id: None,
+                manager_id: Some(manager_id),
+                to_address: None,
+                kind: EmailKind::Digest,
+                subject: format!(
+                    "{}, {}",
+                    count(digest.overdue_accounts.len(), "overdue account"),
+                    count(digest.open_complaints.len(), "open complaint")
+                ),
+                body: body(&digest),
+                attachments: Vec::new(),
+                dedupe_key: Some(format!("digest:{manager_id}:{window}")),
+            },
+        )
+        .await?;
+        queued += usize::from(inserted.is_some());
+    }
+    Ok(queued)
+}
+
+fn body(digest: &ManagerDigest) -> String {
+    let mut out = format!(
+        "Hello {},\n\nHere is where your properties stand on {}.\n",
+        digest.manager_name,
+        digest.as_of.format("%-d %B %Y")
+    );
+
+    out.push_str(&format!(
+        "\nOVERDUE ACCOUNTS ({}, KES {} in total)\n",
+        digest.overdue_accounts.len(),
+        money(digest.total_overdue)
+    ));
+    if digest.overdue_accounts.is_empty() {
+        out.push_str("None.\n");
+    }
+    for tenant in &digest.overdue_accounts {
+        let unit = match (&tenant.unit_number, &tenant.property_name) {
+            (Some(unit), Some(property)) => format!("{unit}, {property}"),
+            (Some(unit), None) => unit.clone(),
+            (None, Some(property)) => property.clone(),
+            (None, None) => "no unit".into(),
+        };
+        out.push_str(&format!(
+            "- {} ({unit}): KES {}, oldest {} days past due",
+            tenant.tenant_name.as_deref().unwrap_or("Unknown tenant"),
+            money(tenant.buckets.overdue()),
+            tenant.oldest_days_past_due
+        ));
+        if let Some(phone) = &tenant.phone_number {
+            out.push_str(&format!(", {phone}"));
+        }
+        out.push('\n');
+    }
+
+    out.push_str(&format!(
+        "\nOPEN COMPLAINTS ({})\n",
+        digest.open_complaints.len()
+    ));
+    if digest.open_complaints.is_empty() {
+        out.push_str("None.\n");
+    }
+    for complaint in &digest.open_complaints {
+        out.push_str(&format!(
+            "- #{} {}, {} [{}{}]: {}",
+            complaint.complaint_id,
+            complaint.unit_number,
+            complaint.property_name,
+            complaint.status,
+            complaint
+                .priority
+                .as_deref()
+                .map(|p| format!(", {p}"))
+                .unwrap_or_default(),
+            complaint.description.lines().next().unwrap_or_default()
+        ));
+        if complaint.overdue {
+            out.push_str(" (past due)");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// `1 open complaint`, `3 open complaints`.
+fn count(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("1 {noun}")
+    } else {
+        format!("{n} {noun}s")
+    }
+}
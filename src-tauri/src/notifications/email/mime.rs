@@ -0,0 +1,278 @@
+//! Builds the RFC 5322 text of a message: a plain-text body and, when there
+//! are attachments, a `multipart/mixed` wrapper around it.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+
+/// Longest line allowed by RFC 5322, less the CRLF.
+const MAX_LINE: usize = 998;
+/// Base64 is wrapped to this width.
+const BASE64_LINE: usize = 76;
+
+pub struct Part<'a> {
+    pub file_name: &'a str,
+    pub content_type: &'a str,
+    pub content: &'a [u8],
+}
+
+pub struct Message<'a> {
+    /// `Name <address>` or a bare address.
+    pub from: &'a str,
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub attachments: Vec<Part<'a>>,
+    pub date: DateTime<Utc>,
+    /// Globally unique, e.g. `42.1700000000@rentals.example.com`.
+    pub message_id: String,
+}
+
+impl Message<'_> {
+    pub fn render(&self) -> Vec<u8> {
+        let mut out = String::new();
+        header(&mut out, "From", &encode_mailbox(self.from));
+        header(&mut out, "To", &encode_mailbox(self.to));
+        header(&mut out, "Subject", &encode_word(self.subject));
+        header(&mut out, "Date", &self.date.to_rfc2822());
+        header(&mut out, "Message-ID", &format!("<{}>", self.message_id));
+        header(&mut out, "MIME-Version", "1.0");
+        if self.attachments.is_empty() {
+            text_part(&mut out, self.body);
+            return out.into_bytes();
+        }
+        let boundary = format!("=_part_{}", self.message_id.replace(['@', '.'], "_"));
+        header(
+            &mut out,
+            "Content-Type",
+            &format!("multipart/mixed; boundary=\"{boundary}\""),
+        );
+        out.push_str("\r\nThis is a multi-part message in MIME format.\r\n");
+        out.push_str(&format!("--{boundary}\r\n"));
+        text_part(&mut out, self.body);
+        for part in &self.attachments {
+            out.push_str(&format!("\r\n--{boundary}\r\n"));
+            let name = encode_word(&part.file_name.replace(['"', '\\'], ""));
+            header(
+                &mut out,
+                "Content-Type",
+                &format!("{}; name=\"{name}\"", part.content_type),
+            );
+            header(&mut out, "Content-Transfer-Encoding", "base64");
+            header(
+                &mut out,
+                "Content-Disposition",
+                &format!("attachment; filename=\"{name}\""),
+            );
+            out.push_str("\r\n");
+            base64_lines(&mut out, part.content);
+        }
+        out.push_str(&format!("\r\n--{boundary}--\r\n"));
+        out.into_bytes()
+    }
+}
+
+/// The address in `Name <address>`, or the whole value when there are no
+/// brackets. `None` unless it looks like `local@domain`.
+pub fn address(mailbox: &str) -> Option<&str> {
+    let mailbox = mailbox.trim();
+    let address = match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(open), Some(close)) if open < close => &mailbox[open + 1..close],
+        (None, None) => mailbox,
+        _ => return None,
+    };
+    let (local, domain) = address.rsplit_once('@')?;
+    let valid = !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && address
+            .chars()
+            .all(|c| c.is_ascii_graphic() && !"<>()[],;:\"\\".contains(c));
+    valid.then_some(address)
+}
+
+fn header(out: &mut String, name: &str, value: &str) {
+    // Values come from settings and tenant records; a line break would let
+    // them add headers of their own.
+    let value: String = value
+        .chars()
+        .map(|c| if c == '\r' || c == '\n' { ' ' } else { c })
+        .collect();
+    out.push_str(&format!("{name}: {value}\r\n"));
+}
+
+fn text_part(out: &mut String, body: &str) {
+    header(out, "Content-Type", "text/plain; charset=utf-8");
+    let plain = body.is_ascii() && body.lines().all(|line| line.len() <= MAX_LINE);
+    if plain {
+        header(out, "Content-Transfer-Encoding", "7bit");
+        out.push_str("\r\n");
+        for line in body.lines() {
+            out.push_str(line);
+            out.push_str("\r\n");
+        }
+    } else {
+        header(out, "Content-Transfer-Encoding", "base64");
+        out.push_str("\r\n");
+        base64_lines(
+            out,
+            body.replace("\r\n", "\n").replace('\n', "\r\n").as_bytes(),
+        );
+    }
+}
+
+fn base64_lines(out: &mut String, content: &[u8]) {
+    let encoded = STANDARD.encode(content);
+    for chunk in encoded.as_bytes().chunks(BASE64_LINE) {
+        // Base64 output is ASCII.
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+}
+
+/// An RFC 2047 encoded word when `text` is not plain ASCII.
+fn encode_word(text: &str) -> String {
+    if text.is_ascii() {
+        text.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", STANDARD.encode(text))
+    }
+}
+
+/// Encodes the display name of `Name <address>`, leaving the address alone.
+fn encode_mailbox(mailbox: &str) -> String {
+    match mailbox.rsplit_once('<') {
+        Some((name, rest)) if !name.trim().is_empty() => {
+            let name = name.trim().trim_matches('"');
+            if name.is_ascii() {
+                format!("\"{}\" <{rest}", name.replace(['"', '\\'], ""))
+            } else {
+                format!("{} <{rest}", encode_word(name))
+            }
+        }
+        _ => mailbox.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn validates_addresses() {
+        assert_eq!(address("jane@example.com"), Some("jane@example.com"));
+        assert_eq!(
+            address(" Jane Wanjiku <jane@mail.example.co.ke> "),
+            Some("jane@mail.example.co.ke")
+        );
+        for bad in [
+            "",
+            "jane",
+            "@example.com",
+            "jane@localhost",
+            "jane@.example.com",
+            "jane@example.com.",
+            "jane doe@example.com",
+            "Jane <jane@example.com",
+            "Jane jane@example.com>",
+            "<jane@exa(mple).com>",
+            "jane@example.com\r\nBcc: x@example.com",
+        ] {
+            assert_eq!(address(bad), None, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn header_values_cannot_add_headers() {
+        let mut out = String::new();
+        header(
+            &mut out,
+            "Subject",
+            "Rent\r\nBcc: everyone@example.com\nX: y",
+        );
+        assert_eq!(out, "Subject: Rent  Bcc: everyone@example.com X: y\r\n");
+    }
+
+    #[test]
+    fn encodes_non_ascii_display_names() {
+        assert_eq!(encode_word("Rent due"), "Rent due");
+        assert_eq!(
+            encode_word("Kodi ya Mwezi – Mei"),
+            format!("=?UTF-8?B?{}?=", STANDARD.encode("Kodi ya Mwezi – Mei"))
+        );
+        assert_eq!(
+            encode_mailbox("Zoë Akinyi <zoe@example.com>"),
+            format!(
+                "=?UTF-8?B?{}?= <zoe@example.com>",
+                STANDARD.encode("Zoë Akinyi")
+            )
+        );
+        assert_eq!(
+            encode_mailbox("\"Acme \\\"Rentals\" <office@example.com>"),
+            "\"Acme Rentals\" <office@example.com>"
+        );
+        assert_eq!(encode_mailbox(" office@example.com "), "office@example.com");
+    }
+
+    #[test]
+    fn renders_a_plain_message() {
+        let message = Message {
+            from: "Acme Rentals <office@example.com>",
+            to: "jane@example.com",
+            subject: "Receipt\r\nBcc: x@example.com",
+            body: "Thank you.\nPaid in full.",
+            attachments: Vec::new(),
+            date: Utc.with_ymd_and_hms(2024, 5, 1, 8, 30, 0).unwrap(),
+            message_id: "42.1714552200@example.com".into(),
+        };
+        let text = String::from_utf8(message.render()).unwrap();
+        assert_eq!(
+            text,
+            "From: \"Acme Rentals\" <office@example.com>\r\n\
+             To: jane@example.com\r\n\
+             Subject: Receipt  Bcc: x@example.com\r\n\
+             Date: Wed, 1 May 2024 08:30:00 +0000\r\n\
+             Message-ID: <42.1714552200@example.com>\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Transfer-Encoding: 7bit\r\n\
+             \r\n\
+             Thank you.\r\n\
+             Paid in full.\r\n"
+        );
+    }
+
+    #[test]
+    fn attaches_files_in_base64() {
+        let message = Message {
+            from: "office@example.com",
+            to: "jane@example.com",
+            subject: "Statement",
+            body: "Asante sana – see attached.",
+            attachments: vec![Part {
+                file_name: "statement \"May\".pdf",
+                content_type: "application/pdf",
+                content: b"%PDF-1.4",
+            }],
+            date: Utc.with_ymd_and_hms(2024, 5, 1, 8, 30, 0).unwrap(),
+            message_id: "7.1@example.com".into(),
+        };
+        let text = String::from_utf8(message.render()).unwrap();
+        assert!(
+            text.contains("Content-Type: multipart/mixed; boundary=\"=_part_7_1_example_com\"\r\n")
+        );
+        // Non-ASCII body text goes out in base64.
+        assert!(text.contains(&format!(
+            "Content-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+            STANDARD.encode("Asante sana – see attached.")
+        )));
+        assert!(
+            text.contains("Content-Disposition: attachment; filename=\"statement May.pdf\"\r\n")
+        );
+        assert!(text.contains(&format!("\r\n\r\n{}\r\n", STANDARD.encode(b"%PDF-1.4"))));
+        assert!(text.ends_with("\r\n--=_part_7_1_example_com--\r\n"));
+    }
+}
@@ -0,0 +1,509 @@
+//! The email outbox and the SMTP server it sends through.
+//!
+//! `smtp.host` and `smtp.from` turn email on. `smtp.security` is
+//! `starttls` (the default), `tls` or `none`; `none` with a local server
+//! such as MailHog is the way to try messages out without sending them.
+//! Attachments are stored with the message, so a retry sends exactly what
+//! was queued.
+
+mod mime;
+pub mod smtp;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use super::{Channel, DispatchRun};
+use crate::db;
+use crate::error::{Error, Result};
+use crate::settings;
+
+pub use mime::address;
+use smtp::{Outcome, Security};
+
+/// Messages sent per dispatch run; each is a connection to the server.
+const BATCH_SIZE: i64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum EmailStatus {
+    Queued,
+    Sent,
+    Failed,
+    /// Never sent: the tenant opted out, had no usable address, or it was
+    /// cancelled.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum EmailKind {
+    Statement,
+    Receipt,
+    Digest,
+    Custom,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailMessage {
+    pub email_id: i64,
+    pub tenant_id: Option<i64>,
+    pub manager_id: Option<i64>,
+    pub recipient_name: Option<String>,
+    pub to_address: String,
+    pub kind: EmailKind,
+    pub subject: String,
+    pub body: String,
+    pub attachments: i64,
+    pub status: EmailStatus,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub created_at: Option<String>,
+    pub sent_at: Option<String>,
+}
+
+const EMAIL_COLUMNS: &str = "e.email_id, e.tenant_id, e.manager_id,
+            COALESCE(t.full_name, m.name) AS recipient_name, e.to_address, e.kind, e.subject,
+            e.body,
+            (SELECT COUNT(*) FROM email_attachments a WHERE a.email_id = e.email_id) AS attachments,
+            e.status, e.attempts, e.last_error, e.created_at, e.sent_at
+         FROM email_outbox e
+         LEFT JOIN tenants t ON t.tenant_id = e.tenant_id
+         LEFT JOIN managers m ON m.manager_id = e.manager_id";
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn pdf(file_name: impl Into<String>, content: Vec<u8>) -> Self {
+        Self {
+            file_name: file_name.into(),
+            content_type: "application/pdf".into(),
+            content,
+        }
+    }
+}
+
+/// A message to queue.
+#[derive(Debug, Clone)]
+pub struct NewEmail {
+    pub tenant_id: Option<i64>,
+    pub manager_id: Option<i64>,
+    /// Defaults to the tenant's or manager's email address.
+    pub to_address: Option<String>,
+    pub kind: EmailKind,
+    pub subject: String,
+    pub body: String,
+    pub attachments: Vec<Attachment>,
+    /// A second message with the same key is not queued, e.g.
+    /// `statement:7:2025-03` for tenant 7's March statement.
+    pub dedupe_key: Option<String>,
+}
+
+/// The SMTP server and sender address from the settings.
+#[derive(Debug, Clone)]
+pub struct Mailer {
+    pub smtp: smtp::Config,
+    /// `Name <address>` or a bare address.
+    pub from: String,
+}
+
+impl Mailer {
+    /// Sends one message straight away, outside the outbox.
+    pub async fn send(&self, to: &str, message: &mime::Message<'_>) -> Result<Outcome> {
+        let from = address(&self.from).ok_or_else(|| {
+            Error::InvalidInput(format!("'{}' is not a valid sender address", self.from))
+        })?;
+        let to = address(to)
+            .ok_or_else(|| Error::InvalidInput(format!("'{to}' is not a valid email address")))?;
+        smtp::send(&self.smtp, from, &[to], &message.render()).await
+    }
+
+    fn message_id(&self, email_id: i64) -> String {
+        let domain = address(&self.from)
+            .and_then(|from| from.rsplit_once('@'))
+            .map_or("localhost", |(_, domain)| domain);
+        let stamp = chrono::Utc::now().timestamp_micros();
+        format!("{email_id}.{stamp}@{domain}")
+    }
+}
+
+/// Outbox messages, newest first.
+#[tauri::command]
+pub async fn get_email_outbox(
+    app: AppHandle,
+    status: Option<EmailStatus>,
+    tenant_id: Option<i64>,
+    limit: Option<i64>,
+) -> Result<Vec<EmailMessage>> {
+    let pool = db::pool(&app).await?;
+    let messages = sqlx::query_as(&format!(
+        "SELECT {EMAIL_COLUMNS}
+         WHERE (?1 IS NULL OR e.status = ?1) AND (?2 IS NULL OR e.tenant_id = ?2)
+         ORDER BY e.email_id DESC
+         LIMIT ?3"
+    ))
+    .bind(status)
+    .bind(tenant_id)
+    .bind(limit.unwrap_or(200))
+    .fetch_all(&pool)
+    .await?;
+    Ok(messages)
+}
+
+/// Queues a one-off message to a tenant or any address and sends it right
+/// away if a mail server is configured.
+#[tauri::command]
+pub async fn send_email(
+    app: AppHandle,
+    tenant_id: Option<i64>,
+    to_address: Option<String>,
+    subject: String,
+    body: String,
+) -> Result<EmailMessage> {
+    if subject.trim().is_empty() || body.trim().is_empty() {
+        return Err(Error::InvalidInput(
+            "subject and message are required".into(),
+        ));
+    }
+    if tenant_id.is_none() && to_address.is_none() {
+        return Err(Error::InvalidInput(
+            "choose a tenant or enter an email address".into(),
+        ));
+    }
+    let pool = db::pool(&app).await?;
+    let email_id = queue(
+        &pool,
+        &NewEmail {
+            tenant_id,
+            manager_id: None,
+            to_address,
+            kind: EmailKind::Custom,
+            subject: subject.trim().to_string(),
+            body: body.trim().to_string(),
+            attachments: Vec::new(),
+            dedupe_key: None,
+        },
+    )
+    .await?
+    .ok_or_else(|| Error::InvalidInput("message was not queued".into()))?;
+    if let Some(mailer) = mailer(&pool).await? {
+        dispatch(&pool, &mailer).await?;
+    }
+    load(&pool, email_id).await
+}
+
+/// Sends a short message directly, to check the mail settings. Errors come
+/// back as they are instead of being queued for a retry.
+#[tauri::command]
+pub async fn send_test_email(app: AppHandle, to_address: String) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let Some(mailer) = mailer(&pool).await? else {
+        return Err(Error::InvalidInput("no mail server is configured".into()));
+    };
+    let message = mime::Message {
+        from: &mailer.from,
+        to: &to_address,
+        subject: "Test message",
+        body: "This is a test message from the rental manager. Email is set up correctly.",
+        attachments: Vec::new(),
+        date: chrono::Utc::now(),
+        message_id: mailer.message_id(0),
+    };
+    match mailer.send(&to_address, &message).await? {
+        Outcome::Sent { .. } => Ok(()),
+        Outcome::Rejected(reason) => Err(Error::Delivery(reason)),
+    }
+}
+
+/// Sends everything queued now instead of waiting for the worker.
+#[tauri::command]
+pub async fn send_pending_email(app: AppHandle) -> Result<DispatchRun> {
+    let pool = db::pool(&app).await?;
+    let Some(mailer) = mailer(&pool).await? else {
+        return Err(Error::InvalidInput("no mail server is configured".into()));
+    };
+    dispatch(&pool, &mailer).await
+}
+
+/// Queues a failed message for another try.
+#[tauri::command]
+pub async fn retry_email(app: AppHandle, email_id: i64) -> Result<EmailMessage> {
+    let pool = db::pool(&app).await?;
+    set_status(&pool, email_id, EmailStatus::Failed, EmailStatus::Queued).await
+}
+
+/// Stops a queued message from being sent.
+#[tauri::command]
+pub async fn cancel_email(app: AppHandle, email_id: i64) -> Result<EmailMessage> {
+    let pool = db::pool(&app).await?;
+    set_status(&pool, email_id, EmailStatus::Queued, EmailStatus::Skipped).await
+}
+
+/// The configured server, or `None` when `smtp.host` is unset.
+pub async fn mailer(pool: &SqlitePool) -> Result<Option<Mailer>> {
+    let setting = |value: Option<String>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let Some(host) = setting(settings::get(pool, "smtp.host").await?) else {
+        return Ok(None);
+    };
+    let security = match setting(settings::get(pool, "smtp.security").await?).as_deref() {
+        None | Some("starttls") => Security::StartTls,
+        Some("tls") => Security::Tls,
+        Some("none") => Security::None,
+        Some(other) => {
+            return Err(Error::InvalidInput(format!(
+                "unknown SMTP security '{other}'; use starttls, tls or none"
+            )))
+        }
+    };
+    let from = setting(settings::get(pool, "smtp.from").await?)
+        .ok_or_else(|| Error::InvalidInput("setting smtp.from is required".into()))?;
+    if address(&from).is_none() {
+        return Err(Error::InvalidInput(format!(
+            "smtp.from '{from}' is not a valid email address"
+        )));
+    }
+    let mut config = smtp::Config::new(host, security);
+    if let Some(port) = setting(settings::get(pool, "smtp.port").await?) {
+        config.port = port
+            .parse()
+            .map_err(|_| Error::InvalidInput(format!("smtp.port '{port}' is not a port number")))?;
+    }
+    config.username = setting(settings::get(pool, "smtp.username").await?);
+    config.password = settings::get(pool, "smtp.password").await?;
+    if let Some(hello) = setting(settings::get(pool, "smtp.hello").await?) {
+        config.hello = hello;
+    }
+    Ok(Some(Mailer { smtp: config, from }))
+}
+
+/// Adds a message to the outbox. Returns `None` when a message with the same
+/// dedupe key exists. Messages to tenants who opted out, or without a usable
+/// address, are recorded as skipped.
+pub async fn queue(pool: &SqlitePool, email: &NewEmail) -> Result<Option<i64>> {
+    let mut to = email.to_address.clone();
+    let mut skip = None;
+    if let Some(tenant_id) = email.tenant_id {
+        let tenant: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT email FROM tenants WHERE tenant_id = ?1")
+                .bind(tenant_id)
+                .fetch_optional(pool)
+                .await?;
+        let Some((tenant_email,)) = tenant else {
+            return Err(Error::NotFound(format!("tenant {tenant_id}")));
+        };
+        to = to.or(tenant_email);
+        if super::opted_out(pool, tenant_id, Channel::Email).await? {
+            skip = Some("tenant opted out");
+        }
+    }
+    if let Some(manager_id) = email.manager_id {
+        let manager: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT email FROM managers WHERE manager_id = ?1")
+                .bind(manager_id)
+                .fetch_optional(pool)
+                .await?;
+        let Some((manager_email,)) = manager else {
+            return Err(Error::NotFound(format!("manager {manager_id}")));
+        };
+        to = to.or(manager_email);
+    }
+    let raw = to.unwrap_or_default();
+    let valid = address(&raw).map(str::to_string);
+    if valid.is_none() && skip.is_none() {
+        skip = Some("no valid email address");
+    }
+    let status = if skip.is_some() {
+        EmailStatus::Skipped
+    } else {
+        EmailStatus::Queued
+    };
+
+    let mut tx = pool.begin().await?;
+    let inserted = sqlx::query(
+        "INSERT INTO email_outbox
+            (tenant_id, manager_id, to_address, kind, subject, body, dedupe_key, status, last_error)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+         ON CONFLICT (dedupe_key) DO NOTHING",
+    )
+    .bind(email.tenant_id)
+    .bind(email.manager_id)
+    .bind(valid.unwrap_or(raw))
+    .bind(email.kind)
+    .bind(&email.subject)
+    .bind(&email.body)
+    .bind(&email.dedupe_key)
+    .bind(status)
+    .bind(skip)
+    .execute(&mut *tx)
+    .await?;
+    if inserted.rows_affected() == 0 {
+        return Ok(None);
+    }
+    let email_id = inserted.last_insert_rowid();
+    for attachment in &email.attachments {
+        sqlx::query(
+            "INSERT INTO email_attachments (email_id, file_name, content_type, content)
+             VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(email_id)
+        .bind(&attachment.file_name)
+        .bind(&attachment.content_type)
+        .bind(&attachment.content)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(Some(email_id))
+}
+
+/// Sends queued messages that are due. Failures are retried with growing
+/// delays (5, 10, 20... minutes) up to `email.max_attempts` tries.
+pub async fn dispatch(pool: &SqlitePool, mailer: &Mailer) -> Result<DispatchRun> {
+    // The worker and the "send now" button must not send the same batch.
+    static RUNNING: AtomicBool = AtomicBool::new(false);
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(DispatchRun::default());
+    }
+    struct Done;
+    impl Drop for Done {
+        fn drop(&mut self) {
+            RUNNING.store(false, Ordering::SeqCst);
+        }
+    }
+    let _done = Done;
+
+    let max_attempts: i64 = settings::get_or(pool, "email.max_attempts", 5).await?;
+    let due: Vec<EmailMessage> = sqlx::query_as(&format!(
+        "SELECT {EMAIL_COLUMNS}
+         WHERE e.status = 'Queued'
+           AND (e.next_attempt_at IS NULL OR e.next_attempt_at <= datetime('now'))
+         ORDER BY e.email_id
+         LIMIT ?1"
+    ))
+    .bind(BATCH_SIZE)
+    .fetch_all(pool)
+    .await?;
+
+    let mut run = DispatchRun::default();
+    for email in due {
+        let attachments: Vec<(String, String, Vec<u8>)> = sqlx::query_as(
+            "SELECT file_name, content_type, content FROM email_attachments
+             WHERE email_id = ?1 ORDER BY attachment_id",
+        )
+        .bind(email.email_id)
+        .fetch_all(pool)
+        .await?;
+        let to = match &email.recipient_name {
+            Some(name) => format!("{name} <{}>", email.to_address),
+            None => email.to_address.clone(),
+        };
+        let message = mime::Message {
+            from: &mailer.from,
+            to: &to,
+            subject: &email.subject,
+            body: &email.body,
+            attachments: attachments
+                .iter()
+                .map(|(file_name, content_type, content)| mime::Part {
+                    file_name,
+                    content_type,
+                    content,
+                })
+                .collect(),
+            date: chrono::Utc::now(),
+            message_id: mailer.message_id(email.email_id),
+        };
+        match mailer.send(&email.to_address, &message).await {
+            Ok(Outcome::Sent { .. }) => {
+                sqlx::query(
+                    "UPDATE email_outbox
+                     SET status = 'Sent', attempts = attempts + 1, last_error = NULL,
+                         sent_at = CURRENT_TIMESTAMP
+                     WHERE email_id = ?1",
+                )
+                .bind(email.email_id)
+                .execute(pool)
+                .await?;
+                run.sent += 1;
+            }
+            Ok(Outcome::Rejected(reason)) => {
+                sqlx::query(
+                    "UPDATE email_outbox
+                     SET status = 'Failed', attempts = attempts + 1, last_error = ?1
+                     WHERE email_id = ?2",
+                )
+                .bind(reason)
+                .bind(email.email_id)
+                .execute(pool)
+                .await?;
+                run.failed += 1;
+            }
+            Err(err) => {
+                let give_up = email.attempts + 1 >= max_attempts;
+                sqlx::query(
+                    "UPDATE email_outbox
+                     SET status = CASE WHEN ?1 THEN 'Failed' ELSE 'Queued' END,
+                         attempts = attempts + 1, last_error = ?2,
+                         next_attempt_at = datetime('now', '+' || (5 << MIN(attempts, 8)) || ' minutes')
+                     WHERE email_id = ?3",
+                )
+                .bind(give_up)
+                .bind(err.to_string())
+                .bind(email.email_id)
+                .execute(pool)
+                .await?;
+                if give_up {
+                    run.failed += 1;
+                } else {
+                    run.retrying += 1;
+                }
+            }
+        }
+    }
+    Ok(run)
+}
+
+async fn set_status(
+    pool: &SqlitePool,
+    email_id: i64,
+    from: EmailStatus,
+    to: EmailStatus,
+) -> Result<EmailMessage> {
+    let email = load(pool, email_id).await?;
+    if email.status != from {
+        return Err(Error::InvalidInput(format!(
+            "email {email_id} is {:?}, not {from:?}",
+            email.status
+        )));
+    }
+    sqlx::query(
+        "UPDATE email_outbox
+         SET status = ?1, attempts = CASE WHEN ?1 = 'Queued' THEN 0 ELSE attempts END,
+             next_attempt_at = NULL,
+             last_error = CASE WHEN ?1 = 'Skipped' THEN 'cancelled' ELSE last_error END
+         WHERE email_id = ?2",
+    )
+    .bind(to)
+    .bind(email_id)
+    .execute(pool)
+    .await?;
+    load(pool, email_id).await
+}
+
+pub async fn load(pool: &SqlitePool, email_id: i64) -> Result<EmailMessage> {
+    let email = sqlx::query_as(&format!("SELECT {EMAIL_COLUMNS} WHERE e.email_id = ?1"))
+        .bind(email_id)
+        .fetch_optional(pool)
+        .await?;
+    email.ok_or_else(|| Error::NotFound(format!("email {email_id}")))
+}
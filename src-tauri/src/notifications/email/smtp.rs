@@ -0,0 +1,465 @@
+//! An SMTP client for one message per connection: EHLO, optional STARTTLS
+//! and AUTH, then MAIL, RCPT and DATA.
+
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::error::{Error, Result};
+use crate::net;
+
+/// How long to wait for each reply; servers may take a while after DATA.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    /// Plain connection upgraded with STARTTLS, usually port 587.
+    StartTls,
+    /// TLS from the first byte, usually port 465.
+    Tls,
+    /// No encryption, for a local relay or a test server.
+    None,
+}
+
+impl Security {
+    fn default_port(self) -> u16 {
+        match self {
+            Security::StartTls => 587,
+            Security::Tls => 465,
+            Security::None => 25,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub security: Security,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Name given in EHLO.
+    pub hello: String,
+}
+
+impl Config {
+    pub fn new(host: impl Into<String>, security: Security) -> Self {
+        Self {
+            host: host.into(),
+            port: security.default_port(),
+            security,
+            username: None,
+            password: None,
+            hello: "localhost".into(),
+        }
+    }
+}
+
+/// What the server said to a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// Accepted for at least one recipient. `rejected` lists the others with
+    /// the server's reply, e.g. `a@example.com: 550 no such user`.
+    Sent { rejected: Vec<String> },
+    /// Refused for good (a 5xx reply), e.g. an unknown mailbox; it is not
+    /// retried.
+    Rejected(String),
+}
+
+struct Reply {
+    code: u16,
+    lines: Vec<String>,
+}
+
+impl Reply {
+    fn text(&self) -> String {
+        format!("{} {}", self.code, self.lines.join(" "))
+    }
+}
+
+struct Session<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Session<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    async fn read_reply(&mut self) -> Result<Reply> {
+        let read = async {
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                if self.stream.read_line(&mut line).await? == 0 {
+                    return Err(Error::Delivery(
+                        "the mail server closed the connection".into(),
+                    ));
+                }
+                let line = line.trim_end();
+                let code = line
+                    .get(..3)
+                    .and_then(|code| code.parse::<u16>().ok())
+                    .ok_or_else(|| Error::Delivery(format!("unexpected reply '{line}'")))?;
+                lines.push(line.get(4..).unwrap_or_default().to_string());
+                if line.as_bytes().get(3) != Some(&b'-') {
+                    return Ok(Reply { code, lines });
+                }
+            }
+        };
+        tokio::time::timeout(REPLY_TIMEOUT, read)
+            .await
+            .map_err(|_| Error::Delivery("timed out waiting for the mail server".into()))?
+    }
+
+    async fn command(&mut self, line: &str) -> Result<Reply> {
+        let stream = self.stream.get_mut();
+        stream.write_all(line.as_bytes()).await?;
+        stream.write_all(b"\r\n").await?;
+        stream.flush().await?;
+        self.read_reply().await
+    }
+
+    /// Sends `line` and fails unless the reply code is `expected`.
+    async fn expect(&mut self, line: &str, expected: u16) -> Result<Reply> {
+        let reply = self.command(line).await?;
+        if reply.code != expected {
+            return Err(Error::Delivery(reply.text()));
+        }
+        Ok(reply)
+    }
+
+    /// EHLO, returning the extensions the server offers, upper-cased.
+    async fn hello(&mut self, name: &str) -> Result<Vec<String>> {
+        let reply = self.expect(&format!("EHLO {name}"), 250).await?;
+        Ok(reply
+            .lines
+            .iter()
+            .skip(1)
+            .map(|line| line.to_ascii_uppercase())
+            .collect())
+    }
+
+    async fn authenticate(&mut self, extensions: &[String], user: &str, pass: &str) -> Result<()> {
+        let mechanisms = extensions
+            .iter()
+            .find_map(|line| line.strip_prefix("AUTH").map(str::to_string))
+            .ok_or_else(|| {
+                Error::Delivery("the mail server does not accept a username and password".into())
+            })?;
+        let offers = |name: &str| mechanisms.split_whitespace().any(|m| m == name);
+        let reply = if offers("PLAIN") {
+            let token = STANDARD.encode(format!("\0{user}\0{pass}"));
+            self.command(&format!("AUTH PLAIN {token}")).await?
+        } else if offers("LOGIN") {
+            self.expect("AUTH LOGIN", 334).await?;
+            self.expect(&STANDARD.encode(user), 334).await?;
+            self.command(&STANDARD.encode(pass)).await?
+        } else {
+            return Err(Error::Delivery(format!(
+                "no supported login method among '{}'",
+                mechanisms.trim()
+            )));
+        };
+        if reply.code != 235 {
+            return Err(Error::Delivery(format!("login refused: {}", reply.text())));
+        }
+        Ok(())
+    }
+
+    /// MAIL, RCPT and DATA. 5xx replies are a rejection, anything else
+    /// unexpected an error. A recipient the server refuses is skipped; the
+    /// message is only rejected when every one is. A 4xx reply to any RCPT
+    /// fails the whole transfer before DATA, so nothing has gone out and the
+    /// outbox retries the message later for every recipient.
+    async fn transfer(&mut self, from: &str, to: &[&str], data: &[u8]) -> Result<Outcome> {
+        if to.is_empty() {
+            return Err(Error::InvalidInput("a message needs a recipient".into()));
+        }
+        let mail = self.command(&format!("MAIL FROM:<{from}>")).await?;
+        if let Some(outcome) = check(&mail, 250)? {
+            return Ok(outcome);
+        }
+        let mut rejected = Vec::new();
+        for to in to {
+            let reply = self.command(&format!("RCPT TO:<{to}>")).await?;
+            // 251: not local, but the server will forward it.
+            if reply.code == 251 {
+                continue;
+            }
+            // `check` turns a 4xx (e.g. greylisting) into an error.
+            if let Some(Outcome::Rejected(reason)) = check(&reply, 250)? {
+                rejected.push(format!("{to}: {reason}"));
+            }
+        }
+        if rejected.len() == to.len() {
+            self.command("RSET").await.ok();
+            return Ok(Outcome::Rejected(rejected.join("; ")));
+        }
+        let reply = self.command("DATA").await?;
+        if let Some(outcome) = check(&reply, 354)? {
+            self.command("RSET").await.ok();
+            return Ok(outcome);
+        }
+        let stream = self.stream.get_mut();
+        stream.write_all(&dot_stuff(data)).await?;
+        stream.write_all(b".\r\n").await?;
+        stream.flush().await?;
+        let reply = self.read_reply().await?;
+        Ok(check(&reply, 250)?.unwrap_or(Outcome::Sent { rejected }))
+    }
+}
+
+/// `None` when the reply is the expected one.
+fn check(reply: &Reply, expected: u16) -> Result<Option<Outcome>> {
+    match reply.code {
+        code if code == expected => Ok(None),
+        500..=599 => Ok(Some(Outcome::Rejected(reply.text()))),
+        _ => Err(Error::Delivery(reply.text())),
+    }
+}
+
+/// Sends one message. `data` is the full message with headers; `from` and
+/// `to` are bare addresses.
+pub async fn send(config: &Config, from: &str, to: &[&str], data: &[u8]) -> Result<Outcome> {
+    let stream = net::connect(&config.host, config.port).await?;
+    match config.security {
+        Security::Tls => {
+            let stream = net::tls(stream, &config.host).await?;
+            deliver(Session::new(stream), config, false, from, to, data).await
+        }
+        Security::StartTls => {
+            let mut session = Session::new(stream);
+            greet(&mut session).await?;
+            let extensions = session.hello(&config.hello).await?;
+            if !extensions.iter().any(|line| line == "STARTTLS") {
+                return Err(Error::Delivery(
+                    "the mail server does not support STARTTLS".into(),
+                ));
+            }
+            session.expect("STARTTLS", 220).await?;
+            let stream = net::tls(session.stream.into_inner(), &config.host).await?;
+            deliver(Session::new(stream), config, true, from, to, data).await
+        }
+        Security::None => deliver(Session::new(stream), config, false, from, to, data).await,
+    }
+}
+
+async fn greet<S: AsyncRead + AsyncWrite + Unpin>(session: &mut Session<S>) -> Result<()> {
+    let greeting = session.read_reply().await?;
+    if greeting.code != 220 {
+        return Err(Error::Delivery(greeting.text()));
+    }
+    Ok(())
+}
+
+/// Runs the session from EHLO; `greeted` when the greeting was read before
+/// STARTTLS.
+async fn deliver<S: AsyncRead + AsyncWrite + Unpin>(
+    mut session: Session<S>,
+    config: &Config,
+    greeted: bool,
+    from: &str,
+    to: &[&str],
+    data: &[u8],
+) -> Result<Outcome> {
+    if !greeted {
+        greet(&mut session).await?;
+    }
+    let extensions = session.hello(&config.hello).await?;
+    if let Some(user) = config.username.as_deref().filter(|u| !u.is_empty()) {
+        let pass = config.password.as_deref().unwrap_or_default();
+        session.authenticate(&extensions, user, pass).await?;
+    }
+    let outcome = session.transfer(from, to, data).await?;
+    // The message is accepted whatever QUIT says.
+    session.command("QUIT").await.ok();
+    Ok(outcome)
+}
+
+/// Normalizes line endings to CRLF and doubles a leading dot on any line, so
+/// the body cannot end the DATA section early. Ends with CRLF.
+fn dot_stuff(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 64);
+    let mut line_start = true;
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        if line_start && byte == b'.' {
+            out.push(b'.');
+        }
+        match byte {
+            b'\r' if data.get(i + 1) == Some(&b'\n') => {
+                out.extend_from_slice(b"\r\n");
+                i += 1;
+                line_start = true;
+            }
+            b'\r' | b'\n' => {
+                out.extend_from_slice(b"\r\n");
+                line_start = true;
+            }
+            _ => {
+                out.push(byte);
+                line_start = false;
+            }
+        }
+        i += 1;
+    }
+    if !line_start {
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncReadExt, DuplexStream};
+
+    use super::*;
+
+    /// A session whose server side has already queued `replies`; returns the
+    /// server end to read back what the client sent.
+    async fn scripted(replies: &str) -> (Session<DuplexStream>, DuplexStream) {
+        let (client, mut server) = duplex(64 * 1024);
+        server.write_all(replies.as_bytes()).await.unwrap();
+        (Session::new(client), server)
+    }
+
+    async fn sent(session: Session<DuplexStream>, mut server: DuplexStream) -> String {
+        drop(session);
+        let mut out = String::new();
+        server.read_to_string(&mut out).await.unwrap();
+        out
+    }
+
+    #[test]
+    fn dot_stuffs_and_normalizes_line_endings() {
+        assert_eq!(dot_stuff(b".hidden\r\n"), b"..hidden\r\n");
+        assert_eq!(dot_stuff(b"a\r\n.\r\nb\r\n"), b"a\r\n..\r\nb\r\n");
+        assert_eq!(dot_stuff(b"one\ntwo\n.three"), b"one\r\ntwo\r\n..three\r\n");
+        assert_eq!(
+            dot_stuff(b"one\rtwo\r.three\r"),
+            b"one\r\ntwo\r\n..three\r\n"
+        );
+        assert_eq!(
+            dot_stuff(b"no final line break"),
+            b"no final line break\r\n"
+        );
+        assert_eq!(dot_stuff(b"a.b\r\n"), b"a.b\r\n");
+        assert_eq!(dot_stuff(b""), b"");
+    }
+
+    #[test]
+    fn reads_multi_line_replies() {
+        tauri::async_runtime::block_on(async {
+            let (mut session, server) =
+                scripted("250-mail.example.com\r\n250-starttls\r\n250 AUTH PLAIN LOGIN\r\n").await;
+            let extensions = session.hello("rentals.local").await.unwrap();
+            assert_eq!(extensions, ["STARTTLS", "AUTH PLAIN LOGIN"]);
+            assert_eq!(sent(session, server).await, "EHLO rentals.local\r\n");
+
+            let (mut session, _server) = scripted("421-busy\r\n421 try later\r\n").await;
+            let reply = session.read_reply().await.unwrap();
+            assert_eq!(reply.code, 421);
+            assert_eq!(reply.text(), "421 busy try later");
+        });
+    }
+
+    #[test]
+    fn fails_on_garbled_or_closed_replies() {
+        tauri::async_runtime::block_on(async {
+            let (mut session, _server) = scripted("hello\r\n").await;
+            assert!(matches!(
+                session.read_reply().await,
+                Err(Error::Delivery(_))
+            ));
+
+            let (mut session, server) = scripted("250-first\r\n").await;
+            drop(server);
+            assert!(matches!(
+                session.read_reply().await,
+                Err(Error::Delivery(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn skips_a_rejected_recipient() {
+        tauri::async_runtime::block_on(async {
+            let (mut session, server) = scripted(
+                "250 ok\r\n550 5.1.1 no such user\r\n250 ok\r\n354 go ahead\r\n250 queued\r\n",
+            )
+            .await;
+            let outcome = session
+                .transfer(
+                    "office@example.com",
+                    &["gone@example.com", "tenant@example.com"],
+                    b"Subject: Rent\r\n\r\n.Paid\n",
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                outcome,
+                Outcome::Sent {
+                    rejected: vec!["gone@example.com: 550 5.1.1 no such user".into()]
+                }
+            );
+            assert_eq!(
+                sent(session, server).await,
+                "MAIL FROM:<office@example.com>\r\n\
+                 RCPT TO:<gone@example.com>\r\n\
+                 RCPT TO:<tenant@example.com>\r\n\
+                 DATA\r\n\
+                 Subject: Rent\r\n\r\n..Paid\r\n.\r\n"
+            );
+        });
+    }
+
+    #[test]
+    fn rejects_when_every_recipient_is_refused() {
+        tauri::async_runtime::block_on(async {
+            let (mut session, server) =
+                scripted("250 ok\r\n550 no such user\r\n553 bad mailbox\r\n250 reset\r\n").await;
+            let outcome = session
+                .transfer(
+                    "office@example.com",
+                    &["a@example.com", "b@example.com"],
+                    b"x",
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                outcome,
+                Outcome::Rejected(
+                    "a@example.com: 550 no such user; b@example.com: 553 bad mailbox".into()
+                )
+            );
+            assert!(sent(session, server).await.ends_with("RSET\r\n"));
+        });
+    }
+
+    #[test]
+    fn a_temporary_recipient_failure_fails_before_data() {
+        tauri::async_runtime::block_on(async {
+            let (mut session, server) =
+                scripted("250 ok\r\n250 ok\r\n450 4.2.0 greylisted\r\n").await;
+            let result = session
+                .transfer(
+                    "office@example.com",
+                    &["a@example.com", "b@example.com"],
+                    b"x",
+                )
+                .await;
+            assert!(
+                matches!(result, Err(Error::Delivery(reason)) if reason.contains("greylisted"))
+            );
+            assert!(!sent(session, server).await.contains("DATA"));
+        });
+    }
+}
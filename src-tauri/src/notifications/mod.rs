@@ -0,0 +1,285 @@
+//! Messages to tenants and managers: rent reminders, overdue notices and
+//! payment receipts by SMS; statements, receipts and manager digests by
+//! email; and broadcasts to a property, block or arrears list on either.
+//!
+//! Messages are written to an outbox first and sent by a worker that runs
+//! every minute, so nothing is lost while the gateway or mail server is down
+//! and a tenant never gets the same reminder twice. Tenants can opt out per
+//! channel.
+
+pub mod broadcasts;
+pub mod digest;
+pub mod email;
+pub mod reminders;
+pub mod sms;
+pub mod statements;
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Manager};
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::period;
+use crate::settings;
+
+const WORKER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Receipts are sent for payments dated this many days back, so a payment
+/// entered the morning after still gets one.
+const RECEIPT_LOOKBACK_DAYS: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Channel {
+    Sms,
+    Email,
+}
+
+/// What one pass over an outbox did.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DispatchRun {
+    pub sent: usize,
+    pub failed: usize,
+    /// Failed for now and queued for another attempt.
+    pub retrying: usize,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct OptOut {
+    pub tenant_id: i64,
+    pub tenant_name: String,
+    pub channel: Channel,
+    pub reason: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[tauri::command]
+pub async fn get_notification_opt_outs(
+    app: AppHandle,
+    tenant_id: Option<i64>,
+) -> Result<Vec<OptOut>> {
+    let pool = db::pool(&app).await?;
+    let opt_outs = sqlx::query_as(
+        "SELECT o.tenant_id, t.full_name AS tenant_name, o.channel, o.reason, o.created_at
+         FROM notification_opt_outs o JOIN tenants t ON t.tenant_id = o.tenant_id
+         WHERE ?1 IS NULL OR o.tenant_id = ?1
+         ORDER BY t.full_name, o.channel",
+    )
+    .bind(tenant_id)
+    .fetch_all(&pool)
+    .await?;
+    Ok(opt_outs)
+}
+
+/// Stops (or, with `opted_out` false, resumes) messages to a tenant on one
+/// channel. Messages already queued for them are skipped.
+#[tauri::command]
+pub async fn set_notification_opt_out(
+    app: AppHandle,
+    tenant_id: i64,
+    channel: Channel,
+    opted_out: bool,
+    reason: Option<String>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    set_opt_out(&pool, tenant_id, channel, opted_out, reason.as_deref()).await
+}
+
+pub async fn set_opt_out(
+    pool: &SqlitePool,
+    tenant_id: i64,
+    channel: Channel,
+    opted_out: bool,
+    reason: Option<&str>,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    if !opted_out {
+        sqlx::query("DELETE FROM notification_opt_outs WHERE tenant_id = ?1 AND channel = ?2")
+            .bind(tenant_id)
+            .bind(channel)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        return Ok(());
+    }
+    let exists: Option<(i64,)> =
+        sqlx::query_as("SELECT tenant_id FROM tenants WHERE tenant_id = ?1")
+            .bind(tenant_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if exists.is_none() {
+        return Err(Error::NotFound(format!("tenant {tenant_id}")));
+    }
+    sqlx::query(
+        "INSERT INTO notification_opt_outs (tenant_id, channel, reason) VALUES (?1, ?2, ?3)
+         ON CONFLICT (tenant_id, channel) DO UPDATE SET reason = excluded.reason",
+    )
+    .bind(tenant_id)
+    .bind(channel)
+    .bind(reason)
+    .execute(&mut *tx)
+    .await?;
+    match channel {
+        Channel::Sms => {
+            sqlx::query(
+                "UPDATE sms_outbox SET status = 'Skipped', last_error = 'tenant opted out'
+                 WHERE tenant_id = ?1 AND status = 'Queued'",
+            )
+            .bind(tenant_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        Channel::Email => {
+            sqlx::query(
+                "UPDATE email_outbox SET status = 'Skipped', last_error = 'tenant opted out'
+                 WHERE tenant_id = ?1 AND status = 'Queued'",
+            )
+            .bind(tenant_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn opted_out<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+    tenant_id: i64,
+    channel: Channel,
+) -> Result<bool> {
+    let row: Option<(i64,)> =
+        sqlx::query_as("SELECT 1 FROM notification_opt_outs WHERE tenant_id = ?1 AND channel = ?2")
+            .bind(tenant_id)
+            .bind(channel)
+            .fetch_optional(executor)
+            .await?;
+    Ok(row.is_some())
+}
+
+/// Who a tenant message is addressed to.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Recipient {
+    pub name: String,
+    pub unit_number: Option<String>,
+    pub property_name: Option<String>,
+}
+
+impl Recipient {
+    pub fn first_name(&self) -> &str {
+        self.name.split_whitespace().next().unwrap_or(&self.name)
+    }
+}
+
+/// A tenant with the unit of their latest lease, or of the tenant record
+/// when they have no lease.
+pub async fn tenant(pool: &SqlitePool, tenant_id: i64) -> Result<Recipient> {
+    let tenant = sqlx::query_as(
+        "SELECT t.full_name AS name, u.unit_number, p.name AS property_name
+         FROM tenants t
+         LEFT JOIN leases l ON l.lease_id = (SELECT lease_id FROM leases
+                                             WHERE tenant_id = t.tenant_id
+                                             ORDER BY lease_start_date DESC LIMIT 1)
+         LEFT JOIN units u ON u.unit_id = COALESCE(l.unit_id, t.unit_id)
+         LEFT JOIN properties p ON p.property_id = u.property_id
+         WHERE t.tenant_id = ?1",
+    )
+    .bind(tenant_id)
+    .fetch_optional(pool)
+    .await?;
+    tenant.ok_or_else(|| Error::NotFound(format!("tenant {tenant_id}")))
+}
+
+/// Puts a phone number in international form, `+254712345678`. Local
+/// numbers starting with 0 get `country_code`. `None` when it cannot be a
+/// phone number.
+pub fn normalize_phone(number: &str, country_code: &str) -> Option<String> {
+    let trimmed = number.trim();
+    let digits: String = trimmed.chars().filter(char::is_ascii_digit).collect();
+    if trimmed
+        .chars()
+        .any(|c| !(c.is_ascii_digit() || " +-().".contains(c)))
+    {
+        return None;
+    }
+    let international = if trimmed.starts_with('+') {
+        digits
+    } else if let Some(rest) = digits.strip_prefix("00") {
+        rest.to_string()
+    } else if let Some(rest) = digits.strip_prefix('0') {
+        format!("{country_code}{rest}")
+    } else if digits.starts_with(country_code) {
+        digits
+    } else if digits.len() == 9 {
+        // Kenyan numbers written without the leading 0, e.g. 712345678.
+        format!("{country_code}{digits}")
+    } else {
+        digits
+    };
+    (8..=15)
+        .contains(&international.len())
+        .then(|| format!("+{international}"))
+}
+
+/// Sends queued messages every minute while the app is open, and queues
+/// receipts for new payments.
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        db::migrated(&app).await;
+        loop {
+            match db::pool(&app).await {
+                Ok(pool) => {
+                    if let Err(err) = run_sms(&app, &pool).await {
+                        eprintln!("SMS worker failed: {err}");
+                    }
+                    if let Err(err) = run_email(&pool).await {
+                        eprintln!("email worker failed: {err}");
+                    }
+                }
+                Err(err) => eprintln!("message worker skipped: {err}"),
+            }
+            tokio::time::sleep(WORKER_INTERVAL).await;
+        }
+    });
+}
+
+async fn run_sms(app: &AppHandle, pool: &SqlitePool) -> Result<()> {
+    let Some(provider) = sms::provider(pool, &data_dir(app)?).await? else {
+        return Ok(());
+    };
+    if settings::get_or(pool, "sms.receipts", true).await? {
+        reminders::queue_receipts(pool, receipts_since()).await?;
+    }
+    sms::dispatch(pool, provider.as_ref()).await?;
+    sms::refresh_delivery(pool, provider.as_ref()).await?;
+    Ok(())
+}
+
+async fn run_email(pool: &SqlitePool) -> Result<()> {
+    let Some(mailer) = email::mailer(pool).await? else {
+        return Ok(());
+    };
+    if settings::get_or(pool, "email.receipts", true).await? {
+        statements::queue_receipts(pool, receipts_since()).await?;
+    }
+    email::dispatch(pool, &mailer).await?;
+    Ok(())
+}
+
+fn receipts_since() -> chrono::NaiveDate {
+    period::today() - chrono::Duration::days(RECEIPT_LOOKBACK_DAYS)
+}
+
+/// Where the file sink writes by default.
+fn data_dir(app: &AppHandle) -> Result<std::path::PathBuf> {
+    Ok(app
+        .path()
+        .app_data_dir()
+        .map_err(|_| std::io::Error::other("the app data directory is unavailable"))?)
+}
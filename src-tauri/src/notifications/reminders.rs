@@ -0,0 +1,323 @@
+//! Rent due reminders, overdue notices and payment receipts.
+//!
+//! The wording of each comes from a template in the settings
+//! (`sms.template.rent_due`, `sms.template.overdue`, `sms.template.receipt`)
+//! written in the same `{{field}}` language as letters, with a built-in
+//! default when unset.
+
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use super::sms::{self, MessageKind, NewSms};
+use crate::billing::allocations;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::period;
+use crate::settings;
+use crate::templates::Template;
+
+/// The kinds of message sent automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ReminderKind {
+    #[serde(rename = "Rent Due")]
+    RentDue,
+    Overdue,
+    Receipt,
+}
+
+impl ReminderKind {
+    const ALL: [ReminderKind; 3] = [
+        ReminderKind::RentDue,
+        ReminderKind::Overdue,
+        ReminderKind::Receipt,
+    ];
+
+    fn setting(self) -> &'static str {
+        match self {
+            ReminderKind::RentDue => "sms.template.rent_due",
+            ReminderKind::Overdue => "sms.template.overdue",
+            ReminderKind::Receipt => "sms.template.receipt",
+        }
+    }
+
+    fn default_body(self) -> &'static str {
+        match self {
+            ReminderKind::RentDue => {
+                "Dear {{tenant.first_name}}, your rent of KES {{amount}} for {{unit.number}}, \
+                 {{property.name}} is due on {{due_date}}. Thank you."
+            }
+            ReminderKind::Overdue => {
+                "Dear {{tenant.first_name}}, KES {{balance}} for {{unit.number}}, \
+                 {{property.name}} is overdue since {{due_date}}. Please pay promptly to avoid \
+                 late fees."
+            }
+            ReminderKind::Receipt => {
+                "Dear {{tenant.first_name}}, we have received KES {{amount}} on {{date}}\
+                 {{#if reference}}, ref {{reference}}{{/if}}. {{#if balance}}Balance due: KES \
+                 {{balance}}.{{else}}Your account is fully paid.{{/if}} Thank you."
+            }
+        }
+    }
+
+    /// Fields beyond the tenant, unit and property ones every message has.
+    fn fields(self) -> &'static [&'static str] {
+        match self {
+            ReminderKind::RentDue => &["amount", "due_date", "description"],
+            ReminderKind::Overdue => &["balance", "due_date"],
+            ReminderKind::Receipt => &["amount", "date", "reference", "balance"],
+        }
+    }
+
+    fn message_kind(self) -> MessageKind {
+        match self {
+            ReminderKind::RentDue => MessageKind::RentDue,
+            ReminderKind::Overdue => MessageKind::Overdue,
+            ReminderKind::Receipt => MessageKind::Receipt,
+        }
+    }
+}
+
+const COMMON_FIELDS: &[&str] = &[
+    "tenant.name",
+    "tenant.first_name",
+    "unit.number",
+    "property.name",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SmsTemplate {
+    pub kind: ReminderKind,
+    pub body: String,
+    /// Whether the built-in wording is in use.
+    pub is_default: bool,
+    pub fields: Vec<&'static str>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReminderRun {
+    pub rent_due: usize,
+    pub overdue: usize,
+}
+
+#[tauri::command]
+pub async fn get_sms_templates(app: AppHandle) -> Result<Vec<SmsTemplate>> {
+    let pool = db::pool(&app).await?;
+    let mut templates = Vec::new();
+    for kind in ReminderKind::ALL {
+        let stored = settings::get(&pool, kind.setting()).await?;
+        templates.push(SmsTemplate {
+            kind,
+            is_default: stored.is_none(),
+            body: stored.unwrap_or_else(|| kind.default_body().to_string()),
+            fields: COMMON_FIELDS.iter().chain(kind.fields()).copied().collect(),
+        });
+    }
+    Ok(templates)
+}
+
+/// Saves the wording for one kind of message; an empty body restores the
+/// default.
+#[tauri::command]
+pub async fn save_sms_template(app: AppHandle, kind: ReminderKind, body: String) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    if body.trim().is_empty() {
+        sqlx::query("DELETE FROM settings WHERE key = ?1")
+            .bind(kind.setting())
+            .execute(&pool)
+            .await?;
+        return Ok(());
+    }
+    for name in Template::parse(&body)?.fields() {
+        if !COMMON_FIELDS.contains(&name) && !kind.fields().contains(&name) {
+            return Err(Error::InvalidInput(format!(
+                "{{{{{name}}}}} is not a field of this message"
+            )));
+        }
+    }
+    sqlx::query(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(kind.setting())
+    .bind(body.trim())
+    .execute(&pool)
+    .await?;
+    Ok(())
+}
+
+/// Queues today's rent due reminders and overdue notices now.
+#[tauri::command]
+pub async fn run_sms_reminders(app: AppHandle, as_of: Option<String>) -> Result<ReminderRun> {
+    let as_of = as_of
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let pool = db::pool(&app).await?;
+    queue_reminders(&pool, as_of).await
+}
+
+/// Rent due reminders go out `sms.reminder_days` (default 3) before the due
+/// date, once per charge. Overdue notices go to tenants with charges more
+/// than `sms.overdue_days` (default 3) past due, at most once every
+/// `sms.overdue_repeat_days` (default 7).
+pub async fn queue_reminders(pool: &SqlitePool, as_of: NaiveDate) -> Result<ReminderRun> {
+    let reminder_days: i64 = settings::get_or(pool, "sms.reminder_days", 3).await?;
+    let overdue_days: i64 = settings::get_or(pool, "sms.overdue_days", 3).await?;
+    let repeat_days: i64 = settings::get_or(pool, "sms.overdue_repeat_days", 7).await?;
+    let mut run = ReminderRun::default();
+
+    let template = template_for(pool, ReminderKind::RentDue).await?;
+    let due: Vec<(i64, i64, f64, String, Option<String>)> = sqlx::query_as(
+        "SELECT charge_id, tenant_id, CAST(outstanding AS REAL), due_date, description
+         FROM charge_balances
+         WHERE status = 'Open' AND category = 'Rent' AND outstanding > 0.005
+           AND due_date >= ?1 AND due_date <= ?2
+         ORDER BY due_date, charge_id",
+    )
+    .bind(as_of.to_string())
+    .bind((as_of + Duration::days(reminder_days)).to_string())
+    .fetch_all(pool)
+    .await?;
+    for (charge_id, tenant_id, outstanding, due_date, description) in due {
+        let mut values = tenant_values(pool, tenant_id).await?;
+        values.insert("amount".into(), money(outstanding));
+        values.insert("due_date".into(), short_date(&due_date));
+        if let Some(description) = description {
+            values.insert("description".into(), description);
+        }
+        let queued = sms::queue(
+            pool,
+            &NewSms {
+                tenant_id: Some(tenant_id),
+                phone_number: None,
+                kind: ReminderKind::RentDue.message_kind(),
+                body: template.render(&values),
+                dedupe_key: Some(format!("rent_due:{charge_id}")),
+            },
+        )
+        .await?;
+        run.rent_due += usize::from(queued.is_some());
+    }
+
+    let template = template_for(pool, ReminderKind::Overdue).await?;
+    let overdue: Vec<(i64, f64, String)> = sqlx::query_as(
+        "SELECT tenant_id, CAST(SUM(outstanding) AS REAL), MIN(due_date)
+         FROM charge_balances
+         WHERE status = 'Open' AND outstanding > 0.005 AND due_date < ?1
+         GROUP BY tenant_id
+         ORDER BY tenant_id",
+    )
+    .bind((as_of - Duration::days(overdue_days)).to_string())
+    .fetch_all(pool)
+    .await?;
+    // Notices are keyed by a window of `repeat_days`, so one goes out per
+    // window however often this runs.
+    let window = (as_of - NaiveDate::default()).num_days() / repeat_days.max(1);
+    for (tenant_id, balance, oldest_due) in overdue {
+        let mut values = tenant_values(pool, tenant_id).await?;
+        values.insert("balance".into(), money(balance));
+        values.insert("due_date".into(), short_date(&oldest_due));
+        let queued = sms::queue(
+            pool,
+            &NewSms {
+                tenant_id: Some(tenant_id),
+                phone_number: None,
+                kind: ReminderKind::Overdue.message_kind(),
+                body: template.render(&values),
+                dedupe_key: Some(format!("overdue:{tenant_id}:{window}")),
+            },
+        )
+        .await?;
+        run.overdue += usize::from(queued.is_some());
+    }
+    Ok(run)
+}
+
+/// Queues a receipt for every payment dated on or after `since` that has not
+/// had one.
+pub async fn queue_receipts(pool: &SqlitePool, since: NaiveDate) -> Result<usize> {
+    let template = template_for(pool, ReminderKind::Receipt).await?;
+    let payments: Vec<(String, i64, f64, String, Option<String>)> = sqlx::query_as(
+        "SELECT p.payment_id, CAST(p.tenant_id AS INTEGER), CAST(p.amount_paid AS REAL),
+                p.payment_date, COALESCE(p.transaction_reference, p.receipt_number)
+         FROM payments p
+         WHERE p.payment_status = 'Paid' AND p.payment_date >= ?1
+           AND NOT EXISTS (SELECT 1 FROM sms_outbox m
+                           WHERE m.dedupe_key = 'receipt:' || p.payment_id)
+         ORDER BY p.payment_date, p.payment_id",
+    )
+    .bind(since.to_string())
+    .fetch_all(pool)
+    .await?;
+    let mut queued = 0;
+    for (payment_id, tenant_id, amount, payment_date, reference) in payments {
+        let mut values = tenant_values(pool, tenant_id).await?;
+        values.insert("amount".into(), money(amount));
+        values.insert("date".into(), short_date(&payment_date));
+        if let Some(reference) = reference {
+            values.insert("reference".into(), reference);
+        }
+        let balance = allocations::tenant_balance(pool, tenant_id).await?;
+        if balance.outstanding > 0.005 {
+            values.insert("balance".into(), money(balance.outstanding));
+        }
+        let inserted = sms::queue(
+            pool,
+            &NewSms {
+                tenant_id: Some(tenant_id),
+                phone_number: None,
+                kind: ReminderKind::Receipt.message_kind(),
+                body: template.render(&values),
+                dedupe_key: Some(format!("receipt:{payment_id}")),
+            },
+        )
+        .await?;
+        queued += usize::from(inserted.is_some());
+    }
+    Ok(queued)
+}
+
+async fn template_for(pool: &SqlitePool, kind: ReminderKind) -> Result<Template> {
+    let body = settings::get(pool, kind.setting())
+        .await?
+        .unwrap_or_else(|| kind.default_body().to_string());
+    Template::parse(&body)
+}
+
+/// Tenant, unit and property fields for a tenant's messages.
+async fn tenant_values(pool: &SqlitePool, tenant_id: i64) -> Result<HashMap<String, String>> {
+    let tenant = super::tenant(pool, tenant_id).await?;
+    let mut values = HashMap::new();
+    values.insert("tenant.first_name".into(), tenant.first_name().to_string());
+    values.insert("tenant.name".into(), tenant.name);
+    if let Some(unit) = tenant.unit_number {
+        values.insert("unit.number".into(), unit);
+    }
+    if let Some(property) = tenant.property_name {
+        values.insert("property.name".into(), property);
+    }
+    Ok(values)
+}
+
+/// `5 Mar`; messages are about the next few weeks, so the year is noise.
+fn short_date(date: &str) -> String {
+    period::parse_date(date)
+        .map(|date| date.format("%-d %b").to_string())
+        .unwrap_or_else(|_| date.to_string())
+}
+
+/// `25,000`, or `25,000.50` when there are cents.
+fn money(amount: f64) -> String {
+    let formatted = crate::templates::money(amount);
+    formatted
+        .strip_suffix(".00")
+        .map(str::to_string)
+        .unwrap_or(formatted)
+}
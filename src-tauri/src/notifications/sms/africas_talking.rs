@@ -0,0 +1,116 @@
+//! Africa's Talking bulk SMS API.
+//!
+//! Use the username `sandbox` with a sandbox API key to test against the
+//! simulator. Delivery reports are only pushed to a callback URL, so sent
+//! messages stay `Sent`.
+
+use serde::Deserialize;
+
+use super::{BoxFuture, SmsProvider, Submission};
+use crate::error::{Error, Result};
+use crate::net::http;
+
+const LIVE_URL: &str = "https://api.africastalking.com/version1/messaging";
+const SANDBOX_URL: &str = "https://api.sandbox.africastalking.com/version1/messaging";
+
+pub struct AfricasTalking {
+    pub username: String,
+    pub api_key: String,
+    /// Registered alphanumeric sender ID or short code; the shared one when
+    /// unset.
+    pub sender_id: Option<String>,
+    /// Overrides the API endpoint, e.g. for a local stand-in.
+    pub url: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Reply {
+    #[serde(rename = "SMSMessageData")]
+    data: MessageData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MessageData {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    recipients: Vec<Recipient>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Recipient {
+    status_code: i64,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    cost: Option<String>,
+    #[serde(default)]
+    message_id: Option<String>,
+}
+
+impl SmsProvider for AfricasTalking {
+    fn name(&self) -> &'static str {
+        "africastalking"
+    }
+
+    fn send<'a>(&'a self, to: &'a str, body: &'a str) -> BoxFuture<'a, Result<Submission>> {
+        Box::pin(async move {
+            let url = self
+                .url
+                .as_deref()
+                .unwrap_or(if self.username == "sandbox" {
+                    SANDBOX_URL
+                } else {
+                    LIVE_URL
+                });
+            let mut fields = vec![
+                ("username", self.username.as_str()),
+                ("to", to),
+                ("message", body),
+            ];
+            if let Some(sender_id) = &self.sender_id {
+                fields.push(("from", sender_id));
+            }
+            let response = http::send(
+                "POST",
+                url,
+                &[
+                    ("apiKey", &self.api_key),
+                    ("Accept", "application/json"),
+                    ("Content-Type", "application/x-www-form-urlencoded"),
+                ],
+                http::form(&fields).as_bytes(),
+            )
+            .await?;
+            if !response.is_success() {
+                return Err(Error::Delivery(format!(
+                    "Africa's Talking returned {}: {}",
+                    response.status,
+                    response.text().trim()
+                )));
+            }
+            let reply: Reply = serde_json::from_slice(&response.body).map_err(|err| {
+                Error::Delivery(format!("unexpected reply from Africa's Talking: {err}"))
+            })?;
+            let Some(recipient) = reply.data.recipients.into_iter().next() else {
+                return Ok(Submission::Rejected(reply.data.message));
+            };
+            match recipient.status_code {
+                // Processed, Sent, Queued.
+                100..=102 => Ok(Submission::Accepted {
+                    message_id: recipient.message_id.filter(|id| id != "None"),
+                    cost: recipient.cost,
+                }),
+                // Insufficient balance and gateway errors clear up on their own.
+                405 | 500 | 501 => Err(Error::Delivery(format!(
+                    "Africa's Talking: {}",
+                    recipient.status
+                ))),
+                _ => Ok(Submission::Rejected(recipient.status)),
+            }
+        })
+    }
+}
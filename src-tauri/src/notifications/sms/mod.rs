@@ -0,0 +1,474 @@
+//! The SMS outbox and the gateways it sends through.
+//!
+//! `sms.provider` picks the gateway: `africastalking`, `twilio`, or `file`,
+//! which appends each message to a local file instead of sending it. With no
+//! provider set, messages stay queued.
+
+mod africas_talking;
+mod file;
+mod twilio;
+
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use super::{Channel, DispatchRun};
+use crate::db;
+use crate::error::{Error, Result};
+use crate::settings;
+
+pub use africas_talking::AfricasTalking;
+pub use file::FileSink;
+pub use twilio::Twilio;
+
+/// Messages sent per dispatch run; the rest wait for the next minute.
+const BATCH_SIZE: i64 = 100;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// What the gateway said to a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Submission {
+    Accepted {
+        message_id: Option<String>,
+        cost: Option<String>,
+    },
+    /// Refused for good, e.g. an invalid number; it is not retried.
+    Rejected(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Delivery {
+    /// Not known yet, or the gateway only reports by callback.
+    Pending,
+    Delivered,
+    Failed(String),
+}
+
+/// An SMS gateway. An `Err` from [`send`](SmsProvider::send) means the
+/// message may not have gone out and is retried later.
+pub trait SmsProvider: Send + Sync {
+    /// Stored with each message, e.g. `twilio`.
+    fn name(&self) -> &'static str;
+
+    /// `to` is in international form, `+254712345678`.
+    fn send<'a>(&'a self, to: &'a str, body: &'a str) -> BoxFuture<'a, Result<Submission>>;
+
+    fn delivery<'a>(&'a self, _message_id: &'a str) -> BoxFuture<'a, Result<Delivery>> {
+        Box::pin(async { Ok(Delivery::Pending) })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum SmsStatus {
+    Queued,
+    Sent,
+    Delivered,
+    Failed,
+    /// Never sent: the tenant opted out, had no usable number, or it was
+    /// cancelled.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum MessageKind {
+    #[serde(rename = "Rent Due")]
+    #[sqlx(rename = "Rent Due")]
+    RentDue,
+    Overdue,
+    Receipt,
+    Custom,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct SmsMessage {
+    pub message_id: i64,
+    pub tenant_id: Option<i64>,
+    pub tenant_name: Option<String>,
+    pub phone_number: String,
+    pub kind: MessageKind,
+    pub body: String,
+    pub status: SmsStatus,
+    pub provider: Option<String>,
+    pub provider_message_id: Option<String>,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub cost: Option<String>,
+    pub created_at: Option<String>,
+    pub sent_at: Option<String>,
+    pub delivered_at: Option<String>,
+}
+
+const MESSAGE_COLUMNS: &str = "m.message_id, m.tenant_id, t.full_name AS tenant_name,
+            m.phone_number, m.kind, m.body, m.status, m.provider, m.provider_message_id,
+            m.attempts, m.last_error, m.cost, m.created_at, m.sent_at, m.delivered_at
+         FROM sms_outbox m LEFT JOIN tenants t ON t.tenant_id = m.tenant_id";
+
+/// A message to queue.
+#[derive(Debug, Clone)]
+pub struct NewSms {
+    pub tenant_id: Option<i64>,
+    /// Defaults to the tenant's phone number.
+    pub phone_number: Option<String>,
+    pub kind: MessageKind,
+    pub body: String,
+    /// A second message with the same key is not queued, e.g.
+    /// `rent_due:42` for the reminder about charge 42.
+    pub dedupe_key: Option<String>,
+}
+
+/// Outbox messages, newest first.
+#[tauri::command]
+pub async fn get_sms_outbox(
+    app: AppHandle,
+    status: Option<SmsStatus>,
+    tenant_id: Option<i64>,
+    limit: Option<i64>,
+) -> Result<Vec<SmsMessage>> {
+    let pool = db::pool(&app).await?;
+    let messages = sqlx::query_as(&format!(
+        "SELECT {MESSAGE_COLUMNS}
+         WHERE (?1 IS NULL OR m.status = ?1) AND (?2 IS NULL OR m.tenant_id = ?2)
+         ORDER BY m.message_id DESC
+         LIMIT ?3"
+    ))
+    .bind(status)
+    .bind(tenant_id)
+    .bind(limit.unwrap_or(200))
+    .fetch_all(&pool)
+    .await?;
+    Ok(messages)
+}
+
+/// Queues a one-off message to a tenant or any number and sends it right
+/// away if a provider is configured.
+#[tauri::command]
+pub async fn send_sms(
+    app: AppHandle,
+    tenant_id: Option<i64>,
+    phone_number: Option<String>,
+    message: String,
+) -> Result<SmsMessage> {
+    if message.trim().is_empty() {
+        return Err(Error::InvalidInput("message is empty".into()));
+    }
+    if tenant_id.is_none() && phone_number.is_none() {
+        return Err(Error::InvalidInput(
+            "choose a tenant or enter a phone number".into(),
+        ));
+    }
+    let pool = db::pool(&app).await?;
+    let message_id = queue(
+        &pool,
+        &NewSms {
+            tenant_id,
+            phone_number,
+            kind: MessageKind::Custom,
+            body: message.trim().to_string(),
+            dedupe_key: None,
+        },
+    )
+    .await?
+    .ok_or_else(|| Error::InvalidInput("message was not queued".into()))?;
+    if let Some(provider) = provider(&pool, &super::data_dir(&app)?).await? {
+        dispatch(&pool, provider.as_ref()).await?;
+    }
+    load(&pool, message_id).await
+}
+
+/// Sends everything queued now instead of waiting for the worker.
+#[tauri::command]
+pub async fn send_pending_sms(app: AppHandle) -> Result<DispatchRun> {
+    let pool = db::pool(&app).await?;
+    let Some(provider) = provider(&pool, &super::data_dir(&app)?).await? else {
+        return Err(Error::InvalidInput("no SMS provider is configured".into()));
+    };
+    dispatch(&pool, provider.as_ref()).await
+}
+
+/// Queues a failed message for another try.
+#[tauri::command]
+pub async fn retry_sms(app: AppHandle, message_id: i64) -> Result<SmsMessage> {
+    let pool = db::pool(&app).await?;
+    set_status(&pool, message_id, SmsStatus::Failed, SmsStatus::Queued).await
+}
+
+/// Stops a queued message from being sent.
+#[tauri::command]
+pub async fn cancel_sms(app: AppHandle, message_id: i64) -> Result<SmsMessage> {
+    let pool = db::pool(&app).await?;
+    set_status(&pool, message_id, SmsStatus::Queued, SmsStatus::Skipped).await
+}
+
+/// The configured gateway, or `None` when `sms.provider` is unset.
+pub async fn provider(pool: &SqlitePool, data_dir: &Path) -> Result<Option<Box<dyn SmsProvider>>> {
+    let Some(name) = settings::get(pool, "sms.provider").await? else {
+        return Ok(None);
+    };
+    let required = |key: &'static str, value: Option<String>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| Error::InvalidInput(format!("setting {key} is required")))
+    };
+    let optional = |value: Option<String>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let provider: Box<dyn SmsProvider> = match name.trim() {
+        "" => return Ok(None),
+        "africastalking" => Box::new(AfricasTalking {
+            username: required(
+                "sms.africastalking.username",
+                settings::get(pool, "sms.africastalking.username").await?,
+            )?,
+            api_key: required(
+                "sms.africastalking.api_key",
+                settings::get(pool, "sms.africastalking.api_key").await?,
+            )?,
+            sender_id: optional(settings::get(pool, "sms.africastalking.sender_id").await?),
+            url: optional(settings::get(pool, "sms.africastalking.url").await?),
+        }),
+        "twilio" => Box::new(Twilio {
+            account_sid: required(
+                "sms.twilio.account_sid",
+                settings::get(pool, "sms.twilio.account_sid").await?,
+            )?,
+            auth_token: required(
+                "sms.twilio.auth_token",
+                settings::get(pool, "sms.twilio.auth_token").await?,
+            )?,
+            from: required(
+                "sms.twilio.from",
+                settings::get(pool, "sms.twilio.from").await?,
+            )?,
+            url: settings::get_or(pool, "sms.twilio.url", twilio::URL.to_string()).await?,
+        }),
+        "file" => Box::new(FileSink {
+            path: optional(settings::get(pool, "sms.file.path").await?)
+                .map(Into::into)
+                .unwrap_or_else(|| data_dir.join("sms-outbox.jsonl")),
+        }),
+        other => {
+            return Err(Error::InvalidInput(format!(
+                "unknown SMS provider '{other}'; use africastalking, twilio or file"
+            )))
+        }
+    };
+    Ok(Some(provider))
+}
+
+/// Adds a message to the outbox. Returns `None` when a message with the same
+/// dedupe key exists. Messages to tenants who opted out, or without a usable
+/// number, are recorded as skipped.
+pub async fn queue(pool: &SqlitePool, sms: &NewSms) -> Result<Option<i64>> {
+    let country_code = settings::get_or(pool, "sms.country_code", "254".to_string()).await?;
+    let mut phone = sms.phone_number.clone();
+    let mut skip = None;
+    if let Some(tenant_id) = sms.tenant_id {
+        let tenant: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT phone_number FROM tenants WHERE tenant_id = ?1")
+                .bind(tenant_id)
+                .fetch_optional(pool)
+                .await?;
+        let Some((tenant_phone,)) = tenant else {
+            return Err(Error::NotFound(format!("tenant {tenant_id}")));
+        };
+        phone = phone.or(tenant_phone);
+        if super::opted_out(pool, tenant_id, Channel::Sms).await? {
+            skip = Some("tenant opted out");
+        }
+    }
+    let raw = phone.unwrap_or_default();
+    let normalized = super::normalize_phone(&raw, &country_code);
+    if normalized.is_none() && skip.is_none() {
+        skip = Some("no valid phone number");
+    }
+    let status = if skip.is_some() {
+        SmsStatus::Skipped
+    } else {
+        SmsStatus::Queued
+    };
+    let inserted = sqlx::query(
+        "INSERT INTO sms_outbox (tenant_id, phone_number, kind, body, dedupe_key, status, last_error)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (dedupe_key) DO NOTHING",
+    )
+    .bind(sms.tenant_id)
+    .bind(normalized.unwrap_or(raw))
+    .bind(sms.kind)
+    .bind(&sms.body)
+    .bind(&sms.dedupe_key)
+    .bind(status)
+    .bind(skip)
+    .execute(pool)
+    .await?;
+    Ok((inserted.rows_affected() > 0).then(|| inserted.last_insert_rowid()))
+}
+
+/// Sends queued messages that are due. Failures are retried with growing
+/// delays (5, 10, 20... minutes) up to `sms.max_attempts` tries.
+pub async fn dispatch(pool: &SqlitePool, provider: &dyn SmsProvider) -> Result<DispatchRun> {
+    // The worker and the "send now" button must not send the same batch.
+    static RUNNING: AtomicBool = AtomicBool::new(false);
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(DispatchRun::default());
+    }
+    struct Done;
+    impl Drop for Done {
+        fn drop(&mut self) {
+            RUNNING.store(false, Ordering::SeqCst);
+        }
+    }
+    let _done = Done;
+
+    let max_attempts: i64 = settings::get_or(pool, "sms.max_attempts", 5).await?;
+    let due: Vec<(i64, String, String, i64)> = sqlx::query_as(
+        "SELECT message_id, phone_number, body, attempts FROM sms_outbox
+         WHERE status = 'Queued'
+           AND (next_attempt_at IS NULL OR next_attempt_at <= datetime('now'))
+         ORDER BY message_id
+         LIMIT ?1",
+    )
+    .bind(BATCH_SIZE)
+    .fetch_all(pool)
+    .await?;
+
+    let mut run = DispatchRun::default();
+    for (message_id, phone, body, attempts) in due {
+        match provider.send(&phone, &body).await {
+            Ok(Submission::Accepted {
+                message_id: id,
+                cost,
+            }) => {
+                sqlx::query(
+                    "UPDATE sms_outbox
+                     SET status = 'Sent', provider = ?1, provider_message_id = ?2, cost = ?3,
+                         attempts = attempts + 1, last_error = NULL, sent_at = CURRENT_TIMESTAMP
+                     WHERE message_id = ?4",
+                )
+                .bind(provider.name())
+                .bind(id)
+                .bind(cost)
+                .bind(message_id)
+                .execute(pool)
+                .await?;
+                run.sent += 1;
+            }
+            Ok(Submission::Rejected(reason)) => {
+                sqlx::query(
+                    "UPDATE sms_outbox
+                     SET status = 'Failed', provider = ?1, attempts = attempts + 1, last_error = ?2
+                     WHERE message_id = ?3",
+                )
+                .bind(provider.name())
+                .bind(reason)
+                .bind(message_id)
+                .execute(pool)
+                .await?;
+                run.failed += 1;
+            }
+            Err(err) => {
+                let give_up = attempts + 1 >= max_attempts;
+                sqlx::query(
+                    "UPDATE sms_outbox
+                     SET status = CASE WHEN ?1 THEN 'Failed' ELSE 'Queued' END,
+                         provider = ?2, attempts = attempts + 1, last_error = ?3,
+                         next_attempt_at = datetime('now', '+' || (5 << MIN(attempts, 8)) || ' minutes')
+                     WHERE message_id = ?4",
+                )
+                .bind(give_up)
+                .bind(provider.name())
+                .bind(err.to_string())
+                .bind(message_id)
+                .execute(pool)
+                .await?;
+                if give_up {
+                    run.failed += 1;
+                } else {
+                    run.retrying += 1;
+                }
+            }
+        }
+    }
+    Ok(run)
+}
+
+/// Asks the gateway what became of messages sent in the last three days.
+pub async fn refresh_delivery(pool: &SqlitePool, provider: &dyn SmsProvider) -> Result<usize> {
+    let sent: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT message_id, provider_message_id FROM sms_outbox
+         WHERE status = 'Sent' AND provider = ?1 AND provider_message_id IS NOT NULL
+           AND sent_at >= datetime('now', '-3 days')
+         ORDER BY message_id
+         LIMIT ?2",
+    )
+    .bind(provider.name())
+    .bind(BATCH_SIZE)
+    .fetch_all(pool)
+    .await?;
+    let mut updated = 0;
+    for (message_id, provider_message_id) in sent {
+        let (status, error) = match provider.delivery(&provider_message_id).await {
+            // A lookup failure is not a delivery failure; try again next time.
+            Err(_) | Ok(Delivery::Pending) => continue,
+            Ok(Delivery::Delivered) => (SmsStatus::Delivered, None),
+            Ok(Delivery::Failed(reason)) => (SmsStatus::Failed, Some(reason)),
+        };
+        sqlx::query(
+            "UPDATE sms_outbox
+             SET status = ?1, last_error = ?2,
+                 delivered_at = CASE WHEN ?1 = 'Delivered' THEN CURRENT_TIMESTAMP END
+             WHERE message_id = ?3",
+        )
+        .bind(status)
+        .bind(error)
+        .bind(message_id)
+        .execute(pool)
+        .await?;
+        updated += 1;
+    }
+    Ok(updated)
+}
+
+async fn set_status(
+    pool: &SqlitePool,
+    message_id: i64,
+    from: SmsStatus,
+    to: SmsStatus,
+) -> Result<SmsMessage> {
+    let message = load(pool, message_id).await?;
+    if message.status != from {
+        return Err(Error::InvalidInput(format!(
+            "message {message_id} is {:?}, not {from:?}",
+            message.status
+        )));
+    }
+    sqlx::query(
+        "UPDATE sms_outbox
+         SET status = ?1, attempts = CASE WHEN ?1 = 'Queued' THEN 0 ELSE attempts END,
+             next_attempt_at = NULL,
+             last_error = CASE WHEN ?1 = 'Skipped' THEN 'cancelled' ELSE last_error END
+         WHERE message_id = ?2",
+    )
+    .bind(to)
+    .bind(message_id)
+    .execute(pool)
+    .await?;
+    load(pool, message_id).await
+}
+
+pub async fn load(pool: &SqlitePool, message_id: i64) -> Result<SmsMessage> {
+    let message = sqlx::query_as(&format!("SELECT {MESSAGE_COLUMNS} WHERE m.message_id = ?1"))
+        .bind(message_id)
+        .fetch_optional(pool)
+        .await?;
+    message.ok_or_else(|| Error::NotFound(format!("SMS {message_id}")))
+}
@@ -0,0 +1,143 @@
+//! Twilio Programmable Messaging.
+
+use base64::Engine;
+use serde::Deserialize;
+
+use super::{BoxFuture, Delivery, SmsProvider, Submission};
+use crate::error::{Error, Result};
+use crate::net::http;
+
+pub const URL: &str = "https://api.twilio.com";
+
+pub struct Twilio {
+    pub account_sid: String,
+    pub auth_token: String,
+    /// A Twilio number, or a messaging service SID starting with `MG`.
+    pub from: String,
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    sid: String,
+    status: String,
+    #[serde(default)]
+    error_code: Option<i64>,
+    #[serde(default)]
+    error_message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorReply {
+    #[serde(default)]
+    code: Option<i64>,
+    #[serde(default)]
+    message: String,
+}
+
+impl Twilio {
+    fn messages_url(&self) -> String {
+        format!(
+            "{}/2010-04-01/Accounts/{}/Messages",
+            self.url.trim_end_matches('/'),
+            http::encode(&self.account_sid)
+        )
+    }
+
+    fn authorization(&self) -> String {
+        let credentials = format!("{}:{}", self.account_sid, self.auth_token);
+        format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(credentials)
+        )
+    }
+
+    fn parse(response: &http::Response) -> Result<Message> {
+        serde_json::from_slice(&response.body)
+            .map_err(|err| Error::Delivery(format!("unexpected reply from Twilio: {err}")))
+    }
+}
+
+impl SmsProvider for Twilio {
+    fn name(&self) -> &'static str {
+        "twilio"
+    }
+
+    fn send<'a>(&'a self, to: &'a str, body: &'a str) -> BoxFuture<'a, Result<Submission>> {
+        Box::pin(async move {
+            let sender = if self.from.starts_with("MG") {
+                "MessagingServiceSid"
+            } else {
+                "From"
+            };
+            let form = http::form(&[("To", to), (sender, &self.from), ("Body", body)]);
+            let authorization = self.authorization();
+            let response = http::send(
+                "POST",
+                &format!("{}.json", self.messages_url()),
+                &[
+                    ("Authorization", &authorization),
+                    ("Accept", "application/json"),
+                    ("Content-Type", "application/x-www-form-urlencoded"),
+                ],
+                form.as_bytes(),
+            )
+            .await?;
+            if response.status == 400 {
+                // Bad number, unverified trial recipient and the like.
+                let reply: ErrorReply = serde_json::from_slice(&response.body).map_err(|err| {
+                    Error::Delivery(format!("unexpected reply from Twilio: {err}"))
+                })?;
+                return Ok(Submission::Rejected(match reply.code {
+                    Some(code) => format!("{} (error {code})", reply.message),
+                    None => reply.message,
+                }));
+            }
+            if !response.is_success() {
+                return Err(Error::Delivery(format!(
+                    "Twilio returned {}: {}",
+                    response.status,
+                    response.text().trim()
+                )));
+            }
+            let message = Self::parse(&response)?;
+            Ok(Submission::Accepted {
+                message_id: Some(message.sid),
+                cost: None,
+            })
+        })
+    }
+
+    fn delivery<'a>(&'a self, message_id: &'a str) -> BoxFuture<'a, Result<Delivery>> {
+        Box::pin(async move {
+            let authorization = self.authorization();
+            let response = http::send(
+                "GET",
+                &format!("{}/{}.json", self.messages_url(), http::encode(message_id)),
+                &[
+                    ("Authorization", &authorization),
+                    ("Accept", "application/json"),
+                ],
+                b"",
+            )
+            .await?;
+            if !response.is_success() {
+                return Err(Error::Delivery(format!(
+                    "Twilio returned {}",
+                    response.status
+                )));
+            }
+            let message = Self::parse(&response)?;
+            Ok(match message.status.as_str() {
+                "delivered" => Delivery::Delivered,
+                "undelivered" | "failed" => Delivery::Failed(
+                    message
+                        .error_message
+                        .or(message.error_code.map(|code| format!("error {code}")))
+                        .unwrap_or(message.status),
+                ),
+                _ => Delivery::Pending,
+            })
+        })
+    }
+}
@@ -0,0 +1,468 @@
+//! Monthly tenant statements and payment receipts by email, each with the
+//! same document attached as a PDF.
+
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use super::email::{self, Attachment, EmailKind, EmailMessage, NewEmail};
+use crate::billing::allocations;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::export::{self, Block, Letter, LetterFormat};
+use crate::period::{self, Month};
+use crate::settings;
+use crate::templates::money;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementCharge {
+    pub charge_id: i64,
+    pub category: String,
+    pub description: Option<String>,
+    pub due_date: String,
+    pub amount: f64,
+    pub outstanding: f64,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementPayment {
+    pub payment_id: String,
+    pub payment_date: String,
+    pub payment_method: String,
+    pub reference: Option<String>,
+    pub amount: f64,
+}
+
+#[derive(sqlx::FromRow)]
+struct PaidPayment {
+    tenant_id: i64,
+    payment_id: String,
+    payment_date: String,
+    payment_method: String,
+    payment_category: String,
+    reference: Option<String>,
+    amount: f64,
+}
+
+/// What a tenant was charged and paid in a month, and what they owe now.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Statement {
+    pub tenant_id: i64,
+    pub tenant_name: String,
+    pub unit_number: Option<String>,
+    pub property_name: Option<String>,
+    pub month: Month,
+    pub charges: Vec<StatementCharge>,
+    pub payments: Vec<StatementPayment>,
+    pub total_charged: f64,
+    pub total_received: f64,
+    pub balance_due: f64,
+    pub credit: f64,
+}
+
+#[tauri::command]
+pub async fn get_tenant_statement(
+    app: AppHandle,
+    tenant_id: i64,
+    month: Month,
+) -> Result<Statement> {
+    let pool = db::pool(&app).await?;
+    statement(&pool, tenant_id, month).await
+}
+
+/// Queues statements for `month` (default last month) to one tenant or to
+/// every tenant with activity or a balance. Each tenant gets one per month.
+#[tauri::command]
+pub async fn email_tenant_statements(
+    app: AppHandle,
+    month: Option<Month>,
+    tenant_id: Option<i64>,
+) -> Result<usize> {
+    let pool = db::pool(&app).await?;
+    let month = month.unwrap_or_else(|| previous_month(period::today()));
+    queue_statements(&pool, month, tenant_id).await
+}
+
+/// Emails a receipt for one payment, again if one was already sent.
+#[tauri::command]
+pub async fn email_payment_receipt(app: AppHandle, payment_id: String) -> Result<EmailMessage> {
+    let pool = db::pool(&app).await?;
+    let email = receipt(&pool, &payment_id, None).await?;
+    let email_id = email::queue(&pool, &email)
+        .await?
+        .ok_or_else(|| Error::InvalidInput("receipt was not queued".into()))?;
+    email::load(&pool, email_id).await
+}
+
+pub async fn statement(pool: &SqlitePool, tenant_id: i64, month: Month) -> Result<Statement> {
+    let tenant = super::tenant(pool, tenant_id).await?;
+    let (from, to) = (month.first_day().to_string(), month.last_day().to_string());
+    let charges: Vec<StatementCharge> = sqlx::query_as(
+        "SELECT charge_id, category, description, due_date, CAST(amount AS REAL) AS amount,
+                CAST(outstanding AS REAL) AS outstanding
+         FROM charge_balances
+         WHERE tenant_id = ?1 AND status <> 'Waived' AND due_date BETWEEN ?2 AND ?3
+         ORDER BY due_date, charge_id",
+    )
+    .bind(tenant_id)
+    .bind(&from)
+    .bind(&to)
+    .fetch_all(pool)
+    .await?;
+    let payments: Vec<StatementPayment> = sqlx::query_as(
+        "SELECT payment_id, payment_date, payment_method,
+                COALESCE(receipt_number, transaction_reference) AS reference,
+                CAST(amount_paid AS REAL) AS amount
+         FROM payments
+         WHERE CAST(tenant_id AS INTEGER) = ?1 AND payment_status = 'Paid'
+           AND payment_date BETWEEN ?2 AND ?3
+         ORDER BY payment_date, payment_id",
+    )
+    .bind(tenant_id)
+    .bind(&from)
+    .bind(&to)
+    .fetch_all(pool)
+    .await?;
+    let balance = allocations::tenant_balance(pool, tenant_id).await?;
+    Ok(Statement {
+        tenant_id,
+        tenant_name: tenant.name,
+        unit_number: tenant.unit_number,
+        property_name: tenant.property_name,
+        month,
+        total_charged: allocations::round_cents(charges.iter().map(|c| c.amount).sum()),
+        total_received: allocations::round_cents(payments.iter().map(|p| p.amount).sum()),
+        charges,
+        payments,
+        balance_due: balance.outstanding,
+        credit: balance.credit,
+    })
+}
+
+pub async fn queue_statements(
+    pool: &SqlitePool,
+    month: Month,
+    tenant_id: Option<i64>,
+) -> Result<usize> {
+    let (from, to) = (month.first_day().to_string(), month.last_day().to_string());
+    let tenants: Vec<(i64,)> = sqlx::query_as(
+        "SELECT tenant_id FROM tenants t
+         WHERE (?1 IS NULL OR tenant_id = ?1)
+           AND (EXISTS (SELECT 1 FROM charge_balances c
+                        WHERE c.tenant_id = t.tenant_id AND c.status <> 'Waived'
+                          AND (c.due_date BETWEEN ?2 AND ?3 OR c.outstanding > 0.005))
+                OR EXISTS (SELECT 1 FROM payments p
+                           WHERE CAST(p.tenant_id AS INTEGER) = t.tenant_id
+                             AND p.payment_status = 'Paid'
+                             AND p.payment_date BETWEEN ?2 AND ?3))
+         ORDER BY tenant_id",
+    )
+    .bind(tenant_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+    let sender = sender_name(pool).await?;
+    let mut queued = 0;
+    for (tenant_id,) in tenants {
+        let statement = statement(pool, tenant_id, month).await?;
+        let title = month.first_day().format("%B %Y").to_string();
+        let pdf = export::render_letter(&statement_letter(&statement), LetterFormat::Pdf);
+        let inserted = email::queue(
+            pool,
+            &NewEmail {
+                tenant_id: Some(tenant_id),
+                manager_id: None,
+                to_address: None,
+                kind: EmailKind::Statement,
+                subject: format!("Your statement for {title}"),
+                body: statement_body(&statement, &title, &sender),
+                attachments: vec![Attachment::pdf(format!("statement-{month}.pdf"), pdf)],
+                dedupe_key: Some(format!("statement:{tenant_id}:{month}")),
+            },
+        )
+        .await?;
+        queued += usize::from(inserted.is_some());
+    }
+    Ok(queued)
+}
+
+/// Queues a receipt for every payment dated on or after `since` that has not
+/// had one.
+pub async fn queue_receipts(pool: &SqlitePool, since: NaiveDate) -> Result<usize> {
+    let payments: Vec<(String,)> = sqlx::query_as(
+        "SELECT p.payment_id FROM payments p
+         WHERE p.payment_status = 'Paid' AND p.payment_date >= ?1
+           AND NOT EXISTS (SELECT 1 FROM email_outbox e
+                           WHERE e.dedupe_key = 'receipt:' || p.payment_id)
+         ORDER BY p.payment_date, p.payment_id",
+    )
+    .bind(since.to_string())
+    .fetch_all(pool)
+    .await?;
+    let mut queued = 0;
+    for (payment_id,) in payments {
+        let key = format!("receipt:{payment_id}");
+        let email = receipt(pool, &payment_id, Some(key)).await?;
+        queued += usize::from(email::queue(pool, &email).await?.is_some());
+    }
+    Ok(queued)
+}
+
+/// The receipt email for a payment, with the receipt as a PDF.
+async fn receipt(
+    pool: &SqlitePool,
+    payment_id: &str,
+    dedupe_key: Option<String>,
+) -> Result<NewEmail> {
+    let payment: Option<PaidPayment> = sqlx::query_as(
+        "SELECT CAST(tenant_id AS INTEGER) AS tenant_id, payment_id, payment_date,
+                payment_method, payment_category,
+                COALESCE(receipt_number, transaction_reference) AS reference,
+                CAST(amount_paid AS REAL) AS amount
+         FROM payments WHERE payment_id = ?1 AND payment_status = 'Paid'",
+    )
+    .bind(payment_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(payment) = payment else {
+        return Err(Error::NotFound(format!("paid payment {payment_id}")));
+    };
+    let tenant_id = payment.tenant_id;
+    let tenant = super::tenant(pool, tenant_id).await?;
+    let balance = allocations::tenant_balance(pool, tenant_id).await?;
+    let number = payment
+        .reference
+        .clone()
+        .unwrap_or_else(|| payment.payment_id.clone());
+    let date = long_date(&payment.payment_date);
+    let unit = unit_line(
+        tenant.unit_number.as_deref(),
+        tenant.property_name.as_deref(),
+    );
+
+    let mut details = vec![
+        format!("Receipt no:     {number}"),
+        format!("Date:           {date}"),
+        format!("Received from:  {}", tenant.name),
+    ];
+    if let Some(unit) = &unit {
+        details.push(format!("Unit:           {unit}"));
+    }
+    details.extend([
+        format!("For:            {}", payment.payment_category),
+        format!("Method:         {}", payment.payment_method),
+        format!("Amount:         KES {}", money(payment.amount)),
+        format!("Balance due:    KES {}", money(balance.outstanding)),
+    ]);
+    if balance.credit > 0.005 {
+        details.push(format!("Credit:         KES {}", money(balance.credit)));
+    }
+    let sender = sender_name(pool).await?;
+    let letter = Letter {
+        blocks: vec![
+            Block::Heading("Payment Receipt".into()),
+            Block::Paragraph(details.join("\n")),
+            Block::Paragraph(format!("Thank you for your payment.\n\n{sender}")),
+        ],
+    };
+    let outstanding = if balance.outstanding > 0.005 {
+        format!(
+            "Your balance due is now KES {}.",
+            money(balance.outstanding)
+        )
+    } else {
+        "Your account is fully paid.".to_string()
+    };
+    let body = format!(
+        "Dear {},\n\nWe have received your payment of KES {} on {date}. {outstanding} \
+         Your receipt, number {number}, is attached.\n\nThank you,\n{sender}\n",
+        tenant.first_name(),
+        money(payment.amount),
+    );
+    Ok(NewEmail {
+        tenant_id: Some(tenant_id),
+        manager_id: None,
+        to_address: None,
+        kind: EmailKind::Receipt,
+        subject: format!("Receipt {number}"),
+        body,
+        attachments: vec![Attachment::pdf(
+            format!("receipt-{}.pdf", file_safe(&number)),
+            export::render_letter(&letter, LetterFormat::Pdf),
+        )],
+        dedupe_key,
+    })
+}
+
+fn statement_letter(statement: &Statement) -> Letter {
+    let title = statement.month.first_day().format("%B %Y");
+    let mut header = vec![statement.tenant_name.clone()];
+    header.extend(unit_line(
+        statement.unit_number.as_deref(),
+        statement.property_name.as_deref(),
+    ));
+    header.push(format!("Issued {}", period::today().format("%-d %B %Y")));
+
+    let mut charges = vec![format!(
+        "{:<12} {:<30} {:>12} {:>12}",
+        "Due", "Description", "Amount", "Outstanding"
+    )];
+    for charge in &statement.charges {
+        let description = charge.description.as_deref().unwrap_or(&charge.category);
+        charges.push(format!(
+            "{:<12} {:<30} {:>12} {:>12}",
+            short_date(&charge.due_date),
+            truncate(description, 30),
+            money(charge.amount),
+            money(charge.outstanding)
+        ));
+    }
+    if statement.charges.is_empty() {
+        charges.push("No charges this month.".into());
+    }
+
+    let mut payments = vec![format!(
+        "{:<12} {:<18} {:<24} {:>12}",
+        "Date", "Method", "Reference", "Amount"
+    )];
+    for payment in &statement.payments {
+        payments.push(format!(
+            "{:<12} {:<18} {:<24} {:>12}",
+            short_date(&payment.payment_date),
+            truncate(&payment.payment_method, 18),
+            truncate(payment.reference.as_deref().unwrap_or(""), 24),
+            money(payment.amount)
+        ));
+    }
+    if statement.payments.is_empty() {
+        payments.push("No payments this month.".into());
+    }
+
+    let mut summary = vec![
+        format!(
+            "Charged this month:   KES {:>12}",
+            money(statement.total_charged)
+        ),
+        format!(
+            "Received this month:  KES {:>12}",
+            money(statement.total_received)
+        ),
+        format!(
+            "Balance due now:      KES {:>12}",
+            money(statement.balance_due)
+        ),
+    ];
+    if statement.credit > 0.005 {
+        summary.push(format!(
+            "Credit on account:    KES {:>12}",
+            money(statement.credit)
+        ));
+    }
+
+    Letter {
+        blocks: vec![
+            Block::Heading(format!("Statement for {title}")),
+            Block::Paragraph(header.join("\n")),
+            Block::Heading("Charges".into()),
+            Block::Paragraph(charges.join("\n")),
+            Block::Heading("Payments received".into()),
+            Block::Paragraph(payments.join("\n")),
+            Block::Heading("Summary".into()),
+            Block::Paragraph(summary.join("\n")),
+        ],
+    }
+}
+
+fn statement_body(statement: &Statement, title: &str, sender: &str) -> String {
+    let first_name = statement
+        .tenant_name
+        .split_whitespace()
+        .next()
+        .unwrap_or(&statement.tenant_name);
+    let owing = if statement.balance_due > 0.005 {
+        format!("Your balance due is KES {}.", money(statement.balance_due))
+    } else {
+        "Your account is fully paid.".to_string()
+    };
+    format!(
+        "Dear {first_name},\n\nPlease find attached your statement for {title}. You were \
+         charged KES {} and we received KES {} from you during the month. {owing}\n\n\
+         Thank you,\n{sender}\n",
+        money(statement.total_charged),
+        money(statement.total_received),
+    )
+}
+
+/// Signs emails and receipts: setting `letters.landlord_name`, as on
+/// letters, or else the sender name in `smtp.from`.
+async fn sender_name(pool: &SqlitePool) -> Result<String> {
+    if let Some(name) = settings::get(pool, "letters.landlord_name").await? {
+        return Ok(name);
+    }
+    let from = settings::get(pool, "smtp.from").await?.unwrap_or_default();
+    Ok(from
+        .split_once('<')
+        .map(|(name, _)| name.trim().trim_matches('"').to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "The management".into()))
+}
+
+fn previous_month(today: NaiveDate) -> Month {
+    Month::of(Month::of(today).first_day() - Duration::days(1))
+}
+
+/// The month whose statements are due on `today`: last month, once
+/// `email.statement_day` (default the 1st) has come.
+pub async fn statement_month(pool: &SqlitePool, today: NaiveDate) -> Result<Option<Month>> {
+    use chrono::Datelike;
+    let day: u32 = settings::get_or(pool, "email.statement_day", 1).await?;
+    Ok((today.day() >= day).then(|| previous_month(today)))
+}
+
+fn unit_line(unit: Option<&str>, property: Option<&str>) -> Option<String> {
+    match (unit, property) {
+        (Some(unit), Some(property)) => Some(format!("Unit {unit}, {property}")),
+        (Some(unit), None) => Some(format!("Unit {unit}")),
+        (None, Some(property)) => Some(property.to_string()),
+        (None, None) => None,
+    }
+}
+
+fn short_date(date: &str) -> String {
+    period::parse_date(date)
+        .map(|date| date.format("%d %b %Y").to_string())
+        .unwrap_or_else(|_| date.to_string())
+}
+
+fn long_date(date: &str) -> String {
+    period::parse_date(date)
+        .map(|date| date.format("%-d %B %Y").to_string())
+        .unwrap_or_else(|_| date.to_string())
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        text.to_string()
+    } else {
+        let mut cut: String = text.chars().take(width - 1).collect();
+        cut.push('~');
+        cut
+    }
+}
+
+fn file_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
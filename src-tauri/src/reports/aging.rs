@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::Result;
+use crate::period::{self, Month};
+use crate::tasks::{self, TaskPriority};
+
+/// Unpaid amounts split by how many days past `due_date` they are.
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgingBuckets {
+    /// Not yet due.
+    pub current: f64,
+    pub days_1_30: f64,
+    pub days_31_60: f64,
+    pub days_61_90: f64,
+    pub over_90: f64,
+    pub total: f64,
+}
+
+impl AgingBuckets {
+    fn add(&mut self, days_past_due: i64, amount: f64) {
+        let bucket = match days_past_due {
+            i64::MIN..=0 => &mut self.current,
+            1..=30 => &mut self.days_1_30,
+            31..=60 => &mut self.days_31_60,
+            61..=90 => &mut self.days_61_90,
+            _ => &mut self.over_90,
+        };
+        *bucket += amount;
+        self.total += amount;
+    }
+
+    /// Everything already past due.
+    pub fn overdue(&self) -> f64 {
+        self.total - self.current
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantAging {
+    pub tenant_id: i64,
+    pub tenant_name: Option<String>,
+    pub phone_number: Option<String>,
+    pub unit_id: i64,
+    pub unit_number: Option<String>,
+    pub property_id: i64,
+    pub property_name: Option<String>,
+    pub manager_id: Option<i64>,
+    /// Age of the oldest unpaid charge, zero when nothing is past due.
+    pub oldest_days_past_due: i64,
+    pub buckets: AgingBuckets,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgingGroup {
+    pub id: Option<i64>,
+    pub name: Option<String>,
+    pub buckets: AgingBuckets,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgingReport {
+    pub as_of: NaiveDate,
+    pub tenants: Vec<TenantAging>,
+    pub by_property: Vec<AgingGroup>,
+    pub by_manager: Vec<AgingGroup>,
+    pub totals: AgingBuckets,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionItem {
+    pub priority: TaskPriority,
+    pub overdue_amount: f64,
+    #[serde(flatten)]
+    pub tenant: TenantAging,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorAging {
+    pub vendor_id: i64,
+    pub vendor_name: String,
+    pub phone_number: Option<String>,
+    pub open_bills: usize,
+    /// Age of the oldest unpaid bill, zero when nothing is past due.
+    pub oldest_days_past_due: i64,
+    pub buckets: AgingBuckets,
+}
+
+/// What is owed to vendors on unpaid bills.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayablesAgingReport {
+    pub as_of: NaiveDate,
+    pub vendors: Vec<VendorAging>,
+    pub totals: AgingBuckets,
+}
+
+#[derive(sqlx::FromRow)]
+struct UnpaidCharge {
+    tenant_id: i64,
+    tenant_name: Option<String>,
+    phone_number: Option<String>,
+    unit_id: i64,
+    unit_number: Option<String>,
+    property_id: i64,
+    property_name: Option<String>,
+    manager_id: Option<i64>,
+    manager_name: Option<String>,
+    amount: f64,
+    due_date: String,
+}
+
+#[tauri::command]
+pub async fn get_arrears_aging(
+    app: AppHandle,
+    as_of: Option<String>,
+    property_id: Option<i64>,
+    manager_id: Option<i64>,
+) -> Result<AgingReport> {
+    let as_of = parse_as_of(as_of)?;
+    let pool = db::pool(&app).await?;
+    arrears_aging(&pool, as_of, property_id, manager_id).await
+}
+
+/// Tenants with anything at least `min_days_past_due` days overdue, most
+/// urgent first.
+#[tauri::command]
+pub async fn get_collections_worklist(
+    app: AppHandle,
+    as_of: Option<String>,
+    property_id: Option<i64>,
+    manager_id: Option<i64>,
+    min_days_past_due: Option<i64>,
+) -> Result<Vec<CollectionItem>> {
+    let as_of = parse_as_of(as_of)?;
+    let pool = db::pool(&app).await?;
+    let report = arrears_aging(&pool, as_of, property_id, manager_id).await?;
+    Ok(worklist(report, min_days_past_due.unwrap_or(1)))
+}
+
+/// Adds a follow-up task for every worklist entry and returns how many were
+/// new. Task names carry the tenant, unit and month, so re-running within a
+/// month does not duplicate them.
+#[tauri::command]
+pub async fn create_collection_tasks(
+    app: AppHandle,
+    as_of: Option<String>,
+    property_id: Option<i64>,
+    manager_id: Option<i64>,
+    min_days_past_due: Option<i64>,
+) -> Result<usize> {
+    let as_of = parse_as_of(as_of)?;
+    let pool = db::pool(&app).await?;
+    let report = arrears_aging(&pool, as_of, property_id, manager_id).await?;
+
+    let mut tx = pool.begin().await?;
+    let mut created = 0;
+    for item in worklist(report, min_days_past_due.unwrap_or(1)) {
+        let tenant = &item.tenant;
+        let name = format!(
+            "Collect arrears: {} - {} {} ({})",
+            tenant.tenant_name.as_deref().unwrap_or("Unknown tenant"),
+            tenant.property_name.as_deref().unwrap_or(""),
+            tenant.unit_number.as_deref().unwrap_or(""),
+            Month::of(as_of),
+        );
+        let lead_days = match item.priority {
+            TaskPriority::High => 1,
+            TaskPriority::Medium => 3,
+            TaskPriority::Low => 7,
+        };
+        if tasks::create_task(
+            &mut *tx,
+            &name,
+            as_of + Duration::days(lead_days),
+            item.priority,
+        )
+        .await?
+        {
+            created += 1;
+        }
+    }
+    tx.commit().await?;
+    Ok(created)
+}
+
+/// Unpaid vendor bills dated on or before `as_of`, by vendor.
+#[tauri::command]
+pub async fn get_payables_aging(
+    app: AppHandle,
+    as_of: Option<String>,
+    property_id: Option<i64>,
+) -> Result<PayablesAgingReport> {
+    let as_of = parse_as_of(as_of)?;
+    let pool = db::pool(&app).await?;
+    payables_aging(&pool, as_of, property_id).await
+}
+
+fn parse_as_of(as_of: Option<String>) -> Result<NaiveDate> {
+    Ok(as_of
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today))
+}
+
+pub async fn arrears_aging(
+    pool: &SqlitePool,
+    as_of: NaiveDate,
+    property_id: Option<i64>,
+    manager_id: Option<i64>,
+) -> Result<AgingReport> {
+    let charges: Vec<UnpaidCharge> = sqlx::query_as(
+        "SELECT c.tenant_id, t.full_name AS tenant_name, t.phone_number,
+                COALESCE(c.unit_id, 0) AS unit_id, u.unit_number,
+                COALESCE(c.property_id, 0) AS property_id, pr.name AS property_name,
+                pr.manager_id, m.name AS manager_name,
+                CAST(c.outstanding AS REAL) AS amount, c.due_date
+         FROM charge_balances c
+         LEFT JOIN tenants t ON t.tenant_id = c.tenant_id
+         LEFT JOIN units u ON u.unit_id = c.unit_id
+         LEFT JOIN properties pr ON pr.property_id = c.property_id
+         LEFT JOIN managers m ON m.manager_id = pr.manager_id
+         WHERE c.status = 'Open' AND c.outstanding > 0.005
+           AND (?1 IS NULL OR c.property_id = ?1)
+           AND (?2 IS NULL OR pr.manager_id = ?2)",
+    )
+    .bind(property_id)
+    .bind(manager_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut tenants: BTreeMap<(i64, i64), TenantAging> = BTreeMap::new();
+    let mut by_property: BTreeMap<i64, AgingGroup> = BTreeMap::new();
+    let mut by_manager: BTreeMap<Option<i64>, AgingGroup> = BTreeMap::new();
+    let mut totals = AgingBuckets::default();
+
+    for charge in charges {
+        let days = (as_of - period::parse_date(&charge.due_date)?).num_days();
+        let tenant = tenants
+            .entry((charge.tenant_id, charge.unit_id))
+            .or_insert_with(|| TenantAging {
+                tenant_id: charge.tenant_id,
+                tenant_name: charge.tenant_name.clone(),
+                phone_number: charge.phone_number.clone(),
+                unit_id: charge.unit_id,
+                unit_number: charge.unit_number.clone(),
+                property_id: charge.property_id,
+                property_name: charge.property_name.clone(),
+                manager_id: charge.manager_id,
+                oldest_days_past_due: 0,
+                buckets: AgingBuckets::default(),
+            });
+        tenant.buckets.add(days, charge.amount);
+        tenant.oldest_days_past_due = tenant.oldest_days_past_due.max(days);
+
+        by_property
+            .entry(charge.property_id)
+            .or_insert_with(|| AgingGroup {
+                id: Some(charge.property_id),
+                name: charge.property_name.clone(),
+                buckets: AgingBuckets::default(),
+            })
+            .buckets
+            .add(days, charge.amount);
+        by_manager
+            .entry(charge.manager_id)
+            .or_insert_with(|| AgingGroup {
+                id: charge.manager_id,
+                name: charge.manager_name.clone(),
+                buckets: AgingBuckets::default(),
+            })
+            .buckets
+            .add(days, charge.amount);
+        totals.add(days, charge.amount);
+    }
+
+    let mut tenants: Vec<TenantAging> = tenants.into_values().collect();
+    tenants.sort_by(|a, b| b.buckets.overdue().total_cmp(&a.buckets.overdue()));
+
+    Ok(AgingReport {
+        as_of,
+        tenants,
+        by_property: by_property.into_values().collect(),
+        by_manager: by_manager.into_values().collect(),
+        totals,
+    })
+}
+
+#[derive(sqlx::FromRow)]
+struct UnpaidBill {
+    vendor_id: i64,
+    vendor_name: String,
+    phone_number: Option<String>,
+    amount: f64,
+    due_date: String,
+}
+
+pub async fn payables_aging(
+    pool: &SqlitePool,
+    as_of: NaiveDate,
+    property_id: Option<i64>,
+) -> Result<PayablesAgingReport> {
+    let bills: Vec<UnpaidBill> = sqlx::query_as(
+        "SELECT b.vendor_id, v.name AS vendor_name, v.phone_number,
+                CAST(b.outstanding AS REAL) AS amount, b.due_date
+         FROM vendor_bill_balances b
+         JOIN vendors v ON v.vendor_id = b.vendor_id
+         WHERE b.status = 'Open' AND b.outstanding > 0.005 AND b.bill_date <= ?1
+           AND (?2 IS NULL OR b.property_id = ?2)",
+    )
+    .bind(as_of.to_string())
+    .bind(property_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut vendors: BTreeMap<i64, VendorAging> = BTreeMap::new();
+    let mut totals = AgingBuckets::default();
+    for bill in bills {
+        let days = (as_of - period::parse_date(&bill.due_date)?).num_days();
+        let vendor = vendors
+            .entry(bill.vendor_id)
+            .or_insert_with(|| VendorAging {
+                vendor_id: bill.vendor_id,
+                vendor_name: bill.vendor_name.clone(),
+                phone_number: bill.phone_number.clone(),
+                open_bills: 0,
+                oldest_days_past_due: 0,
+                buckets: AgingBuckets::default(),
+            });
+        vendor.open_bills += 1;
+        vendor.buckets.add(days, bill.amount);
+        vendor.oldest_days_past_due = vendor.oldest_days_past_due.max(days);
+        totals.add(days, bill.amount);
+    }
+
+    let mut vendors: Vec<VendorAging> = vendors.into_values().collect();
+    vendors.sort_by(|a, b| b.buckets.overdue().total_cmp(&a.buckets.overdue()));
+    Ok(PayablesAgingReport {
+        as_of,
+        vendors,
+        totals,
+    })
+}
+
+/// Orders tenants by urgency: anything over 60 days is high priority, over 30
+/// medium, the rest low; ties go to the larger overdue balance.
+pub fn worklist(report: AgingReport, min_days_past_due: i64) -> Vec<CollectionItem> {
+    let mut items: Vec<CollectionItem> = report
+        .tenants
+        .into_iter()
+        .filter(|t| t.buckets.overdue() > 0.0 && t.oldest_days_past_due >= min_days_past_due)
+        .map(|tenant| CollectionItem {
+            priority: match tenant.oldest_days_past_due {
+                61.. => TaskPriority::High,
+                31..=60 => TaskPriority::Medium,
+                _ => TaskPriority::Low,
+            },
+            overdue_amount: tenant.buckets.overdue(),
+            tenant,
+        })
+        .collect();
+    items.sort_by(|a, b| {
+        b.priority
+            .cmp(&a.priority)
+            .then(b.overdue_amount.total_cmp(&a.overdue_amount))
+    });
+    items
+}
@@ -0,0 +1,3 @@
+pub mod aging;
+pub mod rent_roll;
+pub mod tax;
@@ -0,0 +1,287 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::export::{self, Cell, ExportFormat, Table};
+use crate::period;
+
+/// One row per unit, describing the lease in force on the report date.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct RentRollEntry {
+    pub unit_id: i64,
+    pub unit_number: String,
+    pub block_name: Option<String>,
+    pub unit_status: String,
+    pub tenant_id: Option<i64>,
+    pub tenant_name: Option<String>,
+    pub lease_start_date: Option<String>,
+    pub lease_end_date: Option<String>,
+    /// Lease rent, falling back to the unit's listed rent when vacant.
+    pub contracted_rent: f64,
+    pub deposit_held: f64,
+    /// Unpaid part of open charges due on or before the report date.
+    pub balance_due: f64,
+    #[sqlx(skip)]
+    pub status: RentRollStatus,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub enum RentRollStatus {
+    #[default]
+    Vacant,
+    Current,
+    #[serde(rename = "In Arrears")]
+    InArrears,
+}
+
+impl RentRollStatus {
+    fn label(self) -> &'static str {
+        match self {
+            RentRollStatus::Vacant => "Vacant",
+            RentRollStatus::Current => "Current",
+            RentRollStatus::InArrears => "In Arrears",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RentRollTotals {
+    pub units: usize,
+    pub occupied_units: usize,
+    /// Rent under lease on occupied units.
+    pub contracted_rent: f64,
+    /// Listed rent of vacant units.
+    pub vacant_rent: f64,
+    pub deposits_held: f64,
+    pub balance_due: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RentRoll {
+    pub property_id: i64,
+    pub property_name: String,
+    pub as_of: NaiveDate,
+    pub entries: Vec<RentRollEntry>,
+    pub totals: RentRollTotals,
+}
+
+#[tauri::command]
+pub async fn get_rent_roll(
+    app: AppHandle,
+    property_id: i64,
+    as_of: Option<String>,
+) -> Result<RentRoll> {
+    let as_of = as_of
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let pool = db::pool(&app).await?;
+    rent_roll(&pool, property_id, as_of).await
+}
+
+/// Writes the rent roll to `path` and returns the path back.
+#[tauri::command]
+pub async fn export_rent_roll(
+    app: AppHandle,
+    property_id: i64,
+    as_of: Option<String>,
+    format: ExportFormat,
+    path: PathBuf,
+) -> Result<PathBuf> {
+    let roll = get_rent_roll(app, property_id, as_of).await?;
+    export::write(&roll.to_table(), format, &path)?;
+    Ok(path)
+}
+
+pub async fn rent_roll(pool: &SqlitePool, property_id: i64, as_of: NaiveDate) -> Result<RentRoll> {
+    let property_name: Option<(String,)> =
+        sqlx::query_as("SELECT name FROM properties WHERE property_id = ?1")
+            .bind(property_id)
+            .fetch_optional(pool)
+            .await?;
+    let (property_name,) =
+        property_name.ok_or_else(|| Error::NotFound(format!("property {property_id}")))?;
+
+    let mut entries: Vec<RentRollEntry> = sqlx::query_as(
+        "SELECT u.unit_id, u.unit_number, b.block_name, u.unit_status,
+                l.tenant_id, t.full_name AS tenant_name, l.lease_start_date, l.lease_end_date,
+                CAST(COALESCE(l.rent_amount, u.monthly_rent, 0) AS REAL) AS contracted_rent,
+                CAST(COALESCE(l.deposit_paid, 0) AS REAL) AS deposit_held,
+                CASE WHEN l.lease_id IS NULL THEN 0.0 ELSE (
+                    SELECT CAST(COALESCE(SUM(c.outstanding), 0) AS REAL) FROM charge_balances c
+                    WHERE c.tenant_id = l.tenant_id
+                      AND c.unit_id = u.unit_id
+                      AND c.status = 'Open'
+                      AND c.due_date <= ?2
+                ) END AS balance_due
+         FROM units u
+         LEFT JOIN blocks b ON b.block_id = u.block_id
+         LEFT JOIN leases l ON l.lease_id = (
+             SELECT lease_id FROM leases
+             WHERE unit_id = u.unit_id
+               AND lease_start_date <= ?2 AND lease_end_date >= ?2
+               AND COALESCE(lower(status), 'active') <> 'terminated'
+             ORDER BY lease_start_date DESC
+             LIMIT 1
+         )
+         LEFT JOIN tenants t ON t.tenant_id = l.tenant_id
+         WHERE u.property_id = ?1
+         ORDER BY b.block_name, u.unit_number",
+    )
+    .bind(property_id)
+    .bind(as_of.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    let mut totals = RentRollTotals::default();
+    for entry in &mut entries {
+        entry.status = match entry.tenant_id {
+            None => RentRollStatus::Vacant,
+            Some(_) if entry.balance_due > 0.0 => RentRollStatus::InArrears,
+            Some(_) => RentRollStatus::Current,
+        };
+        totals.units += 1;
+        if entry.tenant_id.is_some() {
+            totals.occupied_units += 1;
+            totals.contracted_rent += entry.contracted_rent;
+        } else {
+            totals.vacant_rent += entry.contracted_rent;
+        }
+        totals.deposits_held += entry.deposit_held;
+        totals.balance_due += entry.balance_due;
+    }
+
+    Ok(RentRoll {
+        property_id,
+        property_name,
+        as_of,
+        entries,
+        totals,
+    })
+}
+
+impl RentRoll {
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new(
+            format!("Rent Roll - {} as of {}", self.property_name, self.as_of),
+            &[
+                "Unit",
+                "Block",
+                "Tenant",
+                "Lease Start",
+                "Lease End",
+                "Rent",
+                "Deposit Held",
+                "Balance Due",
+                "Status",
+            ],
+        );
+        for entry in &self.entries {
+            table.push(vec![
+                Cell::text(&entry.unit_number),
+                Cell::opt_text(entry.block_name.as_deref()),
+                Cell::opt_text(entry.tenant_name.as_deref()),
+                Cell::opt_text(entry.lease_start_date.as_deref()),
+                Cell::opt_text(entry.lease_end_date.as_deref()),
+                Cell::Money(entry.contracted_rent),
+                Cell::Money(entry.deposit_held),
+                Cell::Money(entry.balance_due),
+                Cell::text(entry.status.label()),
+            ]);
+        }
+        let totals = &self.totals;
+        table.push(vec![
+            Cell::text("Total"),
+            Cell::Empty,
+            Cell::text(format!(
+                "{} of {} occupied",
+                totals.occupied_units, totals.units
+            )),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Money(totals.contracted_rent),
+            Cell::Money(totals.deposits_held),
+            Cell::Money(totals.balance_due),
+            Cell::Empty,
+        ]);
+        // Vacant units list their asking rent above, which the total leaves out.
+        table.push(vec![
+            Cell::text("Vacant Rent"),
+            Cell::Empty,
+            Cell::text(format!("{} vacant", totals.units - totals.occupied_units)),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Money(totals.vacant_rent),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+        ]);
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(unit_number: &str, tenant: Option<&str>, rent: f64) -> RentRollEntry {
+        RentRollEntry {
+            unit_id: 1,
+            unit_number: unit_number.into(),
+            block_name: None,
+            unit_status: "occupied".into(),
+            tenant_id: tenant.map(|_| 1),
+            tenant_name: tenant.map(Into::into),
+            lease_start_date: None,
+            lease_end_date: None,
+            contracted_rent: rent,
+            deposit_held: 0.0,
+            balance_due: 0.0,
+            status: if tenant.is_some() {
+                RentRollStatus::Current
+            } else {
+                RentRollStatus::Vacant
+            },
+        }
+    }
+
+    #[test]
+    fn table_totals_show_vacant_rent_apart() {
+        let roll = RentRoll {
+            property_id: 1,
+            property_name: "Sunrise".into(),
+            as_of: NaiveDate::from_ymd_opt(2025, 6, 1).unwrap(),
+            entries: vec![
+                entry("A1", Some("Jane"), 25_000.0),
+                entry("A2", None, 18_000.0),
+                entry("A3", None, 20_000.0),
+            ],
+            totals: RentRollTotals {
+                units: 3,
+                occupied_units: 1,
+                contracted_rent: 25_000.0,
+                vacant_rent: 38_000.0,
+                deposits_held: 0.0,
+                balance_due: 0.0,
+            },
+        };
+        let csv = String::from_utf8(export::render(&roll.to_table(), ExportFormat::Csv)).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[lines.len() - 2..],
+            [
+                "Total,,1 of 3 occupied,,,25000.00,0.00,0.00,",
+                "Vacant Rent,,2 vacant,,,38000.00,,,",
+            ]
+        );
+    }
+}
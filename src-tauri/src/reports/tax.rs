@@ -0,0 +1,896 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::{Datelike, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::billing::allocations::round_cents;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::export::{self, Cell, ExportFormat, Table};
+use crate::period::{self, Month};
+use crate::settings;
+
+/// Jurisdiction of properties without their own, unless the
+/// `tax.jurisdiction` setting names another.
+pub const DEFAULT_JURISDICTION: &str = "KE";
+
+/// A tax on gross rent as one jurisdiction levies it over a date range.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxRule {
+    #[serde(default)]
+    pub rule_id: Option<i64>,
+    pub jurisdiction: String,
+    pub tax_name: String,
+    /// Percent of gross rent received.
+    pub rate: f64,
+    /// Owners whose gross rent over the last twelve months falls outside
+    /// these bounds are not liable under this rule.
+    pub min_annual_gross: Option<f64>,
+    pub max_annual_gross: Option<f64>,
+    /// Day of the following month the return is due.
+    pub filing_due_day: i64,
+    pub effective_from: String,
+    pub effective_to: Option<String>,
+}
+
+impl TaxRule {
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+        if self.jurisdiction.trim().is_empty() || self.tax_name.trim().is_empty() {
+            return invalid("tax rules need a jurisdiction and a name");
+        }
+        if !(0.0..=100.0).contains(&self.rate) {
+            return invalid("tax rate must be between 0% and 100%");
+        }
+        if let (Some(min), Some(max)) = (self.min_annual_gross, self.max_annual_gross) {
+            if min > max {
+                return invalid("minimum annual rent cannot exceed the maximum");
+            }
+        }
+        if !(1..=31).contains(&self.filing_due_day) {
+            return invalid("filing due day must be a day of the month");
+        }
+        let from = period::parse_date(&self.effective_from)?;
+        if let Some(to) = &self.effective_to {
+            if period::parse_date(to)? < from {
+                return invalid("a tax rule cannot end before it starts");
+            }
+        }
+        Ok(())
+    }
+
+    fn covers(&self, month: Month) -> bool {
+        self.effective_from.as_str() <= month.last_day().to_string().as_str()
+            && self
+                .effective_to
+                .as_deref()
+                .is_none_or(|to| to >= month.first_day().to_string().as_str())
+    }
+
+    fn status(&self, annual_gross: f64) -> TaxStatus {
+        if self.min_annual_gross.is_some_and(|min| annual_gross < min) {
+            TaxStatus::BelowThreshold
+        } else if self.max_annual_gross.is_some_and(|max| annual_gross > max) {
+            TaxStatus::AboveThreshold
+        } else {
+            TaxStatus::Taxable
+        }
+    }
+
+    /// Tax on a month's `gross_rent`, zero unless the owner's
+    /// `annual_gross` makes them liable under this rule.
+    fn tax_due(&self, gross_rent: f64, annual_gross: f64) -> (TaxStatus, f64) {
+        match self.status(annual_gross) {
+            TaxStatus::Taxable => (
+                TaxStatus::Taxable,
+                round_cents(gross_rent * self.rate / 100.0),
+            ),
+            status => (status, 0.0),
+        }
+    }
+
+    /// Filing deadline for `month`, moved to the last day of the following
+    /// month when that month is shorter.
+    fn due_date(&self, month: Month) -> NaiveDate {
+        let next = month.next();
+        let day = (self.filing_due_day as u32).min(next.last_day().day());
+        next.first_day().with_day(day).expect("valid day")
+    }
+}
+
+const RULE_COLUMNS: &str = "rule_id, jurisdiction, tax_name, CAST(rate AS REAL) AS rate,
+     CAST(min_annual_gross AS REAL) AS min_annual_gross,
+     CAST(max_annual_gross AS REAL) AS max_annual_gross,
+     filing_due_day, effective_from, effective_to";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub enum TaxStatus {
+    #[default]
+    Taxable,
+    /// Not liable under the rule; the owner's rent is below its threshold.
+    #[serde(rename = "Below Threshold")]
+    BelowThreshold,
+    /// Not liable under the rule; the owner's rent has to be declared under
+    /// the jurisdiction's ordinary income tax instead.
+    #[serde(rename = "Above Threshold")]
+    AboveThreshold,
+}
+
+impl TaxStatus {
+    fn label(self) -> &'static str {
+        match self {
+            TaxStatus::Taxable => "Taxable",
+            TaxStatus::BelowThreshold => "Below Threshold",
+            TaxStatus::AboveThreshold => "Above Threshold",
+        }
+    }
+}
+
+/// Tax a tenant withheld from a rent payment and paid over on the owner's
+/// behalf.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxWithholding {
+    pub withholding_id: i64,
+    pub payment_id: String,
+    pub amount: f64,
+    pub certificate_number: Option<String>,
+    pub withheld_on: String,
+    pub payment_date: String,
+    pub amount_paid: f64,
+    pub property_id: i64,
+    pub tenant_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTaxWithholding {
+    pub payment_id: String,
+    pub amount: f64,
+    #[serde(default)]
+    pub certificate_number: Option<String>,
+    pub withheld_on: String,
+}
+
+const WITHHOLDING_COLUMNS: &str =
+    "w.withholding_id, w.payment_id, CAST(w.amount AS REAL) AS amount, w.certificate_number,
+     w.withheld_on, p.payment_date, CAST(p.amount_paid AS REAL) AS amount_paid,
+     CAST(p.property_id AS INTEGER) AS property_id, t.full_name AS tenant_name";
+
+/// Rent received on one property in one month.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RentalIncomeLine {
+    pub month: Month,
+    pub property_id: i64,
+    pub property_name: String,
+    pub owner_name: Option<String>,
+    pub owner_tax_id: Option<String>,
+    pub payments: i64,
+    /// Rent received plus any tax tenants withheld from it.
+    pub gross_rent: f64,
+    pub tax_withheld: f64,
+}
+
+/// One owner's return for one month.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RentalIncomeFiling {
+    pub month: Month,
+    pub owner_name: Option<String>,
+    pub owner_tax_id: Option<String>,
+    pub properties: Vec<String>,
+    pub gross_rent: f64,
+    /// The owner's gross rent over the twelve months ending with `month`,
+    /// checked against the rule's thresholds.
+    pub annual_gross: f64,
+    pub status: TaxStatus,
+    pub rate: f64,
+    pub tax_due: f64,
+    pub tax_withheld: f64,
+    /// Tax due less what tenants already withheld, never below zero.
+    pub net_payable: f64,
+    pub due_date: NaiveDate,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RentalIncomeTaxTotals {
+    pub gross_rent: f64,
+    pub tax_due: f64,
+    pub tax_withheld: f64,
+    pub net_payable: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RentalIncomeTax {
+    pub jurisdiction: String,
+    pub from: Month,
+    pub to: Month,
+    pub property_id: Option<i64>,
+    /// Rules applied, oldest first.
+    pub rules: Vec<TaxRule>,
+    pub lines: Vec<RentalIncomeLine>,
+    pub filings: Vec<RentalIncomeFiling>,
+    pub totals: RentalIncomeTaxTotals,
+}
+
+#[tauri::command]
+pub async fn get_tax_rules(app: AppHandle, jurisdiction: Option<String>) -> Result<Vec<TaxRule>> {
+    let pool = db::pool(&app).await?;
+    rules(&pool, jurisdiction.as_deref()).await
+}
+
+/// Inserts the rule, or updates it when `ruleId` is set. A jurisdiction's
+/// rules may not overlap in time.
+#[tauri::command]
+pub async fn save_tax_rule(app: AppHandle, rule: TaxRule) -> Result<TaxRule> {
+    let pool = db::pool(&app).await?;
+    save_rule(&pool, rule).await
+}
+
+#[tauri::command]
+pub async fn delete_tax_rule(app: AppHandle, rule_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    sqlx::query("DELETE FROM tax_rules WHERE rule_id = ?1")
+        .bind(rule_id)
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+/// Sets who files tax on a property's rent and under which jurisdiction;
+/// `None` or blank values clear the field.
+#[tauri::command]
+pub async fn set_property_tax_profile(
+    app: AppHandle,
+    property_id: i64,
+    owner_name: Option<String>,
+    owner_tax_id: Option<String>,
+    tax_jurisdiction: Option<String>,
+) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let blank_to_none = |value: Option<String>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let result = sqlx::query(
+        "UPDATE properties
+         SET owner_name = ?2, owner_tax_id = ?3, tax_jurisdiction = ?4,
+             updated_at = CURRENT_TIMESTAMP
+         WHERE property_id = ?1",
+    )
+    .bind(property_id)
+    .bind(blank_to_none(owner_name))
+    .bind(blank_to_none(owner_tax_id).map(|id| id.to_uppercase()))
+    .bind(blank_to_none(tax_jurisdiction).map(|j| j.to_uppercase()))
+    .execute(&pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!("property {property_id}")));
+    }
+    Ok(())
+}
+
+/// Records tax a tenant withheld from a rent payment, replacing any earlier
+/// record for the same payment. The withheld amount counts towards the
+/// owner's gross rent and is credited against the tax due.
+#[tauri::command]
+pub async fn record_tax_withholding(
+    app: AppHandle,
+    withholding: NewTaxWithholding,
+) -> Result<TaxWithholding> {
+    let pool = db::pool(&app).await?;
+    record_withholding(&pool, &withholding).await
+}
+
+/// Withholdings on rent paid within the range, newest first.
+#[tauri::command]
+pub async fn get_tax_withholdings(
+    app: AppHandle,
+    from: Option<Month>,
+    to: Option<Month>,
+    property_id: Option<i64>,
+) -> Result<Vec<TaxWithholding>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(&format!(
+        "SELECT {WITHHOLDING_COLUMNS}
+         FROM tax_withholdings w
+         JOIN payments p ON p.payment_id = w.payment_id
+         LEFT JOIN tenants t ON t.tenant_id = CAST(p.tenant_id AS INTEGER)
+         WHERE (?1 IS NULL OR p.payment_date >= ?1)
+           AND (?2 IS NULL OR p.payment_date <= ?2)
+           AND (?3 IS NULL OR CAST(p.property_id AS INTEGER) = ?3)
+         ORDER BY p.payment_date DESC, w.withholding_id DESC"
+    ))
+    .bind(from.map(|m| m.first_day().to_string()))
+    .bind(to.map(|m| m.last_day().to_string()))
+    .bind(property_id)
+    .fetch_all(&pool)
+    .await?)
+}
+
+#[tauri::command]
+pub async fn delete_tax_withholding(app: AppHandle, withholding_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    sqlx::query("DELETE FROM tax_withholdings WHERE withholding_id = ?1")
+        .bind(withholding_id)
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+/// Gross rent, tax due and withholdings per owner per month from `from` to
+/// `to` (default `from`), under the given jurisdiction's rules.
+#[tauri::command]
+pub async fn get_rental_income_tax(
+    app: AppHandle,
+    from: Month,
+    to: Option<Month>,
+    jurisdiction: Option<String>,
+    property_id: Option<i64>,
+) -> Result<RentalIncomeTax> {
+    let pool = db::pool(&app).await?;
+    rental_income_tax(
+        &pool,
+        from,
+        to.unwrap_or(from),
+        jurisdiction.as_deref(),
+        property_id,
+    )
+    .await
+}
+
+/// Writes the owners' returns to `path` and returns the path back.
+#[tauri::command]
+pub async fn export_rental_income_tax(
+    app: AppHandle,
+    from: Month,
+    to: Option<Month>,
+    jurisdiction: Option<String>,
+    property_id: Option<i64>,
+    format: ExportFormat,
+    path: PathBuf,
+) -> Result<PathBuf> {
+    let report = get_rental_income_tax(app, from, to, jurisdiction, property_id).await?;
+    export::write(&report.to_table(), format, &path)?;
+    Ok(path)
+}
+
+pub async fn rules(pool: &SqlitePool, jurisdiction: Option<&str>) -> Result<Vec<TaxRule>> {
+    Ok(sqlx::query_as(&format!(
+        "SELECT {RULE_COLUMNS} FROM tax_rules
+         WHERE ?1 IS NULL OR jurisdiction = ?1
+         ORDER BY jurisdiction, effective_from"
+    ))
+    .bind(jurisdiction.map(str::to_uppercase))
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn save_rule(pool: &SqlitePool, rule: TaxRule) -> Result<TaxRule> {
+    rule.validate()?;
+    let rule = TaxRule {
+        jurisdiction: rule.jurisdiction.trim().to_uppercase(),
+        tax_name: rule.tax_name.trim().to_string(),
+        ..rule
+    };
+
+    let mut tx = pool.begin().await?;
+    let (overlaps,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (
+             SELECT 1 FROM tax_rules
+             WHERE jurisdiction = ?1 AND rule_id IS NOT ?2
+               AND effective_from <= COALESCE(?4, '9999-12-31')
+               AND COALESCE(effective_to, '9999-12-31') >= ?3
+         )",
+    )
+    .bind(&rule.jurisdiction)
+    .bind(rule.rule_id)
+    .bind(&rule.effective_from)
+    .bind(&rule.effective_to)
+    .fetch_one(&mut *tx)
+    .await?;
+    if overlaps {
+        return Err(Error::InvalidInput(format!(
+            "another {} tax rule is in effect during these dates; end it first",
+            rule.jurisdiction
+        )));
+    }
+
+    let rule_id = match rule.rule_id {
+        Some(rule_id) => {
+            let result = sqlx::query(
+                "UPDATE tax_rules
+                 SET jurisdiction = ?2, tax_name = ?3, rate = ?4, min_annual_gross = ?5,
+                     max_annual_gross = ?6, filing_due_day = ?7, effective_from = ?8,
+                     effective_to = ?9, updated_at = CURRENT_TIMESTAMP
+                 WHERE rule_id = ?1",
+            )
+            .bind(rule_id)
+            .bind(&rule.jurisdiction)
+            .bind(&rule.tax_name)
+            .bind(rule.rate)
+            .bind(rule.min_annual_gross)
+            .bind(rule.max_annual_gross)
+            .bind(rule.filing_due_day)
+            .bind(&rule.effective_from)
+            .bind(&rule.effective_to)
+            .execute(&mut *tx)
+            .await?;
+            if result.rows_affected() == 0 {
+                return Err(Error::NotFound(format!("tax rule {rule_id}")));
+            }
+            rule_id
+        }
+        None => sqlx::query(
+            "INSERT INTO tax_rules
+                 (jurisdiction, tax_name, rate, min_annual_gross, max_annual_gross,
+                  filing_due_day, effective_from, effective_to)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )
+        .bind(&rule.jurisdiction)
+        .bind(&rule.tax_name)
+        .bind(rule.rate)
+        .bind(rule.min_annual_gross)
+        .bind(rule.max_annual_gross)
+        .bind(rule.filing_due_day)
+        .bind(&rule.effective_from)
+        .bind(&rule.effective_to)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid(),
+    };
+    tx.commit().await?;
+    Ok(TaxRule {
+        rule_id: Some(rule_id),
+        ..rule
+    })
+}
+
+pub async fn record_withholding(
+    pool: &SqlitePool,
+    withholding: &NewTaxWithholding,
+) -> Result<TaxWithholding> {
+    period::parse_date(&withholding.withheld_on)?;
+    if withholding.amount <= 0.0 {
+        return Err(Error::InvalidInput(
+            "withheld amount must be positive".into(),
+        ));
+    }
+    let payment: Option<(String, String)> = sqlx::query_as(
+        "SELECT payment_category, payment_status FROM payments WHERE payment_id = ?1",
+    )
+    .bind(&withholding.payment_id)
+    .fetch_optional(pool)
+    .await?;
+    match payment {
+        None => {
+            return Err(Error::NotFound(format!(
+                "payment {}",
+                withholding.payment_id
+            )))
+        }
+        Some((category, status)) if category != "Rent" || status != "Paid" => {
+            return Err(Error::InvalidInput(format!(
+                "payment {} is not a paid rent payment",
+                withholding.payment_id
+            )))
+        }
+        Some(_) => {}
+    }
+
+    let (withholding_id,): (i64,) = sqlx::query_as(
+        "INSERT INTO tax_withholdings (payment_id, amount, certificate_number, withheld_on)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (payment_id) DO UPDATE SET amount = excluded.amount,
+                                                certificate_number = excluded.certificate_number,
+                                                withheld_on = excluded.withheld_on
+         RETURNING withholding_id",
+    )
+    .bind(&withholding.payment_id)
+    .bind(round_cents(withholding.amount))
+    .bind(
+        withholding
+            .certificate_number
+            .as_deref()
+            .map(str::trim)
+            .filter(|c| !c.is_empty()),
+    )
+    .bind(&withholding.withheld_on)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(sqlx::query_as(&format!(
+        "SELECT {WITHHOLDING_COLUMNS}
+         FROM tax_withholdings w
+         JOIN payments p ON p.payment_id = w.payment_id
+         LEFT JOIN tenants t ON t.tenant_id = CAST(p.tenant_id AS INTEGER)
+         WHERE w.withholding_id = ?1"
+    ))
+    .bind(withholding_id)
+    .fetch_one(pool)
+    .await?)
+}
+
+#[derive(sqlx::FromRow)]
+struct IncomeRow {
+    month: String,
+    property_id: i64,
+    property_name: String,
+    owner_name: Option<String>,
+    owner_tax_id: Option<String>,
+    payments: i64,
+    gross_rent: f64,
+    tax_withheld: f64,
+}
+
+impl RentalIncomeLine {
+    /// Properties of the same owner share a key; properties without owner
+    /// details are filed on their own.
+    fn owner_key(&self) -> String {
+        match (&self.owner_tax_id, &self.owner_name) {
+            (Some(tax_id), _) => format!("pin:{tax_id}"),
+            (None, Some(name)) => format!("name:{}", name.to_lowercase()),
+            (None, None) => format!("property:{}", self.property_id),
+        }
+    }
+}
+
+pub async fn rental_income_tax(
+    pool: &SqlitePool,
+    from: Month,
+    to: Month,
+    jurisdiction: Option<&str>,
+    property_id: Option<i64>,
+) -> Result<RentalIncomeTax> {
+    if to < from {
+        return Err(Error::InvalidInput(format!("{to} is before {from}")));
+    }
+    let default_jurisdiction =
+        settings::get_or(pool, "tax.jurisdiction", DEFAULT_JURISDICTION.to_string())
+            .await?
+            .to_uppercase();
+    let jurisdiction =
+        jurisdiction.map_or_else(|| default_jurisdiction.clone(), |j| j.trim().to_uppercase());
+
+    let all_rules = rules(pool, Some(&jurisdiction)).await?;
+    let mut month_rules = BTreeMap::new();
+    let mut month = from;
+    while month <= to {
+        let rule = all_rules
+            .iter()
+            .rev()
+            .find(|rule| rule.covers(month))
+            .ok_or_else(|| {
+                Error::InvalidInput(format!("no {jurisdiction} tax rule covers {month}"))
+            })?;
+        month_rules.insert(month, rule);
+        month = month.next();
+    }
+
+    // Thresholds look at the twelve months up to each reported month, and at
+    // all of an owner's properties, so the query reaches back eleven months
+    // and ignores `property_id`.
+    let since = from.first_day() - Months::new(11);
+    let rows: Vec<IncomeRow> = sqlx::query_as(
+        "SELECT strftime('%Y-%m', p.payment_date) AS month, pr.property_id,
+                pr.name AS property_name,
+                NULLIF(trim(pr.owner_name), '') AS owner_name,
+                NULLIF(trim(pr.owner_tax_id), '') AS owner_tax_id,
+                COUNT(*) AS payments,
+                CAST(SUM(p.amount_paid + COALESCE(w.amount, 0)) AS REAL) AS gross_rent,
+                CAST(COALESCE(SUM(w.amount), 0) AS REAL) AS tax_withheld
+         FROM payments p
+         JOIN properties pr ON pr.property_id = CAST(p.property_id AS INTEGER)
+         LEFT JOIN tax_withholdings w ON w.payment_id = p.payment_id
+         WHERE p.payment_category = 'Rent' AND p.payment_status = 'Paid'
+           AND p.payment_date BETWEEN ?1 AND ?2
+           AND upper(COALESCE(pr.tax_jurisdiction, ?3)) = ?4
+         GROUP BY month, pr.property_id
+         ORDER BY month, pr.name",
+    )
+    .bind(since.to_string())
+    .bind(to.last_day().to_string())
+    .bind(&default_jurisdiction)
+    .bind(&jurisdiction)
+    .fetch_all(pool)
+    .await?;
+
+    let mut history: Vec<RentalIncomeLine> = Vec::with_capacity(rows.len());
+    for row in rows {
+        history.push(RentalIncomeLine {
+            month: row.month.parse()?,
+            property_id: row.property_id,
+            property_name: row.property_name,
+            owner_name: row.owner_name,
+            owner_tax_id: row.owner_tax_id,
+            payments: row.payments,
+            gross_rent: round_cents(row.gross_rent),
+            tax_withheld: round_cents(row.tax_withheld),
+        });
+    }
+    let lines: Vec<RentalIncomeLine> = history
+        .iter()
+        .filter(|line| line.month >= from)
+        .filter(|line| property_id.is_none_or(|id| line.property_id == id))
+        .cloned()
+        .collect();
+
+    let mut grouped: BTreeMap<(Month, String), Vec<&RentalIncomeLine>> = BTreeMap::new();
+    for line in &lines {
+        grouped
+            .entry((line.month, line.owner_key()))
+            .or_default()
+            .push(line);
+    }
+
+    let mut filings = Vec::with_capacity(grouped.len());
+    let mut totals = RentalIncomeTaxTotals::default();
+    for ((month, owner_key), owner_lines) in grouped {
+        let rule = month_rules[&month];
+        let window_start = Month::of(month.first_day() - Months::new(11));
+        let annual_gross = round_cents(
+            history
+                .iter()
+                .filter(|line| line.month >= window_start && line.month <= month)
+                .filter(|line| line.owner_key() == owner_key)
+                .map(|line| line.gross_rent)
+                .sum(),
+        );
+        let gross_rent = round_cents(owner_lines.iter().map(|line| line.gross_rent).sum());
+        let tax_withheld = round_cents(owner_lines.iter().map(|line| line.tax_withheld).sum());
+        let (status, tax_due) = rule.tax_due(gross_rent, annual_gross);
+        let net_payable = round_cents((tax_due - tax_withheld).max(0.0));
+
+        totals.gross_rent += gross_rent;
+        totals.tax_due += tax_due;
+        totals.tax_withheld += tax_withheld;
+        totals.net_payable += net_payable;
+        filings.push(RentalIncomeFiling {
+            month,
+            owner_name: owner_lines[0].owner_name.clone(),
+            owner_tax_id: owner_lines[0].owner_tax_id.clone(),
+            properties: owner_lines
+                .iter()
+                .map(|line| line.property_name.clone())
+                .collect(),
+            gross_rent,
+            annual_gross,
+            status,
+            rate: rule.rate,
+            tax_due,
+            tax_withheld,
+            net_payable,
+            due_date: rule.due_date(month),
+        });
+    }
+    totals.gross_rent = round_cents(totals.gross_rent);
+    totals.tax_due = round_cents(totals.tax_due);
+    totals.tax_withheld = round_cents(totals.tax_withheld);
+    totals.net_payable = round_cents(totals.net_payable);
+
+    let mut applied: Vec<TaxRule> = Vec::new();
+    for rule in month_rules.into_values() {
+        if applied.last().map(|r| r.rule_id) != Some(rule.rule_id) {
+            applied.push(rule.clone());
+        }
+    }
+
+    Ok(RentalIncomeTax {
+        jurisdiction,
+        from,
+        to,
+        property_id,
+        rules: applied,
+        lines,
+        filings,
+        totals,
+    })
+}
+
+impl RentalIncomeTax {
+    pub fn to_table(&self) -> Table {
+        let name = self
+            .rules
+            .first()
+            .map_or("Rental Income Tax", |rule| rule.tax_name.as_str());
+        let range = if self.from == self.to {
+            self.from.to_string()
+        } else {
+            format!("{} to {}", self.from, self.to)
+        };
+        let mut table = Table::new(
+            format!("{name} ({}) - {range}", self.jurisdiction),
+            &[
+                "Month",
+                "Owner",
+                "Owner PIN",
+                "Properties",
+                "Gross Rent",
+                "Rate %",
+                "Tax Due",
+                "Tax Withheld",
+                "Net Payable",
+                "Status",
+                "Due Date",
+            ],
+        );
+        for filing in &self.filings {
+            table.push(vec![
+                Cell::text(filing.month.to_string()),
+                Cell::opt_text(filing.owner_name.as_deref()),
+                Cell::opt_text(filing.owner_tax_id.as_deref()),
+                Cell::text(filing.properties.join("; ")),
+                Cell::Money(filing.gross_rent),
+                Cell::text(filing.rate.to_string()),
+                Cell::Money(filing.tax_due),
+                Cell::Money(filing.tax_withheld),
+                Cell::Money(filing.net_payable),
+                Cell::text(filing.status.label()),
+                Cell::text(filing.due_date.to_string()),
+            ]);
+        }
+        table.push(vec![
+            Cell::text("Total"),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Money(self.totals.gross_rent),
+            Cell::Empty,
+            Cell::Money(self.totals.tax_due),
+            Cell::Money(self.totals.tax_withheld),
+            Cell::Money(self.totals.net_payable),
+            Cell::Empty,
+            Cell::Empty,
+        ]);
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(min: Option<f64>, max: Option<f64>) -> TaxRule {
+        TaxRule {
+            rule_id: None,
+            jurisdiction: "KE".into(),
+            tax_name: "Monthly Rental Income Tax".into(),
+            rate: 7.5,
+            min_annual_gross: min,
+            max_annual_gross: max,
+            filing_due_day: 20,
+            effective_from: "2024-01-01".into(),
+            effective_to: None,
+        }
+    }
+
+    fn month(s: &str) -> Month {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn thresholds_include_their_bounds() {
+        let rule = rule(Some(288_000.0), Some(15_000_000.0));
+        assert_eq!(rule.status(287_999.99), TaxStatus::BelowThreshold);
+        assert_eq!(rule.status(288_000.0), TaxStatus::Taxable);
+        assert_eq!(rule.status(15_000_000.0), TaxStatus::Taxable);
+        assert_eq!(rule.status(15_000_000.01), TaxStatus::AboveThreshold);
+        assert_eq!(rule.status(0.0), TaxStatus::BelowThreshold);
+    }
+
+    #[test]
+    fn open_bounds_tax_everything_on_that_side() {
+        assert_eq!(rule(None, None).status(0.0), TaxStatus::Taxable);
+        assert_eq!(rule(None, None).status(1e12), TaxStatus::Taxable);
+        assert_eq!(rule(Some(100.0), None).status(1e12), TaxStatus::Taxable);
+        assert_eq!(rule(None, Some(100.0)).status(0.0), TaxStatus::Taxable);
+        assert_eq!(
+            rule(None, Some(100.0)).status(100.01),
+            TaxStatus::AboveThreshold
+        );
+    }
+
+    #[test]
+    fn covers_months_the_rule_touches() {
+        let rule = TaxRule {
+            effective_from: "2024-03-31".into(),
+            effective_to: Some("2024-06-01".into()),
+            ..rule(None, None)
+        };
+        assert!(!rule.covers(month("2024-02")));
+        assert!(rule.covers(month("2024-03")));
+        assert!(rule.covers(month("2024-06")));
+        assert!(!rule.covers(month("2024-07")));
+    }
+
+    #[test]
+    fn due_date_falls_back_to_the_end_of_a_short_month() {
+        let last_day = TaxRule {
+            filing_due_day: 31,
+            ..rule(None, None)
+        };
+        let due = |m| last_day.due_date(month(m)).to_string();
+        assert_eq!(due("2024-01"), "2024-02-29");
+        assert_eq!(due("2023-01"), "2023-02-28");
+        assert_eq!(due("2024-03"), "2024-04-30");
+        assert_eq!(due("2024-12"), "2025-01-31");
+        assert_eq!(
+            rule(None, None).due_date(month("2024-12")).to_string(),
+            "2025-01-20"
+        );
+    }
+
+    #[test]
+    fn only_taxable_owners_owe_tax() {
+        let banded = rule(Some(288_000.0), Some(15_000_000.0));
+        assert_eq!(
+            banded.tax_due(30_000.0, 360_000.0),
+            (TaxStatus::Taxable, 2_250.0)
+        );
+        assert_eq!(
+            banded.tax_due(30_000.0, 288_000.0),
+            (TaxStatus::Taxable, 2_250.0)
+        );
+        assert_eq!(
+            banded.tax_due(20_000.0, 240_000.0),
+            (TaxStatus::BelowThreshold, 0.0)
+        );
+        assert_eq!(
+            banded.tax_due(2_000_000.0, 24_000_000.0),
+            (TaxStatus::AboveThreshold, 0.0)
+        );
+        // A jurisdiction that exempts rent has a zero rate.
+        let exempt = TaxRule {
+            rate: 0.0,
+            ..banded.clone()
+        };
+        assert_eq!(
+            exempt.tax_due(30_000.0, 360_000.0),
+            (TaxStatus::Taxable, 0.0)
+        );
+        // Rounded to the cent.
+        assert_eq!(banded.tax_due(333.33, 300_000.0).1, 25.0);
+        assert_eq!(banded.tax_due(1_234.56, 300_000.0).1, 92.59);
+    }
+
+    #[test]
+    fn rejects_inconsistent_rules() {
+        assert!(rule(Some(100.0), Some(100.0)).validate().is_ok());
+        for bad in [
+            rule(Some(100.0), Some(99.0)),
+            TaxRule {
+                rate: 100.5,
+                ..rule(None, None)
+            },
+            TaxRule {
+                rate: -1.0,
+                ..rule(None, None)
+            },
+            TaxRule {
+                filing_due_day: 0,
+                ..rule(None, None)
+            },
+            TaxRule {
+                effective_to: Some("2023-12-31".into()),
+                ..rule(None, None)
+            },
+            TaxRule {
+                jurisdiction: " ".into(),
+                ..rule(None, None)
+            },
+        ] {
+            assert!(
+                matches!(bad.validate(), Err(Error::InvalidInput(_))),
+                "{bad:?}"
+            );
+        }
+    }
+}
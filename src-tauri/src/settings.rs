@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+
+/// All stored settings by key. Keys are dotted, e.g. `c2b.port`; anything
+/// not stored uses the default of the feature that reads it.
+#[tauri::command]
+pub async fn get_settings(app: AppHandle) -> Result<HashMap<String, String>> {
+    let pool = db::pool(&app).await?;
+    let rows: Vec<(String, String)> = sqlx::query_as("SELECT key, value FROM settings")
+        .fetch_all(&pool)
+        .await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Stores the given values; a `null` value removes the key, restoring its
+/// default.
+#[tauri::command]
+pub async fn save_settings(app: AppHandle, values: HashMap<String, Option<String>>) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    for (key, value) in values {
+        if key.trim().is_empty() {
+            return Err(Error::InvalidInput("setting keys cannot be empty".into()));
+        }
+        match value {
+            Some(value) => {
+                sqlx::query(
+                    "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                     ON CONFLICT (key) DO UPDATE SET value = excluded.value,
+                                                     updated_at = CURRENT_TIMESTAMP",
+                )
+                .bind(&key)
+                .bind(value)
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM settings WHERE key = ?1")
+                    .bind(&key)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get(pool: &SqlitePool, key: &str) -> Result<Option<String>> {
+    let row: Option<(String,)> = sqlx::query_as("SELECT value FROM settings WHERE key = ?1")
+        .bind(key)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|(value,)| value))
+}
+
+/// Parses the stored value, falling back to `default` when it is missing.
+/// A value that does not parse is an error rather than silently ignored.
+pub async fn get_or<T: FromStr>(pool: &SqlitePool, key: &str, default: T) -> Result<T> {
+    match get(pool, key).await? {
+        None => Ok(default),
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| Error::InvalidInput(format!("setting {key} has invalid value '{value}'"))),
+    }
+}
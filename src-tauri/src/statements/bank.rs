@@ -0,0 +1,1075 @@
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::billing::allocations::{self, AllocationMode};
+use crate::billing::payments::{self, NewPayment};
+use crate::db;
+use crate::error::{Error, Result};
+use crate::expenses::{self, NewExpense};
+use crate::period;
+use crate::statements::{self, csv, mt940, ofx};
+
+/// Suggestions below this are not shown.
+const SUGGEST_CONFIDENCE: f64 = 0.6;
+/// Records further apart from the statement line than this are not considered.
+const MAX_DAYS_APART: i64 = 14;
+const EPSILON: f64 = 0.005;
+
+/// One statement line; credits are positive, debits negative.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BankLine {
+    pub posted_on: NaiveDate,
+    pub amount: f64,
+    pub description: String,
+    pub reference: Option<String>,
+    pub check_number: Option<String>,
+    /// The bank's own id for the line (OFX `FITID`, MT940 bank reference).
+    pub external_id: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedStatement {
+    pub account: Option<String>,
+    pub lines: Vec<BankLine>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BankFormat {
+    Csv,
+    Ofx,
+    Mt940,
+}
+
+impl BankFormat {
+    fn as_str(self) -> &'static str {
+        match self {
+            BankFormat::Csv => "CSV",
+            BankFormat::Ofx => "OFX",
+            BankFormat::Mt940 => "MT940",
+        }
+    }
+
+    /// Goes by the file extension, then by the content.
+    pub fn detect(path: &Path, text: &str) -> BankFormat {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "ofx" | "qfx" => BankFormat::Ofx,
+            "sta" | "mt940" | "940" => BankFormat::Mt940,
+            "csv" => BankFormat::Csv,
+            _ if text.to_ascii_uppercase().contains("<OFX>") => BankFormat::Ofx,
+            _ if text.contains(":20:") && text.contains(":61:") => BankFormat::Mt940,
+            _ => BankFormat::Csv,
+        }
+    }
+
+    pub fn parse(self, text: &str) -> Result<ParsedStatement> {
+        match self {
+            BankFormat::Csv => parse_csv(text),
+            BankFormat::Ofx => ofx::parse(text),
+            BankFormat::Mt940 => mt940::parse(text),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct BankStatement {
+    pub statement_id: i64,
+    pub account: Option<String>,
+    pub file_name: Option<String>,
+    pub format: String,
+    pub period_start: Option<String>,
+    pub period_end: Option<String>,
+    pub imported_at: String,
+    pub line_count: i64,
+    pub reconciled_count: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct BankStatementLine {
+    pub line_id: i64,
+    pub statement_id: i64,
+    pub posted_on: String,
+    pub amount: f64,
+    pub description: Option<String>,
+    pub reference: Option<String>,
+    pub check_number: Option<String>,
+    /// `Unreconciled`, `Suggested`, `Reconciled` or `Ignored`.
+    pub status: String,
+    #[sqlx(skip)]
+    pub matches: Vec<LineMatch>,
+}
+
+/// A payment or expense paired with a statement line, either suggested by
+/// the matcher or confirmed by the user.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct LineMatch {
+    pub match_id: i64,
+    pub line_id: i64,
+    pub payment_id: Option<String>,
+    pub expense_id: Option<i64>,
+    pub amount: f64,
+    pub confidence: f64,
+    pub is_confirmed: bool,
+    pub record_date: Option<String>,
+    /// Tenant name for payments, vendor for expenses.
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankImport {
+    pub statement_id: i64,
+    pub imported: usize,
+    /// Lines already imported from an earlier statement of the same account.
+    pub skipped: usize,
+    pub suggested: usize,
+    pub lines: Vec<BankStatementLine>,
+}
+
+/// Part of a statement line assigned to one payment or expense.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitPart {
+    #[serde(default)]
+    pub payment_id: Option<String>,
+    #[serde(default)]
+    pub expense_id: Option<i64>,
+    pub amount: f64,
+}
+
+/// What to create for a statement line nothing matched.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum NewRecord {
+    Payment(LinePayment),
+    Expense(LineExpense),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinePayment {
+    pub tenant_id: i64,
+    /// Defaults to the unit on the tenant's latest lease.
+    #[serde(default)]
+    pub unit_id: Option<i64>,
+    #[serde(default)]
+    pub payment_category: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineExpense {
+    pub category: String,
+    pub vendor: String,
+    /// Defaults to the statement narrative.
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub property_id: Option<i64>,
+    #[serde(default)]
+    pub unit_id: Option<i64>,
+}
+
+/// Imports a CSV, OFX or MT940 bank statement and suggests matching
+/// payments (for credits) and expenses (for debits).
+#[tauri::command]
+pub async fn import_bank_statement(
+    app: AppHandle,
+    path: PathBuf,
+    format: Option<BankFormat>,
+    account: Option<String>,
+) -> Result<BankImport> {
+    let text = statements::read_text(&path)?;
+    let format = format.unwrap_or_else(|| BankFormat::detect(&path, &text));
+    let mut parsed = format.parse(&text)?;
+    if account.is_some() {
+        parsed.account = account;
+    }
+    let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+    let pool = db::pool(&app).await?;
+    import_statement(&pool, file_name.as_deref(), format, parsed).await
+}
+
+#[tauri::command]
+pub async fn get_bank_statements(app: AppHandle) -> Result<Vec<BankStatement>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(
+        "SELECT s.statement_id, s.account, s.file_name, s.format, s.period_start, s.period_end,
+                s.imported_at, COUNT(l.line_id) AS line_count,
+                COALESCE(SUM(CASE WHEN l.status IN ('Reconciled', 'Ignored') THEN 1 END), 0)
+                    AS reconciled_count
+         FROM bank_statements s
+         LEFT JOIN bank_statement_lines l ON l.statement_id = s.statement_id
+         GROUP BY s.statement_id
+         ORDER BY s.imported_at DESC, s.statement_id DESC",
+    )
+    .fetch_all(&pool)
+    .await?)
+}
+
+#[tauri::command]
+pub async fn get_bank_statement_lines(
+    app: AppHandle,
+    statement_id: i64,
+    status: Option<String>,
+) -> Result<Vec<BankStatementLine>> {
+    let pool = db::pool(&app).await?;
+    let mut conn = pool.acquire().await?;
+    statement_lines(&mut conn, statement_id, status.as_deref()).await
+}
+
+/// Confirms a suggestion and drops the line's other suggestions.
+#[tauri::command]
+pub async fn accept_bank_match(app: AppHandle, match_id: i64) -> Result<BankStatementLine> {
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    #[derive(sqlx::FromRow)]
+    struct Suggestion {
+        line_id: i64,
+        payment_id: Option<String>,
+        expense_id: Option<i64>,
+        amount: f64,
+        is_confirmed: bool,
+    }
+    let suggestion: Suggestion = sqlx::query_as(
+        "SELECT line_id, payment_id, expense_id, CAST(amount AS REAL) AS amount, is_confirmed
+         FROM bank_line_matches WHERE match_id = ?1",
+    )
+    .bind(match_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("bank match {match_id}")))?;
+    if suggestion.is_confirmed {
+        return Err(Error::InvalidInput(format!(
+            "bank match {match_id} is already confirmed"
+        )));
+    }
+    let line_id = suggestion.line_id;
+    confirm(
+        &mut tx,
+        line_id,
+        &[SplitPart {
+            payment_id: suggestion.payment_id,
+            expense_id: suggestion.expense_id,
+            amount: suggestion.amount,
+        }],
+    )
+    .await?;
+    let line = statement_line(&mut tx, line_id).await?;
+    tx.commit().await?;
+    Ok(line)
+}
+
+/// Reconciles one statement line against several payments or expenses, e.g.
+/// a single transfer covering two months of rent.
+#[tauri::command]
+pub async fn split_bank_line(
+    app: AppHandle,
+    line_id: i64,
+    parts: Vec<SplitPart>,
+) -> Result<BankStatementLine> {
+    if parts.is_empty() {
+        return Err(Error::InvalidInput("split needs at least one part".into()));
+    }
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    confirm(&mut tx, line_id, &parts).await?;
+    let line = statement_line(&mut tx, line_id).await?;
+    tx.commit().await?;
+    Ok(line)
+}
+
+/// Records the unreconciled part of a line as a new payment (credits, which
+/// are then allocated to the tenant's oldest charges) or expense (debits).
+#[tauri::command]
+pub async fn create_from_bank_line(
+    app: AppHandle,
+    line_id: i64,
+    record: NewRecord,
+) -> Result<BankStatementLine> {
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let line = statement_line(&mut tx, line_id).await?;
+    let amount = unreconciled_amount(&line);
+    if amount <= EPSILON {
+        return Err(Error::InvalidInput(format!(
+            "statement line {line_id} is already reconciled"
+        )));
+    }
+    let payment_method = if line.check_number.is_some() {
+        "Check"
+    } else {
+        "Bank Transfer"
+    };
+
+    let part = match record {
+        NewRecord::Payment(payment) => {
+            if line.amount < 0.0 {
+                return Err(Error::InvalidInput(
+                    "payments can only be created from credits".into(),
+                ));
+            }
+            let unit_id = match payment.unit_id {
+                Some(unit_id) => unit_id,
+                None => payments::tenant_unit(&mut tx, payment.tenant_id)
+                    .await?
+                    .ok_or_else(|| {
+                        Error::InvalidInput(format!("tenant {} has no unit", payment.tenant_id))
+                    })?,
+            };
+            let property_id = payments::unit_property(&mut tx, unit_id).await?;
+            let new_payment = NewPayment {
+                tenant_id: payment.tenant_id,
+                unit_id,
+                property_id,
+                amount,
+                payment_date: line.posted_on.clone(),
+                due_date: None,
+                payment_method: payment_method.into(),
+                payment_category: payment.payment_category,
+                receipt_number: None,
+                transaction_reference: line.reference.clone().or(line.check_number.clone()),
+                remarks: Some("Created from bank statement".into()),
+            };
+            let payment_id = payments::insert_payment(&mut tx, &new_payment).await?;
+            allocations::allocate(
+                &mut tx,
+                payment.tenant_id,
+                &payment_id,
+                amount,
+                AllocationMode::OldestFirst,
+            )
+            .await?;
+            SplitPart {
+                payment_id: Some(payment_id),
+                expense_id: None,
+                amount,
+            }
+        }
+        NewRecord::Expense(expense) => {
+            if line.amount > 0.0 {
+                return Err(Error::InvalidInput(
+                    "expenses can only be created from debits".into(),
+                ));
+            }
+            let new_expense = NewExpense {
+                amount,
+                category: expense.category,
+                description: expense.description.or(line.description.clone()),
+                expense_date: line.posted_on.clone(),
+                unit_id: expense.unit_id,
+                block_id: None,
+                property_id: expense.property_id,
+                payment_method: payment_method.into(),
+                vendor: expense.vendor,
+                invoice_number: line.check_number.clone(),
+                paid_by: None,
+                entered_by: None,
+            };
+            let expense_id = expenses::insert_expense(&mut tx, &new_expense).await?;
+            SplitPart {
+                payment_id: None,
+                expense_id: Some(expense_id),
+                amount,
+            }
+        }
+    };
+    confirm(&mut tx, line_id, &[part]).await?;
+    let line = statement_line(&mut tx, line_id).await?;
+    tx.commit().await?;
+    Ok(line)
+}
+
+/// Marks a line that needs no record, such as bank charges already booked
+/// elsewhere or transfers between own accounts.
+#[tauri::command]
+pub async fn ignore_bank_line(app: AppHandle, line_id: i64) -> Result<BankStatementLine> {
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM bank_line_matches WHERE line_id = ?1 AND is_confirmed = 0")
+        .bind(line_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "UPDATE bank_statement_lines SET status = 'Ignored', updated_at = CURRENT_TIMESTAMP
+         WHERE line_id = ?1",
+    )
+    .bind(line_id)
+    .execute(&mut *tx)
+    .await?;
+    let line = statement_line(&mut tx, line_id).await?;
+    tx.commit().await?;
+    Ok(line)
+}
+
+/// Undoes confirmations and ignores on a line. Records created from it are
+/// kept.
+#[tauri::command]
+pub async fn reset_bank_line(app: AppHandle, line_id: i64) -> Result<BankStatementLine> {
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "UPDATE bank_statement_lines SET status = 'Unreconciled', updated_at = CURRENT_TIMESTAMP
+         WHERE line_id = ?1",
+    )
+    .bind(line_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM bank_line_matches WHERE line_id = ?1")
+        .bind(line_id)
+        .execute(&mut *tx)
+        .await?;
+    let line = statement_line(&mut tx, line_id).await?;
+    tx.commit().await?;
+    Ok(line)
+}
+
+pub async fn import_statement(
+    pool: &SqlitePool,
+    file_name: Option<&str>,
+    format: BankFormat,
+    parsed: ParsedStatement,
+) -> Result<BankImport> {
+    let lines: Vec<BankLine> = parsed
+        .lines
+        .into_iter()
+        .filter(|l| l.amount.abs() > EPSILON)
+        .collect();
+    let mut tx = pool.begin().await?;
+    let mut candidates = candidates(&mut tx).await?;
+    let statement_id = sqlx::query(
+        "INSERT INTO bank_statements (account, file_name, format, period_start, period_end)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )
+    .bind(&parsed.account)
+    .bind(file_name)
+    .bind(format.as_str())
+    .bind(
+        lines
+            .iter()
+            .map(|l| l.posted_on)
+            .min()
+            .map(|d| d.to_string()),
+    )
+    .bind(
+        lines
+            .iter()
+            .map(|l| l.posted_on)
+            .max()
+            .map(|d| d.to_string()),
+    )
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+
+    let (mut imported, mut skipped, mut suggested) = (0, 0, 0);
+    for line in &lines {
+        if already_imported(&mut tx, statement_id, parsed.account.as_deref(), line).await? {
+            skipped += 1;
+            continue;
+        }
+        let line_id = sqlx::query(
+            "INSERT INTO bank_statement_lines (
+                 statement_id, posted_on, amount, description, reference, check_number, external_id
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )
+        .bind(statement_id)
+        .bind(line.posted_on.to_string())
+        .bind(line.amount)
+        .bind(&line.description)
+        .bind(&line.reference)
+        .bind(&line.check_number)
+        .bind(&line.external_id)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        imported += 1;
+
+        let suggestions: Vec<(Candidate, f64)> = suggest(line, &candidates)
+            .into_iter()
+            .map(|(c, confidence)| (c.clone(), confidence))
+            .collect();
+        if let Some((best, _)) = suggestions.first() {
+            // Each record is suggested as the best match for one line only.
+            candidates
+                .retain(|c| c.payment_id != best.payment_id || c.expense_id != best.expense_id);
+            suggested += 1;
+        }
+        for (candidate, confidence) in &suggestions {
+            sqlx::query(
+                "INSERT INTO bank_line_matches (line_id, payment_id, expense_id, amount, confidence)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(line_id)
+            .bind(&candidate.payment_id)
+            .bind(candidate.expense_id)
+            .bind(line.amount.abs())
+            .bind(confidence)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    let lines = statement_lines(&mut tx, statement_id, None).await?;
+    tx.commit().await?;
+    Ok(BankImport {
+        statement_id,
+        imported,
+        skipped,
+        suggested,
+        lines,
+    })
+}
+
+/// A payment or expense not yet fully reconciled.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Candidate {
+    pub payment_id: Option<String>,
+    pub expense_id: Option<i64>,
+    pub record_date: String,
+    pub remaining: f64,
+    /// Transaction reference or receipt number for payments, invoice number
+    /// for expenses.
+    pub reference: Option<String>,
+    pub label: Option<String>,
+}
+
+async fn candidates(conn: &mut SqliteConnection) -> Result<Vec<Candidate>> {
+    Ok(sqlx::query_as(
+        "SELECT * FROM (
+             SELECT p.payment_id, NULL AS expense_id, p.payment_date AS record_date,
+                    CAST(p.amount_paid - COALESCE((SELECT SUM(m.amount) FROM bank_line_matches m
+                         WHERE m.payment_id = p.payment_id AND m.is_confirmed = 1), 0) AS REAL)
+                        AS remaining,
+                    COALESCE(p.transaction_reference, p.receipt_number) AS reference,
+                    t.full_name AS label
+             FROM payments p
+             LEFT JOIN tenants t ON t.tenant_id = CAST(p.tenant_id AS INTEGER)
+             WHERE p.payment_status = 'Paid' AND p.payment_method IN ('Bank Transfer', 'Check')
+             UNION ALL
+             SELECT NULL, e.expense_id, e.expense_date,
+                    CAST(e.amount - COALESCE((SELECT SUM(m.amount) FROM bank_line_matches m
+                         WHERE m.expense_id = e.expense_id AND m.is_confirmed = 1), 0) AS REAL),
+                    e.invoice_number, e.vendor
+             FROM expenses e
+             WHERE lower(e.payment_method) NOT IN ('cash', 'mobile money', 'm-pesa', 'mpesa')
+               AND e.approval_status <> 'Rejected'
+         ) WHERE remaining > 0.005",
+    )
+    .fetch_all(conn)
+    .await?)
+}
+
+/// Up to three candidates with the same amount as `line`, best first. Dates
+/// closer together, a shared reference and the tenant or vendor name in the
+/// narrative all add confidence.
+pub fn suggest<'a>(line: &BankLine, candidates: &'a [Candidate]) -> Vec<(&'a Candidate, f64)> {
+    let mut scored: Vec<(&Candidate, f64)> = candidates
+        .iter()
+        .filter_map(|c| score(line, c).map(|s| (c, s)))
+        .filter(|(_, s)| *s >= SUGGEST_CONFIDENCE)
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(3);
+    scored
+}
+
+fn score(line: &BankLine, candidate: &Candidate) -> Option<f64> {
+    let is_payment = candidate.payment_id.is_some();
+    if is_payment != (line.amount > 0.0)
+        || (line.amount.abs() - candidate.remaining).abs() > EPSILON
+    {
+        return None;
+    }
+    let record_date = period::parse_date(&candidate.record_date).ok()?;
+    let days = (line.posted_on - record_date).num_days().abs();
+    let mut confidence = 0.5
+        + match days {
+            0 => 0.3,
+            1..=3 => 0.2,
+            4..=7 => 0.1,
+            8..=MAX_DAYS_APART => 0.0,
+            _ => return None,
+        };
+
+    let narrative = key(&format!(
+        "{} {} {}",
+        line.description,
+        line.reference.as_deref().unwrap_or_default(),
+        line.check_number.as_deref().unwrap_or_default()
+    ));
+    if let Some(reference) = candidate.reference.as_deref().map(key) {
+        if reference.len() >= 4 && narrative.contains(&reference) {
+            confidence += 0.3;
+        }
+    }
+    if let Some(label) = &candidate.label {
+        let named = label
+            .split_whitespace()
+            .map(key)
+            .any(|word| word.len() > 2 && narrative.contains(&word));
+        if named {
+            confidence += 0.1;
+        }
+    }
+    Some(statements::round_confidence(confidence))
+}
+
+fn key(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// Same bank id, or same date, amount, narrative and reference, in an earlier
+/// statement for the same account.
+async fn already_imported(
+    conn: &mut SqliteConnection,
+    statement_id: i64,
+    account: Option<&str>,
+    line: &BankLine,
+) -> Result<bool> {
+    let found: Option<(i64,)> = sqlx::query_as(
+        "SELECT l.line_id FROM bank_statement_lines l
+         JOIN bank_statements s ON s.statement_id = l.statement_id
+         WHERE l.statement_id <> ?1 AND COALESCE(s.account, '') = COALESCE(?2, '')
+           AND CASE WHEN ?3 IS NOT NULL THEN l.external_id = ?3
+                    ELSE l.posted_on = ?4 AND ABS(l.amount - ?5) < 0.005
+                         AND COALESCE(l.description, '') = ?6
+                         AND COALESCE(l.reference, '') = COALESCE(?7, '') END
+         LIMIT 1",
+    )
+    .bind(statement_id)
+    .bind(account)
+    .bind(&line.external_id)
+    .bind(line.posted_on.to_string())
+    .bind(line.amount)
+    .bind(&line.description)
+    .bind(&line.reference)
+    .fetch_optional(conn)
+    .await?;
+    Ok(found.is_some())
+}
+
+/// Confirms `parts` against the line, replacing any open suggestions.
+/// Triggers set the line to `Reconciled` once its full amount is covered.
+async fn confirm(conn: &mut SqliteConnection, line_id: i64, parts: &[SplitPart]) -> Result<()> {
+    let line = statement_line(&mut *conn, line_id).await?;
+    if line.status == "Ignored" {
+        return Err(Error::InvalidInput(format!(
+            "statement line {line_id} is ignored; reset it first"
+        )));
+    }
+    let total: f64 = parts.iter().map(|p| p.amount).sum();
+    if total > unreconciled_amount(&line) + EPSILON {
+        return Err(Error::InvalidInput(format!(
+            "parts total {total:.2} but only {:.2} of the line is unreconciled",
+            unreconciled_amount(&line)
+        )));
+    }
+
+    sqlx::query("DELETE FROM bank_line_matches WHERE line_id = ?1 AND is_confirmed = 0")
+        .bind(line_id)
+        .execute(&mut *conn)
+        .await?;
+    for part in parts {
+        if part.amount <= 0.0 {
+            return Err(Error::InvalidInput("split amounts must be positive".into()));
+        }
+        let remaining = match (&part.payment_id, part.expense_id) {
+            (Some(payment_id), None) if line.amount > 0.0 => {
+                record_remaining(&mut *conn, "payment_id", payment_id).await?
+            }
+            (None, Some(expense_id)) if line.amount < 0.0 => {
+                record_remaining(&mut *conn, "expense_id", &expense_id.to_string()).await?
+            }
+            _ => {
+                return Err(Error::InvalidInput(
+                    "credits reconcile against payments and debits against expenses".into(),
+                ))
+            }
+        };
+        if part.amount > remaining + EPSILON {
+            return Err(Error::InvalidInput(format!(
+                "only {remaining:.2} of that record is left to reconcile"
+            )));
+        }
+        sqlx::query(
+            "INSERT INTO bank_line_matches (line_id, payment_id, expense_id, amount, confidence, is_confirmed)
+             VALUES (?1, ?2, ?3, ?4, 1, 1)",
+        )
+        .bind(line_id)
+        .bind(&part.payment_id)
+        .bind(part.expense_id)
+        .bind(part.amount)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Amount of a payment or expense not yet covered by confirmed matches.
+async fn record_remaining(conn: &mut SqliteConnection, column: &str, id: &str) -> Result<f64> {
+    let sql = match column {
+        "payment_id" => {
+            "SELECT CAST(amount_paid - COALESCE((SELECT SUM(amount) FROM bank_line_matches
+                     WHERE payment_id = ?1 AND is_confirmed = 1), 0) AS REAL)
+             FROM payments WHERE payment_id = ?1"
+        }
+        _ => {
+            "SELECT CAST(amount - COALESCE((SELECT SUM(amount) FROM bank_line_matches
+                     WHERE expense_id = ?1 AND is_confirmed = 1), 0) AS REAL)
+             FROM expenses WHERE expense_id = ?1"
+        }
+    };
+    let row: Option<(f64,)> = sqlx::query_as(sql).bind(id).fetch_optional(conn).await?;
+    row.map(|(remaining,)| remaining)
+        .ok_or_else(|| Error::NotFound(format!("{} {id}", column.trim_end_matches("_id"))))
+}
+
+fn unreconciled_amount(line: &BankStatementLine) -> f64 {
+    let confirmed: f64 = line
+        .matches
+        .iter()
+        .filter(|m| m.is_confirmed)
+        .map(|m| m.amount)
+        .sum();
+    allocations::round_cents(line.amount.abs() - confirmed)
+}
+
+const LINE_COLUMNS: &str = "line_id, statement_id, posted_on, CAST(amount AS REAL) AS amount,
+    description, reference, check_number, status";
+
+async fn statement_line(conn: &mut SqliteConnection, line_id: i64) -> Result<BankStatementLine> {
+    let mut line: BankStatementLine = sqlx::query_as(&format!(
+        "SELECT {LINE_COLUMNS} FROM bank_statement_lines WHERE line_id = ?1"
+    ))
+    .bind(line_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("statement line {line_id}")))?;
+    line.matches = matches(conn, None, Some(line_id)).await?;
+    Ok(line)
+}
+
+async fn statement_lines(
+    conn: &mut SqliteConnection,
+    statement_id: i64,
+    status: Option<&str>,
+) -> Result<Vec<BankStatementLine>> {
+    let mut lines: Vec<BankStatementLine> = sqlx::query_as(&format!(
+        "SELECT {LINE_COLUMNS} FROM bank_statement_lines
+         WHERE statement_id = ?1 AND (?2 IS NULL OR status = ?2)
+         ORDER BY posted_on, line_id"
+    ))
+    .bind(statement_id)
+    .bind(status)
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut matches = matches(conn, Some(statement_id), None).await?;
+    for line in &mut lines {
+        let (own, rest) = matches.into_iter().partition(|m| m.line_id == line.line_id);
+        line.matches = own;
+        matches = rest;
+    }
+    Ok(lines)
+}
+
+async fn matches(
+    conn: &mut SqliteConnection,
+    statement_id: Option<i64>,
+    line_id: Option<i64>,
+) -> Result<Vec<LineMatch>> {
+    Ok(sqlx::query_as(
+        "SELECT m.match_id, m.line_id, m.payment_id, m.expense_id, CAST(m.amount AS REAL) AS amount,
+                CAST(m.confidence AS REAL) AS confidence, m.is_confirmed,
+                COALESCE(p.payment_date, e.expense_date) AS record_date,
+                COALESCE(t.full_name, e.vendor) AS label
+         FROM bank_line_matches m
+         JOIN bank_statement_lines l ON l.line_id = m.line_id
+         LEFT JOIN payments p ON p.payment_id = m.payment_id
+         LEFT JOIN tenants t ON t.tenant_id = CAST(p.tenant_id AS INTEGER)
+         LEFT JOIN expenses e ON e.expense_id = m.expense_id
+         WHERE (?1 IS NULL OR l.statement_id = ?1) AND (?2 IS NULL OR m.line_id = ?2)
+         ORDER BY m.is_confirmed DESC, m.confidence DESC, m.match_id",
+    )
+    .bind(statement_id)
+    .bind(line_id)
+    .fetch_all(conn)
+    .await?)
+}
+
+/// Bank CSV exports differ per bank; columns are found by their headers.
+/// Either a signed amount column or separate debit and credit columns is
+/// needed.
+fn parse_csv(text: &str) -> Result<ParsedStatement> {
+    const DATE: &[&str] = &[
+        "date",
+        "transactiondate",
+        "postingdate",
+        "posteddate",
+        "bookingdate",
+        "trandate",
+        "valuedate",
+    ];
+    let rows = csv::parse(text);
+    let header_at = rows
+        .iter()
+        .take(30)
+        .position(|row| {
+            row.iter()
+                .any(|f| DATE.contains(&statements::header_key(f).as_str()))
+        })
+        .ok_or_else(|| Error::InvalidInput("statement has no date column".into()))?;
+    let header: Vec<String> = rows[header_at]
+        .iter()
+        .map(|h| statements::header_key(h))
+        .collect();
+    // First listed name wins, so transaction date beats value date.
+    let column = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| header.iter().position(|h| h == name))
+    };
+    let date_col = column(DATE).unwrap_or_default();
+    let description_col = column(&[
+        "description",
+        "narration",
+        "narrative",
+        "details",
+        "particulars",
+        "transactiondetails",
+        "memo",
+    ]);
+    let reference_col = column(&[
+        "reference",
+        "ref",
+        "refno",
+        "referencenumber",
+        "transactionreference",
+    ]);
+    let check_col = column(&["chequeno", "chequenumber", "checkno", "checknumber"]);
+    let amount_col = column(&["amount", "transactionamount"]);
+    let credit_col = column(&[
+        "credit",
+        "credits",
+        "creditamount",
+        "deposit",
+        "deposits",
+        "moneyin",
+        "paidin",
+    ]);
+    let debit_col = column(&[
+        "debit",
+        "debits",
+        "debitamount",
+        "withdrawal",
+        "withdrawals",
+        "moneyout",
+        "paidout",
+    ]);
+    if amount_col.is_none() && credit_col.is_none() && debit_col.is_none() {
+        return Err(Error::InvalidInput(
+            "statement has no amount, credit or debit column".into(),
+        ));
+    }
+
+    let mut lines = Vec::new();
+    for row in &rows[header_at + 1..] {
+        let cell = |col: Option<usize>| {
+            col.and_then(|c| row.get(c))
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+        // Opening/closing balance rows and footers carry no date.
+        let Some(posted_on) = cell(Some(date_col)).and_then(|d| statements::parse_datetime(d).ok())
+        else {
+            continue;
+        };
+        let amount = match amount_col {
+            Some(col) => cell(Some(col)).and_then(statements::parse_amount),
+            None => {
+                let credit = cell(credit_col)
+                    .and_then(statements::parse_amount)
+                    .unwrap_or(0.0);
+                let debit = cell(debit_col)
+                    .and_then(statements::parse_amount)
+                    .unwrap_or(0.0);
+                Some(credit.abs() - debit.abs())
+            }
+        };
+        let Some(amount) = amount else {
+            continue;
+        };
+        lines.push(BankLine {
+            posted_on: posted_on.date(),
+            amount,
+            description: cell(description_col).unwrap_or_default().to_string(),
+            reference: cell(reference_col).map(str::to_string),
+            check_number: cell(check_col).map(str::to_string),
+            external_id: None,
+        });
+    }
+    Ok(ParsedStatement {
+        account: None,
+        lines,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(posted_on: &str, amount: f64, description: &str) -> BankLine {
+        BankLine {
+            posted_on: period::parse_date(posted_on).unwrap(),
+            amount,
+            description: description.into(),
+            reference: None,
+            check_number: None,
+            external_id: None,
+        }
+    }
+
+    fn payment(id: &str, date: &str, remaining: f64, reference: &str, label: &str) -> Candidate {
+        Candidate {
+            payment_id: Some(id.into()),
+            expense_id: None,
+            record_date: date.into(),
+            remaining,
+            reference: Some(reference.into()),
+            label: Some(label.into()),
+        }
+    }
+
+    #[test]
+    fn csv_with_debit_and_credit_columns() {
+        let text = "\
+Account Statement,,,,,
+Account No,0123456789,,,,
+,,,,,
+Transaction Date,Value Date,Narration,Cheque No,Debit,Credit
+,,Opening Balance,,,\"125,000.00\"
+15/01/2024,16/01/2024,FT JANE WANJIKU RENT A12,,,\"15,000.00\"
+16/01/2024,16/01/2024,CHQ PLUMB AND CO,000123,\"2,500.00\",
+17/01/2024,17/01/2024,LEDGER FEE,,(35.50),
+,,Closing Balance,,,\"137,464.50\"
+";
+        let statement = parse_csv(text).unwrap();
+        assert_eq!(statement.account, None);
+        assert_eq!(
+            statement.lines,
+            [
+                line("2024-01-15", 15_000.0, "FT JANE WANJIKU RENT A12"),
+                BankLine {
+                    check_number: Some("000123".into()),
+                    ..line("2024-01-16", -2_500.0, "CHQ PLUMB AND CO")
+                },
+                // Debit columns count as money out whatever their sign.
+                line("2024-01-17", -35.5, "LEDGER FEE"),
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_with_a_signed_amount_column() {
+        let text = "\
+Date,Description,Reference,Amount,Balance
+2024-02-01,Deposit,FT2402A,1500.00,1500.00
+2024-02-02,Service charge,,-12.40,1487.60
+2024-02-03,No amount,,,1487.60
+";
+        let statement = parse_csv(text).unwrap();
+        assert_eq!(statement.lines.len(), 2);
+        assert_eq!(statement.lines[0].amount, 1_500.0);
+        assert_eq!(statement.lines[0].reference.as_deref(), Some("FT2402A"));
+        assert_eq!(statement.lines[1].amount, -12.4);
+        assert_eq!(statement.lines[1].reference, None);
+    }
+
+    #[test]
+    fn csv_without_needed_columns() {
+        assert!(parse_csv("Description,Amount\nRent,100\n").is_err());
+        assert!(parse_csv("Date,Description\n2024-02-01,Rent\n").is_err());
+    }
+
+    #[test]
+    fn format_detection() {
+        let detect = |name: &str, text: &str| BankFormat::detect(Path::new(name), text);
+        assert_eq!(detect("jan.QFX", ""), BankFormat::Ofx);
+        assert_eq!(detect("jan.sta", ""), BankFormat::Mt940);
+        assert_eq!(detect("jan.txt", "OFXHEADER:100\n<ofx>"), BankFormat::Ofx);
+        assert_eq!(detect("jan.txt", ":20:X\n:61:"), BankFormat::Mt940);
+        assert_eq!(detect("jan.txt", "Date,Amount"), BankFormat::Csv);
+    }
+
+    #[test]
+    fn scoring() {
+        let mut credit = line("2024-01-15", 15_000.0, "FT JANE WANJIKU RENT A12");
+        credit.reference = Some("FT24015ABCDE".into());
+
+        // Same day, reference and name: capped at 1.
+        let exact = payment(
+            "P1",
+            "2024-01-15",
+            15_000.0,
+            "FT24015-ABCDE",
+            "Jane Wanjiku",
+        );
+        assert_eq!(score(&credit, &exact), Some(1.0));
+        // Two days apart, name only.
+        let named = payment("P2", "2024-01-13", 15_000.0, "OTHER", "Jane Doe");
+        assert_eq!(score(&credit, &named), Some(0.8));
+        // Too far apart, different amount, or short references and names.
+        let late = payment("P3", "2024-01-30", 15_000.0, "OTHER", "X");
+        assert_eq!(score(&credit, &late), None);
+        let partial = payment("P4", "2024-01-15", 14_999.0, "OTHER", "X");
+        assert_eq!(score(&credit, &partial), None);
+        let short = payment("P5", "2024-01-09", 15_000.0, "FT", "Al");
+        assert_eq!(score(&credit, &short), Some(0.6));
+
+        // Payments only match credits, expenses only debits.
+        let expense = Candidate {
+            payment_id: None,
+            expense_id: Some(7),
+            ..payment("", "2024-01-15", 15_000.0, "INV-7", "Plumb")
+        };
+        assert_eq!(score(&credit, &expense), None);
+        let debit = line("2024-01-15", -15_000.0, "CHQ PLUMB AND CO INV7");
+        assert_eq!(score(&debit, &expense), Some(1.0));
+        assert_eq!(score(&debit, &exact), None);
+    }
+
+    #[test]
+    fn suggestions_are_best_first_and_above_the_threshold() {
+        let credit = line("2024-01-15", 500.0, "TRANSFER");
+        let candidates: Vec<Candidate> = [
+            ("far", "2024-01-27"),
+            ("near", "2024-01-14"),
+            ("same", "2024-01-15"),
+            ("week", "2024-01-10"),
+            ("also", "2024-01-16"),
+        ]
+        .into_iter()
+        .map(|(id, date)| payment(id, date, 500.0, "NONE", "Nobody"))
+        .collect();
+        let ids: Vec<(&str, f64)> = suggest(&credit, &candidates)
+            .into_iter()
+            .map(|(c, s)| (c.payment_id.as_deref().unwrap(), s))
+            .collect();
+        // "far" scores 0.5, under the threshold; only three are kept.
+        assert_eq!(ids, [("same", 0.8), ("near", 0.7), ("also", 0.7)]);
+    }
+}
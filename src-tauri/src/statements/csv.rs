@@ -0,0 +1,68 @@
+/// Splits RFC 4180 CSV into rows of fields: quoted fields may contain commas,
+/// doubled quotes and line breaks; blank lines are dropped.
+pub fn parse(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => quoted = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                push_row(&mut rows, std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        push_row(&mut rows, row);
+    }
+    rows
+}
+
+fn push_row(rows: &mut Vec<Vec<String>>, row: Vec<String>) {
+    if row.iter().any(|f| !f.trim().is_empty()) {
+        rows.push(row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_fields() {
+        let rows = parse("a,\"1,000.00\",\"say \"\"hi\"\"\"\r\n\"two\nlines\",,x\n");
+        assert_eq!(
+            rows,
+            [
+                vec!["a", "1,000.00", "say \"hi\""],
+                vec!["two\nlines", "", "x"],
+            ]
+        );
+    }
+
+    #[test]
+    fn blank_lines_dropped_and_last_line_kept() {
+        let rows = parse("a,b\n\n , \nc,d");
+        assert_eq!(rows, [vec!["a", "b"], vec!["c", "d"]]);
+        assert!(parse("").is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+//! Importers for payment statements exported by banks and mobile-money
+//! providers, and the matching that turns their lines into records.
+
+use std::path::Path;
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::error::{Error, Result};
+
+pub mod bank;
+pub mod csv;
+pub mod mpesa;
+pub mod mt940;
+pub mod ofx;
+
+/// Reads a statement export as text, tolerating a UTF-8 BOM and stray
+/// non-UTF-8 bytes (some exports are Windows-1252).
+pub fn read_text(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(text.trim_start_matches('\u{feff}').to_string())
+}
+
+/// Parses `1,234.50`, `-1,234.50`, `(1,234.50)` and `KES 1234.5`; blank
+/// cells are `None`.
+pub fn parse_amount(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let negative = value.starts_with('-') || (value.starts_with('(') && value.ends_with(')'));
+    let digits: String = value
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    if digits.is_empty() {
+        return None;
+    }
+    let amount: f64 = digits.parse().ok()?;
+    Some(if negative { -amount } else { amount })
+}
+
+const DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%d/%m/%Y %H:%M:%S",
+    "%d/%m/%Y %H:%M",
+    "%d-%m-%Y %H:%M:%S",
+    "%d-%m-%Y %H:%M",
+];
+
+const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d", "%d/%m/%Y", "%d-%m-%Y", "%d.%m.%Y", "%Y%m%d", "%d-%b-%Y", "%d %b %Y",
+];
+
+/// Parses the date and time formats seen in Kenyan statement exports. Day
+/// comes before month in slashed dates; a bare date means midnight.
+pub fn parse_datetime(value: &str) -> Result<NaiveDateTime> {
+    let value = value.trim();
+    for format in DATETIME_FORMATS {
+        if let Ok(parsed) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(parsed);
+        }
+    }
+    for format in DATE_FORMATS {
+        if let Ok(parsed) = NaiveDate::parse_from_str(value, format) {
+            return Ok(parsed.and_hms_opt(0, 0, 0).unwrap_or_default());
+        }
+    }
+    Err(Error::InvalidInput(format!("unrecognised date '{value}'")))
+}
+
+/// Caps a sum of match weights at 1 and rounds it to two decimals, so it
+/// compares cleanly with thresholds.
+pub fn round_confidence(confidence: f64) -> f64 {
+    (confidence.min(1.0) * 100.0).round() / 100.0
+}
+
+/// Lower-cases a header and drops everything but letters and digits, so
+/// `"A/C No."` and `"a/c no"` compare equal.
+pub fn header_key(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_in_statement_formats() {
+        assert_eq!(parse_amount("1,234.50"), Some(1_234.5));
+        assert_eq!(parse_amount("-1,234.50"), Some(-1_234.5));
+        assert_eq!(parse_amount("(1,234.50)"), Some(-1_234.5));
+        assert_eq!(parse_amount("KES 1234.5"), Some(1_234.5));
+        assert_eq!(parse_amount("  "), None);
+        assert_eq!(parse_amount("n/a"), None);
+        assert_eq!(parse_amount("1.2.3"), None);
+    }
+
+    #[test]
+    fn dates_put_the_day_first() {
+        let at = |s: &str| parse_datetime(s).unwrap().to_string();
+        assert_eq!(at("2024-01-15 14:30:00"), "2024-01-15 14:30:00");
+        assert_eq!(at("2024-01-15T14:30:00"), "2024-01-15 14:30:00");
+        assert_eq!(at("03/02/2024 09:05"), "2024-02-03 09:05:00");
+        assert_eq!(at("03.02.2024"), "2024-02-03 00:00:00");
+        assert_eq!(at("20240203"), "2024-02-03 00:00:00");
+        assert_eq!(at("3 Feb 2024"), "2024-02-03 00:00:00");
+        assert!(parse_datetime("02/30/2024").is_err());
+        assert!(parse_datetime("").is_err());
+    }
+
+    #[test]
+    fn confidence_is_capped_and_rounded() {
+        assert_eq!(round_confidence(0.15 + 0.15 + 0.5), 0.8);
+        assert_eq!(round_confidence(1.3), 1.0);
+    }
+
+    #[test]
+    fn header_keys_ignore_punctuation_and_case() {
+        assert_eq!(header_key("A/C No."), "acno");
+        assert_eq!(header_key(" Receipt No. "), "receiptno");
+    }
+}
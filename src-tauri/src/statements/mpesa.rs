@@ -0,0 +1,1054 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use chrono::NaiveDateTime;
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::billing::allocations::{self, AllocationMode, AllocationResult};
+use crate::billing::payments::{self, NewPayment};
+use crate::db;
+use crate::error::{Error, Result};
+use crate::statements::{self, csv};
+
+/// `accept_mpesa_matches` records proposals at least this sure by default.
+pub const AUTO_ACCEPT_CONFIDENCE: f64 = 0.8;
+/// Weaker candidates are left for the user to match by hand.
+const MIN_CONFIDENCE: f64 = 0.3;
+
+/// A completed money-in line from an M-Pesa statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementEntry {
+    pub transaction_code: String,
+    pub completed_at: NaiveDateTime,
+    pub details: String,
+    pub amount: f64,
+    pub phone_number: Option<String>,
+    pub payer_name: Option<String>,
+    pub account_reference: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct MpesaTransaction {
+    pub transaction_id: i64,
+    pub import_id: i64,
+    pub transaction_code: String,
+    pub completed_at: String,
+    pub details: Option<String>,
+    pub amount: f64,
+    pub phone_number: Option<String>,
+    pub payer_name: Option<String>,
+    pub account_reference: Option<String>,
+    pub tenant_id: Option<i64>,
+    pub tenant_name: Option<String>,
+    pub unit_id: Option<i64>,
+    pub unit_number: Option<String>,
+    pub property_id: Option<i64>,
+    /// 0 to 1; how sure the match on `tenant_id` is.
+    pub confidence: f64,
+    /// Comma-separated signals behind the match, e.g. `phone, amount`.
+    pub match_reason: Option<String>,
+    /// `Unmatched`, `Proposed`, `Accepted`, `Ignored` or `Duplicate`.
+    pub status: String,
+    /// Where the transaction code was already seen, for duplicates.
+    pub duplicate_of: Option<String>,
+    pub payment_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MpesaImport {
+    pub import_id: i64,
+    pub proposed: usize,
+    pub unmatched: usize,
+    pub duplicates: usize,
+    pub transactions: Vec<MpesaTransaction>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptFailure {
+    pub transaction_id: i64,
+    pub transaction_code: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptedMatches {
+    pub accepted: Vec<AllocationResult>,
+    /// Proposals left as they were, e.g. because the payment date falls in a
+    /// closed accounting period.
+    pub failures: Vec<AcceptFailure>,
+}
+
+/// The tenant a statement line most likely belongs to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchProposal {
+    pub tenant_id: i64,
+    pub unit_id: Option<i64>,
+    pub property_id: Option<i64>,
+    pub confidence: f64,
+    pub reasons: Vec<&'static str>,
+}
+
+/// A tenant with what is needed to recognise their payments.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct TenantCandidate {
+    pub tenant_id: i64,
+    pub full_name: String,
+    pub phone_number: Option<String>,
+    pub id_number: Option<String>,
+    pub unit_id: Option<i64>,
+    pub unit_number: Option<String>,
+    pub property_id: Option<i64>,
+    pub rent: f64,
+    pub outstanding: f64,
+}
+
+/// Imports an M-Pesa statement export (the CSV from the M-Pesa portal, or
+/// text copied out of the PDF statement) and proposes a tenant for each
+/// incoming payment. Nothing is recorded until a proposal is accepted.
+#[tauri::command]
+pub async fn import_mpesa_statement(app: AppHandle, path: PathBuf) -> Result<MpesaImport> {
+    let entries = parse_statement(&statements::read_text(&path)?)?;
+    let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+    let pool = db::pool(&app).await?;
+    import_statement(&pool, file_name.as_deref(), &entries).await
+}
+
+#[tauri::command]
+pub async fn get_mpesa_transactions(
+    app: AppHandle,
+    import_id: Option<i64>,
+    status: Option<String>,
+) -> Result<Vec<MpesaTransaction>> {
+    let pool = db::pool(&app).await?;
+    transactions(&pool, import_id, status.as_deref()).await
+}
+
+/// Records a transaction as a Mobile Money payment and allocates it to the
+/// tenant's oldest charges. `tenant_id` and `unit_id` override the proposal.
+#[tauri::command]
+pub async fn accept_mpesa_transaction(
+    app: AppHandle,
+    transaction_id: i64,
+    tenant_id: Option<i64>,
+    unit_id: Option<i64>,
+    payment_category: Option<String>,
+) -> Result<AllocationResult> {
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let result = accept(
+        &mut tx,
+        transaction_id,
+        tenant_id,
+        unit_id,
+        payment_category,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+/// Accepts every proposal at or above `min_confidence` (0.8 by default).
+/// Each is recorded on its own, so one that cannot be accepted is reported
+/// without holding back the rest.
+#[tauri::command]
+pub async fn accept_mpesa_matches(
+    app: AppHandle,
+    import_id: Option<i64>,
+    min_confidence: Option<f64>,
+) -> Result<AcceptedMatches> {
+    let pool = db::pool(&app).await?;
+    accept_matches(
+        &pool,
+        import_id,
+        min_confidence.unwrap_or(AUTO_ACCEPT_CONFIDENCE),
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn ignore_mpesa_transaction(app: AppHandle, transaction_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let status: Option<(String,)> =
+        sqlx::query_as("SELECT status FROM mpesa_transactions WHERE transaction_id = ?1")
+            .bind(transaction_id)
+            .fetch_optional(&pool)
+            .await?;
+    match status {
+        None => Err(Error::NotFound(format!(
+            "M-Pesa transaction {transaction_id}"
+        ))),
+        Some((status,)) if status == "Accepted" => Err(Error::InvalidInput(format!(
+            "M-Pesa transaction {transaction_id} is already recorded as a payment"
+        ))),
+        // Duplicates are already out of the review queue, and must stay
+        // duplicates so the original keeps its code.
+        Some((status,)) if status == "Duplicate" => Ok(()),
+        Some(_) => {
+            sqlx::query(
+                "UPDATE mpesa_transactions SET status = 'Ignored', updated_at = CURRENT_TIMESTAMP
+                 WHERE transaction_id = ?1",
+            )
+            .bind(transaction_id)
+            .execute(&pool)
+            .await?;
+            Ok(())
+        }
+    }
+}
+
+/// Stores parsed entries as a new import, flagging transaction codes already
+/// imported or typed into `payments.transaction_reference` as duplicates.
+pub async fn import_statement(
+    pool: &SqlitePool,
+    file_name: Option<&str>,
+    entries: &[StatementEntry],
+) -> Result<MpesaImport> {
+    let candidates = tenant_candidates(pool).await?;
+    let mut tx = pool.begin().await?;
+    let import_id =
+        sqlx::query("INSERT INTO mpesa_imports (file_name, transaction_count) VALUES (?1, ?2)")
+            .bind(file_name)
+            .bind(entries.len() as i64)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+
+    let mut seen: HashMap<&str, i64> = HashMap::new();
+    for entry in entries {
+        let duplicate_of = match seen.get(entry.transaction_code.as_str()) {
+            Some(id) => Some(format!("transaction {id}")),
+            None => first_seen(&mut tx, &entry.transaction_code).await?,
+        };
+        let proposal = match duplicate_of {
+            Some(_) => None,
+            None => propose(entry, &candidates),
+        };
+        let transaction_id = insert_transaction(
+            &mut tx,
+            import_id,
+            entry,
+            proposal.as_ref(),
+            duplicate_of.as_deref(),
+        )
+        .await
+        .map_err(recorded_meanwhile)?;
+        seen.entry(&entry.transaction_code)
+            .or_insert(transaction_id);
+    }
+    tx.commit().await?;
+
+    let transactions = transactions(pool, Some(import_id), None).await?;
+    let count = |status: &str| transactions.iter().filter(|t| t.status == status).count();
+    Ok(MpesaImport {
+        import_id,
+        proposed: count("Proposed"),
+        unmatched: count("Unmatched"),
+        duplicates: count("Duplicate"),
+        transactions,
+    })
+}
+
+pub async fn accept_matches(
+    pool: &SqlitePool,
+    import_id: Option<i64>,
+    min_confidence: f64,
+) -> Result<AcceptedMatches> {
+    let proposals: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT transaction_id, transaction_code FROM mpesa_transactions
+         WHERE status = 'Proposed' AND confidence >= ?1 AND (?2 IS NULL OR import_id = ?2)
+         ORDER BY completed_at, transaction_id",
+    )
+    .bind(min_confidence)
+    .bind(import_id)
+    .fetch_all(pool)
+    .await?;
+    let mut run = AcceptedMatches::default();
+    for (transaction_id, transaction_code) in proposals {
+        let mut tx = pool.begin().await?;
+        match accept(&mut tx, transaction_id, None, None, None).await {
+            Ok(result) => {
+                tx.commit().await?;
+                run.accepted.push(result);
+            }
+            Err(err) => run.failures.push(AcceptFailure {
+                transaction_id,
+                transaction_code,
+                message: err.to_string(),
+            }),
+        }
+    }
+    Ok(run)
+}
+
+/// Records a payment notification from the C2B callback. Safaricom retries
+/// notifications, so a code seen before is acknowledged without recording
+/// it again. Matches at or above `auto_accept` become payments at once; the
+/// rest wait in the callback import for review like statement lines.
+pub async fn record_callback(
+    pool: &SqlitePool,
+    entry: &StatementEntry,
+    auto_accept: f64,
+) -> Result<()> {
+    let candidates = tenant_candidates(pool).await?;
+    let mut tx = pool.begin().await?;
+    if first_seen(&mut tx, &entry.transaction_code)
+        .await?
+        .is_some()
+    {
+        return Ok(());
+    }
+
+    let existing: Option<(i64,)> =
+        sqlx::query_as("SELECT import_id FROM mpesa_imports WHERE source = 'Callback' LIMIT 1")
+            .fetch_optional(&mut *tx)
+            .await?;
+    let import_id = match existing {
+        Some((import_id,)) => import_id,
+        None => sqlx::query(
+            "INSERT INTO mpesa_imports (file_name, source) VALUES ('C2B callbacks', 'Callback')",
+        )
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid(),
+    };
+    sqlx::query(
+        "UPDATE mpesa_imports SET transaction_count = transaction_count + 1 WHERE import_id = ?1",
+    )
+    .bind(import_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let proposal = propose(entry, &candidates);
+    // The unique index on live codes catches a retry that raced this one past
+    // `first_seen`.
+    let transaction_id =
+        match insert_transaction(&mut tx, import_id, entry, proposal.as_ref(), None).await {
+            Ok(transaction_id) => transaction_id,
+            Err(sqlx::Error::Database(db)) if db.is_unique_violation() => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+    if proposal.is_some_and(|p| p.confidence >= auto_accept) {
+        accept(&mut tx, transaction_id, None, None, None).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn insert_transaction(
+    conn: &mut SqliteConnection,
+    import_id: i64,
+    entry: &StatementEntry,
+    proposal: Option<&MatchProposal>,
+    duplicate_of: Option<&str>,
+) -> sqlx::Result<i64> {
+    let status = match (duplicate_of, proposal) {
+        (Some(_), _) => "Duplicate",
+        (None, Some(_)) => "Proposed",
+        (None, None) => "Unmatched",
+    };
+    Ok(sqlx::query(
+        "INSERT INTO mpesa_transactions (
+             import_id, transaction_code, completed_at, details, amount, phone_number,
+             payer_name, account_reference, tenant_id, unit_id, property_id, confidence,
+             match_reason, status, duplicate_of
+         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+    )
+    .bind(import_id)
+    .bind(&entry.transaction_code)
+    .bind(entry.completed_at.format("%Y-%m-%d %H:%M:%S").to_string())
+    .bind(&entry.details)
+    .bind(entry.amount)
+    .bind(&entry.phone_number)
+    .bind(&entry.payer_name)
+    .bind(&entry.account_reference)
+    .bind(proposal.map(|p| p.tenant_id))
+    .bind(proposal.and_then(|p| p.unit_id))
+    .bind(proposal.and_then(|p| p.property_id))
+    .bind(proposal.map_or(0.0, |p| p.confidence))
+    .bind(proposal.map(|p| p.reasons.join(", ")))
+    .bind(status)
+    .bind(duplicate_of)
+    .execute(conn)
+    .await?
+    .last_insert_rowid())
+}
+
+fn recorded_meanwhile(err: sqlx::Error) -> Error {
+    match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => Error::InvalidInput(
+            "a transaction in this statement was recorded while importing it; import it again"
+                .into(),
+        ),
+        _ => err.into(),
+    }
+}
+
+pub async fn transactions(
+    pool: &SqlitePool,
+    import_id: Option<i64>,
+    status: Option<&str>,
+) -> Result<Vec<MpesaTransaction>> {
+    Ok(sqlx::query_as(
+        "SELECT m.transaction_id, m.import_id, m.transaction_code, m.completed_at, m.details,
+                CAST(m.amount AS REAL) AS amount, m.phone_number, m.payer_name,
+                m.account_reference, m.tenant_id, t.full_name AS tenant_name, m.unit_id,
+                u.unit_number, m.property_id, CAST(m.confidence AS REAL) AS confidence,
+                m.match_reason, m.status, m.duplicate_of, m.payment_id
+         FROM mpesa_transactions m
+         LEFT JOIN tenants t ON t.tenant_id = m.tenant_id
+         LEFT JOIN units u ON u.unit_id = m.unit_id
+         WHERE (?1 IS NULL OR m.import_id = ?1) AND (?2 IS NULL OR m.status = ?2)
+         ORDER BY m.completed_at, m.transaction_id",
+    )
+    .bind(import_id)
+    .bind(status)
+    .fetch_all(pool)
+    .await?)
+}
+
+async fn accept(
+    conn: &mut SqliteConnection,
+    transaction_id: i64,
+    tenant_id: Option<i64>,
+    unit_id: Option<i64>,
+    payment_category: Option<String>,
+) -> Result<AllocationResult> {
+    #[derive(sqlx::FromRow)]
+    struct Row {
+        transaction_code: String,
+        completed_at: String,
+        amount: f64,
+        status: String,
+        duplicate_of: Option<String>,
+        payment_id: Option<String>,
+        tenant_id: Option<i64>,
+        unit_id: Option<i64>,
+    }
+    let row: Row = sqlx::query_as(
+        "SELECT transaction_code, completed_at, CAST(amount AS REAL) AS amount, status,
+                duplicate_of, payment_id, tenant_id, unit_id
+         FROM mpesa_transactions WHERE transaction_id = ?1",
+    )
+    .bind(transaction_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("M-Pesa transaction {transaction_id}")))?;
+
+    match row.status.as_str() {
+        "Accepted" => {
+            return Err(Error::InvalidInput(format!(
+                "{} is already recorded as payment {}",
+                row.transaction_code,
+                row.payment_id.unwrap_or_default()
+            )))
+        }
+        "Duplicate" => {
+            return Err(Error::InvalidInput(format!(
+                "{} is a duplicate of {}",
+                row.transaction_code,
+                row.duplicate_of.unwrap_or_default()
+            )))
+        }
+        _ => {}
+    }
+
+    let tenant_id = tenant_id.or(row.tenant_id).ok_or_else(|| {
+        Error::InvalidInput(format!(
+            "choose a tenant for {} before accepting it",
+            row.transaction_code
+        ))
+    })?;
+    let unit_id = match unit_id {
+        Some(unit_id) => Some(unit_id),
+        None if row.tenant_id == Some(tenant_id) => row.unit_id,
+        None => payments::tenant_unit(&mut *conn, tenant_id).await?,
+    }
+    .ok_or_else(|| Error::InvalidInput(format!("tenant {tenant_id} has no unit")))?;
+    let property_id = payments::unit_property(&mut *conn, unit_id).await?;
+
+    let payment = NewPayment {
+        tenant_id,
+        unit_id,
+        property_id,
+        amount: row.amount,
+        payment_date: row.completed_at[..10].to_string(),
+        due_date: None,
+        payment_method: "Mobile Money".into(),
+        payment_category,
+        receipt_number: None,
+        transaction_reference: Some(row.transaction_code),
+        remarks: Some("Imported from M-Pesa statement".into()),
+    };
+    let payment_id = payments::insert_payment(&mut *conn, &payment).await?;
+    let result = allocations::allocate(
+        conn,
+        tenant_id,
+        &payment_id,
+        payment.amount,
+        AllocationMode::OldestFirst,
+    )
+    .await?;
+
+    sqlx::query(
+        "UPDATE mpesa_transactions
+         SET status = 'Accepted', payment_id = ?2, tenant_id = ?3, unit_id = ?4, property_id = ?5,
+             updated_at = CURRENT_TIMESTAMP
+         WHERE transaction_id = ?1",
+    )
+    .bind(transaction_id)
+    .bind(&payment_id)
+    .bind(tenant_id)
+    .bind(unit_id)
+    .bind(property_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(result)
+}
+
+/// Where `code` was seen before: a payment carrying it as its reference, or
+/// an earlier import.
+async fn first_seen(conn: &mut SqliteConnection, code: &str) -> Result<Option<String>> {
+    let row: Option<(String,)> = sqlx::query_as(
+        "SELECT 'payment ' || payment_id FROM payments WHERE upper(trim(transaction_reference)) = ?1
+         UNION ALL
+         SELECT 'transaction ' || transaction_id FROM mpesa_transactions
+         WHERE transaction_code = ?1 AND status <> 'Duplicate'
+         LIMIT 1",
+    )
+    .bind(code)
+    .fetch_optional(conn)
+    .await?;
+    Ok(row.map(|(seen,)| seen))
+}
+
+pub async fn tenant_candidates(pool: &SqlitePool) -> Result<Vec<TenantCandidate>> {
+    Ok(sqlx::query_as(
+        "SELECT t.tenant_id, t.full_name, t.phone_number, t.id_number,
+                u.unit_id, u.unit_number, u.property_id,
+                CAST(COALESCE(l.rent_amount, t.rent_amount, u.monthly_rent, 0) AS REAL) AS rent,
+                CAST((SELECT COALESCE(SUM(c.outstanding), 0) FROM charge_balances c
+                      WHERE c.tenant_id = t.tenant_id AND c.status = 'Open') AS REAL) AS outstanding
+         FROM tenants t
+         LEFT JOIN leases l ON l.lease_id = (
+             SELECT lease_id FROM leases WHERE tenant_id = t.tenant_id
+             ORDER BY lease_start_date DESC LIMIT 1
+         )
+         LEFT JOIN units u ON u.unit_id = COALESCE(l.unit_id, t.unit_id)",
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Picks the best candidate for `entry`. Ties between tenants are capped
+/// below the auto-accept threshold, and an amount alone only counts when a
+/// single tenant owes exactly that much.
+pub fn propose(entry: &StatementEntry, candidates: &[TenantCandidate]) -> Option<MatchProposal> {
+    let mut scored: Vec<MatchProposal> =
+        candidates.iter().filter_map(|c| score(entry, c)).collect();
+    scored.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let mut best = scored.first()?.clone();
+    let tied = scored
+        .iter()
+        .skip(1)
+        .any(|p| (p.confidence - best.confidence).abs() < 1e-9);
+
+    if best.reasons == ["amount"] {
+        if tied {
+            return None;
+        }
+        best.confidence = MIN_CONFIDENCE;
+    } else if tied {
+        best.confidence = best.confidence.min(0.5);
+        best.reasons.push("ambiguous");
+    }
+    (best.confidence >= MIN_CONFIDENCE).then_some(best)
+}
+
+fn score(entry: &StatementEntry, candidate: &TenantCandidate) -> Option<MatchProposal> {
+    let mut confidence = 0.0;
+    let mut reasons = Vec::new();
+
+    if let (Some(paid_from), Some(phone)) = (&entry.phone_number, &candidate.phone_number) {
+        match phone_match(paid_from, phone) {
+            Some(PhoneMatch::Exact) => {
+                confidence += 0.5;
+                reasons.push("phone");
+            }
+            Some(PhoneMatch::Masked) => {
+                confidence += 0.3;
+                reasons.push("masked phone");
+            }
+            None => {}
+        }
+    }
+    if let Some(reference) = entry.account_reference.as_deref().map(reference_key) {
+        let matches = |value: Option<&str>| value.is_some_and(|v| reference_key(v) == reference);
+        if !reference.is_empty()
+            && (matches(candidate.unit_number.as_deref())
+                || matches(candidate.id_number.as_deref())
+                || candidate
+                    .phone_number
+                    .as_deref()
+                    .is_some_and(|phone| phone_match(&reference, phone) == Some(PhoneMatch::Exact)))
+        {
+            confidence += 0.35;
+            reasons.push("account reference");
+        }
+    }
+    if let Some(payer) = &entry.payer_name {
+        if name_overlap(payer, &candidate.full_name) >= 0.5 {
+            confidence += 0.15;
+            reasons.push("name");
+        }
+    }
+    let close = |expected: f64| expected > 0.0 && (entry.amount - expected).abs() < 0.005;
+    if close(candidate.rent) || close(candidate.outstanding) {
+        confidence += 0.15;
+        reasons.push("amount");
+    }
+
+    (!reasons.is_empty()).then(|| MatchProposal {
+        tenant_id: candidate.tenant_id,
+        unit_id: candidate.unit_id,
+        property_id: candidate.property_id,
+        confidence: statements::round_confidence(confidence),
+        reasons,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum PhoneMatch {
+    Exact,
+    /// Statements mask the middle digits, e.g. `2547*****678`.
+    Masked,
+}
+
+fn phone_match(statement: &str, tenant: &str) -> Option<PhoneMatch> {
+    // Daraja callbacks may carry the SHA-256 of the number instead of the number.
+    if statement.len() == 64 && statement.chars().all(|c| c.is_ascii_hexdigit()) {
+        let digest = Sha256::digest(normalize_phone(tenant).as_bytes());
+        return statement
+            .eq_ignore_ascii_case(&format!("{digest:x}"))
+            .then_some(PhoneMatch::Exact);
+    }
+    let (statement, tenant) = (normalize_phone(statement), normalize_phone(tenant));
+    if statement.len() < 9 || statement.len() != tenant.len() || tenant.contains('*') {
+        return None;
+    }
+    if statement == tenant {
+        return Some(PhoneMatch::Exact);
+    }
+    let masked = statement.contains('*')
+        && statement
+            .chars()
+            .zip(tenant.chars())
+            .all(|(s, t)| s == '*' || s == t);
+    masked.then_some(PhoneMatch::Masked)
+}
+
+/// `0712 345 678`, `+254712345678` and `712345678` all become `254712345678`.
+fn normalize_phone(phone: &str) -> String {
+    let digits: String = phone
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '*')
+        .collect();
+    match digits.len() {
+        10 if digits.starts_with('0') => format!("254{}", &digits[1..]),
+        9 => format!("254{digits}"),
+        _ => digits,
+    }
+}
+
+fn reference_key(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '*')
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// Share of the payer's name words (statements often shorten the surname to
+/// an initial, which is ignored) found in the tenant's name.
+fn name_overlap(payer: &str, tenant: &str) -> f64 {
+    let tenant: Vec<String> = tenant.split_whitespace().map(str::to_uppercase).collect();
+    let words: Vec<String> = payer
+        .split_whitespace()
+        .map(str::to_uppercase)
+        .filter(|w| w.chars().filter(|c| c.is_alphabetic()).count() > 1)
+        .collect();
+    if words.is_empty() {
+        return 0.0;
+    }
+    let found = words.iter().filter(|w| tenant.contains(w)).count();
+    found as f64 / words.len() as f64
+}
+
+/// Parses either export format, keeping completed incoming payments only.
+pub fn parse_statement(text: &str) -> Result<Vec<StatementEntry>> {
+    let rows = csv::parse(text);
+    let header = rows
+        .iter()
+        .take(30)
+        .position(|row| row.iter().any(|f| statements::header_key(f) == "receiptno"));
+    match header {
+        Some(header) => parse_csv(&rows[header..]),
+        None => parse_text(text),
+    }
+}
+
+fn parse_csv(rows: &[Vec<String>]) -> Result<Vec<StatementEntry>> {
+    let header: Vec<String> = rows[0].iter().map(|h| statements::header_key(h)).collect();
+    let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+    let required = |name: &str, names: &[&str]| {
+        column(names)
+            .ok_or_else(|| Error::InvalidInput(format!("statement has no '{name}' column")))
+    };
+    let code_col = required("Receipt No.", &["receiptno"])?;
+    let time_col = required("Completion Time", &["completiontime"])?;
+    let paid_in_col = required("Paid In", &["paidin"])?;
+    let details_col = column(&["details"]);
+    let status_col = column(&["transactionstatus"]);
+    let party_col = column(&["otherpartyinfo"]);
+    let account_col = column(&["acno", "accountno", "accountnumber", "accountreference"]);
+
+    let mut entries = Vec::new();
+    for row in &rows[1..] {
+        let cell = |col: Option<usize>| {
+            col.and_then(|c| row.get(c))
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+        // Footers and repeated headers have no usable receipt number.
+        let Some(code) = cell(Some(code_col)).filter(|c| statements::header_key(c) != "receiptno")
+        else {
+            continue;
+        };
+        if cell(status_col).is_some_and(|s| !s.eq_ignore_ascii_case("completed")) {
+            continue;
+        }
+        let Some(amount) = cell(Some(paid_in_col))
+            .and_then(statements::parse_amount)
+            .filter(|a| *a > 0.0)
+        else {
+            continue;
+        };
+        let details = cell(details_col).unwrap_or_default().to_string();
+        let (mut phone_number, mut payer_name, account_reference) = party_from_details(&details);
+        if let Some(info) = cell(party_col) {
+            (phone_number, payer_name) = party(info);
+        }
+        entries.push(StatementEntry {
+            transaction_code: code.to_uppercase(),
+            completed_at: statements::parse_datetime(cell(Some(time_col)).unwrap_or_default())?,
+            details,
+            amount,
+            phone_number,
+            payer_name,
+            account_reference: cell(account_col).map(str::to_string).or(account_reference),
+        });
+    }
+    Ok(entries)
+}
+
+static TEXT_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^([A-Z0-9]{10})\s+(\d{4}-\d{2}-\d{2}\s+\d{2}:\d{2}(?::\d{2})?)\s+(.*)$").unwrap()
+});
+static STATUS_AMOUNTS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\s(completed|failed|cancelled|reversed|pending)\s+(-?[\d,]+\.\d{2})(?:\s+-?[\d,]+\.\d{2})*")
+        .unwrap()
+});
+static PARTY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*(\+?[0-9*]{9,13})\s*-\s*(.+?)\s*$").unwrap());
+static DETAILS_PARTY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:from|by)\s+(\+?[0-9*]{9,13})\s*-\s*(.+?)(?:\s+acc(?:ount)?\.?(?:\s*no\.?)?\s+(\S.*?))?\s*$")
+        .unwrap()
+});
+
+/// Text copied from the PDF statement: each transaction starts with its
+/// receipt number and completion time; details that wrap onto following
+/// lines are joined back up. Withdrawals show as negative amounts.
+fn parse_text(text: &str) -> Result<Vec<StatementEntry>> {
+    let mut records: Vec<(String, String, String)> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if let Some(caps) = TEXT_LINE.captures(line) {
+            records.push((
+                caps[1].to_string(),
+                caps[2].to_string(),
+                caps[3].to_string(),
+            ));
+        } else if let Some((_, _, rest)) = records.last_mut() {
+            let lower = line.to_lowercase();
+            if !line.is_empty() && !lower.contains("receipt no") && !lower.starts_with("page") {
+                rest.push(' ');
+                rest.push_str(line);
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (code, completed_at, rest) in records {
+        let Some(caps) = STATUS_AMOUNTS.captures(&rest) else {
+            continue;
+        };
+        if !caps[1].eq_ignore_ascii_case("completed") {
+            continue;
+        }
+        let Some(amount) = statements::parse_amount(&caps[2]).filter(|a| *a > 0.0) else {
+            continue;
+        };
+        let whole = caps.get(0).map_or(0..0, |m| m.range());
+        let details = format!("{} {}", &rest[..whole.start], &rest[whole.end..])
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let (phone_number, payer_name, account_reference) = party_from_details(&details);
+        entries.push(StatementEntry {
+            transaction_code: code,
+            completed_at: statements::parse_datetime(&completed_at)?,
+            details,
+            amount,
+            phone_number,
+            payer_name,
+            account_reference,
+        });
+    }
+    Ok(entries)
+}
+
+/// `254712345678 - JANE WANJIKU` into phone and name.
+fn party(info: &str) -> (Option<String>, Option<String>) {
+    match PARTY.captures(info) {
+        Some(caps) => (Some(caps[1].to_string()), Some(caps[2].to_string())),
+        None => (None, Some(info.trim().to_string())),
+    }
+}
+
+/// Phone, name and account from details such as
+/// `Pay Bill from 254712345678 - JANE WANJIKU Acc. A1`.
+fn party_from_details(details: &str) -> (Option<String>, Option<String>, Option<String>) {
+    match DETAILS_PARTY.captures(details) {
+        Some(caps) => (
+            Some(caps[1].to_string()),
+            Some(caps[2].to_string()),
+            caps.get(3).map(|m| m.as_str().to_string()),
+        ),
+        None => (None, None, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The head of an organisation statement from the M-Pesa portal.
+    const PORTAL_CSV: &str = "\
+Organization Name:,SUNRISE APARTMENTS LTD
+Time Period:,01-01-2024 to 31-01-2024
+Receipt No.,Completion Time,Initiation Time,Details,Transaction Status,Paid In,Withdrawn,Balance,Balance Confirmed,Reason Type,Other Party Info,Linked Transaction ID,A/C No.
+RKTQDM7W6S,15-01-2024 14:30:00,15-01-2024 14:30:00,Pay Bill from 254712345678 - JANE WANJIKU Acc. A12,Completed,\"15,000.00\",,\"215,000.00\",true,Pay Bill,254712345678 - JANE WANJIKU,,A12
+RKTQDM7W7T,15-01-2024 15:02:11,15-01-2024 15:02:11,Business Payment to 600000 - KPLC,Completed,,\"-2,500.00\",\"212,500.00\",true,Business Payment,600000 - KPLC,,
+RKTQDM7W8U,16-01-2024 09:12:45,16-01-2024 09:12:45,Pay Bill from 2547*****321 - PETER O,Failed,\"9,000.00\",,\"212,500.00\",true,Pay Bill,2547*****321 - PETER O,,B4
+rktqdm7w9v,17-01-2024 08:00:00,17-01-2024 08:00:00,Pay Bill from 2547*****321 - PETER O,Completed,\"9,000.50\",,\"221,500.50\",true,Pay Bill,2547*****321 - PETER OTIENO,,
+Receipt No.,Completion Time,Initiation Time,Details,Transaction Status,Paid In,Withdrawn,Balance,Balance Confirmed,Reason Type,Other Party Info,Linked Transaction ID,A/C No.
+";
+
+    /// The same kind of lines copied out of the PDF statement, with details
+    /// that wrap onto the next line.
+    const PDF_TEXT: &str = "\
+MPESA FULL STATEMENT
+Receipt No. Completion Time Details Transaction Status Paid In Withdrawn Balance
+SAB1C2D3E4 2024-03-01 10:15:22 Pay Bill from 254722000111 - MARY
+ACHIENG Acc. B7 Completed 12,500.00 140,250.00
+SAB1C2D3E5 2024-03-01 11:00:00 Customer Transfer to 254700000000 - Completed -1,000.00 139,250.00
+JOHN DOE
+SAB1C2D3E6 2024-03-02 08:30 Pay Bill from 254733444555 - ALI HASSAN Reversed 5,000.00 144,250.00
+Page 1 of 3
+";
+
+    fn candidate(
+        tenant_id: i64,
+        name: &str,
+        phone: &str,
+        unit: &str,
+        rent: f64,
+    ) -> TenantCandidate {
+        TenantCandidate {
+            tenant_id,
+            full_name: name.into(),
+            phone_number: Some(phone.into()),
+            id_number: None,
+            unit_id: Some(tenant_id * 10),
+            unit_number: Some(unit.into()),
+            property_id: Some(1),
+            rent,
+            outstanding: 0.0,
+        }
+    }
+
+    fn entry(
+        phone: Option<&str>,
+        name: Option<&str>,
+        account: Option<&str>,
+        amount: f64,
+    ) -> StatementEntry {
+        StatementEntry {
+            transaction_code: "RKTQDM7W6S".into(),
+            completed_at: at("2024-01-15 14:30:00"),
+            details: String::new(),
+            amount,
+            phone_number: phone.map(str::to_string),
+            payer_name: name.map(str::to_string),
+            account_reference: account.map(str::to_string),
+        }
+    }
+
+    fn at(value: &str) -> NaiveDateTime {
+        statements::parse_datetime(value).unwrap()
+    }
+
+    #[test]
+    fn portal_csv_keeps_completed_money_in() {
+        let entries = parse_statement(PORTAL_CSV).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let jane = &entries[0];
+        assert_eq!(jane.transaction_code, "RKTQDM7W6S");
+        assert_eq!(jane.completed_at, at("2024-01-15 14:30:00"));
+        assert_eq!(jane.amount, 15_000.0);
+        assert_eq!(jane.phone_number.as_deref(), Some("254712345678"));
+        assert_eq!(jane.payer_name.as_deref(), Some("JANE WANJIKU"));
+        assert_eq!(jane.account_reference.as_deref(), Some("A12"));
+
+        // Lower-case codes are normalised; Other Party Info wins over details.
+        let peter = &entries[1];
+        assert_eq!(peter.transaction_code, "RKTQDM7W9V");
+        assert_eq!(peter.amount, 9_000.5);
+        assert_eq!(peter.phone_number.as_deref(), Some("2547*****321"));
+        assert_eq!(peter.payer_name.as_deref(), Some("PETER OTIENO"));
+        assert_eq!(peter.account_reference, None);
+    }
+
+    #[test]
+    fn portal_csv_needs_its_columns() {
+        let missing = "Receipt No.,Completion Time,Details\nRKTQDM7W6S,15-01-2024 14:30:00,x\n";
+        assert!(parse_statement(missing).is_err());
+    }
+
+    #[test]
+    fn pdf_text_joins_wrapped_details() {
+        let entries = parse_statement(PDF_TEXT).unwrap();
+        assert_eq!(entries.len(), 1);
+        let mary = &entries[0];
+        assert_eq!(mary.transaction_code, "SAB1C2D3E4");
+        assert_eq!(mary.amount, 12_500.0);
+        assert_eq!(
+            mary.details,
+            "Pay Bill from 254722000111 - MARY ACHIENG Acc. B7"
+        );
+        assert_eq!(mary.phone_number.as_deref(), Some("254722000111"));
+        assert_eq!(mary.payer_name.as_deref(), Some("MARY ACHIENG"));
+        assert_eq!(mary.account_reference.as_deref(), Some("B7"));
+    }
+
+    #[test]
+    fn phone_formats_compare_equal() {
+        assert_eq!(normalize_phone("0712 345 678"), "254712345678");
+        assert_eq!(normalize_phone("+254712345678"), "254712345678");
+        assert_eq!(normalize_phone("712345678"), "254712345678");
+        assert_eq!(
+            phone_match("254712345678", "0712345678"),
+            Some(PhoneMatch::Exact)
+        );
+        assert_eq!(
+            phone_match("2547*****678", "0712345678"),
+            Some(PhoneMatch::Masked)
+        );
+        assert_eq!(phone_match("2547*****679", "0712345678"), None);
+        assert_eq!(phone_match("12345", "12345"), None);
+    }
+
+    #[test]
+    fn hashed_phone_from_daraja_matches() {
+        let hashed = format!("{:x}", Sha256::digest(b"254712345678"));
+        assert_eq!(
+            phone_match(&hashed, "0712 345 678"),
+            Some(PhoneMatch::Exact)
+        );
+        assert_eq!(phone_match(&hashed, "0712345679"), None);
+    }
+
+    #[test]
+    fn strong_signals_add_up() {
+        let tenants = [
+            candidate(1, "Jane Wanjiku", "0712345678", "A12", 15_000.0),
+            candidate(2, "Peter Otieno", "0722000111", "B4", 9_000.0),
+        ];
+        let proposal = propose(
+            &entry(
+                Some("254712345678"),
+                Some("JANE WANJIKU"),
+                Some("a-12"),
+                15_000.0,
+            ),
+            &tenants,
+        )
+        .unwrap();
+        assert_eq!(proposal.tenant_id, 1);
+        assert_eq!(proposal.confidence, 1.0);
+        assert_eq!(
+            proposal.reasons,
+            ["phone", "account reference", "name", "amount"]
+        );
+
+        let proposal = propose(&entry(Some("2547*****111"), None, None, 1.0), &tenants).unwrap();
+        assert_eq!(proposal.tenant_id, 2);
+        assert_eq!(proposal.confidence, 0.3);
+        assert_eq!(proposal.reasons, ["masked phone"]);
+    }
+
+    #[test]
+    fn amount_alone_is_weak_and_must_be_unique() {
+        let tenants = [
+            candidate(1, "Jane Wanjiku", "0712345678", "A12", 15_000.0),
+            candidate(2, "Peter Otieno", "0722000111", "B4", 9_000.0),
+            candidate(3, "Ali Hassan", "0733444555", "B5", 9_000.0),
+        ];
+        let proposal = propose(&entry(None, None, None, 15_000.0), &tenants).unwrap();
+        assert_eq!(
+            (proposal.tenant_id, proposal.confidence),
+            (1, MIN_CONFIDENCE)
+        );
+        assert_eq!(propose(&entry(None, None, None, 9_000.0), &tenants), None);
+        assert_eq!(propose(&entry(None, None, None, 4_321.0), &tenants), None);
+    }
+
+    #[test]
+    fn ties_stay_below_auto_accept() {
+        // Two tenants sharing a phone, e.g. a parent paying for two units.
+        let tenants = [
+            candidate(1, "Jane Wanjiku", "0712345678", "A12", 15_000.0),
+            candidate(2, "Tom Wanjiku", "0712345678", "A14", 15_000.0),
+        ];
+        let proposal =
+            propose(&entry(Some("254712345678"), None, None, 15_000.0), &tenants).unwrap();
+        assert!(proposal.confidence < AUTO_ACCEPT_CONFIDENCE);
+        assert!(proposal.reasons.contains(&"ambiguous"));
+    }
+
+    #[test]
+    fn initials_do_not_count_as_names() {
+        assert_eq!(name_overlap("PETER O", "Peter Otieno"), 1.0);
+        assert_eq!(name_overlap("J W", "Jane Wanjiku"), 0.0);
+        assert_eq!(name_overlap("JOHN DOE", "Jane Wanjiku"), 0.0);
+    }
+}
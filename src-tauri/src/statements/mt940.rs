@@ -0,0 +1,157 @@
+use std::sync::LazyLock;
+
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::error::{Error, Result};
+use crate::statements::bank::{BankLine, ParsedStatement};
+use crate::statements::parse_amount;
+
+/// `:61:` value date, optional entry date, debit/credit mark, optional funds
+/// code, amount with a decimal comma, transaction type, customer reference
+/// and optional `//` bank reference.
+static STATEMENT_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(\d{6})(\d{4})?(RC|RD|C|D)[A-Z]?(\d+,\d*)([NFS][A-Z0-9]{3})([^/\n]*)(?://([^\n]*))?",
+    )
+    .unwrap()
+});
+
+/// Parses a SWIFT MT940 customer statement. Several statements in one file
+/// are read as one; the account comes from the first `:25:` field.
+pub fn parse(text: &str) -> Result<ParsedStatement> {
+    let mut account = None;
+    let mut lines: Vec<BankLine> = Vec::new();
+
+    for (tag, value) in fields(text) {
+        match tag.as_str() {
+            "25" if account.is_none() => account = Some(value.trim().to_string()),
+            "61" => {
+                let caps = STATEMENT_LINE.captures(&value).ok_or_else(|| {
+                    Error::InvalidInput(format!("unrecognised MT940 statement line '{value}'"))
+                })?;
+                let posted_on = NaiveDate::parse_from_str(&format!("20{}", &caps[1]), "%Y%m%d")
+                    .map_err(|_| Error::InvalidInput(format!("bad MT940 date '{}'", &caps[1])))?;
+                let amount = parse_amount(&caps[4].replace(',', ".")).ok_or_else(|| {
+                    Error::InvalidInput(format!("bad MT940 amount '{}'", &caps[4]))
+                })?;
+                // A reversed credit takes money out; a reversed debit puts it back.
+                let amount = match &caps[3] {
+                    "C" | "RD" => amount,
+                    _ => -amount,
+                };
+                let reference = caps[6].trim();
+                let supplementary = value.split_once('\n').map(|(_, s)| s.trim());
+                lines.push(BankLine {
+                    posted_on,
+                    amount,
+                    description: supplementary.unwrap_or_default().to_string(),
+                    reference: (!reference.is_empty() && reference != "NONREF")
+                        .then(|| reference.to_string()),
+                    check_number: (&caps[5] == "NCHK").then(|| reference.to_string()),
+                    external_id: caps.get(7).map(|m| m.as_str().trim().to_string()),
+                });
+            }
+            // Narrative for the preceding :61: line.
+            "86" => {
+                if let Some(line) = lines.last_mut() {
+                    let narrative = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                    line.description = if line.description.is_empty() {
+                        narrative
+                    } else {
+                        format!("{} {narrative}", line.description)
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(ParsedStatement { account, lines })
+}
+
+/// Splits the message into `(tag, value)` pairs; a value runs until the next
+/// line that starts a tag, so multi-line `:86:` fields stay together.
+fn fields(text: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end();
+        let tag = line
+            .strip_prefix(':')
+            .and_then(|rest| rest.split_once(':'))
+            .filter(|(tag, _)| {
+                (2..=3).contains(&tag.len()) && tag.starts_with(|c: char| c.is_ascii_digit())
+            });
+        match tag {
+            Some((tag, value)) => fields.push((tag[..2].to_string(), value.to_string())),
+            None if line.starts_with('-') || line.starts_with('{') => {}
+            None => {
+                if let Some((_, value)) = fields.last_mut() {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MT940: &str = "\
+{1:F01EQBLKENAXXXX0000000000}{2:I940EQBLKENAXXXXN}{4:
+:20:STMT240131
+:25:0123456789012
+:28C:00012/001
+:60F:C240101KES125000,00
+:61:2401150115C15000,00NTRFNONREF//FT24015ABCDE
+:86:FROM JANE WANJIKU
+RENT A12 JAN
+:61:240116D2500,NCHK000123//CHQ123
+:86:PLUMB AND CO
+:61:240117RD1000,50NTRFREV001
+:61:240118RC200,NMSCCHARGE
+:62F:C240131KES136701,50
+-}";
+
+    #[test]
+    fn statement_lines_and_signs() {
+        let statement = parse(MT940).unwrap();
+        assert_eq!(statement.account.as_deref(), Some("0123456789012"));
+        let lines = &statement.lines;
+        assert_eq!(lines.len(), 4);
+
+        assert_eq!(lines[0].posted_on.to_string(), "2024-01-15");
+        assert_eq!(lines[0].amount, 15_000.0);
+        assert_eq!(lines[0].description, "FROM JANE WANJIKU RENT A12 JAN");
+        assert_eq!(lines[0].reference, None);
+        assert_eq!(lines[0].external_id.as_deref(), Some("FT24015ABCDE"));
+
+        // Trailing decimal comma, cheque number from the reference.
+        assert_eq!(lines[1].amount, -2_500.0);
+        assert_eq!(lines[1].check_number.as_deref(), Some("000123"));
+        assert_eq!(lines[1].reference.as_deref(), Some("000123"));
+
+        // Reversals flip the sign; lines without :86: keep an empty narrative.
+        assert_eq!(lines[2].amount, 1_000.5);
+        assert_eq!(lines[2].description, "");
+        assert_eq!(lines[2].external_id, None);
+        assert_eq!(lines[3].amount, -200.0);
+    }
+
+    #[test]
+    fn supplementary_details_lead_the_narrative() {
+        let text = ":25:1\n:61:240201C50,00NTRF123\nSUPPLEMENTARY\n:86:NARRATIVE\n";
+        let statement = parse(text).unwrap();
+        assert_eq!(statement.lines[0].description, "SUPPLEMENTARY NARRATIVE");
+        assert_eq!(statement.lines[0].reference.as_deref(), Some("123"));
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        assert!(parse(":25:1\n:61:2402C50,00NTRF\n").is_err());
+        assert!(parse(":25:1\n:61:241301C50,00NTRF\n").is_err());
+        assert!(parse(":25:1\n:61:240201X50,00NTRF\n").is_err());
+    }
+}
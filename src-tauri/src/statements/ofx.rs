@@ -0,0 +1,168 @@
+use crate::error::{Error, Result};
+use crate::statements::bank::{BankLine, ParsedStatement};
+use crate::statements::parse_amount;
+
+/// Parses OFX 1.x (SGML, leaf elements unclosed) and 2.x (XML) statements.
+/// Only the bank transaction list and account id are read.
+pub fn parse(text: &str) -> Result<ParsedStatement> {
+    let account = element(text, "ACCTID");
+    let upper = text.to_ascii_uppercase();
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while let Some(found) = upper[offset..].find("<STMTTRN>") {
+        let start = offset + found + "<STMTTRN>".len();
+        // Some SGML exports leave the aggregate unclosed too; it then ends
+        // where the next transaction or the list does.
+        let end = ["</STMTTRN>", "<STMTTRN>", "</BANKTRANLIST>"]
+            .iter()
+            .filter_map(|tag| upper[start..].find(tag))
+            .min()
+            .map_or(text.len(), |end| start + end);
+        let transaction = &text[start..end];
+        offset = end;
+
+        let posted = element(transaction, "DTPOSTED")
+            .ok_or_else(|| Error::InvalidInput("OFX transaction without DTPOSTED".into()))?;
+        let amount = element(transaction, "TRNAMT")
+            .as_deref()
+            .and_then(parse_amount)
+            .ok_or_else(|| Error::InvalidInput("OFX transaction without TRNAMT".into()))?;
+        let description = [element(transaction, "NAME"), element(transaction, "MEMO")]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        lines.push(BankLine {
+            posted_on: super::parse_datetime(posted.get(..8).unwrap_or(&posted))?.date(),
+            amount,
+            description,
+            reference: element(transaction, "REFNUM"),
+            check_number: element(transaction, "CHECKNUM"),
+            external_id: element(transaction, "FITID"),
+        });
+    }
+    Ok(ParsedStatement { account, lines })
+}
+
+/// Case-insensitive search, since some banks emit lower-case tags.
+fn find_tag(haystack: &str, tag: &str) -> Option<usize> {
+    haystack.to_ascii_uppercase().find(tag)
+}
+
+/// Text of the first `<name>` element: up to the next tag, which is its
+/// closing tag in XML and the next element in SGML.
+fn element(text: &str, name: &str) -> Option<String> {
+    let open = format!("<{name}>");
+    let start = find_tag(text, &open)? + open.len();
+    let value = &text[start..];
+    let value = value[..value.find('<').unwrap_or(value.len())].trim();
+    (!value.is_empty()).then(|| {
+        value
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    const OFX_SGML: &str = "\
+OFXHEADER:100
+DATA:OFXSGML
+VERSION:102
+ENCODING:USASCII
+
+<OFX>
+<SIGNONMSGSRSV1><SONRS><STATUS><CODE>0<SEVERITY>INFO</STATUS><DTSERVER>20240201</SONRS></SIGNONMSGSRSV1>
+<BANKMSGSRSV1><STMTTRNRS><TRNUID>1<STMTRS><CURDEF>KES
+<BANKACCTFROM><BANKID>01<ACCTID>0123456789<ACCTTYPE>CHECKING</BANKACCTFROM>
+<BANKTRANLIST><DTSTART>20240101<DTEND>20240131
+<STMTTRN>
+<TRNTYPE>CREDIT
+<DTPOSTED>20240115120000.000[+3:EAT]
+<TRNAMT>15000.00
+<FITID>2024011501
+<NAME>JANE WANJIKU
+<MEMO>RENT A12
+</STMTTRN>
+<STMTTRN>
+<TRNTYPE>CHECK
+<DTPOSTED>20240116
+<TRNAMT>-2,500.00
+<FITID>2024011602
+<CHECKNUM>000123
+<REFNUM>CHQ123
+<NAME>PLUMB &amp; CO
+</STMTTRN>
+</BANKTRANLIST>
+<LEDGERBAL><BALAMT>137500.00<DTASOF>20240131</LEDGERBAL>
+</STMTRS></STMTTRNRS></BANKMSGSRSV1>
+</OFX>
+";
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn sgml_leaf_elements_without_close_tags() {
+        let statement = parse(OFX_SGML).unwrap();
+        assert_eq!(statement.account.as_deref(), Some("0123456789"));
+        assert_eq!(
+            statement.lines,
+            [
+                BankLine {
+                    posted_on: date("2024-01-15"),
+                    amount: 15_000.0,
+                    description: "JANE WANJIKU RENT A12".into(),
+                    reference: None,
+                    check_number: None,
+                    external_id: Some("2024011501".into()),
+                },
+                BankLine {
+                    posted_on: date("2024-01-16"),
+                    amount: -2_500.0,
+                    description: "PLUMB & CO".into(),
+                    reference: Some("CHQ123".into()),
+                    check_number: Some("000123".into()),
+                    external_id: Some("2024011602".into()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_transactions_end_at_the_next_one() {
+        let text = OFX_SGML.replace("</STMTTRN>\n", "");
+        let statement = parse(&text).unwrap();
+        assert_eq!(statement.lines.len(), 2);
+        assert_eq!(statement.lines[0].description, "JANE WANJIKU RENT A12");
+        assert_eq!(statement.lines[1].amount, -2_500.0);
+    }
+
+    #[test]
+    fn xml_with_lower_case_tags() {
+        let text = r#"<?xml version="1.0"?><?OFX OFXHEADER="200" VERSION="220"?>
+<ofx><bankmsgsrsv1><stmttrnrs><stmtrs>
+<bankacctfrom><acctid>555</acctid></bankacctfrom>
+<banktranlist><stmttrn><trntype>DEBIT</trntype><dtposted>20240105</dtposted>
+<trnamt>-99.95</trnamt><fitid>A1</fitid><memo>Bank &lt;charges&gt;</memo></stmttrn></banktranlist>
+</stmtrs></stmttrnrs></bankmsgsrsv1></ofx>"#;
+        let statement = parse(text).unwrap();
+        assert_eq!(statement.account.as_deref(), Some("555"));
+        assert_eq!(statement.lines.len(), 1);
+        assert_eq!(statement.lines[0].amount, -99.95);
+        assert_eq!(statement.lines[0].description, "Bank <charges>");
+    }
+
+    #[test]
+    fn missing_fields_are_errors() {
+        assert!(parse("<OFX><STMTTRN><TRNAMT>10.00</STMTTRN></OFX>").is_err());
+        assert!(parse("<OFX><STMTTRN><DTPOSTED>20240105</STMTTRN></OFX>").is_err());
+        assert!(parse("<OFX></OFX>").unwrap().lines.is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::period::{self, Month};
+
+/// Dashboard KPIs for a range of months.
+///
+/// Collections and expenses come from `payment_monthly_summary` and
+/// `expense_monthly_summary`, which triggers keep up to date on every write to
+/// `payments` and `expenses`; arrears come from the paid totals the allocation
+/// triggers keep on `charges`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsCards {
+    pub from: Month,
+    pub to: Month,
+    pub property_id: Option<i64>,
+    /// Paid payments dated within the period.
+    pub collected: f64,
+    /// Unpaid part of open charges due on or before the end of the period.
+    pub outstanding: f64,
+    pub total_units: i64,
+    pub occupied_units: i64,
+    /// Occupied units as a percentage of all units, `0.0` when there are none.
+    pub occupancy_rate: f64,
+    /// Expenses dated within the period.
+    pub expenses: f64,
+    /// Open charges already past their due date.
+    pub overdue_count: i64,
+}
+
+#[tauri::command]
+pub async fn get_stats_cards(
+    app: AppHandle,
+    from: Option<Month>,
+    to: Option<Month>,
+    property_id: Option<i64>,
+) -> Result<StatsCards> {
+    let to = to.unwrap_or_else(Month::current);
+    let from = from.unwrap_or(to);
+    if from > to {
+        return Err(Error::InvalidInput(format!(
+            "period start {from} is after period end {to}"
+        )));
+    }
+    let pool = db::pool(&app).await?;
+    stats_cards(&pool, from, to, property_id).await
+}
+
+pub async fn stats_cards(
+    pool: &SqlitePool,
+    from: Month,
+    to: Month,
+    property_id: Option<i64>,
+) -> Result<StatsCards> {
+    let (from_key, to_key) = (from.to_string(), to.to_string());
+
+    let (collected,): (f64,) = sqlx::query_as(
+        "SELECT CAST(COALESCE(SUM(total_amount), 0) AS REAL) FROM payment_monthly_summary
+         WHERE payment_status = 'Paid' AND period BETWEEN ?1 AND ?2
+           AND (?3 IS NULL OR property_id = ?3)",
+    )
+    .bind(&from_key)
+    .bind(&to_key)
+    .bind(property_id)
+    .fetch_one(pool)
+    .await?;
+
+    // Charges carry partial payments and credits, so outstanding is what is
+    // actually left to collect. `charge_balances` reads the stored paid totals,
+    // so this is a range scan of idx_charges_status_due.
+    let (outstanding, overdue_count): (f64, i64) = sqlx::query_as(
+        "SELECT CAST(COALESCE(SUM(outstanding), 0) AS REAL),
+                COALESCE(SUM(CASE WHEN due_date < ?2 THEN 1 END), 0)
+         FROM charge_balances
+         WHERE status = 'Open' AND outstanding > 0.005 AND due_date <= ?1
+           AND (?3 IS NULL OR property_id = ?3)",
+    )
+    .bind(to.last_day().to_string())
+    .bind(period::today().min(to.last_day()).to_string())
+    .bind(property_id)
+    .fetch_one(pool)
+    .await?;
+
+    let (expenses,): (f64,) = sqlx::query_as(
+        "SELECT CAST(COALESCE(SUM(total_amount), 0) AS REAL) FROM expense_monthly_summary
+         WHERE period BETWEEN ?1 AND ?2 AND (?3 IS NULL OR property_id = ?3)",
+    )
+    .bind(&from_key)
+    .bind(&to_key)
+    .bind(property_id)
+    .fetch_one(pool)
+    .await?;
+
+    let (total_units, occupied_units): (i64, i64) = sqlx::query_as(
+        "SELECT COUNT(*),
+                COALESCE(SUM(CASE WHEN lower(unit_status) = 'occupied' THEN 1 END), 0)
+         FROM units WHERE (?1 IS NULL OR property_id = ?1)",
+    )
+    .bind(property_id)
+    .fetch_one(pool)
+    .await?;
+
+    let occupancy_rate = if total_units == 0 {
+        0.0
+    } else {
+        occupied_units as f64 * 100.0 / total_units as f64
+    };
+
+    Ok(StatsCards {
+        from,
+        to,
+        property_id,
+        collected,
+        outstanding,
+        total_units,
+        occupied_units,
+        occupancy_rate,
+        expenses,
+        overdue_count,
+    })
+}
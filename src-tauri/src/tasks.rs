@@ -0,0 +1,45 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteExecutor;
+
+use crate::error::Result;
+
+/// Values the dashboard understands for `tasks.priority`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskPriority {
+    Low,
+    Medium,
+    High,
+}
+
+impl TaskPriority {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TaskPriority::Low => "low",
+            TaskPriority::Medium => "medium",
+            TaskPriority::High => "high",
+        }
+    }
+}
+
+/// Adds a task unless one with the same name exists (`task_name` is unique),
+/// so generators can run repeatedly without piling up duplicates.
+///
+/// Returns whether a new task was inserted.
+pub async fn create_task<'e>(
+    executor: impl SqliteExecutor<'e>,
+    name: &str,
+    due: NaiveDate,
+    priority: TaskPriority,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "INSERT OR IGNORE INTO tasks (task_name, due_date, priority) VALUES (?1, ?2, ?3)",
+    )
+    .bind(name)
+    .bind(due.to_string())
+    .bind(priority.as_str())
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,371 @@
+//! The letter template language.
+//!
+//! Templates are plain text. `{{tenant.name}}` inserts a field, and
+//! `{{#if notice.reason}}...{{else}}...{{/if}}` keeps a section only when the
+//! field has a value; the `{{else}}` part is optional and sections nest. After
+//! filling in, a line starting with `# ` is a heading and blank lines separate
+//! paragraphs.
+
+use std::collections::HashMap;
+
+use crate::error::{Error, Result};
+use crate::export::{Block, Letter};
+
+/// Printed where a field has no value, so it can be filled in by hand.
+pub const BLANK: &str = "____________";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Field(String),
+    If {
+        field: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+enum Tag<'a> {
+    Field(&'a str),
+    If(&'a str),
+    Else,
+    EndIf,
+}
+
+impl Template {
+    pub fn parse(body: &str) -> Result<Self> {
+        match parse_nodes(body, &mut 0)? {
+            (nodes, End::Eof) => Ok(Self { nodes }),
+            _ => Err(Error::InvalidInput(
+                "{{else}} or {{/if}} without a matching {{#if}}".into(),
+            )),
+        }
+    }
+
+    /// Every field the template refers to, in order of first use.
+    pub fn fields(&self) -> Vec<&str> {
+        fn walk<'a>(nodes: &'a [Node], out: &mut Vec<&'a str>) {
+            for node in nodes {
+                match node {
+                    Node::Text(_) => {}
+                    Node::Field(name) => push(out, name),
+                    Node::If {
+                        field,
+                        then,
+                        otherwise,
+                    } => {
+                        push(out, field);
+                        walk(then, out);
+                        walk(otherwise, out);
+                    }
+                }
+            }
+        }
+        fn push<'a>(out: &mut Vec<&'a str>, name: &'a str) {
+            if !out.contains(&name) {
+                out.push(name);
+            }
+        }
+        let mut out = Vec::new();
+        walk(&self.nodes, &mut out);
+        out
+    }
+
+    /// Fills in the fields. A field missing from `values` or empty prints
+    /// as [`BLANK`].
+    pub fn render(&self, values: &HashMap<String, String>) -> String {
+        fn walk(nodes: &[Node], values: &HashMap<String, String>, out: &mut String) {
+            for node in nodes {
+                match node {
+                    Node::Text(text) => out.push_str(text),
+                    Node::Field(name) => match values.get(name).filter(|v| !v.is_empty()) {
+                        Some(value) => out.push_str(value),
+                        None => out.push_str(BLANK),
+                    },
+                    Node::If {
+                        field,
+                        then,
+                        otherwise,
+                    } => {
+                        let set = values.get(field).is_some_and(|v| !v.trim().is_empty());
+                        walk(if set { then } else { otherwise }, values, out);
+                    }
+                }
+            }
+        }
+        let mut out = String::new();
+        walk(&self.nodes, values, &mut out);
+        out
+    }
+}
+
+/// How a run of nodes ended.
+enum End {
+    Eof,
+    Else,
+    Close,
+}
+
+/// Parses from `*pos` up to the end of `body` or the next `{{else}}` or
+/// `{{/if}}`.
+fn parse_nodes(body: &str, pos: &mut usize) -> Result<(Vec<Node>, End)> {
+    let mut nodes = Vec::new();
+    let push_text = |nodes: &mut Vec<Node>, text: &str| {
+        if !text.is_empty() {
+            nodes.push(Node::Text(text.to_string()));
+        }
+    };
+    loop {
+        let rest = &body[*pos..];
+        let Some(open) = rest.find("{{") else {
+            push_text(&mut nodes, rest);
+            *pos = body.len();
+            return Ok((nodes, End::Eof));
+        };
+        let Some(close) = rest[open..].find("}}") else {
+            return Err(Error::InvalidInput("a {{ is never closed with }}".into()));
+        };
+        let tag = parse_tag(&rest[open + 2..open + close])?;
+        let (mut text_end, mut next) = (*pos + open, *pos + open + close + 2);
+        if !matches!(tag, Tag::Field(_)) {
+            if let Some((line_start, line_end)) = standalone(body, text_end, next) {
+                (text_end, next) = (line_start, line_end);
+            }
+        }
+        push_text(&mut nodes, &body[*pos..text_end]);
+        *pos = next;
+        match tag {
+            Tag::Field(name) => nodes.push(Node::Field(name.to_string())),
+            Tag::If(field) => {
+                let unclosed = || {
+                    Error::InvalidInput(format!(
+                        "{{{{#if {field}}}}} is never closed with {{{{/if}}}}"
+                    ))
+                };
+                let (then, end) = parse_nodes(body, pos)?;
+                let otherwise = match end {
+                    End::Eof => return Err(unclosed()),
+                    End::Close => Vec::new(),
+                    End::Else => match parse_nodes(body, pos)? {
+                        (otherwise, End::Close) => otherwise,
+                        (_, End::Else) => {
+                            return Err(Error::InvalidInput(format!(
+                                "{{{{#if {field}}}}} has more than one {{{{else}}}}"
+                            )))
+                        }
+                        (_, End::Eof) => return Err(unclosed()),
+                    },
+                };
+                nodes.push(Node::If {
+                    field: field.to_string(),
+                    then,
+                    otherwise,
+                });
+            }
+            Tag::Else => return Ok((nodes, End::Else)),
+            Tag::EndIf => return Ok((nodes, End::Close)),
+        }
+    }
+}
+
+/// A section tag alone on its line takes the whole line with it, so
+/// `{{#if}}` and `{{/if}}` can sit on lines of their own. Returns the start
+/// of that line and the start of the next.
+fn standalone(body: &str, start: usize, end: usize) -> Option<(usize, usize)> {
+    let line_start = body[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = body[end..].find('\n').map_or(body.len(), |i| end + i + 1);
+    let blank = |s: &str| s.trim().is_empty();
+    (blank(&body[line_start..start]) && blank(&body[end..line_end]))
+        .then_some((line_start, line_end))
+}
+
+fn parse_tag(inner: &str) -> Result<Tag<'_>> {
+    let inner = inner.trim();
+    let tag = match inner {
+        "else" => Tag::Else,
+        "/if" => Tag::EndIf,
+        _ => match inner.strip_prefix("#if") {
+            Some(field) if field.starts_with(char::is_whitespace) => Tag::If(field.trim()),
+            Some(_) => return Err(Error::InvalidInput(format!("unknown tag {{{{{inner}}}}}"))),
+            None if inner.starts_with(['#', '/']) => {
+                return Err(Error::InvalidInput(format!("unknown tag {{{{{inner}}}}}")))
+            }
+            None => Tag::Field(inner),
+        },
+    };
+    if let Tag::Field(name) | Tag::If(name) = tag {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        if !valid {
+            return Err(Error::InvalidInput(format!("'{name}' is not a field name")));
+        }
+    }
+    Ok(tag)
+}
+
+/// Splits filled-in text into headings and paragraphs.
+pub fn to_letter(text: &str) -> Letter {
+    let mut letter = Letter::default();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let flush = |paragraph: &mut Vec<&str>, letter: &mut Letter| {
+        if !paragraph.is_empty() {
+            letter.blocks.push(Block::Paragraph(paragraph.join("\n")));
+            paragraph.clear();
+        }
+    };
+    for line in text.lines().map(str::trim_end) {
+        if let Some(heading) = line.strip_prefix("# ") {
+            flush(&mut paragraph, &mut letter);
+            letter
+                .blocks
+                .push(Block::Heading(heading.trim().to_string()));
+        } else if line.trim().is_empty() {
+            flush(&mut paragraph, &mut letter);
+        } else {
+            paragraph.push(line);
+        }
+    }
+    flush(&mut paragraph, &mut letter);
+    letter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn render(body: &str, pairs: &[(&str, &str)]) -> String {
+        Template::parse(body).unwrap().render(&values(pairs))
+    }
+
+    #[test]
+    fn fills_in_fields() {
+        assert_eq!(
+            render(
+                "Dear {{tenant.name}}, rent for {{ unit.number }} is {{lease.rent}}.",
+                &[
+                    ("tenant.name", "Jane Wanjiku"),
+                    ("unit.number", "A1"),
+                    ("lease.rent", "25,000.00")
+                ],
+            ),
+            "Dear Jane Wanjiku, rent for A1 is 25,000.00."
+        );
+        assert_eq!(render("No fields at all.", &[]), "No fields at all.");
+    }
+
+    #[test]
+    fn missing_and_empty_fields_print_a_blank() {
+        assert_eq!(
+            render(
+                "ID {{tenant.id_number}}, phone {{tenant.phone}}",
+                &[("tenant.phone", "")]
+            ),
+            format!("ID {BLANK}, phone {BLANK}")
+        );
+    }
+
+    #[test]
+    fn values_and_stray_braces_are_kept_as_written() {
+        // A value is inserted as is, never read as template text.
+        assert_eq!(
+            render(
+                "Reason: {{notice.reason}}",
+                &[("notice.reason", "{{tenant.name}} <b>&</b>")]
+            ),
+            "Reason: {{tenant.name}} <b>&</b>"
+        );
+        assert_eq!(render("a { b } c}}", &[]), "a { b } c}}");
+    }
+
+    #[test]
+    fn keeps_a_section_only_when_its_field_is_set() {
+        let body = "Notice{{#if notice.reason}} because {{notice.reason}}{{else}} given{{/if}}.";
+        assert_eq!(
+            render(body, &[("notice.reason", "renovations")]),
+            "Notice because renovations."
+        );
+        assert_eq!(render(body, &[]), "Notice given.");
+        // Whitespace counts as unset.
+        assert_eq!(render(body, &[("notice.reason", "  ")]), "Notice given.");
+        assert_eq!(render("a{{#if x}}b{{/if}}c", &[]), "ac");
+    }
+
+    #[test]
+    fn sections_nest() {
+        let body = "{{#if a}}A{{#if b}}B{{else}}-{{/if}}{{else}}none{{/if}}";
+        assert_eq!(render(body, &[("a", "1"), ("b", "1")]), "AB");
+        assert_eq!(render(body, &[("a", "1")]), "A-");
+        assert_eq!(render(body, &[("b", "1")]), "none");
+    }
+
+    #[test]
+    fn section_tags_on_their_own_lines_take_the_line() {
+        let body = "Dear tenant,\n  {{#if notice.reason}}  \nReason: {{notice.reason}}\n{{/if}}\nRegards\n";
+        assert_eq!(
+            render(body, &[("notice.reason", "arrears")]),
+            "Dear tenant,\nReason: arrears\nRegards\n"
+        );
+        assert_eq!(render(body, &[]), "Dear tenant,\nRegards\n");
+    }
+
+    #[test]
+    fn lists_fields_once_in_order() {
+        let template = Template::parse(
+            "{{tenant.name}} {{#if notice.reason}}{{notice.reason}}{{else}}{{tenant.name}}{{today}}{{/if}}",
+        )
+        .unwrap();
+        assert_eq!(template.fields(), ["tenant.name", "notice.reason", "today"]);
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for bad in [
+            "{{tenant.name",
+            "{{}}",
+            "{{tenant name}}",
+            "{{tenant-name}}",
+            "{{#each tenants}}{{/each}}",
+            "{{#ifx}}{{/if}}",
+            "{{/each}}",
+            "{{#if a}}open",
+            "{{#if a}}x{{else}}y",
+            "{{#if a}}x{{else}}y{{else}}z{{/if}}",
+            "stray {{/if}}",
+            "stray {{else}}",
+        ] {
+            assert!(
+                matches!(Template::parse(bad), Err(Error::InvalidInput(_))),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn splits_text_into_headings_and_paragraphs() {
+        let letter = to_letter(
+            "# NOTICE TO VACATE \n\nDear Jane,\nPlease note.   \n\n\n#not a heading\nEnd",
+        );
+        assert_eq!(
+            letter.blocks,
+            [
+                Block::Heading("NOTICE TO VACATE".into()),
+                Block::Paragraph("Dear Jane,\nPlease note.".into()),
+                Block::Paragraph("#not a heading\nEnd".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,333 @@
+//! The fields a letter template can use and where their values come from.
+
+use std::collections::HashMap;
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use super::TemplateKind;
+use crate::error::{Error, Result};
+use crate::period;
+use crate::settings;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Field {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The only kind of letter the field makes sense in, if any.
+    pub kind: Option<TemplateKind>,
+}
+
+const fn field(name: &'static str, description: &'static str) -> Field {
+    Field {
+        name,
+        description,
+        kind: None,
+    }
+}
+
+const fn only(kind: TemplateKind, name: &'static str, description: &'static str) -> Field {
+    Field {
+        name,
+        description,
+        kind: Some(kind),
+    }
+}
+
+pub const FIELDS: &[Field] = &[
+    field("today", "Date the letter is generated"),
+    field(
+        "landlord.name",
+        "Setting letters.landlord_name, or else the property name",
+    ),
+    field("lease.id", "Lease number"),
+    field("lease.start_date", "First day of the lease"),
+    field("lease.end_date", "Last day of the lease"),
+    field("lease.term_months", "Length of the lease in whole months"),
+    field("lease.rent", "Monthly rent, e.g. 25,000.00"),
+    field("lease.deposit", "Deposit paid"),
+    field("tenant.name", "Tenant's full name"),
+    field("tenant.id_number", "National ID or passport number"),
+    field("tenant.phone", "Tenant's phone number"),
+    field("tenant.email", "Tenant's email address"),
+    field("unit.number", "Unit number"),
+    field("unit.type", "Unit type, e.g. 2 Bedroom"),
+    field("unit.floor", "Floor number"),
+    field("unit.bedrooms", "Number of bedrooms"),
+    field("unit.bathrooms", "Number of bathrooms"),
+    field("block.name", "Block the unit is in"),
+    field("property.name", "Property name"),
+    field("property.address", "Property address"),
+    field("manager.name", "Property manager's name"),
+    field("manager.phone", "Property manager's phone number"),
+    field("manager.email", "Property manager's email address"),
+    only(
+        TemplateKind::NoticeToVacate,
+        "notice.vacate_date",
+        "Date the tenant must leave by",
+    ),
+    only(
+        TemplateKind::NoticeToVacate,
+        "notice.reason",
+        "Reason for the notice",
+    ),
+    only(
+        TemplateKind::RentIncrease,
+        "increase.new_rent",
+        "Monthly rent after the increase",
+    ),
+    only(
+        TemplateKind::RentIncrease,
+        "increase.effective_date",
+        "First day the new rent applies",
+    ),
+    only(
+        TemplateKind::RentIncrease,
+        "increase.amount",
+        "New rent less the current rent",
+    ),
+    only(
+        TemplateKind::RentIncrease,
+        "increase.percent",
+        "Increase as a percentage of the current rent",
+    ),
+];
+
+/// Fields available to `kind` of letter.
+pub fn for_kind(kind: TemplateKind) -> Vec<&'static Field> {
+    FIELDS
+        .iter()
+        .filter(|field| field.kind.is_none_or(|only| only == kind))
+        .collect()
+}
+
+/// What the user types in when generating a notice or rent increase letter.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LetterInputs {
+    #[serde(default)]
+    pub vacate_date: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub new_rent: Option<f64>,
+    #[serde(default)]
+    pub effective_date: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct LeaseRow {
+    lease_id: i64,
+    lease_start_date: String,
+    lease_end_date: String,
+    rent_amount: Option<f64>,
+    deposit_paid: Option<f64>,
+    full_name: String,
+    id_number: Option<String>,
+    phone_number: Option<String>,
+    tenant_email: Option<String>,
+    unit_number: String,
+    unit_type: String,
+    floor_number: Option<i64>,
+    bedroom_count: Option<f64>,
+    bathroom_count: Option<f64>,
+    block_name: Option<String>,
+    property_name: String,
+    address: String,
+    manager_name: Option<String>,
+    manager_phone: Option<String>,
+    manager_email: Option<String>,
+}
+
+/// Values of every field for `lease_id`. Fields with no value are left out
+/// and print as a blank to fill in.
+pub async fn values(
+    pool: &SqlitePool,
+    lease_id: i64,
+    inputs: &LetterInputs,
+    today: NaiveDate,
+) -> Result<HashMap<String, String>> {
+    let row: Option<LeaseRow> = sqlx::query_as(
+        "SELECT l.lease_id, l.lease_start_date, l.lease_end_date,
+                CAST(l.rent_amount AS REAL) AS rent_amount,
+                CAST(l.deposit_paid AS REAL) AS deposit_paid,
+                t.full_name, t.id_number, t.phone_number, t.email AS tenant_email,
+                u.unit_number, u.unit_type, u.floor_number,
+                CAST(u.bedroom_count AS REAL) AS bedroom_count,
+                CAST(u.bathroom_count AS REAL) AS bathroom_count,
+                b.block_name, p.name AS property_name, p.address,
+                m.name AS manager_name, m.phone AS manager_phone, m.email AS manager_email
+         FROM leases l
+         JOIN tenants t ON t.tenant_id = l.tenant_id
+         JOIN units u ON u.unit_id = l.unit_id
+         JOIN properties p ON p.property_id = u.property_id
+         LEFT JOIN blocks b ON b.block_id = u.block_id
+         LEFT JOIN managers m ON m.manager_id = p.manager_id
+         WHERE l.lease_id = ?1",
+    )
+    .bind(lease_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else {
+        return Err(Error::NotFound(format!("lease {lease_id}")));
+    };
+
+    let mut values = HashMap::new();
+    let mut set = |name: &str, value: Option<String>| {
+        if let Some(value) = value.filter(|v| !v.trim().is_empty()) {
+            values.insert(name.to_string(), value);
+        }
+    };
+    let landlord = settings::get(pool, "letters.landlord_name").await?;
+    set("today", Some(long_date(today)));
+    set(
+        "landlord.name",
+        landlord.or_else(|| Some(row.property_name.clone())),
+    );
+    set("lease.id", Some(row.lease_id.to_string()));
+    let start = period::parse_date(&row.lease_start_date).ok();
+    let end = period::parse_date(&row.lease_end_date).ok();
+    set(
+        "lease.start_date",
+        Some(start.map_or(row.lease_start_date.clone(), long_date)),
+    );
+    set(
+        "lease.end_date",
+        Some(end.map_or(row.lease_end_date.clone(), long_date)),
+    );
+    if let (Some(start), Some(end)) = (start, end) {
+        // The end date is the last day of the lease, so a lease from 1 January
+        // to 31 December runs for twelve months.
+        let after = end.succ_opt().unwrap_or(end);
+        let mut months =
+            (after.year() - start.year()) * 12 + after.month() as i32 - start.month() as i32;
+        if after.day() < start.day() {
+            months -= 1;
+        }
+        set("lease.term_months", Some(months.max(0).to_string()));
+    }
+    set("lease.rent", row.rent_amount.map(money));
+    set("lease.deposit", row.deposit_paid.map(money));
+    set("tenant.name", Some(row.full_name));
+    set("tenant.id_number", row.id_number);
+    set("tenant.phone", row.phone_number);
+    set("tenant.email", row.tenant_email);
+    set("unit.number", Some(row.unit_number));
+    set("unit.type", Some(row.unit_type));
+    set("unit.floor", row.floor_number.map(|n| n.to_string()));
+    set("unit.bedrooms", row.bedroom_count.map(count));
+    set("unit.bathrooms", row.bathroom_count.map(count));
+    set("block.name", row.block_name);
+    set("property.name", Some(row.property_name));
+    set("property.address", Some(row.address));
+    set("manager.name", row.manager_name);
+    set("manager.phone", row.manager_phone);
+    set("manager.email", row.manager_email);
+
+    let date_input = |value: &Option<String>| -> Result<Option<String>> {
+        Ok(value
+            .as_deref()
+            .filter(|v| !v.trim().is_empty())
+            .map(period::parse_date)
+            .transpose()?
+            .map(long_date))
+    };
+    set("notice.vacate_date", date_input(&inputs.vacate_date)?);
+    set("notice.reason", inputs.reason.clone());
+    set(
+        "increase.effective_date",
+        date_input(&inputs.effective_date)?,
+    );
+    if let Some(new_rent) = inputs.new_rent {
+        if new_rent < 0.0 {
+            return Err(Error::InvalidInput("new rent cannot be negative".into()));
+        }
+        set("increase.new_rent", Some(money(new_rent)));
+        if let Some(current) = row.rent_amount {
+            set("increase.amount", Some(money(new_rent - current)));
+            if current > 0.0 {
+                let percent = (new_rent - current) / current * 100.0;
+                set("increase.percent", Some(format!("{percent:.1}")));
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// `1 March 2025`.
+fn long_date(date: NaiveDate) -> String {
+    date.format("%-d %B %Y").to_string()
+}
+
+/// Two decimals with thousands separators: `25,000.00`.
+pub fn money(amount: f64) -> String {
+    let fixed = format!("{:.2}", amount.abs());
+    let (whole, cents) = fixed.split_once('.').unwrap_or((&fixed, "00"));
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    let sign = if amount < 0.0 && fixed != "0.00" {
+        "-"
+    } else {
+        ""
+    };
+    format!("{sign}{grouped}.{cents}")
+}
+
+/// Whole numbers without a decimal point, halves as `1.5`.
+fn count(n: f64) -> String {
+    if n.fract() == 0.0 {
+        format!("{n:.0}")
+    } else {
+        n.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_money_with_separators() {
+        assert_eq!(money(0.0), "0.00");
+        assert_eq!(money(999.5), "999.50");
+        assert_eq!(money(25000.0), "25,000.00");
+        assert_eq!(money(1234567.891), "1,234,567.89");
+        assert_eq!(money(-2500.0), "-2,500.00");
+        assert_eq!(money(-0.001), "0.00");
+    }
+
+    #[test]
+    fn formats_counts_without_needless_decimals() {
+        assert_eq!(count(2.0), "2");
+        assert_eq!(count(1.5), "1.5");
+        assert_eq!(
+            long_date(NaiveDate::from_ymd_opt(2025, 3, 1).unwrap()),
+            "1 March 2025"
+        );
+    }
+
+    #[test]
+    fn letter_fields_only_show_for_their_kind() {
+        let names = |kind| {
+            for_kind(kind)
+                .iter()
+                .map(|field| field.name)
+                .collect::<Vec<_>>()
+        };
+        let lease = names(TemplateKind::Lease);
+        assert!(lease.contains(&"tenant.name"));
+        assert!(!lease.contains(&"notice.reason"));
+        assert!(!lease.contains(&"increase.new_rent"));
+        let notice = names(TemplateKind::NoticeToVacate);
+        assert!(notice.contains(&"notice.reason"));
+        assert!(!notice.contains(&"increase.percent"));
+        assert!(names(TemplateKind::RentIncrease).contains(&"increase.percent"));
+    }
+}
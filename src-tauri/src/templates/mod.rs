@@ -0,0 +1,531 @@
+//! Lease agreements, notices to vacate and rent increase letters generated
+//! from templates.
+//!
+//! A template's body is never edited in place: saving a changed body adds a
+//! new version, and every generated document records the version it came
+//! from, so a signed lease can always be traced to the exact wording.
+
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::export::{self, LetterFormat};
+use crate::period;
+
+mod engine;
+mod fields;
+
+pub use engine::Template;
+pub use fields::{money, Field, LetterInputs};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum TemplateKind {
+    Lease,
+    #[serde(rename = "Notice to Vacate")]
+    #[sqlx(rename = "Notice to Vacate")]
+    NoticeToVacate,
+    #[serde(rename = "Rent Increase")]
+    #[sqlx(rename = "Rent Increase")]
+    RentIncrease,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateInput {
+    #[serde(default)]
+    pub template_id: Option<i64>,
+    pub kind: TemplateKind,
+    pub name: String,
+    pub body: String,
+    /// What changed, kept with the new version.
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub created_by: Option<String>,
+    #[serde(default = "active")]
+    pub is_active: bool,
+}
+
+fn active() -> bool {
+    true
+}
+
+impl TemplateInput {
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+        if self.name.trim().is_empty() {
+            return invalid("template name is required");
+        }
+        if self.body.trim().is_empty() {
+            return invalid("template body is required");
+        }
+        let template = Template::parse(&self.body)?;
+        let allowed = fields::for_kind(self.kind);
+        for name in template.fields() {
+            if !allowed.iter().any(|field| field.name == name) {
+                return Err(Error::InvalidInput(format!(
+                    "{{{{{name}}}}} is not a field of a {} template",
+                    kind_label(self.kind)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn kind_label(kind: TemplateKind) -> &'static str {
+    match kind {
+        TemplateKind::Lease => "lease",
+        TemplateKind::NoticeToVacate => "notice to vacate",
+        TemplateKind::RentIncrease => "rent increase",
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentTemplate {
+    pub template_id: i64,
+    pub kind: TemplateKind,
+    pub name: String,
+    pub is_active: bool,
+    /// The latest version, which new documents use.
+    pub version_id: i64,
+    pub version: i64,
+    pub body: String,
+    pub updated_at: Option<String>,
+    /// Documents generated from any version.
+    pub generated_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateVersion {
+    pub version_id: i64,
+    pub template_id: i64,
+    pub version: i64,
+    pub body: String,
+    pub notes: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: Option<String>,
+    pub generated_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LetterPreview {
+    pub text: String,
+    /// Fields with no value, printed as blanks to fill in by hand.
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateLetter {
+    pub template_id: i64,
+    /// Defaults to the latest version.
+    #[serde(default)]
+    pub version_id: Option<i64>,
+    pub lease_id: i64,
+    #[serde(default)]
+    pub inputs: LetterInputs,
+    pub format: LetterFormat,
+    pub path: PathBuf,
+    #[serde(default)]
+    pub generated_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct GeneratedDocument {
+    pub generated_id: i64,
+    pub template_id: i64,
+    pub template_name: String,
+    pub kind: TemplateKind,
+    pub version_id: i64,
+    pub version: i64,
+    pub lease_id: i64,
+    pub tenant_id: i64,
+    pub tenant_name: String,
+    pub unit_number: String,
+    pub format: LetterFormat,
+    pub path: String,
+    /// SHA-256 of the file as written, to tell whether it was altered.
+    pub sha256: String,
+    pub generated_by: Option<String>,
+    pub generated_at: Option<String>,
+    pub signed_on: Option<String>,
+}
+
+const TEMPLATE_COLUMNS: &str = "t.template_id, t.kind, t.name, t.is_active,
+            v.version_id, v.version, v.body, v.created_at AS updated_at,
+            (SELECT COUNT(*) FROM generated_documents g
+             JOIN document_template_versions gv ON gv.version_id = g.version_id
+             WHERE gv.template_id = t.template_id) AS generated_count";
+
+/// Joins `t` to its latest version `v`.
+const LATEST_VERSION: &str = "document_templates t
+         JOIN document_template_versions v ON v.template_id = t.template_id
+          AND v.version = (SELECT MAX(version) FROM document_template_versions
+                           WHERE template_id = t.template_id)";
+
+const GENERATED_COLUMNS: &str = "g.generated_id, t.template_id, t.name AS template_name, t.kind,
+            v.version_id, v.version, g.lease_id, l.tenant_id, tn.full_name AS tenant_name,
+            u.unit_number, g.format, g.path, g.sha256, g.generated_by, g.generated_at,
+            g.signed_on
+         FROM generated_documents g
+         JOIN document_template_versions v ON v.version_id = g.version_id
+         JOIN document_templates t ON t.template_id = v.template_id
+         JOIN leases l ON l.lease_id = g.lease_id
+         JOIN tenants tn ON tn.tenant_id = l.tenant_id
+         JOIN units u ON u.unit_id = l.unit_id";
+
+#[tauri::command]
+pub async fn get_document_templates(
+    app: AppHandle,
+    kind: Option<TemplateKind>,
+    include_inactive: Option<bool>,
+) -> Result<Vec<DocumentTemplate>> {
+    let pool = db::pool(&app).await?;
+    let templates = sqlx::query_as(&format!(
+        "SELECT {TEMPLATE_COLUMNS}
+         FROM {LATEST_VERSION}
+         WHERE (?1 IS NULL OR t.kind = ?1) AND (?2 OR t.is_active)
+         ORDER BY t.kind, t.name"
+    ))
+    .bind(kind)
+    .bind(include_inactive.unwrap_or(false))
+    .fetch_all(&pool)
+    .await?;
+    Ok(templates)
+}
+
+/// Every version of a template, newest first.
+#[tauri::command]
+pub async fn get_template_versions(
+    app: AppHandle,
+    template_id: i64,
+) -> Result<Vec<TemplateVersion>> {
+    let pool = db::pool(&app).await?;
+    let versions = sqlx::query_as(
+        "SELECT v.version_id, v.template_id, v.version, v.body, v.notes, v.created_by,
+                v.created_at,
+                (SELECT COUNT(*) FROM generated_documents g
+                 WHERE g.version_id = v.version_id) AS generated_count
+         FROM document_template_versions v
+         WHERE v.template_id = ?1
+         ORDER BY v.version DESC",
+    )
+    .bind(template_id)
+    .fetch_all(&pool)
+    .await?;
+    Ok(versions)
+}
+
+/// Creates or renames a template. A changed body is saved as a new version;
+/// earlier versions are kept as they were.
+#[tauri::command]
+pub async fn save_document_template(
+    app: AppHandle,
+    template: TemplateInput,
+) -> Result<DocumentTemplate> {
+    let pool = db::pool(&app).await?;
+    save(&pool, &template).await
+}
+
+/// Fields a template of `kind` can use, for the editor's field picker.
+#[tauri::command]
+pub async fn get_template_fields(kind: TemplateKind) -> Result<Vec<&'static Field>> {
+    Ok(fields::for_kind(kind))
+}
+
+/// Fills in a template version for a lease without writing a file.
+#[tauri::command]
+pub async fn preview_letter(
+    app: AppHandle,
+    version_id: i64,
+    lease_id: i64,
+    inputs: Option<LetterInputs>,
+) -> Result<LetterPreview> {
+    let pool = db::pool(&app).await?;
+    preview(
+        &pool,
+        version_id,
+        lease_id,
+        &inputs.unwrap_or_default(),
+        period::today(),
+    )
+    .await
+}
+
+/// Writes the letter to `path` and records which version it came from.
+#[tauri::command]
+pub async fn generate_letter(app: AppHandle, letter: GenerateLetter) -> Result<GeneratedDocument> {
+    let pool = db::pool(&app).await?;
+    generate(&pool, &letter, period::today()).await
+}
+
+#[tauri::command]
+pub async fn get_generated_documents(
+    app: AppHandle,
+    lease_id: Option<i64>,
+    tenant_id: Option<i64>,
+) -> Result<Vec<GeneratedDocument>> {
+    let pool = db::pool(&app).await?;
+    let documents = sqlx::query_as(&format!(
+        "SELECT {GENERATED_COLUMNS}
+         WHERE (?1 IS NULL OR g.lease_id = ?1) AND (?2 IS NULL OR l.tenant_id = ?2)
+         ORDER BY g.generated_at DESC, g.generated_id DESC"
+    ))
+    .bind(lease_id)
+    .bind(tenant_id)
+    .fetch_all(&pool)
+    .await?;
+    Ok(documents)
+}
+
+/// Records the day the tenant signed, today unless given. Pass `clear` to
+/// undo a mistaken entry.
+#[tauri::command]
+pub async fn mark_document_signed(
+    app: AppHandle,
+    generated_id: i64,
+    signed_on: Option<String>,
+    clear: Option<bool>,
+) -> Result<GeneratedDocument> {
+    let pool = db::pool(&app).await?;
+    let signed_on = if clear.unwrap_or(false) {
+        None
+    } else {
+        Some(
+            signed_on
+                .as_deref()
+                .map(period::parse_date)
+                .transpose()?
+                .unwrap_or_else(period::today),
+        )
+    };
+    mark_signed(&pool, generated_id, signed_on).await
+}
+
+pub async fn save(pool: &SqlitePool, input: &TemplateInput) -> Result<DocumentTemplate> {
+    input.validate()?;
+    let name = input.name.trim();
+    let duplicate = |err: sqlx::Error| match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => Error::InvalidInput(format!(
+            "there is already a {} template named '{name}'",
+            kind_label(input.kind)
+        )),
+        _ => err.into(),
+    };
+
+    let mut tx = pool.begin().await?;
+    let template_id = match input.template_id {
+        Some(template_id) => {
+            let kind: Option<(TemplateKind,)> =
+                sqlx::query_as("SELECT kind FROM document_templates WHERE template_id = ?1")
+                    .bind(template_id)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+            match kind {
+                None => return Err(Error::NotFound(format!("template {template_id}"))),
+                Some((kind,)) if kind != input.kind => {
+                    return Err(Error::InvalidInput(
+                        "a template cannot change kind; create a new template instead".into(),
+                    ))
+                }
+                Some(_) => {}
+            }
+            sqlx::query(
+                "UPDATE document_templates SET name = ?1, is_active = ?2 WHERE template_id = ?3",
+            )
+            .bind(name)
+            .bind(input.is_active)
+            .bind(template_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(duplicate)?;
+            template_id
+        }
+        None => sqlx::query(
+            "INSERT INTO document_templates (kind, name, is_active) VALUES (?1, ?2, ?3)",
+        )
+        .bind(input.kind)
+        .bind(name)
+        .bind(input.is_active)
+        .execute(&mut *tx)
+        .await
+        .map_err(duplicate)?
+        .last_insert_rowid(),
+    };
+
+    let latest: Option<(i64, String)> = sqlx::query_as(
+        "SELECT version, body FROM document_template_versions
+         WHERE template_id = ?1 ORDER BY version DESC LIMIT 1",
+    )
+    .bind(template_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if latest.as_ref().is_none_or(|(_, body)| *body != input.body) {
+        sqlx::query(
+            "INSERT INTO document_template_versions (template_id, version, body, notes, created_by)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(template_id)
+        .bind(latest.map_or(1, |(version, _)| version + 1))
+        .bind(&input.body)
+        .bind(&input.notes)
+        .bind(&input.created_by)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    let template = sqlx::query_as(&format!(
+        "SELECT {TEMPLATE_COLUMNS} FROM {LATEST_VERSION} WHERE t.template_id = ?1"
+    ))
+    .bind(template_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(template)
+}
+
+pub async fn preview(
+    pool: &SqlitePool,
+    version_id: i64,
+    lease_id: i64,
+    inputs: &LetterInputs,
+    today: NaiveDate,
+) -> Result<LetterPreview> {
+    let body: Option<(String,)> =
+        sqlx::query_as("SELECT body FROM document_template_versions WHERE version_id = ?1")
+            .bind(version_id)
+            .fetch_optional(pool)
+            .await?;
+    let Some((body,)) = body else {
+        return Err(Error::NotFound(format!("template version {version_id}")));
+    };
+    let template = Template::parse(&body)?;
+    let values = fields::values(pool, lease_id, inputs, today).await?;
+    let missing = template
+        .fields()
+        .into_iter()
+        .filter(|name| !values.contains_key(*name))
+        .map(String::from)
+        .collect();
+    Ok(LetterPreview {
+        text: template.render(&values),
+        missing,
+    })
+}
+
+pub async fn generate(
+    pool: &SqlitePool,
+    letter: &GenerateLetter,
+    today: NaiveDate,
+) -> Result<GeneratedDocument> {
+    let version: Option<(i64,)> = sqlx::query_as(
+        "SELECT version_id FROM document_template_versions
+         WHERE template_id = ?1 AND (?2 IS NULL OR version_id = ?2)
+         ORDER BY version DESC LIMIT 1",
+    )
+    .bind(letter.template_id)
+    .bind(letter.version_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((version_id,)) = version else {
+        return Err(Error::NotFound(match letter.version_id {
+            Some(version_id) => format!("version {version_id} of template {}", letter.template_id),
+            None => format!("template {}", letter.template_id),
+        }));
+    };
+
+    let preview = preview(pool, version_id, letter.lease_id, &letter.inputs, today).await?;
+    let bytes = export::render_letter(&engine::to_letter(&preview.text), letter.format);
+    std::fs::write(&letter.path, &bytes)?;
+    let generated_id = sqlx::query(
+        "INSERT INTO generated_documents (version_id, lease_id, format, path, sha256, generated_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )
+    .bind(version_id)
+    .bind(letter.lease_id)
+    .bind(letter.format)
+    .bind(letter.path.to_string_lossy())
+    .bind(format!("{:x}", Sha256::digest(&bytes)))
+    .bind(&letter.generated_by)
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+    load_generated(pool, generated_id).await
+}
+
+pub async fn mark_signed(
+    pool: &SqlitePool,
+    generated_id: i64,
+    signed_on: Option<NaiveDate>,
+) -> Result<GeneratedDocument> {
+    let updated =
+        sqlx::query("UPDATE generated_documents SET signed_on = ?1 WHERE generated_id = ?2")
+            .bind(signed_on.map(|date| date.to_string()))
+            .bind(generated_id)
+            .execute(pool)
+            .await?;
+    if updated.rows_affected() == 0 {
+        return Err(Error::NotFound(format!(
+            "generated document {generated_id}"
+        )));
+    }
+    load_generated(pool, generated_id).await
+}
+
+async fn load_generated(pool: &SqlitePool, generated_id: i64) -> Result<GeneratedDocument> {
+    let document = sqlx::query_as(&format!(
+        "SELECT {GENERATED_COLUMNS} WHERE g.generated_id = ?1"
+    ))
+    .bind(generated_id)
+    .fetch_optional(pool)
+    .await?;
+    document.ok_or_else(|| Error::NotFound(format!("generated document {generated_id}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(kind: TemplateKind, body: &str) -> TemplateInput {
+        TemplateInput {
+            template_id: None,
+            kind,
+            name: "Standard".into(),
+            body: body.into(),
+            notes: None,
+            created_by: None,
+            is_active: true,
+        }
+    }
+
+    #[test]
+    fn rejects_fields_unknown_to_the_kind() {
+        assert!(input(
+            TemplateKind::NoticeToVacate,
+            "{{#if notice.reason}}{{notice.reason}}{{/if}}"
+        )
+        .validate()
+        .is_ok());
+        for (kind, body) in [
+            (TemplateKind::Lease, "{{tenant.nmae}}"),
+            (TemplateKind::Lease, "{{#if notice.reason}}x{{/if}}"),
+            (TemplateKind::NoticeToVacate, "{{increase.new_rent}}"),
+        ] {
+            assert!(
+                matches!(input(kind, body).validate(), Err(Error::InvalidInput(_))),
+                "{body}"
+            );
+        }
+    }
+}
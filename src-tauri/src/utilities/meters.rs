@@ -0,0 +1,536 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::period;
+use crate::settings;
+use crate::utilities::UtilityType;
+
+/// How many earlier readings the extreme-consumption check averages over.
+const HISTORY_READINGS: usize = 6;
+
+/// Fewest earlier readings needed before consumption can be called extreme.
+const MIN_HISTORY: usize = 3;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct UtilityMeter {
+    pub meter_id: i64,
+    pub unit_id: i64,
+    pub unit_number: Option<String>,
+    pub property_id: Option<i64>,
+    pub utility_type: UtilityType,
+    pub meter_number: Option<String>,
+    pub initial_reading: f64,
+    pub installed_on: Option<String>,
+    pub is_active: bool,
+    pub last_reading: Option<f64>,
+    pub last_reading_date: Option<String>,
+}
+
+/// A new meter, or changes to an existing one when `meter_id` is set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterInput {
+    #[serde(default)]
+    pub meter_id: Option<i64>,
+    pub unit_id: i64,
+    pub utility_type: UtilityType,
+    #[serde(default)]
+    pub meter_number: Option<String>,
+    #[serde(default)]
+    pub initial_reading: f64,
+    #[serde(default)]
+    pub installed_on: Option<String>,
+    #[serde(default = "active")]
+    pub is_active: bool,
+}
+
+fn active() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterReading {
+    pub reading_id: i64,
+    pub meter_id: i64,
+    pub utility_type: UtilityType,
+    pub unit_id: i64,
+    pub unit_number: Option<String>,
+    pub reading_date: String,
+    pub reading: f64,
+    pub is_meter_reset: bool,
+    /// Units used since the previous reading.
+    pub consumption: f64,
+    /// `Negative` or `Extreme` when the consumption looks wrong.
+    pub anomaly: Option<String>,
+    pub anomaly_accepted: bool,
+    /// `Pending` until billed; `Flagged` readings wait for review.
+    pub status: String,
+    pub charge_id: Option<i64>,
+    pub recorded_by: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewMeterReading {
+    pub meter_id: i64,
+    pub reading_date: String,
+    pub reading: f64,
+    /// The meter was replaced or rolled over, so it counts from zero again.
+    #[serde(default)]
+    pub is_meter_reset: bool,
+    #[serde(default)]
+    pub recorded_by: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+const METER_COLUMNS: &str = "m.meter_id, m.unit_id, u.unit_number, u.property_id, m.utility_type,
+     m.meter_number, CAST(m.initial_reading AS REAL) AS initial_reading, m.installed_on,
+     m.is_active,
+     (SELECT CAST(r.reading AS REAL) FROM meter_readings r WHERE r.meter_id = m.meter_id
+      ORDER BY r.reading_date DESC LIMIT 1) AS last_reading,
+     (SELECT MAX(r.reading_date) FROM meter_readings r WHERE r.meter_id = m.meter_id)
+         AS last_reading_date";
+
+const READING_COLUMNS: &str = "r.reading_id, r.meter_id, m.utility_type, m.unit_id, u.unit_number,
+     r.reading_date, CAST(r.reading AS REAL) AS reading, r.is_meter_reset,
+     CAST(r.consumption AS REAL) AS consumption, r.anomaly, r.anomaly_accepted, r.status,
+     r.charge_id, r.recorded_by, r.notes";
+
+#[tauri::command]
+pub async fn get_utility_meters(
+    app: AppHandle,
+    unit_id: Option<i64>,
+    property_id: Option<i64>,
+) -> Result<Vec<UtilityMeter>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(&format!(
+        "SELECT {METER_COLUMNS}
+         FROM utility_meters m
+         LEFT JOIN units u ON u.unit_id = m.unit_id
+         WHERE (?1 IS NULL OR m.unit_id = ?1) AND (?2 IS NULL OR u.property_id = ?2)
+         ORDER BY u.unit_number, m.utility_type"
+    ))
+    .bind(unit_id)
+    .bind(property_id)
+    .fetch_all(&pool)
+    .await?)
+}
+
+#[tauri::command]
+pub async fn save_utility_meter(app: AppHandle, meter: MeterInput) -> Result<UtilityMeter> {
+    let pool = db::pool(&app).await?;
+    save_meter(&pool, &meter).await
+}
+
+/// Readings, newest first, optionally for one meter or with one status
+/// (e.g. `Flagged` for the review queue).
+#[tauri::command]
+pub async fn get_meter_readings(
+    app: AppHandle,
+    meter_id: Option<i64>,
+    status: Option<String>,
+) -> Result<Vec<MeterReading>> {
+    let pool = db::pool(&app).await?;
+    Ok(sqlx::query_as(&format!(
+        "SELECT {READING_COLUMNS}
+         FROM meter_readings r
+         JOIN utility_meters m ON m.meter_id = r.meter_id
+         LEFT JOIN units u ON u.unit_id = m.unit_id
+         WHERE (?1 IS NULL OR r.meter_id = ?1) AND (?2 IS NULL OR r.status = ?2)
+         ORDER BY r.reading_date DESC, r.reading_id DESC"
+    ))
+    .bind(meter_id)
+    .bind(status)
+    .fetch_all(&pool)
+    .await?)
+}
+
+#[tauri::command]
+pub async fn record_meter_reading(
+    app: AppHandle,
+    reading: NewMeterReading,
+) -> Result<MeterReading> {
+    let pool = db::pool(&app).await?;
+    record_reading(&pool, &reading).await
+}
+
+/// Corrects a reading that has not been billed yet. Consumption of the
+/// reading and the ones after it is worked out again.
+#[tauri::command]
+pub async fn update_meter_reading(
+    app: AppHandle,
+    reading_id: i64,
+    reading_date: String,
+    reading: f64,
+    is_meter_reset: bool,
+    notes: Option<String>,
+) -> Result<MeterReading> {
+    let pool = db::pool(&app).await?;
+    update_reading(
+        &pool,
+        reading_id,
+        &reading_date,
+        reading,
+        is_meter_reset,
+        notes,
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_meter_reading(app: AppHandle, reading_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    let factor = anomaly_factor(&pool).await?;
+    let mut tx = pool.begin().await?;
+    let meter_id = unbilled(&mut tx, reading_id).await?;
+    sqlx::query("DELETE FROM meter_readings WHERE reading_id = ?1")
+        .bind(reading_id)
+        .execute(&mut *tx)
+        .await?;
+    refresh(&mut tx, meter_id, factor).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Confirms that a flagged reading is right, e.g. a leak that really
+/// happened, so it is billed as read.
+#[tauri::command]
+pub async fn accept_meter_reading(app: AppHandle, reading_id: i64) -> Result<MeterReading> {
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query(
+        "UPDATE meter_readings
+         SET anomaly_accepted = 1, status = 'Pending', updated_at = CURRENT_TIMESTAMP
+         WHERE reading_id = ?1 AND status = 'Flagged'",
+    )
+    .bind(reading_id)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::InvalidInput(format!(
+            "reading {reading_id} is not flagged"
+        )));
+    }
+    let reading = load_reading(&mut tx, reading_id).await?;
+    tx.commit().await?;
+    Ok(reading)
+}
+
+pub async fn save_meter(pool: &SqlitePool, input: &MeterInput) -> Result<UtilityMeter> {
+    if input.initial_reading < 0.0 {
+        return Err(Error::InvalidInput(
+            "initial reading cannot be negative".into(),
+        ));
+    }
+    if let Some(date) = &input.installed_on {
+        period::parse_date(date)?;
+    }
+    let meter_number = input
+        .meter_number
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty());
+    let factor = anomaly_factor(pool).await?;
+
+    let mut tx = pool.begin().await?;
+    let (unit_exists,): (bool,) =
+        sqlx::query_as("SELECT EXISTS (SELECT 1 FROM units WHERE unit_id = ?1)")
+            .bind(input.unit_id)
+            .fetch_one(&mut *tx)
+            .await?;
+    if !unit_exists {
+        return Err(Error::NotFound(format!("unit {}", input.unit_id)));
+    }
+    let meter_id = match input.meter_id {
+        None => sqlx::query(
+            "INSERT INTO utility_meters
+                 (unit_id, utility_type, meter_number, initial_reading, installed_on, is_active)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(input.unit_id)
+        .bind(input.utility_type)
+        .bind(meter_number)
+        .bind(input.initial_reading)
+        .bind(&input.installed_on)
+        .bind(input.is_active)
+        .execute(&mut *tx)
+        .await
+        .map_err(duplicate_meter)?
+        .last_insert_rowid(),
+        Some(meter_id) => {
+            let result = sqlx::query(
+                "UPDATE utility_meters
+                 SET unit_id = ?2, utility_type = ?3, meter_number = ?4, initial_reading = ?5,
+                     installed_on = ?6, is_active = ?7, updated_at = CURRENT_TIMESTAMP
+                 WHERE meter_id = ?1",
+            )
+            .bind(meter_id)
+            .bind(input.unit_id)
+            .bind(input.utility_type)
+            .bind(meter_number)
+            .bind(input.initial_reading)
+            .bind(&input.installed_on)
+            .bind(input.is_active)
+            .execute(&mut *tx)
+            .await
+            .map_err(duplicate_meter)?;
+            if result.rows_affected() == 0 {
+                return Err(Error::NotFound(format!("meter {meter_id}")));
+            }
+            refresh(&mut tx, meter_id, factor).await?;
+            meter_id
+        }
+    };
+    let meter = sqlx::query_as(&format!(
+        "SELECT {METER_COLUMNS}
+         FROM utility_meters m
+         LEFT JOIN units u ON u.unit_id = m.unit_id
+         WHERE m.meter_id = ?1"
+    ))
+    .bind(meter_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(meter)
+}
+
+pub async fn record_reading(pool: &SqlitePool, input: &NewMeterReading) -> Result<MeterReading> {
+    period::parse_date(&input.reading_date)?;
+    if input.reading < 0.0 {
+        return Err(Error::InvalidInput(
+            "meter readings cannot be negative".into(),
+        ));
+    }
+    let factor = anomaly_factor(pool).await?;
+
+    let mut tx = pool.begin().await?;
+    let active: Option<(bool,)> =
+        sqlx::query_as("SELECT is_active FROM utility_meters WHERE meter_id = ?1")
+            .bind(input.meter_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    match active {
+        None => return Err(Error::NotFound(format!("meter {}", input.meter_id))),
+        Some((false,)) => {
+            return Err(Error::InvalidInput(format!(
+                "meter {} is inactive",
+                input.meter_id
+            )))
+        }
+        Some((true,)) => {}
+    }
+    ensure_after_billed(&mut tx, input.meter_id, &input.reading_date).await?;
+
+    let reading_id = sqlx::query(
+        "INSERT INTO meter_readings (meter_id, reading_date, reading, is_meter_reset, recorded_by, notes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )
+    .bind(input.meter_id)
+    .bind(&input.reading_date)
+    .bind(input.reading)
+    .bind(input.is_meter_reset)
+    .bind(&input.recorded_by)
+    .bind(&input.notes)
+    .execute(&mut *tx)
+    .await
+    .map_err(duplicate_reading)?
+    .last_insert_rowid();
+    refresh(&mut tx, input.meter_id, factor).await?;
+    let reading = load_reading(&mut tx, reading_id).await?;
+    tx.commit().await?;
+    Ok(reading)
+}
+
+pub async fn update_reading(
+    pool: &SqlitePool,
+    reading_id: i64,
+    reading_date: &str,
+    reading: f64,
+    is_meter_reset: bool,
+    notes: Option<String>,
+) -> Result<MeterReading> {
+    period::parse_date(reading_date)?;
+    if reading < 0.0 {
+        return Err(Error::InvalidInput(
+            "meter readings cannot be negative".into(),
+        ));
+    }
+    let factor = anomaly_factor(pool).await?;
+
+    let mut tx = pool.begin().await?;
+    let meter_id = unbilled(&mut tx, reading_id).await?;
+    ensure_after_billed(&mut tx, meter_id, reading_date).await?;
+    // A corrected reading is checked afresh.
+    sqlx::query(
+        "UPDATE meter_readings
+         SET reading_date = ?2, reading = ?3, is_meter_reset = ?4, notes = ?5,
+             anomaly_accepted = 0, updated_at = CURRENT_TIMESTAMP
+         WHERE reading_id = ?1",
+    )
+    .bind(reading_id)
+    .bind(reading_date)
+    .bind(reading)
+    .bind(is_meter_reset)
+    .bind(notes)
+    .execute(&mut *tx)
+    .await
+    .map_err(duplicate_reading)?;
+    refresh(&mut tx, meter_id, factor).await?;
+    let reading = load_reading(&mut tx, reading_id).await?;
+    tx.commit().await?;
+    Ok(reading)
+}
+
+/// Consumption over `utilities.anomaly_factor` (default 3) times the meter's
+/// recent average is flagged as extreme.
+async fn anomaly_factor(pool: &SqlitePool) -> Result<f64> {
+    settings::get_or(pool, "utilities.anomaly_factor", 3.0).await
+}
+
+#[derive(sqlx::FromRow)]
+struct StoredReading {
+    reading_id: i64,
+    reading: f64,
+    is_meter_reset: bool,
+    consumption: f64,
+    anomaly: Option<String>,
+    anomaly_accepted: bool,
+    status: String,
+}
+
+/// Works out consumption and anomalies again for every unbilled reading of
+/// the meter, in date order. Billed readings are left as they were billed.
+async fn refresh(conn: &mut SqliteConnection, meter_id: i64, factor: f64) -> Result<()> {
+    let (initial,): (f64,) = sqlx::query_as(
+        "SELECT CAST(initial_reading AS REAL) FROM utility_meters WHERE meter_id = ?1",
+    )
+    .bind(meter_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    let readings: Vec<StoredReading> = sqlx::query_as(
+        "SELECT reading_id, CAST(reading AS REAL) AS reading, is_meter_reset,
+                CAST(consumption AS REAL) AS consumption, anomaly, anomaly_accepted, status
+         FROM meter_readings WHERE meter_id = ?1
+         ORDER BY reading_date, reading_id",
+    )
+    .bind(meter_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut previous = initial;
+    let mut history: Vec<f64> = Vec::new();
+    for stored in readings {
+        if stored.status == "Billed" {
+            previous = stored.reading;
+            if stored.anomaly.is_none() {
+                history.push(stored.consumption);
+            }
+            continue;
+        }
+        let consumption = if stored.is_meter_reset {
+            stored.reading
+        } else {
+            stored.reading - previous
+        };
+        let recent = &history[history.len().saturating_sub(HISTORY_READINGS)..];
+        let average = recent.iter().sum::<f64>() / recent.len().max(1) as f64;
+        let anomaly = if consumption < 0.0 {
+            Some("Negative")
+        } else if recent.len() >= MIN_HISTORY && average > 0.0 && consumption > average * factor {
+            Some("Extreme")
+        } else {
+            None
+        };
+        let status = if anomaly.is_some() && !stored.anomaly_accepted {
+            "Flagged"
+        } else {
+            "Pending"
+        };
+        sqlx::query(
+            "UPDATE meter_readings SET consumption = ?2, anomaly = ?3, status = ?4
+             WHERE reading_id = ?1",
+        )
+        .bind(stored.reading_id)
+        .bind(consumption)
+        .bind(anomaly)
+        .bind(status)
+        .execute(&mut *conn)
+        .await?;
+        previous = stored.reading;
+        if anomaly.is_none() {
+            history.push(consumption);
+        }
+    }
+    Ok(())
+}
+
+/// Meter of a reading that can still be changed.
+async fn unbilled(conn: &mut SqliteConnection, reading_id: i64) -> Result<i64> {
+    let row: Option<(i64, String)> =
+        sqlx::query_as("SELECT meter_id, status FROM meter_readings WHERE reading_id = ?1")
+            .bind(reading_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+    match row {
+        None => Err(Error::NotFound(format!("meter reading {reading_id}"))),
+        Some((_, status)) if status == "Billed" => Err(Error::InvalidInput(format!(
+            "reading {reading_id} has been billed and cannot be changed"
+        ))),
+        Some((meter_id, _)) => Ok(meter_id),
+    }
+}
+
+/// Billed consumption depends on the readings before it, so nothing may be
+/// slotted in ahead of the latest billed reading.
+async fn ensure_after_billed(conn: &mut SqliteConnection, meter_id: i64, date: &str) -> Result<()> {
+    let (billed,): (Option<String>,) = sqlx::query_as(
+        "SELECT MAX(reading_date) FROM meter_readings WHERE meter_id = ?1 AND status = 'Billed'",
+    )
+    .bind(meter_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    match billed {
+        Some(billed) if date <= billed.as_str() => Err(Error::InvalidInput(format!(
+            "readings must be dated after the last billed reading on {billed}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+async fn load_reading(conn: &mut SqliteConnection, reading_id: i64) -> Result<MeterReading> {
+    Ok(sqlx::query_as(&format!(
+        "SELECT {READING_COLUMNS}
+         FROM meter_readings r
+         JOIN utility_meters m ON m.meter_id = r.meter_id
+         LEFT JOIN units u ON u.unit_id = m.unit_id
+         WHERE r.reading_id = ?1"
+    ))
+    .bind(reading_id)
+    .fetch_one(&mut *conn)
+    .await?)
+}
+
+fn duplicate_meter(err: sqlx::Error) -> Error {
+    match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            Error::InvalidInput("another meter of this utility already has that number".into())
+        }
+        _ => err.into(),
+    }
+}
+
+fn duplicate_reading(err: sqlx::Error) -> Error {
+    match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            Error::InvalidInput("this meter already has a reading on that date".into())
+        }
+        _ => err.into(),
+    }
+}
@@ -0,0 +1,206 @@
+//! Water and electricity metering.
+//!
+//! Each unit can have meters; readings entered against them are turned into
+//! `Utilities` charges for the tenant leasing the unit on the reading date,
+//! priced with the property's tariff (or the default one).
+
+pub mod meters;
+pub mod tariffs;
+
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::billing::allocations::round_cents;
+use crate::billing::charges::{self, NewCharge};
+use crate::db;
+use crate::error::Result;
+use crate::period;
+use crate::settings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum UtilityType {
+    Water,
+    Electricity,
+}
+
+impl UtilityType {
+    pub fn label(self) -> &'static str {
+        match self {
+            UtilityType::Water => "Water",
+            UtilityType::Electricity => "Electricity",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedReading {
+    pub reading_id: i64,
+    pub meter_id: i64,
+    pub unit_number: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UtilityBillRun {
+    pub readings_billed: usize,
+    pub charges_created: usize,
+    pub total_amount: f64,
+    /// Readings left pending, e.g. on a vacant unit or without a tariff.
+    pub skipped: Vec<SkippedReading>,
+}
+
+/// Bills every pending reading dated on or before `through` (default today).
+/// Charges fall due on `due_date`, by default `utilities.due_days` (10) days
+/// from today. Flagged readings wait until accepted or corrected.
+#[tauri::command]
+pub async fn generate_utility_charges(
+    app: AppHandle,
+    through: Option<String>,
+    due_date: Option<String>,
+) -> Result<UtilityBillRun> {
+    let through = through
+        .as_deref()
+        .map(period::parse_date)
+        .transpose()?
+        .unwrap_or_else(period::today);
+    let due_date = due_date.as_deref().map(period::parse_date).transpose()?;
+    let pool = db::pool(&app).await?;
+    generate_charges(&pool, through, due_date).await
+}
+
+#[derive(sqlx::FromRow)]
+struct BillableReading {
+    reading_id: i64,
+    meter_id: i64,
+    meter_number: Option<String>,
+    utility_type: UtilityType,
+    reading_date: String,
+    previous_date: Option<String>,
+    consumption: f64,
+    unit_id: i64,
+    unit_number: Option<String>,
+    property_id: Option<i64>,
+    tenant_id: Option<i64>,
+}
+
+pub async fn generate_charges(
+    pool: &SqlitePool,
+    through: NaiveDate,
+    due_date: Option<NaiveDate>,
+) -> Result<UtilityBillRun> {
+    let due_date = match due_date {
+        Some(date) => date,
+        None => {
+            period::today()
+                + Duration::days(settings::get_or(pool, "utilities.due_days", 10).await?)
+        }
+    };
+
+    let mut tx = pool.begin().await?;
+    let readings: Vec<BillableReading> = sqlx::query_as(
+        "SELECT r.reading_id, r.meter_id, m.meter_number, m.utility_type, r.reading_date,
+                (SELECT MAX(p.reading_date) FROM meter_readings p
+                 WHERE p.meter_id = r.meter_id AND p.reading_date < r.reading_date) AS previous_date,
+                CAST(r.consumption AS REAL) AS consumption,
+                m.unit_id, u.unit_number, u.property_id, l.tenant_id
+         FROM meter_readings r
+         JOIN utility_meters m ON m.meter_id = r.meter_id
+         LEFT JOIN units u ON u.unit_id = m.unit_id
+         LEFT JOIN leases l ON l.lease_id = (
+             SELECT lease_id FROM leases
+             WHERE unit_id = m.unit_id
+               AND lease_start_date <= r.reading_date AND lease_end_date >= r.reading_date
+               AND COALESCE(lower(status), 'active') <> 'terminated'
+             ORDER BY lease_start_date DESC
+             LIMIT 1
+         )
+         WHERE r.status = 'Pending' AND r.reading_date <= ?1
+         ORDER BY r.reading_date, r.reading_id",
+    )
+    .bind(through.to_string())
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut run = UtilityBillRun::default();
+    for reading in readings {
+        let skip = |reason: String| SkippedReading {
+            reading_id: reading.reading_id,
+            meter_id: reading.meter_id,
+            unit_number: reading.unit_number.clone(),
+            reason,
+        };
+        let (Some(property_id), Some(tenant_id)) = (reading.property_id, reading.tenant_id) else {
+            run.skipped
+                .push(skip("no lease on the unit on the reading date".into()));
+            continue;
+        };
+        let tariff = tariffs::tariff_for(
+            &mut tx,
+            property_id,
+            reading.utility_type,
+            &reading.reading_date,
+        )
+        .await?;
+        let Some(tariff) = tariff else {
+            run.skipped.push(skip(format!(
+                "no {} tariff in effect on {}",
+                reading.utility_type.label().to_lowercase(),
+                reading.reading_date
+            )));
+            continue;
+        };
+
+        let amount = tariff.cost(reading.consumption);
+        let charge_id = if amount > 0.0 {
+            let description = format!(
+                "{} {} units{}{}",
+                reading.utility_type.label(),
+                reading.consumption,
+                match &reading.previous_date {
+                    Some(from) => format!(" ({from} to {})", reading.reading_date),
+                    None => format!(" (to {})", reading.reading_date),
+                },
+                reading
+                    .meter_number
+                    .as_deref()
+                    .map(|n| format!(", meter {n}"))
+                    .unwrap_or_default(),
+            );
+            let charge_id = charges::insert_charge(
+                &mut *tx,
+                &NewCharge {
+                    tenant_id,
+                    unit_id: Some(reading.unit_id),
+                    property_id: Some(property_id),
+                    category: "Utilities".into(),
+                    description: Some(description),
+                    amount,
+                    due_date: due_date.to_string(),
+                    parent_charge_id: None,
+                },
+            )
+            .await?;
+            run.charges_created += 1;
+            run.total_amount += amount;
+            Some(charge_id)
+        } else {
+            None
+        };
+        sqlx::query(
+            "UPDATE meter_readings SET status = 'Billed', charge_id = ?2, updated_at = CURRENT_TIMESTAMP
+             WHERE reading_id = ?1",
+        )
+        .bind(reading.reading_id)
+        .bind(charge_id)
+        .execute(&mut *tx)
+        .await?;
+        run.readings_billed += 1;
+    }
+    tx.commit().await?;
+    run.total_amount = round_cents(run.total_amount);
+    Ok(run)
+}
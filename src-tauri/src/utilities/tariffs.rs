@@ -0,0 +1,380 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::AppHandle;
+
+use crate::billing::allocations::round_cents;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::period;
+use crate::utilities::UtilityType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+pub enum TariffType {
+    /// `rate` for every unit consumed.
+    Flat,
+    /// Each tier's rate for the units that fall within it.
+    Tiered,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TariffTier {
+    /// Upper bound of the tier in units consumed; `None` for the last tier.
+    pub up_to: Option<f64>,
+    pub rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Tariff {
+    #[serde(default)]
+    pub tariff_id: Option<i64>,
+    /// `None` makes this the default for properties without their own tariff.
+    pub property_id: Option<i64>,
+    pub utility_type: UtilityType,
+    pub tariff_type: TariffType,
+    /// Price per unit of a flat tariff.
+    pub rate: Option<f64>,
+    /// Standing charge added to every bill.
+    #[serde(default)]
+    pub fixed_charge: f64,
+    pub effective_from: String,
+    /// Tiers of a tiered tariff, lowest first.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub tiers: Vec<TariffTier>,
+}
+
+impl Tariff {
+    /// Amount billed for `consumption` units.
+    pub fn cost(&self, consumption: f64) -> f64 {
+        let consumption = consumption.max(0.0);
+        let usage = match self.tariff_type {
+            TariffType::Flat => consumption * self.rate.unwrap_or_default(),
+            TariffType::Tiered => {
+                let mut cost = 0.0;
+                let mut floor = 0.0;
+                for tier in &self.tiers {
+                    let ceiling = tier.up_to.unwrap_or(f64::INFINITY).min(consumption);
+                    if ceiling > floor {
+                        cost += (ceiling - floor) * tier.rate;
+                    }
+                    floor = tier.up_to.unwrap_or(f64::INFINITY);
+                    if floor >= consumption {
+                        break;
+                    }
+                }
+                cost
+            }
+        };
+        round_cents(usage + self.fixed_charge)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: &str| Err(Error::InvalidInput(msg.into()));
+        period::parse_date(&self.effective_from)?;
+        if self.fixed_charge < 0.0 {
+            return invalid("fixed charge cannot be negative");
+        }
+        match self.tariff_type {
+            TariffType::Flat => {
+                if !self.rate.is_some_and(|rate| rate >= 0.0) {
+                    return invalid("a flat tariff needs a rate of zero or more");
+                }
+                if !self.tiers.is_empty() {
+                    return invalid("a flat tariff has no tiers");
+                }
+            }
+            TariffType::Tiered => {
+                if self.tiers.is_empty() {
+                    return invalid("a tiered tariff needs at least one tier");
+                }
+                let mut floor = 0.0;
+                for (i, tier) in self.tiers.iter().enumerate() {
+                    if tier.rate < 0.0 {
+                        return invalid("tier rates cannot be negative");
+                    }
+                    let last = i == self.tiers.len() - 1;
+                    match tier.up_to {
+                        None if last => {}
+                        None => return invalid("only the last tier can be open-ended"),
+                        Some(_) if last => return invalid("the last tier must be open-ended"),
+                        Some(up_to) if up_to <= floor => {
+                            return invalid("tier bounds must increase")
+                        }
+                        Some(up_to) => floor = up_to,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+const TARIFF_COLUMNS: &str =
+    "tariff_id, property_id, utility_type, tariff_type, CAST(rate AS REAL) AS rate,
+     CAST(fixed_charge AS REAL) AS fixed_charge, effective_from";
+
+/// Tariffs with their tiers, optionally only those that apply to a property
+/// (its own and the defaults).
+#[tauri::command]
+pub async fn get_utility_tariffs(app: AppHandle, property_id: Option<i64>) -> Result<Vec<Tariff>> {
+    let pool = db::pool(&app).await?;
+    let mut tariffs: Vec<Tariff> = sqlx::query_as(&format!(
+        "SELECT {TARIFF_COLUMNS} FROM utility_tariffs
+         WHERE ?1 IS NULL OR property_id IS NULL OR property_id = ?1
+         ORDER BY property_id IS NOT NULL, property_id, utility_type, effective_from DESC"
+    ))
+    .bind(property_id)
+    .fetch_all(&pool)
+    .await?;
+    let mut conn = pool.acquire().await?;
+    for tariff in &mut tariffs {
+        tariff.tiers = tiers(&mut conn, tariff.tariff_id.unwrap_or_default()).await?;
+    }
+    Ok(tariffs)
+}
+
+/// Inserts the tariff, or updates it when `tariffId` is set. Applies to
+/// readings billed from now on.
+#[tauri::command]
+pub async fn save_utility_tariff(app: AppHandle, tariff: Tariff) -> Result<Tariff> {
+    let pool = db::pool(&app).await?;
+    save_tariff(&pool, tariff).await
+}
+
+#[tauri::command]
+pub async fn delete_utility_tariff(app: AppHandle, tariff_id: i64) -> Result<()> {
+    let pool = db::pool(&app).await?;
+    sqlx::query("DELETE FROM utility_tariffs WHERE tariff_id = ?1")
+        .bind(tariff_id)
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn save_tariff(pool: &SqlitePool, tariff: Tariff) -> Result<Tariff> {
+    tariff.validate()?;
+    let rate = match tariff.tariff_type {
+        TariffType::Flat => tariff.rate,
+        TariffType::Tiered => None,
+    };
+
+    let mut tx = pool.begin().await?;
+    let tariff_id = match tariff.tariff_id {
+        Some(tariff_id) => {
+            let result = sqlx::query(
+                "UPDATE utility_tariffs
+                 SET property_id = ?2, utility_type = ?3, tariff_type = ?4, rate = ?5,
+                     fixed_charge = ?6, effective_from = ?7, updated_at = CURRENT_TIMESTAMP
+                 WHERE tariff_id = ?1",
+            )
+            .bind(tariff_id)
+            .bind(tariff.property_id)
+            .bind(tariff.utility_type)
+            .bind(tariff.tariff_type)
+            .bind(rate)
+            .bind(tariff.fixed_charge)
+            .bind(&tariff.effective_from)
+            .execute(&mut *tx)
+            .await
+            .map_err(duplicate_tariff)?;
+            if result.rows_affected() == 0 {
+                return Err(Error::NotFound(format!("tariff {tariff_id}")));
+            }
+            sqlx::query("DELETE FROM utility_tariff_tiers WHERE tariff_id = ?1")
+                .bind(tariff_id)
+                .execute(&mut *tx)
+                .await?;
+            tariff_id
+        }
+        None => sqlx::query(
+            "INSERT INTO utility_tariffs
+                 (property_id, utility_type, tariff_type, rate, fixed_charge, effective_from)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(tariff.property_id)
+        .bind(tariff.utility_type)
+        .bind(tariff.tariff_type)
+        .bind(rate)
+        .bind(tariff.fixed_charge)
+        .bind(&tariff.effective_from)
+        .execute(&mut *tx)
+        .await
+        .map_err(duplicate_tariff)?
+        .last_insert_rowid(),
+    };
+    for tier in &tariff.tiers {
+        sqlx::query(
+            "INSERT INTO utility_tariff_tiers (tariff_id, up_to, rate) VALUES (?1, ?2, ?3)",
+        )
+        .bind(tariff_id)
+        .bind(tier.up_to)
+        .bind(tier.rate)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(Tariff {
+        tariff_id: Some(tariff_id),
+        rate,
+        ..tariff
+    })
+}
+
+/// The tariff in force on `date`: the property's own when it has one,
+/// otherwise the default.
+pub async fn tariff_for(
+    conn: &mut SqliteConnection,
+    property_id: i64,
+    utility_type: UtilityType,
+    date: &str,
+) -> Result<Option<Tariff>> {
+    let tariff: Option<Tariff> = sqlx::query_as(&format!(
+        "SELECT {TARIFF_COLUMNS} FROM utility_tariffs
+         WHERE (property_id = ?1 OR property_id IS NULL)
+           AND utility_type = ?2 AND effective_from <= ?3
+         ORDER BY property_id IS NULL, effective_from DESC
+         LIMIT 1"
+    ))
+    .bind(property_id)
+    .bind(utility_type)
+    .bind(date)
+    .fetch_optional(&mut *conn)
+    .await?;
+    match tariff {
+        Some(mut tariff) => {
+            tariff.tiers = tiers(conn, tariff.tariff_id.unwrap_or_default()).await?;
+            Ok(Some(tariff))
+        }
+        None => Ok(None),
+    }
+}
+
+async fn tiers(conn: &mut SqliteConnection, tariff_id: i64) -> Result<Vec<TariffTier>> {
+    Ok(sqlx::query_as(
+        "SELECT up_to, CAST(rate AS REAL) AS rate FROM utility_tariff_tiers
+         WHERE tariff_id = ?1
+         ORDER BY up_to IS NULL, up_to",
+    )
+    .bind(tariff_id)
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+fn duplicate_tariff(err: sqlx::Error) -> Error {
+    match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => Error::InvalidInput(
+            "a tariff for this property and utility already starts on that date".into(),
+        ),
+        _ => err.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(rate: f64, fixed_charge: f64) -> Tariff {
+        Tariff {
+            tariff_id: None,
+            property_id: None,
+            utility_type: UtilityType::Water,
+            tariff_type: TariffType::Flat,
+            rate: Some(rate),
+            fixed_charge,
+            effective_from: "2024-01-01".into(),
+            tiers: Vec::new(),
+        }
+    }
+
+    fn tiered(tiers: &[(Option<f64>, f64)], fixed_charge: f64) -> Tariff {
+        Tariff {
+            tariff_type: TariffType::Tiered,
+            rate: None,
+            tiers: tiers
+                .iter()
+                .map(|&(up_to, rate)| TariffTier { up_to, rate })
+                .collect(),
+            ..flat(0.0, fixed_charge)
+        }
+    }
+
+    /// Water tiers in the shape Kenyan utilities publish them, per m³.
+    fn water() -> Tariff {
+        tiered(
+            &[(Some(6.0), 53.0), (Some(60.0), 64.6), (None, 74.0)],
+            200.0,
+        )
+    }
+
+    #[test]
+    fn flat_cost() {
+        let tariff = flat(25.5, 150.0);
+        assert_eq!(tariff.cost(10.0), 405.0);
+        assert_eq!(tariff.cost(0.0), 150.0);
+        // Fractional units round to cents, not per unit.
+        assert_eq!(flat(23.333, 0.0).cost(3.3), 77.0);
+    }
+
+    #[test]
+    fn tier_boundaries() {
+        let tariff = water();
+        assert_eq!(tariff.cost(0.0), 200.0);
+        assert_eq!(tariff.cost(5.0), 200.0 + 265.0);
+        // Exactly at a bound is all in the lower tier.
+        assert_eq!(tariff.cost(6.0), 200.0 + 318.0);
+        assert_eq!(tariff.cost(6.5), 200.0 + 318.0 + 32.3);
+        assert_eq!(tariff.cost(60.0), 200.0 + 318.0 + 3_488.4);
+        assert_eq!(tariff.cost(75.0), 200.0 + 318.0 + 3_488.4 + 1_110.0);
+    }
+
+    #[test]
+    fn negative_consumption_bills_the_standing_charge() {
+        // A meter swap or misread can make the reading go backwards.
+        assert_eq!(water().cost(-12.0), 200.0);
+        assert_eq!(flat(25.0, 0.0).cost(-1.0), 0.0);
+    }
+
+    #[test]
+    fn single_open_tier_is_flat() {
+        let tariff = tiered(&[(None, 18.75)], 0.0);
+        assert_eq!(tariff.cost(8.4), flat(18.75, 0.0).cost(8.4));
+    }
+
+    #[test]
+    fn validation() {
+        assert!(flat(25.0, 0.0).validate().is_ok());
+        assert!(water().validate().is_ok());
+        assert!(flat(-1.0, 0.0).validate().is_err());
+        assert!(Tariff {
+            rate: None,
+            ..flat(0.0, 0.0)
+        }
+        .validate()
+        .is_err());
+        assert!(flat(25.0, -5.0).validate().is_err());
+        assert!(Tariff {
+            effective_from: "2024-13-01".into(),
+            ..flat(25.0, 0.0)
+        }
+        .validate()
+        .is_err());
+        assert!(tiered(&[], 0.0).validate().is_err());
+        assert!(tiered(&[(Some(6.0), 53.0)], 0.0).validate().is_err());
+        assert!(tiered(&[(None, 53.0), (None, 60.0)], 0.0)
+            .validate()
+            .is_err());
+        assert!(
+            tiered(&[(Some(6.0), 53.0), (Some(6.0), 60.0), (None, 70.0)], 0.0)
+                .validate()
+                .is_err()
+        );
+        assert!(tiered(&[(Some(0.0), 53.0), (None, 60.0)], 0.0)
+            .validate()
+            .is_err());
+        assert!(tiered(&[(Some(6.0), -1.0), (None, 60.0)], 0.0)
+            .validate()
+            .is_err());
+    }
+}